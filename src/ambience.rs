@@ -0,0 +1,176 @@
+use crate::{track_input_with_args, CrackTrackClient, ResolvedTrack};
+use serenity::all::GuildId;
+use songbird::error::ControlError;
+use songbird::tracks::{LoopState, Track, TrackHandle};
+use songbird::Call;
+
+/// Volume ambience plays at by default, in percent, low enough to stay in
+/// the background of the music.
+pub const DEFAULT_AMBIENCE_VOLUME: u8 = 15;
+/// Loudest ambience can play, in percent.
+pub const MAX_AMBIENCE_VOLUME: u8 = 100;
+
+/// Errors that can occur when playing ambience.
+#[derive(Debug, thiserror::Error)]
+pub enum AmbienceError {
+    #[error("Ambience volume must be at most {max}%, not {volume}%")]
+    VolumeTooLoud { volume: u8, max: u8 },
+    #[error("No ambience is playing")]
+    NotPlaying,
+    #[error(transparent)]
+    Control(#[from] ControlError),
+}
+
+/// Background sound looping under the music of a guild, like rain or a
+/// fireplace. It plays alongside the queue at a volume of its own, and isn't
+/// part of it.
+#[derive(Clone, Debug)]
+pub struct Ambience {
+    pub track: ResolvedTrack,
+    /// Volume in percent.
+    pub volume: u8,
+    handle: TrackHandle,
+}
+
+/// Check that an ambience volume is at most [`MAX_AMBIENCE_VOLUME`].
+fn check_volume(volume: u8) -> Result<(), AmbienceError> {
+    if volume > MAX_AMBIENCE_VOLUME {
+        return Err(AmbienceError::VolumeTooLoud {
+            volume,
+            max: MAX_AMBIENCE_VOLUME,
+        });
+    }
+    Ok(())
+}
+
+/// Implement ambience for [`CrackTrackClient`], kept by guild in `ambience`.
+impl CrackTrackClient {
+    /// Loop `track` in the call of a guild at `volume` percent, mixed with
+    /// whatever plays there, in place of any ambience already playing.
+    /// # Errors
+    /// Returns an error if the volume is above [`MAX_AMBIENCE_VOLUME`].
+    pub async fn start_ambience(
+        &self,
+        guild_id: GuildId,
+        call: &mut Call,
+        track: ResolvedTrack,
+        volume: u8,
+    ) -> Result<Ambience, AmbienceError> {
+        check_volume(volume)?;
+        self.stop_ambience(guild_id);
+        let input = track_input_with_args(self.req_client.clone(), &track, self.ytdl_args().await);
+        let handle = call.play(
+            Track::from(input)
+                .volume(f32::from(volume) / 100.0)
+                .loops(LoopState::Infinite),
+        );
+        let ambience = Ambience {
+            track,
+            volume,
+            handle,
+        };
+        self.ambience.insert(guild_id, ambience.clone());
+        Ok(ambience)
+    }
+
+    /// Stop the ambience of a guild, returning it if it was playing.
+    pub fn stop_ambience(&self, guild_id: GuildId) -> Option<Ambience> {
+        let (_, ambience) = self.ambience.remove(&guild_id)?;
+        // It is gone already if the call was stopped or left
+        let _ = ambience.handle.stop();
+        Some(ambience)
+    }
+
+    /// Set the volume of the ambience of a guild, in percent, leaving the
+    /// music as it is.
+    /// # Errors
+    /// Returns an error if the volume is above [`MAX_AMBIENCE_VOLUME`] or
+    /// no ambience is playing.
+    pub fn set_ambience_volume(&self, guild_id: GuildId, volume: u8) -> Result<(), AmbienceError> {
+        check_volume(volume)?;
+        let mut ambience = self
+            .ambience
+            .get_mut(&guild_id)
+            .ok_or(AmbienceError::NotPlaying)?;
+        ambience.handle.set_volume(f32::from(volume) / 100.0)?;
+        ambience.volume = volume;
+        Ok(())
+    }
+
+    /// Get the ambience of a guild, if it is playing.
+    #[must_use]
+    pub fn ambience(&self, guild_id: GuildId) -> Option<Ambience> {
+        self.ambience
+            .get(&guild_id)
+            .map(|ambience| ambience.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{local_track, VoiceRecorder};
+    use serenity::all::UserId;
+    use songbird::input::File;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ambience() {
+        assert!(check_volume(DEFAULT_AMBIENCE_VOLUME).is_ok());
+        assert!(check_volume(MAX_AMBIENCE_VOLUME).is_ok());
+        assert!(matches!(
+            check_volume(MAX_AMBIENCE_VOLUME + 1),
+            Err(AmbienceError::VolumeTooLoud {
+                volume: 101,
+                max: 100
+            })
+        ));
+
+        let client = CrackTrackClient::new();
+        let guild_id = GuildId::new(1);
+        assert!(client.ambience(guild_id).is_none());
+        assert!(client.stop_ambience(guild_id).is_none());
+        assert!(matches!(
+            client.set_ambience_volume(guild_id, 10),
+            Err(AmbienceError::NotPlaying)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ambience_outlives_track() {
+        // A second of silence to loop and to play as the track
+        let recorder = VoiceRecorder::new(1);
+        for _ in 0..50 {
+            recorder.push_tick([]);
+        }
+        let path = std::env::temp_dir().join(format!("ambience-{}.wav", std::process::id()));
+        tokio::fs::write(&path, recorder.clip(None)).await.unwrap();
+        let track = local_track(&path).unwrap();
+
+        let client = CrackTrackClient::new();
+        let guild_id = GuildId::new(1);
+        let mut call = Call::standalone(guild_id, UserId::new(1));
+        let ambience = client
+            .start_ambience(guild_id, &mut call, track, DEFAULT_AMBIENCE_VOLUME)
+            .await
+            .unwrap();
+        let song = call.play(Track::from(File::new(path.clone())).pause());
+
+        // Skipping stops only the track, not the whole call
+        song.stop().unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while song.get_info().await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(ambience.handle.get_info().await.is_ok());
+        assert!(client.set_ambience_volume(guild_id, 30).is_ok());
+        assert_eq!(client.ambience(guild_id).unwrap().volume, 30);
+
+        assert!(client.stop_ambience(guild_id).is_some());
+        assert!(client.ambience(guild_id).is_none());
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+}
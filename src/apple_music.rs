@@ -0,0 +1,276 @@
+use crate::CatalogTrack;
+use crack_types::Error;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Endpoint of the public iTunes Search API used to look tracks up.
+const ITUNES_LOOKUP_URL: &str = "https://itunes.apple.com/lookup";
+
+/// Most IDs to look up in one request.
+const ITUNES_LOOKUP_BATCH: usize = 100;
+
+static APPLE_MUSIC_URL_REGEX_STR: &str =
+    r"music\.apple\.com/(?:([a-z]{2})/)?(album|song|playlist)/(?:[^/?#]+/)?([\w.\-]+)";
+static APPLE_MUSIC_URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(APPLE_MUSIC_URL_REGEX_STR).unwrap());
+
+static APPLE_MUSIC_TRACK_PARAM_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[?&]i=(\d+)").unwrap());
+
+static APPLE_MUSIC_SONG_META_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"property="music:song"\s+content="[^"]*?/song/(?:[^"/]+/)?(\d+)"#).unwrap()
+});
+
+/// Kind of item an Apple Music URL points to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppleMusicMediaType {
+    Song,
+    Album,
+    Playlist,
+}
+
+/// A parsed `music.apple.com` URL. An album URL with a track selected, like
+/// `album/name/123?i=456`, is parsed as the song.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppleMusicUrl {
+    pub media_type: AppleMusicMediaType,
+    pub id: String,
+    /// Storefront country code, e.g. `us`.
+    pub country: Option<String>,
+}
+
+/// Parse an Apple Music URL, returning `None` if it isn't one.
+#[must_use]
+pub fn parse_apple_music_url(url: &str) -> Option<AppleMusicUrl> {
+    let caps = APPLE_MUSIC_URL_REGEX.captures(url)?;
+    let country = caps.get(1).map(|country| country.as_str().to_string());
+    let track = APPLE_MUSIC_TRACK_PARAM_REGEX
+        .captures(url)
+        .map(|track| track[1].to_string());
+    let (media_type, id) = match (&caps[2], track) {
+        ("album", Some(track)) => (AppleMusicMediaType::Song, track),
+        ("album", None) => (AppleMusicMediaType::Album, caps[3].to_string()),
+        ("song", _) => (AppleMusicMediaType::Song, caps[3].to_string()),
+        ("playlist", _) => (AppleMusicMediaType::Playlist, caps[3].to_string()),
+        _ => return None,
+    };
+    Some(AppleMusicUrl {
+        media_type,
+        id,
+        country,
+    })
+}
+
+/// Check if a URL points to Apple Music.
+#[must_use]
+pub fn is_apple_music_url(url: &str) -> bool {
+    APPLE_MUSIC_URL_REGEX.is_match(url)
+}
+
+/// Extract the IDs of the songs listed in the metadata of an Apple Music
+/// playlist page, in order.
+#[must_use]
+pub fn parse_playlist_page(html: &str) -> Vec<String> {
+    APPLE_MUSIC_SONG_META_REGEX
+        .captures_iter(html)
+        .map(|caps| caps[1].to_string())
+        .collect()
+}
+
+/// Response of the iTunes lookup API.
+#[derive(Debug, Deserialize)]
+struct LookupResponse {
+    results: Vec<LookupResult>,
+}
+
+/// An item of a [`LookupResponse`], either a collection or a track.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LookupResult {
+    wrapper_type: String,
+    track_id: Option<u64>,
+    track_name: Option<String>,
+    artist_name: Option<String>,
+    collection_name: Option<String>,
+    track_time_millis: Option<u64>,
+}
+
+/// Implement [`LookupResult`].
+impl LookupResult {
+    /// Convert the result to a [`CatalogTrack`] if it is a track.
+    fn into_track(self) -> Option<(u64, CatalogTrack)> {
+        if self.wrapper_type != "track" {
+            return None;
+        }
+        let track = CatalogTrack::new(
+            self.track_name?,
+            self.artist_name.into_iter().collect(),
+            self.collection_name,
+            Duration::from_millis(self.track_time_millis.unwrap_or_default()),
+        );
+        Some((self.track_id?, track))
+    }
+}
+
+/// Client for Apple Music links. The public iTunes lookup API gives the
+/// tracks of songs and albums, playlists are read from their page metadata.
+/// No credentials are needed.
+#[derive(Clone, Debug)]
+pub struct AppleMusicClient {
+    client: reqwest::Client,
+}
+
+/// Implement [`AppleMusicClient`].
+impl AppleMusicClient {
+    /// Create a new [`AppleMusicClient`].
+    #[must_use]
+    pub fn new(client: reqwest::Client) -> Self {
+        AppleMusicClient { client }
+    }
+
+    /// Get the tracks an Apple Music song, album or playlist URL points to,
+    /// in order.
+    /// # Errors
+    /// Returns an error if the URL isn't an Apple Music URL or a request fails.
+    pub async fn tracks(&self, url: &str) -> Result<Vec<CatalogTrack>, Error> {
+        let Some(AppleMusicUrl {
+            media_type,
+            id,
+            country,
+        }) = parse_apple_music_url(url)
+        else {
+            return Err(format!("Not an Apple Music URL: {url}").into());
+        };
+        let country = country.as_deref().unwrap_or("us");
+        let ids = match media_type {
+            AppleMusicMediaType::Song | AppleMusicMediaType::Album => vec![id],
+            AppleMusicMediaType::Playlist => {
+                let html = self
+                    .client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                parse_playlist_page(&html)
+            }
+        };
+        let tracks = self.lookup(&ids, country).await?;
+        if media_type != AppleMusicMediaType::Playlist {
+            return Ok(tracks.into_iter().map(|(_, track)| track).collect());
+        }
+        // The lookup doesn't keep the order of the IDs
+        let by_id: HashMap<String, CatalogTrack> = tracks
+            .into_iter()
+            .map(|(id, track)| (id.to_string(), track))
+            .collect();
+        Ok(ids.iter().filter_map(|id| by_id.get(id).cloned()).collect())
+    }
+
+    /// Look songs or albums up by ID, an album gives all of its tracks.
+    /// Returns the tracks with their IDs.
+    async fn lookup(
+        &self,
+        ids: &[String],
+        country: &str,
+    ) -> Result<Vec<(u64, CatalogTrack)>, Error> {
+        let mut tracks = Vec::new();
+        for batch in ids.chunks(ITUNES_LOOKUP_BATCH) {
+            let response: LookupResponse = self
+                .client
+                .get(ITUNES_LOOKUP_URL)
+                .query(&[
+                    ("id", batch.join(",").as_str()),
+                    ("entity", "song"),
+                    ("country", country),
+                    ("limit", "200"),
+                ])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            tracks.extend(
+                response
+                    .results
+                    .into_iter()
+                    .filter_map(LookupResult::into_track),
+            );
+        }
+        Ok(tracks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_apple_music_url() {
+        assert_eq!(
+            parse_apple_music_url(
+                "https://music.apple.com/us/album/never-gonna-give-you-up/1558533900?i=1558534271"
+            ),
+            Some(AppleMusicUrl {
+                media_type: AppleMusicMediaType::Song,
+                id: "1558534271".to_string(),
+                country: Some("us".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_apple_music_url(
+                "https://music.apple.com/gb/album/whenever-you-need-somebody/1558533900"
+            )
+            .map(|url| (url.media_type, url.id)),
+            Some((AppleMusicMediaType::Album, "1558533900".to_string()))
+        );
+        assert_eq!(
+            parse_apple_music_url("https://music.apple.com/us/playlist/todays-hits/pl.f4d106fed2bd41149aaacabb233eb5eb")
+                .map(|url| (url.media_type, url.id)),
+            Some((
+                AppleMusicMediaType::Playlist,
+                "pl.f4d106fed2bd41149aaacabb233eb5eb".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_apple_music_url("https://music.apple.com/song/1558534271")
+                .map(|url| (url.media_type, url.country)),
+            Some((AppleMusicMediaType::Song, None))
+        );
+        assert!(!is_apple_music_url("https://open.spotify.com/track/1"));
+        assert!(!is_apple_music_url(
+            "https://music.apple.com/us/artist/rick-astley/669771"
+        ));
+    }
+
+    #[test]
+    fn test_parse_playlist_page() {
+        let html = r#"<meta property="music:song" content="https://music.apple.com/us/song/one/111">
+            <meta property="og:title" content="Playlist">
+            <meta property="music:song" content="https://music.apple.com/us/song/two/222">"#;
+        assert_eq!(parse_playlist_page(html), vec!["111", "222"]);
+    }
+
+    #[test]
+    fn test_lookup_result() {
+        let json = r#"{"resultCount": 2, "results": [
+            {"wrapperType": "collection", "collectionId": 1, "collectionName": "Album"},
+            {"wrapperType": "track", "kind": "song", "trackId": 2, "trackName": "Song",
+             "artistName": "Artist", "collectionName": "Album", "trackTimeMillis": 213000}
+        ]}"#;
+        let response: LookupResponse = serde_json::from_str(json).unwrap();
+        let tracks: Vec<_> = response
+            .results
+            .into_iter()
+            .filter_map(LookupResult::into_track)
+            .collect();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].0, 2);
+        assert_eq!(tracks[0].1.search_query(), "Artist - Song");
+        assert_eq!(tracks[0].1.duration, Duration::from_secs(213));
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{audio_only_video_options, LruCache, ResolvedTrack};
+use crack_types::{Error, TrackResolveError};
+use dashmap::DashSet;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Default most space the [`AudioCache`] takes on disk, in megabytes.
+pub const DEFAULT_AUDIO_CACHE_MAX_MB: u64 = 1024;
+/// Plays a track needs before its audio is cached, so one-off requests
+/// don't push the favorites of a guild out of the cache.
+pub const AUDIO_CACHE_MIN_PLAYS: usize = 2;
+/// Longest track whose audio is cached.
+pub const AUDIO_CACHE_MAX_TRACK_LENGTH: Duration = Duration::from_secs(20 * 60);
+/// Number of tracks whose plays are counted.
+const PLAY_COUNT_CAPACITY: usize = 4096;
+/// Time the plays of a track count towards caching it.
+const PLAY_COUNT_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Extension of cached audio files.
+const AUDIO_EXTENSION: &str = "audio";
+/// Extension of audio files still downloading.
+const PARTIAL_EXTENSION: &str = "part";
+
+/// Key the audio of a track is cached under, its video ID, if it is worth
+/// caching: a YouTube video that isn't live or endless, and no longer than
+/// [`AUDIO_CACHE_MAX_TRACK_LENGTH`].
+#[must_use]
+pub fn audio_cache_key(track: &ResolvedTrack) -> Option<String> {
+    if track.is_unbounded() || track.is_live() || track.local_path().is_some() {
+        return None;
+    }
+    if !track
+        .get_raw_duration()
+        .is_some_and(|duration| duration <= AUDIO_CACHE_MAX_TRACK_LENGTH)
+    {
+        return None;
+    }
+    // The ID names the file, so nothing but the characters of video IDs
+    track.video_id().filter(|id| {
+        !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    })
+}
+
+/// A file in the [`AudioCache`].
+#[derive(Clone, Copy, Debug)]
+struct CachedAudio {
+    size: u64,
+    /// Value of the clock of the index when the file was last used.
+    last_used: u64,
+}
+
+/// The files in the [`AudioCache`], by video ID.
+#[derive(Debug, Default)]
+struct CacheIndex {
+    files: HashMap<String, CachedAudio>,
+    total_bytes: u64,
+    /// Counts the uses of files, to tell which was used least recently.
+    clock: u64,
+}
+
+/// Implement [`CacheIndex`].
+impl CacheIndex {
+    /// Mark the file of a video as just used, if it is cached.
+    fn touch(&mut self, video_id: &str) -> bool {
+        self.clock += 1;
+        match self.files.get_mut(video_id) {
+            Some(file) => {
+                file.last_used = self.clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add the file of a video, as just used.
+    fn insert(&mut self, video_id: String, size: u64) {
+        self.clock += 1;
+        let file = CachedAudio {
+            size,
+            last_used: self.clock,
+        };
+        if let Some(old) = self.files.insert(video_id, file) {
+            self.total_bytes -= old.size;
+        }
+        self.total_bytes += size;
+    }
+
+    /// Drop the least recently used files until the rest fit in
+    /// `max_bytes`, returning the IDs of the videos dropped.
+    fn evict(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total_bytes > max_bytes {
+            let Some(oldest) = self
+                .files
+                .iter()
+                .min_by_key(|(_, file)| file.last_used)
+                .map(|(video_id, _)| video_id.clone())
+            else {
+                break;
+            };
+            if let Some(file) = self.files.remove(&oldest) {
+                self.total_bytes -= file.size;
+            }
+            evicted.push(oldest);
+        }
+        evicted
+    }
+}
+
+/// Keeps the audio of tracks played often on disk, so they play from the
+/// file instead of being fetched from YouTube again. A track is downloaded
+/// once it was played [`AUDIO_CACHE_MIN_PLAYS`] times, and the least
+/// recently played files are removed once the cache is over its size.
+/// Clones share the cache.
+#[derive(Clone, Debug)]
+pub struct AudioCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Arc<Mutex<CacheIndex>>,
+    /// Recent plays of tracks not cached yet, by video ID.
+    plays: LruCache<usize>,
+    /// IDs of the videos being downloaded.
+    downloading: Arc<DashSet<String>>,
+}
+
+/// Implement [`AudioCache`].
+impl AudioCache {
+    /// Create a new [`AudioCache`] keeping at most `max_bytes` of audio in
+    /// the given directory. Files already there are only used once
+    /// [`AudioCache::load`] indexes them.
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        AudioCache {
+            dir: dir.into(),
+            max_bytes,
+            index: Arc::new(Mutex::new(CacheIndex::default())),
+            plays: LruCache::new(PLAY_COUNT_CAPACITY, PLAY_COUNT_TTL),
+            downloading: Arc::new(DashSet::new()),
+        }
+    }
+
+    /// Create a cache in `AUDIO_CACHE_DIR`, holding `AUDIO_CACHE_MAX_MB`
+    /// megabytes or [`DEFAULT_AUDIO_CACHE_MAX_MB`]. Returns `None` unless
+    /// the directory is set.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let dir = std::env::var("AUDIO_CACHE_DIR").ok()?;
+        let max_mb = std::env::var("AUDIO_CACHE_MAX_MB")
+            .ok()
+            .and_then(|max_mb| max_mb.parse().ok())
+            .unwrap_or(DEFAULT_AUDIO_CACHE_MAX_MB);
+        Some(AudioCache::new(dir, max_mb.saturating_mul(1024 * 1024)))
+    }
+
+    /// Get the directory the cache writes to.
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Total size of the cached audio, in bytes.
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.index.lock().map_or(0, |index| index.total_bytes)
+    }
+
+    /// Path of the cached audio of a video.
+    fn file_path(&self, video_id: &str) -> PathBuf {
+        self.dir.join(format!("{video_id}.{AUDIO_EXTENSION}"))
+    }
+
+    /// Index the audio already in the directory, from an earlier run, the
+    /// most recently written files counting as the most recently used.
+    /// Downloads that never finished are removed.
+    /// # Errors
+    /// Returns an error if the directory exists but cannot be read.
+    pub async fn load(&self) -> Result<(), Error> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some(PARTIAL_EXTENSION) => {
+                    let _ = tokio::fs::remove_file(&path).await;
+                }
+                Some(AUDIO_EXTENSION) => {
+                    let Some(video_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                        continue;
+                    };
+                    let metadata = entry.metadata().await?;
+                    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    files.push((modified, video_id.to_string(), metadata.len()));
+                }
+                _ => {}
+            }
+        }
+        files.sort();
+        let evicted = match self.index.lock() {
+            Ok(mut index) => {
+                for (_, video_id, size) in files {
+                    index.insert(video_id, size);
+                }
+                index.evict(self.max_bytes)
+            }
+            Err(_) => return Ok(()),
+        };
+        self.remove_files(evicted).await;
+        Ok(())
+    }
+
+    /// Get the path of the cached audio of a track, if there is one, marking
+    /// it as just used.
+    pub fn get(&self, track: &ResolvedTrack) -> Option<PathBuf> {
+        let video_id = audio_cache_key(track)?;
+        let cached = self.index.lock().ok()?.touch(&video_id);
+        cached.then(|| self.file_path(&video_id))
+    }
+
+    /// Count a play of a track that isn't cached. Returns whether it was
+    /// played often enough that its audio should be cached now, see
+    /// [`AudioCache::store`].
+    pub fn record_play(&self, track: &ResolvedTrack) -> bool {
+        let Some(video_id) = audio_cache_key(track) else {
+            return false;
+        };
+        let cached = self
+            .index
+            .lock()
+            .is_ok_and(|index| index.files.contains_key(&video_id));
+        if cached || self.downloading.contains(&video_id) {
+            return false;
+        }
+        let plays = self.plays.get(&video_id).unwrap_or(0) + 1;
+        self.plays.insert(video_id, plays);
+        plays >= AUDIO_CACHE_MIN_PLAYS
+    }
+
+    /// Download the audio of a track into the cache, removing the least
+    /// recently used files if it no longer fits. Does nothing if the track
+    /// is already being downloaded.
+    /// # Errors
+    /// Returns an error if the track cannot be cached or its audio cannot be
+    /// downloaded or written.
+    pub async fn store(&self, track: &ResolvedTrack) -> Result<(), Error> {
+        let video_id = audio_cache_key(track).ok_or(TrackResolveError::NotFound)?;
+        if !self.downloading.insert(video_id.clone()) {
+            return Ok(());
+        }
+        let result = self.download(&video_id).await;
+        self.downloading.remove(&video_id);
+        result
+    }
+
+    /// Download the audio of a video into the cache.
+    async fn download(&self, video_id: &str) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.file_path(video_id);
+        // Download to a partial file first so a crash never leaves truncated audio.
+        let partial_path = path.with_extension(PARTIAL_EXTENSION);
+        let url = format!("https://www.youtube.com/watch?v={video_id}");
+        let video = rusty_ytdl::Video::new_with_options(&url, audio_only_video_options())?;
+        if let Err(e) = video.download(&partial_path).await {
+            let _ = tokio::fs::remove_file(&partial_path).await;
+            return Err(e.into());
+        }
+        let size = tokio::fs::metadata(&partial_path).await?.len();
+        tokio::fs::rename(&partial_path, &path).await?;
+        self.add(video_id, size).await;
+        Ok(())
+    }
+
+    /// Index a file written to the cache, removing the files evicted for it.
+    async fn add(&self, video_id: &str, size: u64) {
+        let evicted = match self.index.lock() {
+            Ok(mut index) => {
+                index.insert(video_id.to_string(), size);
+                index.evict(self.max_bytes)
+            }
+            Err(_) => return,
+        };
+        self.plays.insert(video_id, 0);
+        self.remove_files(evicted).await;
+    }
+
+    /// Remove the cached audio of videos dropped from the index.
+    async fn remove_files(&self, video_ids: Vec<String>) {
+        for video_id in video_ids {
+            if let Err(_e) = tokio::fs::remove_file(self.file_path(&video_id)).await {
+                #[cfg(feature = "crack-tracing")]
+                tracing::warn!("Failed to remove the cached audio of {video_id}: {_e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crack_types::{AuxMetadata, QueryType};
+
+    fn create_test_track(video_id: &str) -> ResolvedTrack {
+        ResolvedTrack::new(QueryType::VideoLink(format!(
+            "https://www.youtube.com/watch?v={video_id}"
+        )))
+        .with_metadata(AuxMetadata {
+            duration: Some(Duration::from_secs(180)),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_audio_cache_key() {
+        let track = create_test_track("dQw4w9WgXcQ");
+        assert_eq!(audio_cache_key(&track), Some("dQw4w9WgXcQ".to_string()));
+        assert_eq!(audio_cache_key(&track.clone().with_live(true)), None);
+        let long = track.with_metadata(AuxMetadata {
+            duration: Some(AUDIO_CACHE_MAX_TRACK_LENGTH * 2),
+            ..Default::default()
+        });
+        assert_eq!(audio_cache_key(&long), None);
+    }
+
+    #[tokio::test]
+    async fn test_audio_cache_eviction() {
+        let dir = std::env::temp_dir().join(format!("cracktunes-audio-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        for video_id in ["aaaaaaaaaaa", "bbbbbbbbbbb"] {
+            let path = dir.join(format!("{video_id}.{AUDIO_EXTENSION}"));
+            tokio::fs::write(path, vec![0; 400]).await.unwrap();
+        }
+        let partial = dir.join(format!("ccccccccccc.{PARTIAL_EXTENSION}"));
+        tokio::fs::write(&partial, vec![0; 10]).await.unwrap();
+
+        let cache = AudioCache::new(&dir, 1000);
+        cache.load().await.unwrap();
+        assert_eq!(cache.size(), 800);
+        assert!(!partial.exists());
+
+        // Cached tracks aren't counted, the others are cached on their second play
+        let a = create_test_track("aaaaaaaaaaa");
+        assert!(cache.get(&a).is_some());
+        assert!(!cache.record_play(&a));
+        let d = create_test_track("ddddddddddd");
+        assert!(cache.get(&d).is_none());
+        assert!(!cache.record_play(&d));
+        assert!(cache.record_play(&d));
+
+        // Adding a file past the size drops the least recently used one
+        let path = dir.join(format!("ddddddddddd.{AUDIO_EXTENSION}"));
+        tokio::fs::write(&path, vec![0; 400]).await.unwrap();
+        cache.add("ddddddddddd", 400).await;
+        assert_eq!(cache.size(), 800);
+        assert!(cache.get(&create_test_track("bbbbbbbbbbb")).is_none());
+        assert!(!dir.join(format!("bbbbbbbbbbb.{AUDIO_EXTENSION}")).exists());
+        assert_eq!(cache.get(&d), Some(path));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{AudioFilter, EffectsError};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{FRAC_PI_4, PI, SQRT_2};
+use std::fmt::{self, Display};
+
+/// Seconds the audio takes to go around by default.
+pub const DEFAULT_AUTO_PAN_PERIOD: u16 = 8;
+/// Quickest the audio can go around, in seconds.
+pub const MIN_AUTO_PAN_PERIOD: u16 = 2;
+/// Slowest the audio can go around, in seconds.
+pub const MAX_AUTO_PAN_PERIOD: u16 = 60;
+
+/// Settings of the auto-pan, or "8D", effect, which moves the audio from one
+/// side to the other and back so it seems to go around the listener.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoPan {
+    /// Seconds the audio takes to go around.
+    period_secs: u16,
+}
+
+/// Implement [`AutoPan`].
+impl AutoPan {
+    /// Create a new [`AutoPan`] going around every `period_secs` seconds.
+    /// # Errors
+    /// Returns an error if the period is below [`MIN_AUTO_PAN_PERIOD`] or
+    /// above [`MAX_AUTO_PAN_PERIOD`].
+    pub fn new(period_secs: u16) -> Result<Self, EffectsError> {
+        if (MIN_AUTO_PAN_PERIOD..=MAX_AUTO_PAN_PERIOD).contains(&period_secs) {
+            Ok(AutoPan { period_secs })
+        } else {
+            Err(EffectsError::PeriodOutOfRange {
+                min: MIN_AUTO_PAN_PERIOD,
+                max: MAX_AUTO_PAN_PERIOD,
+            })
+        }
+    }
+
+    /// Seconds the audio takes to go around.
+    #[must_use]
+    pub fn period_secs(self) -> u16 {
+        self.period_secs
+    }
+
+    /// Build the filter panning audio at `sample_rate`.
+    #[must_use]
+    pub fn filter(self, sample_rate: u32) -> AutoPanFilter {
+        let period = f64::from(self.period_secs.max(MIN_AUTO_PAN_PERIOD));
+        AutoPanFilter {
+            phase: 0.0,
+            step: 1.0 / (period * f64::from(sample_rate)),
+        }
+    }
+}
+
+/// Implement [`Default`] for [`AutoPan`].
+impl Default for AutoPan {
+    fn default() -> Self {
+        AutoPan {
+            period_secs: DEFAULT_AUTO_PAN_PERIOD,
+        }
+    }
+}
+
+/// Implement [`Display`] for [`AutoPan`].
+impl Display for AutoPan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "around every {}s", self.period_secs)
+    }
+}
+
+/// Applies an [`AutoPan`] to stereo audio. Both channels are mixed down and
+/// panned with constant power, so the audio is as loud at the sides as in
+/// the middle.
+#[derive(Debug)]
+pub struct AutoPanFilter {
+    /// How far around the audio is, in turns.
+    phase: f64,
+    /// Turns per frame.
+    step: f64,
+}
+
+/// Implement [`AutoPanFilter`].
+impl AutoPanFilter {
+    /// Gains of the left and right channels at the current phase, starting
+    /// in the middle.
+    fn gains(&self) -> (f32, f32) {
+        let pan = (2.0 * PI * self.phase).sin();
+        let angle = (pan + 1.0) * FRAC_PI_4;
+        ((angle.cos() * SQRT_2) as f32, (angle.sin() * SQRT_2) as f32)
+    }
+}
+
+impl AudioFilter for AutoPanFilter {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(2) {
+            let (left, right) = self.gains();
+            let mid = (frame[0] + frame[1]) / 2.0;
+            frame[0] = mid * left;
+            frame[1] = mid * right;
+            self.phase = (self.phase + self.step).fract();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_pan() {
+        assert_eq!(AutoPan::default().period_secs(), DEFAULT_AUTO_PAN_PERIOD);
+        assert!(matches!(
+            AutoPan::new(1),
+            Err(EffectsError::PeriodOutOfRange { .. })
+        ));
+
+        // A quarter turn in, the audio is all on the right
+        let mut filter = AutoPan::new(4).unwrap().filter(100);
+        let mut samples = vec![0.5; 2 * 101];
+        filter.process(&mut samples);
+        // It starts in the middle
+        assert!((samples[0] - 0.5).abs() < 1e-6 && (samples[1] - 0.5).abs() < 1e-6);
+        assert!(samples[200].abs() < 1e-6);
+        assert!((samples[201] - 0.5 * SQRT_2 as f32).abs() < 1e-6);
+    }
+}
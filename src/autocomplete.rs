@@ -0,0 +1,114 @@
+use crate::LruCache;
+use dashmap::DashMap;
+use serenity::all::{AutocompleteChoice, UserId};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default number of queries whose suggestions are kept.
+pub const DEFAULT_SUGGESTION_CACHE_CAPACITY: usize = 256;
+/// Default time the suggestions for a query are kept.
+pub const DEFAULT_SUGGESTION_CACHE_TTL: Duration = Duration::from_secs(60);
+/// Default shortest query suggestions are searched for.
+pub const DEFAULT_SUGGESTION_MIN_LEN: usize = 3;
+/// Default time to wait for the user to stop typing before searching.
+pub const DEFAULT_SUGGESTION_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Cache of normalized query to its autocomplete choices.
+pub type SuggestionCache = LruCache<Vec<AutocompleteChoice>>;
+
+/// Implement [`Default`] for [`SuggestionCache`].
+impl Default for SuggestionCache {
+    fn default() -> Self {
+        LruCache::new(
+            DEFAULT_SUGGESTION_CACHE_CAPACITY,
+            DEFAULT_SUGGESTION_CACHE_TTL,
+        )
+    }
+}
+
+/// Normalize a query for the suggestion cache: trimmed, lowercase and with
+/// single spaces, so `"Molly  Nilsson "` and `"molly nilsson"` share
+/// suggestions.
+#[must_use]
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Drops autocomplete requests of a user superseded by a newer one while
+/// waiting, so only the last keystroke of a burst is searched for. Clones
+/// share the state.
+#[derive(Clone, Debug)]
+pub struct SuggestionDebouncer {
+    delay: Duration,
+    counter: Arc<AtomicU64>,
+    latest: Arc<DashMap<UserId, u64>>,
+}
+
+/// Implement [`Default`] for [`SuggestionDebouncer`].
+impl Default for SuggestionDebouncer {
+    fn default() -> Self {
+        SuggestionDebouncer::new(DEFAULT_SUGGESTION_DEBOUNCE)
+    }
+}
+
+/// Implement [`SuggestionDebouncer`].
+impl SuggestionDebouncer {
+    /// Create a new [`SuggestionDebouncer`] waiting `delay` for newer
+    /// requests. A delay of 0 disables it.
+    #[must_use]
+    pub fn new(delay: Duration) -> Self {
+        SuggestionDebouncer {
+            delay,
+            counter: Arc::new(AtomicU64::new(0)),
+            latest: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Wait for the delay, returning whether this is still the newest
+    /// request of the user.
+    pub async fn settle(&self, user: UserId) -> bool {
+        if self.delay.is_zero() {
+            return true;
+        }
+        let request = self.counter.fetch_add(1, Ordering::Relaxed);
+        self.latest.insert(user, request);
+        tokio::time::sleep(self.delay).await;
+        self.latest
+            .remove_if(&user, |_, latest| *latest == request)
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_query() {
+        assert_eq!(normalize_query("  Molly   Nilsson "), "molly nilsson");
+        assert_eq!(normalize_query(""), "");
+    }
+
+    #[tokio::test]
+    async fn test_debounce() {
+        let debouncer = SuggestionDebouncer::new(Duration::from_millis(20));
+        let user = UserId::new(1);
+        let other = UserId::new(2);
+        let first = debouncer.settle(user);
+        let other_first = debouncer.settle(other);
+        let second = async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            debouncer.settle(user).await
+        };
+        let (first, other_first, second) = tokio::join!(first, other_first, second);
+        assert!(!first);
+        assert!(other_first);
+        assert!(second);
+        assert!(SuggestionDebouncer::new(Duration::ZERO).settle(user).await);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{AudioFilter, Biquad, BiquadCoefficients};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// Frequency the bass boost shelves at, in Hz, the bass below it is boosted.
+pub const BASS_BOOST_FREQUENCY: f64 = 120.0;
+
+/// How much the bass is boosted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BassBoost {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Extreme,
+}
+
+/// Implement [`BassBoost`].
+impl BassBoost {
+    /// Gain of the bass, in dB.
+    #[must_use]
+    pub fn gain_db(self) -> f64 {
+        match self {
+            BassBoost::Low => 4.0,
+            BassBoost::Medium => 8.0,
+            BassBoost::High => 12.0,
+            BassBoost::Extreme => 16.0,
+        }
+    }
+
+    /// Build the filter boosting the bass of audio at `sample_rate`.
+    #[must_use]
+    pub fn filter(self, sample_rate: u32) -> BassBoostFilter {
+        let coefficients =
+            BiquadCoefficients::low_shelf(sample_rate, BASS_BOOST_FREQUENCY, self.gain_db());
+        BassBoostFilter {
+            channels: [Biquad::new(coefficients), Biquad::new(coefficients)],
+        }
+    }
+}
+
+/// Implement [`Display`] for [`BassBoost`].
+impl Display for BassBoost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BassBoost::Low => "low",
+            BassBoost::Medium => "medium",
+            BassBoost::High => "high",
+            BassBoost::Extreme => "extreme",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Applies a [`BassBoost`] to stereo audio, a low shelf per channel.
+#[derive(Debug)]
+pub struct BassBoostFilter {
+    channels: [Biquad; 2],
+}
+
+impl AudioFilter for BassBoostFilter {
+    fn process(&mut self, samples: &mut [f32]) {
+        let [left, right] = &mut self.channels;
+        for frame in samples.chunks_exact_mut(2) {
+            frame[0] = left.process(f64::from(frame[0])) as f32;
+            frame[1] = right.process(f64::from(frame[1])) as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bass_boost() {
+        assert!(BassBoost::Low.gain_db() < BassBoost::Extreme.gain_db());
+        assert_eq!(BassBoost::default().to_string(), "medium");
+
+        // A steady signal is all bass, so it is boosted by the full gain
+        let mut filter = BassBoost::High.filter(48_000);
+        let mut samples = vec![0.1; 48_000];
+        filter.process(&mut samples);
+        let gain = samples[samples.len() - 1] / 0.1;
+        assert!((20.0 * gain.log10() - 12.0).abs() < 0.1, "{gain}");
+    }
+}
@@ -0,0 +1,161 @@
+use crack_types::AuxMetadata;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Default number of videos kept in the metadata cache.
+pub const DEFAULT_METADATA_CACHE_CAPACITY: usize = 512;
+/// Default time the metadata of a video is kept.
+pub const DEFAULT_METADATA_CACHE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// An entry of a [`LruCache`].
+#[derive(Clone, Debug)]
+struct CacheEntry<V> {
+    value: V,
+    inserted: Instant,
+    last_used: Instant,
+}
+
+/// A least recently used cache whose entries also expire after a time to
+/// live. Clones share the entries.
+#[derive(Clone, Debug)]
+pub struct LruCache<V> {
+    capacity: usize,
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, CacheEntry<V>>>>,
+}
+
+/// Implement [`LruCache`].
+impl<V: Clone> LruCache<V> {
+    /// Create a new [`LruCache`] holding at most `capacity` entries, each for
+    /// at most `ttl`. A capacity of 0 disables the cache.
+    #[must_use]
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        LruCache {
+            capacity,
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Get the value for `key`, if it is cached and hasn't expired.
+    pub fn get(&self, key: &str) -> Option<V> {
+        let mut entries = self.entries.lock().ok()?;
+        let entry = entries.get_mut(key)?;
+        if entry.inserted.elapsed() > self.ttl {
+            entries.remove(key);
+            return None;
+        }
+        entry.last_used = Instant::now();
+        Some(entry.value.clone())
+    }
+
+    /// Cache `value` for `key`. When full, expired entries are dropped first
+    /// and then the least recently used one.
+    pub fn insert(&self, key: impl Into<String>, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        let key = key.into();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.inserted.elapsed() <= self.ttl);
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        let now = Instant::now();
+        entries.insert(
+            key,
+            CacheEntry {
+                value,
+                inserted: now,
+                last_used: now,
+            },
+        );
+    }
+
+    /// Number of cached entries, including expired ones not dropped yet.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.lock().map_or(0, |entries| entries.len())
+    }
+
+    /// Whether the cache is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every entry.
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+
+/// The details and metadata of a resolved video.
+#[derive(Clone, Debug)]
+pub struct CachedMetadata {
+    pub details: rusty_ytdl::VideoDetails,
+    pub metadata: AuxMetadata,
+}
+
+/// Cache of video ID to its [`CachedMetadata`], so tracks played again don't
+/// have to be resolved again.
+pub type MetadataCache = LruCache<CachedMetadata>;
+
+/// Implement [`Default`] for [`MetadataCache`].
+impl Default for MetadataCache {
+    fn default() -> Self {
+        LruCache::new(DEFAULT_METADATA_CACHE_CAPACITY, DEFAULT_METADATA_CACHE_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = LruCache::new(2, Duration::from_secs(60));
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        // Using "a" makes "b" the least recently used
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c", 3);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+
+        // Replacing a key doesn't evict anything
+        cache.insert("c", 4);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("c"), Some(4));
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_ttl_and_disabled() {
+        let cache = LruCache::new(2, Duration::ZERO);
+        cache.insert("a", 1);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(cache.get("a"), None);
+        assert!(cache.is_empty());
+
+        let disabled = LruCache::new(0, Duration::from_secs(60));
+        disabled.insert("a", 1);
+        assert_eq!(disabled.get("a"), None);
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// Hosts YouTube videos and playlists are linked from.
+const YOUTUBE_HOSTS: &[&str] = &[
+    "youtube.com",
+    "www.youtube.com",
+    "m.youtube.com",
+    "music.youtube.com",
+    "youtube-nocookie.com",
+    "www.youtube-nocookie.com",
+];
+
+/// Paths followed by the video ID, like `/shorts/<id>`.
+const VIDEO_ID_PATH_PREFIXES: &[&str] = &["shorts", "embed", "v", "live"];
+
+/// A YouTube URL reduced to what identifies it: the video, the playlist and
+/// where to start playing. Every form of link to the same video, with or
+/// without tracking parameters, has the same canonical URL.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CanonicalUrl {
+    pub video_id: Option<String>,
+    pub playlist_id: Option<String>,
+    /// Position from the `t` or `start` parameter.
+    pub timestamp: Option<Duration>,
+}
+
+/// Implement [`CanonicalUrl`].
+impl CanonicalUrl {
+    /// Parse any form of YouTube video or playlist URL: `watch`, `youtu.be`,
+    /// `shorts`, `embed`, `live` and `playlist`. Returns `None` for other
+    /// URLs, or YouTube URLs without a video or playlist.
+    #[must_use]
+    pub fn parse(url: &str) -> Option<Self> {
+        let parsed = url::Url::parse(url.trim()).ok()?;
+        let host = parsed.host_str()?.to_lowercase();
+        let mut segments = parsed.path_segments()?.filter(|s| !s.is_empty());
+        let query = |key: &str| {
+            parsed
+                .query_pairs()
+                .find(|(k, v)| k == key && !v.is_empty())
+                .map(|(_, v)| v.into_owned())
+        };
+
+        let video_id = if host == "youtu.be" || host == "www.youtu.be" {
+            segments.next().map(str::to_string)
+        } else if YOUTUBE_HOSTS.contains(&host.as_str()) {
+            match segments.next() {
+                Some("watch") => query("v"),
+                Some(prefix) if VIDEO_ID_PATH_PREFIXES.contains(&prefix) => {
+                    segments.next().map(str::to_string)
+                }
+                _ => None,
+            }
+        } else {
+            return None;
+        };
+        let video_id = video_id.filter(|id| is_id(id));
+        let playlist_id = query("list").filter(|id| is_id(id));
+        if video_id.is_none() && playlist_id.is_none() {
+            return None;
+        }
+        let timestamp = query("t")
+            .or_else(|| query("start"))
+            .or_else(|| {
+                parsed
+                    .fragment()
+                    .and_then(|fragment| fragment.strip_prefix("t="))
+                    .map(str::to_string)
+            })
+            .and_then(|t| parse_url_timestamp(&t));
+
+        Some(CanonicalUrl {
+            video_id,
+            playlist_id,
+            timestamp,
+        })
+    }
+
+    /// Whether this is a link to a playlist, possibly starting at a video.
+    #[must_use]
+    pub fn is_playlist(&self) -> bool {
+        self.playlist_id.is_some()
+    }
+
+    /// Get the canonical URL, without the timestamp.
+    #[must_use]
+    pub fn url(&self) -> String {
+        match (&self.video_id, &self.playlist_id) {
+            (Some(video), Some(list)) => {
+                format!("https://www.youtube.com/watch?v={video}&list={list}")
+            }
+            (Some(video), None) => format!("https://www.youtube.com/watch?v={video}"),
+            (None, Some(list)) => format!("https://www.youtube.com/playlist?list={list}"),
+            (None, None) => "https://www.youtube.com/".to_string(),
+        }
+    }
+
+    /// Get the canonical URL with the timestamp, as whole seconds, so the
+    /// position to start at is kept.
+    #[must_use]
+    pub fn timestamped_url(&self) -> String {
+        match self.timestamp {
+            Some(timestamp) if self.video_id.is_some() => {
+                format!("{}&t={}", self.url(), timestamp.as_secs())
+            }
+            _ => self.url(),
+        }
+    }
+
+    /// Get the canonical URL of the video alone, without the playlist.
+    #[must_use]
+    pub fn video_url(&self) -> Option<String> {
+        self.video_id
+            .as_ref()
+            .map(|id| format!("https://www.youtube.com/watch?v={id}"))
+    }
+}
+
+/// Implement [`Display`] for [`CanonicalUrl`].
+impl Display for CanonicalUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.url())
+    }
+}
+
+/// Get the canonical URL of a YouTube URL, or the URL unchanged if it isn't
+/// a YouTube video or playlist.
+#[must_use]
+pub fn canonicalize_url(url: &str) -> String {
+    CanonicalUrl::parse(url).map_or_else(|| url.to_string(), |canonical| canonical.url())
+}
+
+/// Whether `id` looks like a video or playlist ID.
+fn is_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Parse a timestamp of a YouTube URL, plain seconds like `90` or `90s`, or
+/// with units like `1h2m3s`.
+#[must_use]
+pub fn parse_url_timestamp(t: &str) -> Option<Duration> {
+    let t = t.trim();
+    if let Ok(secs) = t.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let mut total = 0;
+    let mut number = String::new();
+    for c in t.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total += number.parse::<u64>().ok()? * unit;
+        number.clear();
+    }
+    number.is_empty().then(|| Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_forms() {
+        let want = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+        for url in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://youtube.com/watch?si=abc123&v=dQw4w9WgXcQ&feature=share",
+            "https://m.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ&si=xyz",
+            "https://youtu.be/dQw4w9WgXcQ?si=abc123",
+            "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ?start=10",
+            "https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ",
+            "https://www.youtube.com/live/dQw4w9WgXcQ?feature=shared",
+        ] {
+            assert_eq!(canonicalize_url(url), want, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_canonical_playlist_and_timestamp() {
+        let canonical =
+            CanonicalUrl::parse("https://youtu.be/dQw4w9WgXcQ?list=PL123&t=1m30s").unwrap();
+        assert!(canonical.is_playlist());
+        assert_eq!(canonical.timestamp, Some(Duration::from_secs(90)));
+        assert_eq!(
+            canonical.url(),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL123"
+        );
+        assert_eq!(
+            canonical.video_url().as_deref(),
+            Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            canonicalize_url("https://www.youtube.com/playlist?list=PL123&si=abc"),
+            "https://www.youtube.com/playlist?list=PL123"
+        );
+
+        let canonical =
+            CanonicalUrl::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ#t=42").unwrap();
+        assert_eq!(canonical.timestamp, Some(Duration::from_secs(42)));
+        assert_eq!(
+            canonical.timestamped_url(),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42"
+        );
+        assert_eq!(
+            CanonicalUrl::parse(&canonical.timestamped_url()),
+            Some(canonical)
+        );
+        assert_eq!(
+            parse_url_timestamp("1h2m3s"),
+            Some(Duration::from_secs(3723))
+        );
+        assert_eq!(parse_url_timestamp("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_url_timestamp("1x"), None);
+        assert_eq!(parse_url_timestamp("12m3"), None);
+
+        // Not YouTube, or nothing to play
+        assert_eq!(CanonicalUrl::parse("https://example.com/watch?v=abc"), None);
+        assert_eq!(CanonicalUrl::parse("https://www.youtube.com/@artist"), None);
+        assert_eq!(canonicalize_url("not a url"), "not a url");
+    }
+}
@@ -0,0 +1,182 @@
+use crate::SearchCandidate;
+use crack_types::AuxMetadata;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+/// Largest difference in length for a YouTube video to count as the same
+/// recording as a catalog track.
+const MATCH_DURATION_TOLERANCE: Duration = Duration::from_secs(3);
+
+/// How sure a match of a catalog track to a YouTube video is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MatchConfidence {
+    /// Only the search keywords matched.
+    Low,
+    /// The title and length match.
+    Medium,
+    /// Found by the ISRC of the recording, and the title and length match.
+    High,
+}
+
+/// Implement [`Display`] for [`MatchConfidence`].
+impl Display for MatchConfidence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchConfidence::Low => write!(f, "low"),
+            MatchConfidence::Medium => write!(f, "medium"),
+            MatchConfidence::High => write!(f, "high"),
+        }
+    }
+}
+
+/// Lowercase `text` and keep only its letters and digits, to compare titles
+/// regardless of punctuation.
+fn normalize_title(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// A track as listed by a music catalog like Spotify or Apple Music, before
+/// it is matched to a playable source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogTrack {
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    pub duration: Duration,
+    /// International Standard Recording Code, identifying the recording.
+    pub isrc: Option<String>,
+}
+
+/// Implement [`CatalogTrack`].
+impl CatalogTrack {
+    /// Create a new [`CatalogTrack`].
+    #[must_use]
+    pub fn new(
+        title: String,
+        artists: Vec<String>,
+        album: Option<String>,
+        duration: Duration,
+    ) -> Self {
+        CatalogTrack {
+            title,
+            artists,
+            album,
+            duration,
+            isrc: None,
+        }
+    }
+
+    /// Set the ISRC of the track.
+    #[must_use]
+    pub fn with_isrc(mut self, isrc: Option<String>) -> Self {
+        self.isrc = isrc;
+        self
+    }
+
+    /// Check how well a search result matches the track: its length has to
+    /// be within a few seconds and its title has to contain the track's.
+    /// This can only tell [`MatchConfidence::Medium`] from
+    /// [`MatchConfidence::Low`], the ISRC search decides on high.
+    #[must_use]
+    pub fn match_confidence(&self, candidate: &SearchCandidate) -> MatchConfidence {
+        let duration_matches = candidate
+            .duration
+            .is_some_and(|duration| duration.abs_diff(self.duration) <= MATCH_DURATION_TOLERANCE);
+        let title = normalize_title(&self.title);
+        let title_matches = !title.is_empty() && normalize_title(&candidate.title).contains(&title);
+        if duration_matches && title_matches {
+            MatchConfidence::Medium
+        } else {
+            MatchConfidence::Low
+        }
+    }
+
+    /// Keywords to search for the track on YouTube with.
+    #[must_use]
+    pub fn search_query(&self) -> String {
+        match self.artists.first() {
+            Some(artist) => format!("{artist} - {}", self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    /// Metadata of the track as known to the catalog.
+    #[must_use]
+    pub fn metadata(&self) -> AuxMetadata {
+        AuxMetadata {
+            title: Some(self.title.clone()),
+            artist: Some(self.artists.join(", ")),
+            album: self.album.clone(),
+            duration: Some(self.duration),
+            ..Default::default()
+        }
+    }
+}
+
+/// Implement [`Display`] for [`CatalogTrack`].
+impl Display for CatalogTrack {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.search_query())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_query() {
+        let track = CatalogTrack::new(
+            "Never Gonna Give You Up".to_string(),
+            vec!["Rick Astley".to_string()],
+            None,
+            Duration::from_secs(213),
+        );
+        assert_eq!(
+            track.search_query(),
+            "Rick Astley - Never Gonna Give You Up"
+        );
+        assert_eq!(track.to_string(), track.search_query());
+        assert_eq!(track.metadata().duration, Some(Duration::from_secs(213)));
+
+        let track = CatalogTrack::new("Untitled".to_string(), Vec::new(), None, Duration::ZERO);
+        assert_eq!(track.search_query(), "Untitled");
+    }
+
+    #[test]
+    fn test_match_confidence() {
+        let track = CatalogTrack::new(
+            "Never Gonna Give You Up".to_string(),
+            vec!["Rick Astley".to_string()],
+            None,
+            Duration::from_secs(213),
+        )
+        .with_isrc(Some("GBARL9300135".to_string()));
+        let candidate = SearchCandidate {
+            url: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+            title: "Rick Astley - Never Gonna Give You Up (Official Music Video)".to_string(),
+            channel: "Rick Astley".to_string(),
+            channel_id: String::new(),
+            duration: Some(Duration::from_secs(212)),
+            thumbnail: None,
+            uploaded_at: None,
+            views: 0,
+        };
+        assert_eq!(track.match_confidence(&candidate), MatchConfidence::Medium);
+
+        let longer = SearchCandidate {
+            duration: Some(Duration::from_secs(230)),
+            ..candidate.clone()
+        };
+        assert_eq!(track.match_confidence(&longer), MatchConfidence::Low);
+        let other = SearchCandidate {
+            title: "Together Forever".to_string(),
+            ..candidate
+        };
+        assert_eq!(track.match_confidence(&other), MatchConfidence::Low);
+        assert!(MatchConfidence::High > MatchConfidence::Medium);
+    }
+}
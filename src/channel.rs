@@ -0,0 +1,100 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+static CHANNEL_URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"youtube\.com/(?:(@[\w.\-]+)|channel/(UC[\w\-]{22})|c/([\w.\-]+)|user/([\w.\-]+))(?:/(?:videos|featured|streams|shorts))?/?(?:[?#]|$)").unwrap()
+});
+
+static CHANNEL_ID_META_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<link rel="canonical" href="https://www\.youtube\.com/channel/(UC[\w\-]{22})""#)
+        .unwrap()
+});
+
+/// A parsed YouTube channel URL, by ID or by one of its names.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChannelUrl {
+    /// `youtube.com/channel/UC...`
+    Id(String),
+    /// `youtube.com/@handle`, with the `@`.
+    Handle(String),
+    /// `youtube.com/c/name`
+    Custom(String),
+    /// `youtube.com/user/name`
+    User(String),
+}
+
+/// Parse a YouTube channel URL, returning `None` if it isn't one.
+#[must_use]
+pub fn parse_channel_url(url: &str) -> Option<ChannelUrl> {
+    let caps = CHANNEL_URL_REGEX.captures(url)?;
+    let group = |i: usize| caps.get(i).map(|m| m.as_str().to_string());
+    group(2)
+        .map(ChannelUrl::Id)
+        .or_else(|| group(1).map(ChannelUrl::Handle))
+        .or_else(|| group(3).map(ChannelUrl::Custom))
+        .or_else(|| group(4).map(ChannelUrl::User))
+}
+
+/// Check if a URL points to a YouTube channel.
+#[must_use]
+pub fn is_channel_url(url: &str) -> bool {
+    CHANNEL_URL_REGEX.is_match(url)
+}
+
+/// Get the channel ID from the canonical link of a channel page.
+#[must_use]
+pub fn parse_channel_page_id(html: &str) -> Option<String> {
+    CHANNEL_ID_META_REGEX
+        .captures(html)
+        .map(|caps| caps[1].to_string())
+}
+
+/// Get the URL of the playlist of every upload of a channel, which has the
+/// ID of the channel with `UU` in place of `UC`.
+#[must_use]
+pub fn uploads_playlist_url(channel_id: &str) -> Option<String> {
+    let id = channel_id.strip_prefix("UC")?;
+    Some(format!("https://www.youtube.com/playlist?list=UU{id}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_channel_url() {
+        assert_eq!(
+            parse_channel_url("https://www.youtube.com/@RickAstleyYT"),
+            Some(ChannelUrl::Handle("@RickAstleyYT".to_string()))
+        );
+        assert_eq!(
+            parse_channel_url("https://www.youtube.com/channel/UCuAXFkgsw1L7xaCfnd5JJOw/videos"),
+            Some(ChannelUrl::Id("UCuAXFkgsw1L7xaCfnd5JJOw".to_string()))
+        );
+        assert_eq!(
+            parse_channel_url("https://youtube.com/c/RickAstley?si=abc"),
+            Some(ChannelUrl::Custom("RickAstley".to_string()))
+        );
+        assert_eq!(
+            parse_channel_url("https://www.youtube.com/user/RickAstleyVEVO"),
+            Some(ChannelUrl::User("RickAstleyVEVO".to_string()))
+        );
+        assert!(!is_channel_url(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        ));
+        assert!(!is_channel_url(
+            "https://www.youtube.com/@RickAstleyYT/community"
+        ));
+    }
+
+    #[test]
+    fn test_uploads_playlist() {
+        let html = r#"<link rel="canonical" href="https://www.youtube.com/channel/UCuAXFkgsw1L7xaCfnd5JJOw">"#;
+        let id = parse_channel_page_id(html).unwrap();
+        assert_eq!(
+            uploads_playlist_url(&id).as_deref(),
+            Some("https://www.youtube.com/playlist?list=UUuAXFkgsw1L7xaCfnd5JJOw")
+        );
+        assert_eq!(uploads_playlist_url("PL123"), None);
+    }
+}
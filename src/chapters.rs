@@ -0,0 +1,158 @@
+use crate::ResolvedTrack;
+use regex::Regex;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+static CHAPTER_LINE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\W*?(?:(\d{1,2}):)?(\d{1,2}):(\d{2})\W*?\s+(.+)$").unwrap());
+
+/// A chapter of a video, like a song of a full album upload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+    /// End of the chapter, `None` if it runs to the end of an unknown
+    /// length video.
+    pub end: Option<Duration>,
+}
+
+/// Fill in the end of each chapter with the start of the next one, and of
+/// the last one with the length of the video.
+fn with_ends(mut chapters: Vec<Chapter>, total: Option<Duration>) -> Vec<Chapter> {
+    let starts: Vec<Duration> = chapters
+        .iter()
+        .skip(1)
+        .map(|chapter| chapter.start)
+        .collect();
+    for (chapter, end) in chapters
+        .iter_mut()
+        .zip(starts.into_iter().map(Some).chain([total]))
+    {
+        chapter.end = end;
+    }
+    chapters
+}
+
+/// Parse the chapters of a video from timestamps in its description, like
+/// `0:00 Intro` or `[1:02:03] - Outro`. Like YouTube, this needs at least
+/// two timestamps in order, the first at `0:00`, or nothing is returned.
+#[must_use]
+pub fn parse_description_chapters(description: &str, total: Option<Duration>) -> Vec<Chapter> {
+    let chapters: Vec<Chapter> = description
+        .lines()
+        .filter_map(|line| {
+            let caps = CHAPTER_LINE_REGEX.captures(line.trim())?;
+            let number = |i: usize| {
+                caps.get(i)
+                    .map_or(Some(0), |value| value.as_str().parse::<u64>().ok())
+            };
+            let secs = number(1)? * 3600 + number(2)? * 60 + number(3)?;
+            let title = caps[4]
+                .trim_start_matches(|c: char| c.is_whitespace() || "-–—:|)]".contains(c))
+                .trim()
+                .to_string();
+            Some(Chapter {
+                title,
+                start: Duration::from_secs(secs),
+                end: None,
+            })
+        })
+        .collect();
+
+    let in_order = chapters
+        .windows(2)
+        .all(|pair| pair[0].start < pair[1].start);
+    if chapters.len() < 2 || chapters[0].start != Duration::ZERO || !in_order {
+        return Vec::new();
+    }
+    with_ends(chapters, total)
+}
+
+/// Implement chapters for [`ResolvedTrack`].
+impl ResolvedTrack {
+    /// Get the chapters of the video, from its chapter markers or else the
+    /// timestamps in its description. Empty if it has none or wasn't
+    /// resolved with its details.
+    pub fn chapters(&self) -> Vec<Chapter> {
+        let Some(details) = &self.details else {
+            return Vec::new();
+        };
+        let total = self.get_raw_duration();
+        if details.chapters.is_empty() {
+            return parse_description_chapters(&details.description, total);
+        }
+        let chapters = details
+            .chapters
+            .iter()
+            .map(|chapter| Chapter {
+                title: chapter.title.clone(),
+                start: Duration::from_secs(u64::try_from(chapter.start_time).unwrap_or_default()),
+                end: None,
+            })
+            .collect();
+        with_ends(chapters, total)
+    }
+
+    /// Split the track into one track per chapter, each playing only its
+    /// part of the video and titled after the chapter. A track without
+    /// chapters is returned as is.
+    #[must_use]
+    pub fn split_chapters(self) -> Vec<ResolvedTrack> {
+        let chapters = self.chapters();
+        if chapters.is_empty() {
+            return vec![self];
+        }
+        let video_title = self.get_title();
+        let metadata = self.get_metadata().unwrap_or_default();
+        chapters
+            .into_iter()
+            .map(|chapter| {
+                let mut metadata = metadata.clone();
+                metadata.title = Some(chapter.title);
+                metadata.album = Some(video_title.clone());
+                metadata.duration = chapter.end.map(|end| end.saturating_sub(chapter.start));
+                metadata.start_time = Some(chapter.start);
+                self.clone()
+                    .with_metadata(metadata)
+                    .with_offsets(Some(chapter.start), chapter.end)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_description_chapters() {
+        let description = "Full album!\n\
+            Tracklist:\n\
+            0:00 One More Time\n\
+            5:20 - Aerodynamic\n\
+            [1:02:03] Too Long\n\
+            Thanks for watching";
+        let chapters = parse_description_chapters(description, Some(Duration::from_secs(4000)));
+        assert_eq!(chapters.len(), 3);
+        assert_eq!(chapters[0].title, "One More Time");
+        assert_eq!(chapters[0].end, Some(Duration::from_secs(320)));
+        assert_eq!(chapters[1].title, "Aerodynamic");
+        assert_eq!(chapters[1].start, Duration::from_secs(320));
+        assert_eq!(chapters[2].title, "Too Long");
+        assert_eq!(chapters[2].start, Duration::from_secs(3723));
+        assert_eq!(chapters[2].end, Some(Duration::from_secs(4000)));
+
+        // Not starting at 0:00, a single timestamp or out of order isn't chapters
+        assert!(parse_description_chapters("1:00 A\n2:00 B", None).is_empty());
+        assert!(parse_description_chapters("0:00 A", None).is_empty());
+        assert!(parse_description_chapters("0:00 A\n3:00 B\n2:00 C", None).is_empty());
+    }
+
+    #[test]
+    fn test_split_chapters_without_details() {
+        let track = ResolvedTrack::default();
+        let tracks = track.split_chapters();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].start_offset(), None);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{parse_url_timestamp, ResolvedTrack};
+use crack_types::get_human_readable_timestamp;
+use std::time::Duration;
+
+/// Errors that can occur when playing only part of a track.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ClipError {
+    #[error("Invalid time: {0}, use seconds or a time like 1:30")]
+    InvalidTime(String),
+    #[error("The clip must end after it starts")]
+    EndBeforeStart,
+    #[error("The clip starts past the end of the track")]
+    PastEnd,
+    #[error("Streams and livestreams can't be clipped")]
+    Unbounded,
+}
+
+/// Parse a time in a track, like `1:30`, `1:02:03`, `90` or `1m30s`.
+/// # Errors
+/// Returns [`ClipError::InvalidTime`] if it isn't a time.
+pub fn parse_clip_time(time: &str) -> Result<Duration, ClipError> {
+    let time = time.trim();
+    let invalid = || ClipError::InvalidTime(time.to_string());
+    if !time.contains(':') {
+        return parse_url_timestamp(time).ok_or_else(invalid);
+    }
+    let parts = time.split(':').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+    let mut secs = 0;
+    for part in parts {
+        secs = secs * 60 + part.parse::<u64>().map_err(|_| invalid())?;
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// Implement clips for [`ResolvedTrack`], played from their start offset to
+/// their end offset, see [`crate::apply_offsets`].
+impl ResolvedTrack {
+    /// Play only the part of the track from `start` to `end`, like the
+    /// chorus for an intro song or a soundbite, moving on to the next track
+    /// at the end. A bound left out keeps the one the track has, like the
+    /// start of a link with a timestamp.
+    /// # Errors
+    /// Returns an error if the track is a stream, or the clip ends before
+    /// it starts or starts past the end of the track if its length is known.
+    pub fn with_clip(
+        self,
+        start: Option<Duration>,
+        end: Option<Duration>,
+    ) -> Result<Self, ClipError> {
+        if self.is_unbounded() || self.is_live() {
+            return match (start, end) {
+                (None, None) => Ok(self),
+                _ => Err(ClipError::Unbounded),
+            };
+        }
+        let start = start.or(self.start_offset());
+        let end = end.or(self.end_offset());
+        if let (Some(start), Some(end)) = (start, end) {
+            if end <= start {
+                return Err(ClipError::EndBeforeStart);
+            }
+        }
+        if let (Some(start), Some(duration)) = (start, self.get_raw_duration()) {
+            if start >= duration {
+                return Err(ClipError::PastEnd);
+            }
+        }
+        Ok(self.with_offsets(start, end))
+    }
+
+    /// Describe the part of the track played, like `0:30 to 1:00`, `None`
+    /// if it plays whole.
+    #[must_use]
+    pub fn clip_description(&self) -> Option<String> {
+        let timestamp = |time: Duration| get_human_readable_timestamp(Some(time));
+        match (self.start_offset(), self.end_offset()) {
+            (start, Some(end)) => Some(format!(
+                "{} to {}",
+                timestamp(start.unwrap_or_default()),
+                timestamp(end)
+            )),
+            (Some(start), None) => Some(format!("from {}", timestamp(start))),
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crack_types::{AuxMetadata, QueryType};
+
+    #[test]
+    fn test_parse_clip_time() {
+        assert_eq!(parse_clip_time("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_clip_time("1:30"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_clip_time("1m30s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_clip_time(" 1:02:03 "), Ok(Duration::from_secs(3723)));
+        assert!(matches!(
+            parse_clip_time("1:"),
+            Err(ClipError::InvalidTime(_))
+        ));
+        assert!(parse_clip_time("1:2:3:4").is_err());
+        assert!(parse_clip_time("soon").is_err());
+    }
+
+    #[test]
+    fn test_with_clip() {
+        let track = ResolvedTrack::new(QueryType::VideoLink(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+        ))
+        .with_metadata(AuxMetadata {
+            duration: Some(Duration::from_secs(212)),
+            ..Default::default()
+        });
+        assert_eq!(track.clip_description(), None);
+
+        let clip = track
+            .clone()
+            .with_clip(Some(Duration::from_secs(43)), Some(Duration::from_secs(60)))
+            .unwrap();
+        assert_eq!(clip.start_offset(), Some(Duration::from_secs(43)));
+        assert_eq!(clip.end_offset(), Some(Duration::from_secs(60)));
+        assert!(clip.clip_description().unwrap().contains(" to "));
+
+        // The start of a timestamped link is kept
+        let clip = track
+            .clone()
+            .with_start_offset(Some(Duration::from_secs(30)))
+            .with_clip(None, Some(Duration::from_secs(40)))
+            .unwrap();
+        assert_eq!(clip.start_offset(), Some(Duration::from_secs(30)));
+
+        assert_eq!(
+            track
+                .clone()
+                .with_clip(Some(Duration::from_secs(60)), Some(Duration::from_secs(30)))
+                .unwrap_err(),
+            ClipError::EndBeforeStart
+        );
+        assert_eq!(
+            track
+                .clone()
+                .with_clip(Some(Duration::from_secs(300)), None)
+                .unwrap_err(),
+            ClipError::PastEnd
+        );
+        assert_eq!(
+            track
+                .with_unbounded(true)
+                .with_clip(None, Some(Duration::from_secs(30)))
+                .unwrap_err(),
+            ClipError::Unbounded
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use crack_types::Error;
+use reqwest::cookie::Jar;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A cookie of a Netscape cookie jar file, as exported by browser
+/// extensions and used by yt-dlp's `--cookies`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetscapeCookie {
+    pub domain: String,
+    pub path: String,
+    pub secure: bool,
+    pub name: String,
+    pub value: String,
+}
+
+/// Implement [`NetscapeCookie`].
+impl NetscapeCookie {
+    /// Whether the cookie is sent to YouTube.
+    #[must_use]
+    pub fn is_youtube(&self) -> bool {
+        let domain = self.domain.trim_start_matches('.');
+        domain == "youtube.com" || domain.ends_with(".youtube.com")
+    }
+
+    /// The cookie as a `Set-Cookie` header value.
+    fn set_cookie_str(&self) -> String {
+        let secure = if self.secure { "; Secure" } else { "" };
+        format!(
+            "{}={}; Domain={}; Path={}{secure}",
+            self.name, self.value, self.domain, self.path
+        )
+    }
+}
+
+/// Parse the contents of a Netscape cookie jar file, skipping comments and
+/// malformed lines.
+#[must_use]
+pub fn parse_netscape_cookies(contents: &str) -> Vec<NetscapeCookie> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            // HttpOnly cookies are prefixed like a comment
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [domain, _, path, secure, _, name, value] = fields[..] else {
+                return None;
+            };
+            Some(NetscapeCookie {
+                domain: domain.to_string(),
+                path: path.to_string(),
+                secure: secure.eq_ignore_ascii_case("TRUE"),
+                name: name.to_string(),
+                value: value.trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Credentials for YouTube, needed for age-restricted videos and to get past
+/// "confirm you're not a bot" checks. Cookies come from a cookie jar file
+/// exported from a logged in browser, the OAuth token is sent as a bearer
+/// token.
+#[derive(Clone, Debug, Default)]
+pub struct YoutubeCredentials {
+    cookie_file: Option<PathBuf>,
+    cookies: Vec<NetscapeCookie>,
+    oauth_token: Option<String>,
+}
+
+/// Implement [`YoutubeCredentials`].
+impl YoutubeCredentials {
+    /// Create empty [`YoutubeCredentials`].
+    #[must_use]
+    pub fn new() -> Self {
+        YoutubeCredentials::default()
+    }
+
+    /// Load the credentials from `YOUTUBE_COOKIES_FILE`, a cookie jar file,
+    /// and `YOUTUBE_OAUTH_TOKEN`. Returns `None` if neither is set.
+    /// # Errors
+    /// Returns an error if the cookie file can't be read.
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        let cookie_file = std::env::var("YOUTUBE_COOKIES_FILE").ok();
+        let oauth_token = std::env::var("YOUTUBE_OAUTH_TOKEN").ok();
+        if cookie_file.is_none() && oauth_token.is_none() {
+            return Ok(None);
+        }
+        let mut credentials = YoutubeCredentials::new();
+        if let Some(cookie_file) = cookie_file {
+            credentials = credentials.with_cookie_file(cookie_file)?;
+        }
+        if let Some(oauth_token) = oauth_token {
+            credentials = credentials.with_oauth_token(oauth_token);
+        }
+        Ok(Some(credentials))
+    }
+
+    /// Use the YouTube cookies of a Netscape cookie jar file.
+    /// # Errors
+    /// Returns an error if the file can't be read.
+    pub fn with_cookie_file(mut self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path.as_ref())?;
+        self.cookies = parse_netscape_cookies(&contents)
+            .into_iter()
+            .filter(NetscapeCookie::is_youtube)
+            .collect();
+        self.cookie_file = Some(path.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    /// Send an OAuth access token with every YouTube request.
+    #[must_use]
+    pub fn with_oauth_token(mut self, token: impl Into<String>) -> Self {
+        self.oauth_token = Some(token.into());
+        self
+    }
+
+    /// The cookies as a `Cookie` header value, `None` if there are none.
+    #[must_use]
+    pub fn cookie_header(&self) -> Option<String> {
+        if self.cookies.is_empty() {
+            return None;
+        }
+        let cookies: Vec<String> = self
+            .cookies
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        Some(cookies.join("; "))
+    }
+
+    /// Add the credentials to a client builder, the cookies in a cookie jar
+    /// and the token as a default header. A client built with them should
+    /// only be used for YouTube.
+    /// # Errors
+    /// Returns an error if the token isn't a valid header value.
+    pub fn apply(&self, builder: reqwest::ClientBuilder) -> Result<reqwest::ClientBuilder, Error> {
+        let jar = Jar::default();
+        for cookie in &self.cookies {
+            let url = format!("https://{}", cookie.domain.trim_start_matches('.')).parse()?;
+            jar.add_cookie_str(&cookie.set_cookie_str(), &url);
+        }
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &self.oauth_token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {token}"))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(builder
+            .cookie_provider(Arc::new(jar))
+            .default_headers(headers))
+    }
+
+    /// Arguments passing the credentials to yt-dlp.
+    #[must_use]
+    pub fn ytdl_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(cookie_file) = &self.cookie_file {
+            args.push("--cookies".to_string());
+            args.push(cookie_file.display().to_string());
+        }
+        if let Some(token) = &self.oauth_token {
+            args.push("--add-header".to_string());
+            args.push(format!("Authorization:Bearer {token}"));
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOKIES: &str = "# Netscape HTTP Cookie File\n\
+        .youtube.com\tTRUE\t/\tTRUE\t1767225600\tLOGIN_INFO\tabc\n\
+        #HttpOnly_.youtube.com\tTRUE\t/\tTRUE\t1767225600\tSID\tdef\n\
+        .google.com\tTRUE\t/\tFALSE\t1767225600\tNID\tghi\n\
+        not a cookie\n";
+
+    #[test]
+    fn test_parse_netscape_cookies() {
+        let cookies = parse_netscape_cookies(COOKIES);
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies[0].name, "LOGIN_INFO");
+        assert!(cookies[0].secure);
+        assert_eq!(cookies[1].name, "SID");
+        assert!(cookies[1].is_youtube());
+        assert!(!cookies[2].is_youtube());
+    }
+
+    #[test]
+    fn test_credentials() {
+        let path =
+            std::env::temp_dir().join(format!("cracktunes-cookies-{}.txt", std::process::id()));
+        std::fs::write(&path, COOKIES).unwrap();
+        let credentials = YoutubeCredentials::new()
+            .with_cookie_file(&path)
+            .unwrap()
+            .with_oauth_token("token");
+        let _ = std::fs::remove_file(&path);
+
+        // Only the YouTube cookies are kept
+        assert_eq!(
+            credentials.cookie_header().as_deref(),
+            Some("LOGIN_INFO=abc; SID=def")
+        );
+        let args = credentials.ytdl_args();
+        assert_eq!(args[0], "--cookies");
+        assert_eq!(args[3], "Authorization:Bearer token");
+        assert!(credentials.apply(reqwest::Client::builder()).is_ok());
+        assert_eq!(YoutubeCredentials::new().cookie_header(), None);
+    }
+}
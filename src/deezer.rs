@@ -0,0 +1,257 @@
+use crate::CatalogTrack;
+use crack_types::Error;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Base URL of the public Deezer API.
+const DEEZER_API_URL: &str = "https://api.deezer.com";
+
+static DEEZER_URL_REGEX_STR: &str =
+    r"deezer\.com/(?:[a-z]{2}(?:-[a-z]{2})?/)?(track|album|playlist)/(\d+)";
+static DEEZER_URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(DEEZER_URL_REGEX_STR).unwrap());
+
+static DEEZER_SHORT_URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:deezer\.page\.link|link\.deezer\.com)/").unwrap());
+
+/// Kind of item a Deezer URL points to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeezerMediaType {
+    Track,
+    Album,
+    Playlist,
+}
+
+/// A parsed `deezer.com` URL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeezerUrl {
+    pub media_type: DeezerMediaType,
+    pub id: String,
+}
+
+/// Parse a Deezer URL, returning `None` if it isn't one. Short share links
+/// have to be followed first, see [`DeezerClient::tracks`].
+#[must_use]
+pub fn parse_deezer_url(url: &str) -> Option<DeezerUrl> {
+    let caps = DEEZER_URL_REGEX.captures(url)?;
+    let media_type = match &caps[1] {
+        "track" => DeezerMediaType::Track,
+        "album" => DeezerMediaType::Album,
+        "playlist" => DeezerMediaType::Playlist,
+        _ => return None,
+    };
+    Some(DeezerUrl {
+        media_type,
+        id: caps[2].to_string(),
+    })
+}
+
+/// Check if a URL points to Deezer, including short share links.
+#[must_use]
+pub fn is_deezer_url(url: &str) -> bool {
+    DEEZER_URL_REGEX.is_match(url) || DEEZER_SHORT_URL_REGEX.is_match(url)
+}
+
+/// A response of the Deezer API, which reports errors in the body.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum DeezerResponse<T> {
+    Error { error: DeezerApiError },
+    Ok(T),
+}
+
+/// An error reported by the Deezer API.
+#[derive(Debug, Deserialize)]
+struct DeezerApiError {
+    message: String,
+}
+
+/// A page of a Deezer list, with the URL of the next one.
+#[derive(Debug, Deserialize)]
+struct DeezerPage<T> {
+    data: Vec<T>,
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeezerArtist {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeezerAlbum {
+    title: String,
+}
+
+/// A track of the Deezer API, its duration is in seconds.
+#[derive(Debug, Deserialize)]
+struct DeezerTrack {
+    title: String,
+    #[serde(default)]
+    duration: u64,
+    artist: Option<DeezerArtist>,
+    album: Option<DeezerAlbum>,
+    isrc: Option<String>,
+}
+
+/// Implement [`DeezerTrack`].
+impl DeezerTrack {
+    /// Convert to a [`CatalogTrack`], falling back to `album` if the track
+    /// doesn't list its own.
+    fn into_track(self, album: Option<&str>) -> CatalogTrack {
+        CatalogTrack::new(
+            self.title,
+            self.artist.map(|artist| artist.name).into_iter().collect(),
+            self.album
+                .map(|album| album.title)
+                .or_else(|| album.map(str::to_string)),
+            Duration::from_secs(self.duration),
+        )
+        .with_isrc(self.isrc)
+    }
+}
+
+/// Client for Deezer links using the public Deezer API, no credentials are
+/// needed.
+#[derive(Clone, Debug)]
+pub struct DeezerClient {
+    client: reqwest::Client,
+}
+
+/// Implement [`DeezerClient`].
+impl DeezerClient {
+    /// Create a new [`DeezerClient`].
+    #[must_use]
+    pub fn new(client: reqwest::Client) -> Self {
+        DeezerClient { client }
+    }
+
+    /// Get the tracks a Deezer track, album or playlist URL points to, in
+    /// order. Short share links are followed to the URL they redirect to.
+    /// # Errors
+    /// Returns an error if the URL isn't a Deezer URL or a request fails.
+    pub async fn tracks(&self, url: &str) -> Result<Vec<CatalogTrack>, Error> {
+        let parsed = match parse_deezer_url(url) {
+            Some(parsed) => Some(parsed),
+            None if DEEZER_SHORT_URL_REGEX.is_match(url) => {
+                let response = self.client.get(url).send().await?;
+                parse_deezer_url(response.url().as_str())
+            }
+            None => None,
+        };
+        let Some(DeezerUrl { media_type, id }) = parsed else {
+            return Err(format!("Not a Deezer URL: {url}").into());
+        };
+        match media_type {
+            DeezerMediaType::Track => {
+                let track: DeezerTrack = self.get(&format!("{DEEZER_API_URL}/track/{id}")).await?;
+                Ok(vec![track.into_track(None)])
+            }
+            DeezerMediaType::Album => {
+                let album: DeezerAlbum = self.get(&format!("{DEEZER_API_URL}/album/{id}")).await?;
+                let tracks = self
+                    .get_all::<DeezerTrack>(&format!("{DEEZER_API_URL}/album/{id}/tracks"))
+                    .await?;
+                Ok(tracks
+                    .into_iter()
+                    .map(|track| track.into_track(Some(&album.title)))
+                    .collect())
+            }
+            DeezerMediaType::Playlist => {
+                let tracks = self
+                    .get_all::<DeezerTrack>(&format!("{DEEZER_API_URL}/playlist/{id}/tracks"))
+                    .await?;
+                Ok(tracks
+                    .into_iter()
+                    .map(|track| track.into_track(None))
+                    .collect())
+            }
+        }
+    }
+
+    /// Get an object from the API.
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        let response: DeezerResponse<T> = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match response {
+            DeezerResponse::Ok(value) => Ok(value),
+            DeezerResponse::Error { error } => Err(format!("Deezer: {}", error.message).into()),
+        }
+    }
+
+    /// Get every item of a list from the API, following its pages.
+    async fn get_all<T: DeserializeOwned>(&self, url: &str) -> Result<Vec<T>, Error> {
+        let mut items = Vec::new();
+        let mut next = Some(url.to_string());
+        while let Some(url) = next {
+            let page: DeezerPage<T> = self.get(&url).await?;
+            items.extend(page.data);
+            next = page.next;
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_deezer_url() {
+        assert_eq!(
+            parse_deezer_url("https://www.deezer.com/en/track/3135556"),
+            Some(DeezerUrl {
+                media_type: DeezerMediaType::Track,
+                id: "3135556".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_deezer_url("https://www.deezer.com/album/302127").map(|url| url.media_type),
+            Some(DeezerMediaType::Album)
+        );
+        assert_eq!(
+            parse_deezer_url("https://www.deezer.com/pt-br/playlist/908622995?utm_source=x")
+                .map(|url| (url.media_type, url.id)),
+            Some((DeezerMediaType::Playlist, "908622995".to_string()))
+        );
+        assert!(parse_deezer_url("https://www.deezer.com/en/artist/27").is_none());
+        assert!(is_deezer_url("https://deezer.page.link/abc123"));
+        assert!(!is_deezer_url("https://open.spotify.com/track/1"));
+    }
+
+    #[test]
+    fn test_deezer_response() {
+        let json = r#"{"data": [
+            {"id": 1, "title": "One More Time", "duration": 320, "isrc": "GBDUW0000053",
+             "artist": {"id": 27, "name": "Daft Punk"}}
+        ], "total": 2, "next": "https://api.deezer.com/album/302127/tracks?index=1"}"#;
+        let response: DeezerResponse<DeezerPage<DeezerTrack>> = serde_json::from_str(json).unwrap();
+        let DeezerResponse::Ok(page) = response else {
+            panic!("Expected a page");
+        };
+        assert!(page.next.is_some());
+        let track = page
+            .data
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_track(Some("Discovery"));
+        assert_eq!(track.search_query(), "Daft Punk - One More Time");
+        assert_eq!(track.album.as_deref(), Some("Discovery"));
+        assert_eq!(track.duration, Duration::from_secs(320));
+        assert_eq!(track.isrc.as_deref(), Some("GBDUW0000053"));
+
+        let json = r#"{"error": {"type": "DataException", "message": "no data", "code": 800}}"#;
+        let response: DeezerResponse<DeezerTrack> = serde_json::from_str(json).unwrap();
+        assert!(matches!(response, DeezerResponse::Error { .. }));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{CrackTrackQueue, ResolvedTrack};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
+
+/// Changes between two states of a [`CrackTrackQueue`], tracks are matched
+/// by URL.
+#[derive(Clone, Debug, Default)]
+pub struct QueueDiff {
+    /// Tracks that were added, with their index in the new queue.
+    pub added: Vec<(usize, ResolvedTrack)>,
+    /// Tracks that were removed, with their index in the old queue.
+    pub removed: Vec<(usize, ResolvedTrack)>,
+    /// Tracks that were moved, with their old and new index. Only the fewest
+    /// tracks that explain the new order are counted, so moving one track to
+    /// the front doesn't count every track it jumped over.
+    pub moved: Vec<(usize, usize, ResolvedTrack)>,
+}
+
+/// Implement [`QueueDiff`].
+impl QueueDiff {
+    /// Whether nothing changed.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty()
+    }
+}
+
+/// Implement [`Display`] for [`QueueDiff`], as a short summary like
+/// "3 tracks added, 1 removed".
+impl Display for QueueDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let counts = [
+            (self.added.len(), "added"),
+            (self.removed.len(), "removed"),
+            (self.moved.len(), "moved"),
+        ];
+        let mut first = true;
+        for (count, what) in counts.into_iter().filter(|(count, _)| *count > 0) {
+            if first {
+                let noun = if count == 1 { "track" } else { "tracks" };
+                write!(f, "{count} {noun} {what}")?;
+                first = false;
+            } else {
+                write!(f, ", {count} {what}")?;
+            }
+        }
+        if first {
+            write!(f, "No changes")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two lists of tracks. The same tracks, see
+/// [`ResolvedTrack::same_track_as`], are matched in order, so duplicates are
+/// handled.
+#[must_use]
+pub fn diff_tracks(old: &[ResolvedTrack], new: &[ResolvedTrack]) -> QueueDiff {
+    let mut old_indices: HashMap<String, VecDeque<usize>> = HashMap::new();
+    for (index, track) in old.iter().enumerate() {
+        old_indices
+            .entry(track.identity())
+            .or_default()
+            .push_back(index);
+    }
+
+    let mut diff = QueueDiff::default();
+    let mut matched = vec![false; old.len()];
+    // (old index, new index) of the tracks in both, in new order.
+    let mut kept = Vec::new();
+    for (index, track) in new.iter().enumerate() {
+        match old_indices
+            .get_mut(&track.identity())
+            .and_then(VecDeque::pop_front)
+        {
+            Some(old_index) => {
+                matched[old_index] = true;
+                kept.push((old_index, index));
+            }
+            None => diff.added.push((index, track.clone())),
+        }
+    }
+    diff.removed = old
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !matched[*index])
+        .map(|(index, track)| (index, track.clone()))
+        .collect();
+
+    let in_order = longest_increasing(&kept.iter().map(|(old, _)| *old).collect::<Vec<_>>());
+    diff.moved = kept
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !in_order[*i])
+        .map(|(_, &(from, to))| (from, to, new[to].clone()))
+        .collect();
+    diff
+}
+
+/// Mark the elements of a longest strictly increasing subsequence.
+fn longest_increasing(values: &[usize]) -> Vec<bool> {
+    // tails[k] is the index of the smallest tail of an increasing run of length k + 1.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev = vec![None; values.len()];
+    for (i, value) in values.iter().enumerate() {
+        let k = tails.partition_point(|&t| values[t] < *value);
+        if k > 0 {
+            prev[i] = Some(tails[k - 1]);
+        }
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+    let mut marked = vec![false; values.len()];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        marked[i] = true;
+        next = prev[i];
+    }
+    marked
+}
+
+/// Implement diffing for [`CrackTrackQueue`].
+impl CrackTrackQueue {
+    /// Compare the queue to the state it was in at the last
+    /// [`CrackTrackQueue::build_display`], e.g. to post a short update
+    /// instead of the whole queue.
+    pub async fn diff_since_display(&self) -> QueueDiff {
+        self.read_with(|queue| {
+            let current = queue.iter().cloned().collect::<Vec<_>>();
+            diff_tracks(&self.display_tracks, &current)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crack_types::QueryType;
+
+    fn create_test_track(id: &str) -> ResolvedTrack {
+        ResolvedTrack::new(QueryType::VideoLink(format!(
+            "https://www.youtube.com/watch?v={id}"
+        )))
+    }
+
+    fn tracks(ids: &[&str]) -> Vec<ResolvedTrack> {
+        ids.iter().map(|id| create_test_track(id)).collect()
+    }
+
+    #[test]
+    fn test_diff_tracks() {
+        let old = tracks(&["1", "2", "3", "4"]);
+        let new = tracks(&["4", "1", "2", "5"]);
+        let diff = diff_tracks(&old, &new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].0, 3);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].0, 2);
+        // Only the track moved to the front counts as moved
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!((diff.moved[0].0, diff.moved[0].1), (3, 0));
+        assert_eq!(diff.to_string(), "1 track added, 1 removed, 1 moved");
+
+        let diff = diff_tracks(&old, &old);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "No changes");
+
+        // Duplicates are matched one for one
+        let diff = diff_tracks(&tracks(&["1"]), &tracks(&["1", "1", "1"]));
+        assert_eq!(diff.to_string(), "2 tracks added");
+    }
+
+    #[tokio::test]
+    async fn test_diff_since_display() {
+        let mut queue = CrackTrackQueue::new();
+        queue.append_vec(tracks(&["1", "2"])).await;
+        queue.build_display().await;
+        assert!(queue.diff_since_display().await.is_empty());
+
+        queue.append_vec(tracks(&["3", "4", "5"])).await;
+        queue.remove(0).await;
+        let diff = queue.diff_since_display().await;
+        assert_eq!(diff.to_string(), "3 tracks added, 1 removed");
+    }
+}
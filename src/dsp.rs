@@ -0,0 +1,165 @@
+use std::f64::consts::PI;
+
+/// Convert a gain in decibels to a linear amplitude factor.
+#[must_use]
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Coefficients of a biquad filter, normalized so `a0` is 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+/// Implement [`BiquadCoefficients`].
+impl BiquadCoefficients {
+    /// Coefficients that let the signal through untouched.
+    pub const IDENTITY: BiquadCoefficients = BiquadCoefficients {
+        b0: 1.0,
+        b1: 0.0,
+        b2: 0.0,
+        a1: 0.0,
+        a2: 0.0,
+    };
+
+    /// Create new [`BiquadCoefficients`], dividing them by `a0`.
+    #[must_use]
+    pub fn new(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        BiquadCoefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// A peaking filter boosting or cutting the frequencies around
+    /// `frequency` by `gain_db`, narrower the higher `q` is.
+    #[must_use]
+    pub fn peaking(sample_rate: u32, frequency: f64, q: f64, gain_db: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / f64::from(sample_rate);
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        BiquadCoefficients::new(
+            1.0 + alpha * a,
+            -2.0 * cos,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        )
+    }
+
+    /// A low shelf filter boosting or cutting the frequencies below
+    /// `frequency` by `gain_db`, half as much at `frequency` itself.
+    #[must_use]
+    pub fn low_shelf(sample_rate: u32, frequency: f64, gain_db: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / f64::from(sample_rate);
+        let cos = w0.cos();
+        // A slope of 1, as steep as the shelf gets without overshooting
+        let alpha = w0.sin() / 2.0 * 2f64.sqrt();
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        BiquadCoefficients::new(
+            a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+            (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+        )
+    }
+
+    /// How much the filter amplifies a sine at `frequency`.
+    #[must_use]
+    pub fn magnitude(&self, sample_rate: u32, frequency: f64) -> f64 {
+        let w = 2.0 * PI * frequency / f64::from(sample_rate);
+        // H(z) at z = e^(jw), with the real and imaginary parts of each sum
+        let part = |c0: f64, c1: f64, c2: f64| {
+            (
+                c0 + c1 * w.cos() + c2 * (2.0 * w).cos(),
+                -c1 * w.sin() - c2 * (2.0 * w).sin(),
+            )
+        };
+        let (num_re, num_im) = part(self.b0, self.b1, self.b2);
+        let (den_re, den_im) = part(1.0, self.a1, self.a2);
+        num_re.hypot(num_im) / den_re.hypot(den_im)
+    }
+}
+
+/// A biquad filter over one channel, in direct form I.
+#[derive(Clone, Copy, Debug)]
+pub struct Biquad {
+    coefficients: BiquadCoefficients,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+/// Implement [`Biquad`].
+impl Biquad {
+    /// Create a new [`Biquad`] with no signal in it yet.
+    #[must_use]
+    pub fn new(coefficients: BiquadCoefficients) -> Self {
+        Biquad {
+            coefficients,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    /// Filter the next sample.
+    pub fn process(&mut self, x: f64) -> f64 {
+        let c = &self.coefficients;
+        let y = c.b0 * x + c.b1 * self.x1 + c.b2 * self.x2 - c.a1 * self.y1 - c.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_biquad_identity() {
+        let mut filter = Biquad::new(BiquadCoefficients::IDENTITY);
+        for x in [0.5, -0.25, 1.0] {
+            assert_eq!(filter.process(x), x);
+        }
+        assert!((db_to_gain(-6.0) - 0.501).abs() < 0.001);
+        assert_eq!(db_to_gain(0.0), 1.0);
+    }
+
+    #[test]
+    fn test_peaking() {
+        let peaking = BiquadCoefficients::peaking(48_000, 1000.0, 1.41, 6.0);
+        let gain_db = |frequency: f64| 20.0 * peaking.magnitude(48_000, frequency).log10();
+        assert!((gain_db(1000.0) - 6.0).abs() < 0.01);
+        assert!(gain_db(50.0).abs() < 0.1);
+        assert!(gain_db(15_000.0).abs() < 0.1);
+        assert!((BiquadCoefficients::IDENTITY.magnitude(48_000, 440.0) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_low_shelf() {
+        let shelf = BiquadCoefficients::low_shelf(48_000, 120.0, 8.0);
+        let gain_db = |frequency: f64| 20.0 * shelf.magnitude(48_000, frequency).log10();
+        assert!((gain_db(20.0) - 8.0).abs() < 0.1);
+        assert!((gain_db(120.0) - 4.0).abs() < 0.01);
+        assert!(gain_db(5000.0).abs() < 0.01);
+    }
+}
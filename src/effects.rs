@@ -0,0 +1,534 @@
+use crate::{
+    DataInner, Effect, EffectKind, EffectStage, EffectsChain, GateAction, ResolvedTrack,
+    SilenceGate, Speed, SILENCE_CHUNK_FRAMES,
+};
+use serde::{Deserialize, Serialize};
+use serenity::all::{async_trait, GuildId};
+use songbird::input::codecs::{get_codec_registry, get_probe};
+use songbird::input::{
+    AudioStream, AudioStreamError, Compose, Input, LiveInput, Parsed, RawAdapter,
+};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{SeekMode, SeekTo};
+use symphonia::core::io::MediaSource;
+use symphonia::core::units::Time;
+
+/// Channels of the audio coming out of the effects, other layouts are
+/// converted to stereo.
+pub const EFFECTS_CHANNELS: usize = 2;
+/// Bytes of a frame of the audio coming out of the effects, in `f32`.
+const FRAME_BYTES: u64 = (EFFECTS_CHANNELS * std::mem::size_of::<f32>()) as u64;
+
+/// Errors that can occur when changing the audio effects of a guild.
+#[derive(Debug, thiserror::Error)]
+pub enum EffectsError {
+    #[error("There is no band {band}, the equalizer has {bands}")]
+    NoSuchBand { band: usize, bands: usize },
+    #[error("Gain must be between -{max} and {max} dB")]
+    GainOutOfRange { max: f32 },
+    #[error("Speed must be between {min}% and {max}%")]
+    SpeedOutOfRange { min: u16, max: u16 },
+    #[error("Period must be between {min} and {max} seconds")]
+    PeriodOutOfRange { min: u16, max: u16 },
+    #[error("The {0} isn't on")]
+    NotInChain(EffectKind),
+    #[error("Position must be between 1 and {len}")]
+    PositionOutOfRange { len: usize },
+}
+
+/// An effect processing decoded audio.
+pub trait AudioFilter: Send + Sync {
+    /// Process interleaved stereo samples in place.
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+/// The audio effects of a guild.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioEffects {
+    /// The effects in the order they apply, see [`EffectsChain`].
+    #[serde(default)]
+    pub chain: EffectsChain,
+    /// Whether silence at the start and end of tracks is skipped, see
+    /// [`SilenceGate`].
+    #[serde(default)]
+    pub trim_silence: bool,
+}
+
+/// Implement [`AudioEffects`].
+impl AudioEffects {
+    /// Whether any effect changes the audio.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.chain.is_active() || self.trim_silence
+    }
+
+    /// The effects a track plays with, its own speed over the one of the
+    /// guild.
+    #[must_use]
+    pub fn for_track(mut self, speed: Option<Speed>) -> Self {
+        if let Some(speed) = speed {
+            self.chain.add(Effect::Speed(speed));
+        }
+        self
+    }
+
+    /// Describe the effects that are on, one line each, in the order they
+    /// apply.
+    #[must_use]
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = self.chain.describe();
+        if self.trim_silence {
+            lines.push("Silence trimmed".to_string());
+        }
+        lines
+    }
+}
+
+/// The audio effects of a guild, shared with the tracks playing in it so
+/// changes apply to them right away. Clones share the effects.
+#[derive(Clone, Debug, Default)]
+pub struct SharedEffects {
+    effects: Arc<RwLock<AudioEffects>>,
+    /// Bumped on every change, so tracks know to rebuild their filters.
+    version: Arc<AtomicU64>,
+}
+
+/// Implement [`SharedEffects`].
+impl SharedEffects {
+    /// Get a copy of the effects.
+    #[must_use]
+    pub fn get(&self) -> AudioEffects {
+        self.effects
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Change the effects.
+    pub fn update<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut AudioEffects) -> R,
+    {
+        let mut effects = self.effects.write().unwrap_or_else(PoisonError::into_inner);
+        let result = f(&mut effects);
+        self.version.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Relaxed)
+    }
+}
+
+/// Apply the effects to an input, at the speed of its track if it has one.
+/// The input is decoded by the effects, which hand songbird raw PCM to
+/// encode.
+#[must_use]
+pub fn effects_input(input: Input, effects: SharedEffects, track_speed: Option<Speed>) -> Input {
+    Input::Lazy(Box::new(EffectsInput {
+        input: Some(input),
+        effects,
+        track_speed,
+    }))
+}
+
+/// Decodes an input to apply effects to it, see [`effects_input`].
+struct EffectsInput {
+    input: Option<Input>,
+    effects: SharedEffects,
+    track_speed: Option<Speed>,
+}
+
+#[async_trait]
+impl Compose for EffectsInput {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let input = self.input.take().ok_or_else(|| {
+            AudioStreamError::Fail("The input of the effects was already used".into())
+        })?;
+        let input = input
+            .make_playable_async(get_codec_registry(), get_probe())
+            .await
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+        let Input::Live(LiveInput::Parsed(parsed), _) = input else {
+            return Err(AudioStreamError::Fail(
+                "The input couldn't be parsed".into(),
+            ));
+        };
+        let sample_rate = parsed
+            .decoder
+            .codec_params()
+            .sample_rate
+            .ok_or_else(|| AudioStreamError::Fail("The input has no sample rate".into()))?;
+        let source =
+            EffectsSource::new(parsed, self.effects.clone(), self.track_speed, sample_rate);
+        Ok(AudioStream {
+            input: Box::new(RawAdapter::new(
+                source,
+                sample_rate,
+                EFFECTS_CHANNELS as u32,
+            )),
+            hint: None,
+        })
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+}
+
+/// Decoded audio with effects applied, as raw interleaved stereo `f32` PCM.
+/// Positions in it are in playback time, which runs apart from the time of
+/// the track at speeds other than normal.
+struct EffectsSource {
+    parsed: Parsed,
+    effects: SharedEffects,
+    track_speed: Option<Speed>,
+    /// Version of the effects the stages were built from.
+    version: u64,
+    sample_rate: u32,
+    stages: Vec<EffectStage>,
+    speed: Speed,
+    trim_silence: bool,
+    gate: SilenceGate,
+    /// Frames of silence left to play before `held`.
+    silence_out: u64,
+    /// Audio waiting for the silence before it to be played.
+    held: Vec<f32>,
+    samples: Vec<f32>,
+    resampled: Vec<f32>,
+    /// The bytes of the last decoded packet, read up to `pos`.
+    bytes: Vec<u8>,
+    pos: usize,
+    /// Bytes read in total, adjusted by seeking.
+    position: u64,
+    /// Frames of the track read in total, adjusted by seeking.
+    track_frames: f64,
+}
+
+/// Implement [`EffectsSource`].
+impl EffectsSource {
+    fn new(
+        parsed: Parsed,
+        effects: SharedEffects,
+        track_speed: Option<Speed>,
+        sample_rate: u32,
+    ) -> Self {
+        let mut source = EffectsSource {
+            parsed,
+            effects,
+            track_speed,
+            version: 0,
+            sample_rate,
+            stages: Vec::new(),
+            speed: Speed::NORMAL,
+            trim_silence: false,
+            gate: SilenceGate::new(true),
+            silence_out: 0,
+            held: Vec::new(),
+            samples: Vec::new(),
+            resampled: Vec::new(),
+            bytes: Vec::new(),
+            pos: 0,
+            position: 0,
+            track_frames: 0.0,
+        };
+        source.reset_effects();
+        source
+    }
+
+    /// Build the stages of the effects chain from scratch.
+    fn reset_effects(&mut self) {
+        self.version = self.effects.version();
+        let effects = self.effects.get().for_track(self.track_speed);
+        self.stages = effects.chain.stages(self.sample_rate);
+        self.speed = effects.chain.speed();
+        self.trim_silence = effects.trim_silence;
+    }
+
+    /// Rebuild the stages if the effects changed. The resampler carries on
+    /// at the new speed so the audio doesn't skip.
+    fn refresh_stages(&mut self) {
+        let version = self.effects.version();
+        if version == self.version {
+            return;
+        }
+        self.version = version;
+        let effects = self.effects.get().for_track(self.track_speed);
+        let resampler = self.stages.drain(..).find_map(|stage| match stage {
+            EffectStage::Resample(resampler) => Some(resampler),
+            EffectStage::Filter(_) => None,
+        });
+        self.stages = effects.chain.stages(self.sample_rate);
+        let new = self.stages.iter_mut().find_map(|stage| match stage {
+            EffectStage::Resample(resampler) => Some(resampler),
+            EffectStage::Filter(_) => None,
+        });
+        if let (Some(new), Some(mut old)) = (new, resampler) {
+            old.set_speed(effects.chain.speed());
+            *new = old;
+        }
+        self.speed = effects.chain.speed();
+        self.trim_silence = effects.trim_silence;
+    }
+
+    /// Decode the next packet and apply the effects to it. Returns `false`
+    /// at the end of the stream.
+    fn decode_next(&mut self) -> io::Result<bool> {
+        self.refresh_stages();
+        // Play the silence let through by the gate before the audio after it
+        if self.silence_out > 0 {
+            let frames = self.silence_out.min(SILENCE_CHUNK_FRAMES);
+            self.silence_out -= frames;
+            self.samples.clear();
+            self.samples.resize(frames as usize * EFFECTS_CHANNELS, 0.0);
+            self.output();
+            return Ok(true);
+        }
+        if !self.held.is_empty() {
+            std::mem::swap(&mut self.samples, &mut self.held);
+            self.held.clear();
+            self.output();
+            return Ok(true);
+        }
+        loop {
+            let packet = match self.parsed.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(false)
+                }
+                Err(e) => return Err(io::Error::other(e)),
+            };
+            if packet.track_id() != self.parsed.track_id {
+                continue;
+            }
+            let decoded = match self.parsed.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(e) => return Err(io::Error::other(e)),
+            };
+            let spec = *decoded.spec();
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+            to_stereo(buffer.samples(), spec.channels.count(), &mut self.samples);
+
+            if self.trim_silence {
+                match self.gate.process(&self.samples) {
+                    GateAction::Skip(frames) => {
+                        self.track_frames += frames as f64;
+                        continue;
+                    }
+                    GateAction::Hold => continue,
+                    GateAction::Pass(0) => {}
+                    GateAction::Pass(silence) => {
+                        std::mem::swap(&mut self.samples, &mut self.held);
+                        self.silence_out = silence;
+                        return self.decode_next();
+                    }
+                }
+            }
+            self.output();
+            return Ok(true);
+        }
+    }
+
+    /// Apply the effects to the decoded samples in the order of the chain and
+    /// queue them up to be read.
+    fn output(&mut self) {
+        for stage in &mut self.stages {
+            match stage {
+                EffectStage::Filter(filter) => filter.process(&mut self.samples),
+                EffectStage::Resample(resampler) => {
+                    resampler.process(&self.samples, &mut self.resampled);
+                    std::mem::swap(&mut self.samples, &mut self.resampled);
+                }
+            }
+        }
+        self.bytes.clear();
+        self.bytes
+            .extend(self.samples.iter().flat_map(|sample| sample.to_le_bytes()));
+        self.pos = 0;
+    }
+}
+
+impl Read for EffectsSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.bytes.len() {
+            if !self.decode_next()? {
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.bytes.len() - self.pos);
+        buf[..len].copy_from_slice(&self.bytes[self.pos..self.pos + len]);
+        self.pos += len;
+        self.position += len as u64;
+        self.track_frames += len as f64 / FRAME_BYTES as f64 * self.speed.rate();
+        Ok(len)
+    }
+}
+
+impl Seek for EffectsSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(target) => target,
+            SeekFrom::Current(0) => return Ok(self.position),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Effects can only seek from the start",
+                ))
+            }
+        };
+        // Count the frames of the track from where it is, at the speed it
+        // plays at now, as the speed may have changed on the way
+        let frame = target / FRAME_BYTES;
+        let offset = (frame as f64 - (self.position / FRAME_BYTES) as f64) * self.speed.rate();
+        let track_frames = (self.track_frames + offset).max(0.0);
+        let time = Duration::from_secs_f64(track_frames / f64::from(self.sample_rate));
+        self.parsed
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: Time::new(time.as_secs(), f64::from(time.subsec_nanos()) / 1e9),
+                    track_id: Some(self.parsed.track_id),
+                },
+            )
+            .map_err(io::Error::other)?;
+        self.parsed.decoder.reset();
+        // Start the stages over, their state is from before the seek
+        self.reset_effects();
+        // Only silence at the start is skipped, seeks just past it like the
+        // one over the encoder delay of gapless tracks still skip it
+        self.gate = SilenceGate::new(track_frames < f64::from(self.sample_rate));
+        self.silence_out = 0;
+        self.held.clear();
+        self.bytes.clear();
+        self.pos = 0;
+        self.position = frame * FRAME_BYTES;
+        self.track_frames = track_frames;
+        Ok(self.position)
+    }
+}
+
+impl MediaSource for EffectsSource {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Convert interleaved samples with `channels` channels to stereo, mono is
+/// played on both sides and only the front pair of surround is kept.
+fn to_stereo(samples: &[f32], channels: usize, out: &mut Vec<f32>) {
+    out.clear();
+    match channels {
+        0 => {}
+        1 => out.extend(samples.iter().flat_map(|sample| [*sample, *sample])),
+        _ => out.extend(
+            samples
+                .chunks_exact(channels)
+                .flat_map(|frame| [frame[0], frame[1]]),
+        ),
+    }
+}
+
+/// Implement the audio effects of guilds for [`DataInner`], kept in
+/// `effects`.
+impl DataInner {
+    /// Get the audio effects of a guild.
+    pub fn guild_effects(&self, guild_id: GuildId) -> SharedEffects {
+        self.effects.entry(guild_id).or_default().clone()
+    }
+
+    /// Apply the audio effects of a guild to the input of a track, if it
+    /// has any on. Effects turned on while a track plays without any apply
+    /// from the next track, so untouched tracks can skip decoding.
+    pub fn with_effects(&self, guild_id: GuildId, track: &ResolvedTrack, input: Input) -> Input {
+        let effects = self.guild_effects(guild_id);
+        if effects.get().for_track(track.speed()).is_active() {
+            effects_input(input, effects, track.speed())
+        } else {
+            input
+        }
+    }
+
+    /// How fast a track plays in a guild, see [`AudioEffects::for_track`].
+    pub fn playback_speed(&self, guild_id: GuildId, track: &ResolvedTrack) -> Speed {
+        self.guild_effects(guild_id)
+            .get()
+            .for_track(track.speed())
+            .chain
+            .speed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AutoPan, BassBoost, EqPreset, Equalizer};
+
+    #[test]
+    fn test_to_stereo() {
+        let mut out = Vec::new();
+        to_stereo(&[0.1, 0.2], 1, &mut out);
+        assert_eq!(out, vec![0.1, 0.1, 0.2, 0.2]);
+        to_stereo(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 3, &mut out);
+        assert_eq!(out, vec![0.1, 0.2, 0.4, 0.5]);
+    }
+
+    #[test]
+    fn test_shared_effects() {
+        let effects = SharedEffects::default();
+        let clone = effects.clone();
+        assert!(!effects.get().is_active());
+        let version = clone.version();
+        effects.update(|effects| {
+            effects
+                .chain
+                .set_equalizer(Equalizer::from_preset(EqPreset::Rock))
+        });
+        assert!(clone.get().is_active());
+        assert_ne!(clone.version(), version);
+        assert_eq!(clone.get().chain.stages(48_000).len(), 1);
+        effects.update(|effects| effects.chain.set_bass_boost(Some(BassBoost::High)));
+        assert_eq!(clone.get().chain.stages(48_000).len(), 2);
+        effects.update(|effects| effects.chain.set_auto_pan(Some(AutoPan::default())));
+        assert_eq!(clone.get().chain.stages(48_000).len(), 3);
+        assert_eq!(
+            clone.get().describe(),
+            vec![
+                "Equalizer: rock".to_string(),
+                "Bass boost: high".to_string(),
+                "8D: around every 8s".to_string()
+            ]
+        );
+
+        let effects = AudioEffects::default();
+        assert!(!effects.is_active());
+        let effects = effects.for_track(Some(Speed::NIGHTCORE));
+        assert!(effects.is_active());
+        // The speed is a resampling stage
+        assert!(matches!(
+            effects.chain.stages(48_000).as_slice(),
+            [EffectStage::Resample(_)]
+        ));
+        assert_eq!(
+            effects.describe(),
+            vec!["Speed: nightcore (125%)".to_string()]
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{AudioFilter, AutoPan, BassBoost, EffectsError, Equalizer, Resampler, Speed};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// The kinds of effects in an [`EffectsChain`], which holds at most one of
+/// each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EffectKind {
+    Equalizer,
+    BassBoost,
+    Speed,
+    AutoPan,
+}
+
+/// Implement [`Display`] for [`EffectKind`].
+impl Display for EffectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EffectKind::Equalizer => "equalizer",
+            EffectKind::BassBoost => "bass boost",
+            EffectKind::Speed => "speed",
+            EffectKind::AutoPan => "8D",
+        };
+        f.write_str(name)
+    }
+}
+
+/// An effect in an [`EffectsChain`], with its settings.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", content = "settings", rename_all = "snake_case")]
+pub enum Effect {
+    Equalizer(Equalizer),
+    BassBoost(BassBoost),
+    Speed(Speed),
+    AutoPan(AutoPan),
+}
+
+/// Implement [`Effect`].
+impl Effect {
+    /// The kind of the effect.
+    #[must_use]
+    pub fn kind(&self) -> EffectKind {
+        match self {
+            Effect::Equalizer(_) => EffectKind::Equalizer,
+            Effect::BassBoost(_) => EffectKind::BassBoost,
+            Effect::Speed(_) => EffectKind::Speed,
+            Effect::AutoPan(_) => EffectKind::AutoPan,
+        }
+    }
+
+    /// Whether the effect leaves the audio as it is, like a flat equalizer.
+    #[must_use]
+    pub fn is_neutral(&self) -> bool {
+        match self {
+            Effect::Equalizer(equalizer) => equalizer.is_flat(),
+            Effect::Speed(speed) => speed.is_normal(),
+            Effect::BassBoost(_) | Effect::AutoPan(_) => false,
+        }
+    }
+
+    /// Build the stage applying the effect to audio at `sample_rate`, `None`
+    /// if it is neutral.
+    #[must_use]
+    pub fn stage(&self, sample_rate: u32) -> Option<EffectStage> {
+        if self.is_neutral() {
+            return None;
+        }
+        Some(match self {
+            Effect::Equalizer(equalizer) => {
+                EffectStage::Filter(Box::new(equalizer.filter(sample_rate)))
+            }
+            Effect::BassBoost(bass_boost) => {
+                EffectStage::Filter(Box::new(bass_boost.filter(sample_rate)))
+            }
+            Effect::Speed(speed) => EffectStage::Resample(Resampler::new(*speed)),
+            Effect::AutoPan(auto_pan) => {
+                EffectStage::Filter(Box::new(auto_pan.filter(sample_rate)))
+            }
+        })
+    }
+}
+
+/// Implement [`Display`] for [`Effect`].
+impl Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Effect::Equalizer(equalizer) => {
+                let preset = equalizer
+                    .preset()
+                    .map_or_else(|| "custom".to_string(), |preset| preset.to_string());
+                write!(f, "Equalizer: {preset}")
+            }
+            Effect::BassBoost(bass_boost) => write!(f, "Bass boost: {bass_boost}"),
+            Effect::Speed(speed) => write!(f, "Speed: {speed}"),
+            Effect::AutoPan(auto_pan) => write!(f, "8D: {auto_pan}"),
+        }
+    }
+}
+
+/// A step of the processing of audio built from an [`Effect`].
+pub enum EffectStage {
+    /// Processes the samples in place.
+    Filter(Box<dyn AudioFilter>),
+    /// Changes the speed, and so how many samples there are.
+    Resample(Resampler),
+}
+
+/// The effects of a guild in the order they are applied to the audio. Each
+/// kind of effect is in it at most once. It (de)serializes as the list of
+/// effects, so it can be shown and stored.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<Effect>", into = "Vec<Effect>")]
+pub struct EffectsChain {
+    effects: Vec<Effect>,
+}
+
+/// Implement [`From`] for [`Vec<Effect>`] to [`EffectsChain`], later effects
+/// of a kind replace earlier ones.
+impl From<Vec<Effect>> for EffectsChain {
+    fn from(effects: Vec<Effect>) -> Self {
+        let mut chain = EffectsChain::default();
+        for effect in effects {
+            chain.add(effect);
+        }
+        chain
+    }
+}
+
+/// Implement [`From`] for [`EffectsChain`] to [`Vec<Effect>`].
+impl From<EffectsChain> for Vec<Effect> {
+    fn from(chain: EffectsChain) -> Self {
+        chain.effects
+    }
+}
+
+/// Implement [`EffectsChain`].
+impl EffectsChain {
+    /// Add an effect at the end of the chain, or change the settings of the
+    /// effect of its kind where it is.
+    pub fn add(&mut self, effect: Effect) {
+        match self.position(effect.kind()) {
+            Some(index) => self.effects[index] = effect,
+            None => self.effects.push(effect),
+        }
+    }
+
+    /// Remove the effect of a kind from the chain, returning it.
+    pub fn remove(&mut self, kind: EffectKind) -> Option<Effect> {
+        let index = self.position(kind)?;
+        Some(self.effects.remove(index))
+    }
+
+    /// Move the effect of a kind to `position` in the chain, counting from 0,
+    /// the effects in between moving over.
+    /// # Errors
+    /// Returns an error if the effect isn't in the chain or the position is
+    /// past its end.
+    pub fn move_to(&mut self, kind: EffectKind, position: usize) -> Result<(), EffectsError> {
+        let index = self.position(kind).ok_or(EffectsError::NotInChain(kind))?;
+        if position >= self.effects.len() {
+            return Err(EffectsError::PositionOutOfRange {
+                len: self.effects.len(),
+            });
+        }
+        let effect = self.effects.remove(index);
+        self.effects.insert(position, effect);
+        Ok(())
+    }
+
+    /// Remove every effect.
+    pub fn reset(&mut self) {
+        self.effects.clear();
+    }
+
+    /// Where the effect of a kind is in the chain.
+    #[must_use]
+    pub fn position(&self, kind: EffectKind) -> Option<usize> {
+        self.effects.iter().position(|effect| effect.kind() == kind)
+    }
+
+    /// Get the effect of a kind, if it is in the chain.
+    #[must_use]
+    pub fn get(&self, kind: EffectKind) -> Option<&Effect> {
+        self.effects.iter().find(|effect| effect.kind() == kind)
+    }
+
+    /// Iterate over the effects in order.
+    pub fn iter(&self) -> impl Iterator<Item = &Effect> {
+        self.effects.iter()
+    }
+
+    /// Number of effects in the chain.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    /// Whether the chain has no effects.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Whether any effect changes the audio.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.effects.iter().any(|effect| !effect.is_neutral())
+    }
+
+    /// Describe the effects that change the audio, one line each, in order.
+    #[must_use]
+    pub fn describe(&self) -> Vec<String> {
+        self.effects
+            .iter()
+            .filter(|effect| !effect.is_neutral())
+            .map(Effect::to_string)
+            .collect()
+    }
+
+    /// Build the stages applying the effects to audio at `sample_rate`, in
+    /// order.
+    #[must_use]
+    pub fn stages(&self, sample_rate: u32) -> Vec<EffectStage> {
+        self.effects
+            .iter()
+            .filter_map(|effect| effect.stage(sample_rate))
+            .collect()
+    }
+
+    /// The equalizer, flat if it isn't in the chain.
+    #[must_use]
+    pub fn equalizer(&self) -> Equalizer {
+        match self.get(EffectKind::Equalizer) {
+            Some(Effect::Equalizer(equalizer)) => equalizer.clone(),
+            _ => Equalizer::default(),
+        }
+    }
+
+    /// Set the equalizer, a flat one is taken out of the chain.
+    pub fn set_equalizer(&mut self, equalizer: Equalizer) {
+        if equalizer.is_flat() {
+            self.remove(EffectKind::Equalizer);
+        } else {
+            self.add(Effect::Equalizer(equalizer));
+        }
+    }
+
+    /// The bass boost, if it is in the chain.
+    #[must_use]
+    pub fn bass_boost(&self) -> Option<BassBoost> {
+        match self.get(EffectKind::BassBoost) {
+            Some(Effect::BassBoost(bass_boost)) => Some(*bass_boost),
+            _ => None,
+        }
+    }
+
+    /// Set the bass boost, `None` takes it out of the chain.
+    pub fn set_bass_boost(&mut self, bass_boost: Option<BassBoost>) {
+        match bass_boost {
+            Some(bass_boost) => self.add(Effect::BassBoost(bass_boost)),
+            None => {
+                self.remove(EffectKind::BassBoost);
+            }
+        }
+    }
+
+    /// The speed tracks play at, normal if it isn't in the chain.
+    #[must_use]
+    pub fn speed(&self) -> Speed {
+        match self.get(EffectKind::Speed) {
+            Some(Effect::Speed(speed)) => *speed,
+            _ => Speed::NORMAL,
+        }
+    }
+
+    /// Set the speed, the normal speed is taken out of the chain.
+    pub fn set_speed(&mut self, speed: Speed) {
+        if speed.is_normal() {
+            self.remove(EffectKind::Speed);
+        } else {
+            self.add(Effect::Speed(speed));
+        }
+    }
+
+    /// The auto-pan, if it is in the chain.
+    #[must_use]
+    pub fn auto_pan(&self) -> Option<AutoPan> {
+        match self.get(EffectKind::AutoPan) {
+            Some(Effect::AutoPan(auto_pan)) => Some(*auto_pan),
+            _ => None,
+        }
+    }
+
+    /// Set the auto-pan, `None` takes it out of the chain.
+    pub fn set_auto_pan(&mut self, auto_pan: Option<AutoPan>) {
+        match auto_pan {
+            Some(auto_pan) => self.add(Effect::AutoPan(auto_pan)),
+            None => {
+                self.remove(EffectKind::AutoPan);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EqPreset;
+
+    #[test]
+    fn test_effects_chain() {
+        let mut chain = EffectsChain::default();
+        assert!(!chain.is_active());
+        chain.set_bass_boost(Some(BassBoost::High));
+        chain.set_speed(Speed::NIGHTCORE);
+        chain.set_equalizer(Equalizer::from_preset(EqPreset::Rock));
+        assert_eq!(chain.position(EffectKind::Equalizer), Some(2));
+        assert_eq!(chain.stages(48_000).len(), 3);
+
+        // Changing an effect keeps its place
+        chain.set_bass_boost(Some(BassBoost::Low));
+        assert_eq!(chain.position(EffectKind::BassBoost), Some(0));
+
+        chain.move_to(EffectKind::Equalizer, 0).unwrap();
+        assert_eq!(
+            chain.describe(),
+            vec![
+                "Equalizer: rock".to_string(),
+                "Bass boost: low".to_string(),
+                "Speed: nightcore (125%)".to_string()
+            ]
+        );
+        assert!(matches!(
+            chain.move_to(EffectKind::AutoPan, 0),
+            Err(EffectsError::NotInChain(EffectKind::AutoPan))
+        ));
+        assert!(matches!(
+            chain.move_to(EffectKind::Speed, 3),
+            Err(EffectsError::PositionOutOfRange { len: 3 })
+        ));
+
+        chain.set_speed(Speed::NORMAL);
+        assert_eq!(chain.get(EffectKind::Speed), None);
+        assert_eq!(
+            chain.remove(EffectKind::BassBoost),
+            Some(Effect::BassBoost(BassBoost::Low))
+        );
+        chain.reset();
+        assert!(chain.is_empty());
+    }
+
+    #[test]
+    fn test_effects_chain_serde() {
+        let mut chain = EffectsChain::default();
+        chain.set_auto_pan(Some(AutoPan::default()));
+        chain.set_bass_boost(Some(BassBoost::Extreme));
+        let json = serde_json::to_string(&chain).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"effect":"auto_pan","settings":{"period_secs":8}},{"effect":"bass_boost","settings":"Extreme"}]"#
+        );
+        let parsed: EffectsChain = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, chain);
+
+        // A kind given twice is only kept once
+        let parsed: EffectsChain = serde_json::from_str(
+            r#"[{"effect":"speed","settings":80},{"effect":"speed","settings":125}]"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed.speed(), Speed::NIGHTCORE);
+    }
+}
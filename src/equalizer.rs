@@ -0,0 +1,175 @@
+use crate::{AudioFilter, Biquad, BiquadCoefficients, EffectsError};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// Center frequencies of the bands of the equalizer, in Hz, an octave apart.
+pub const EQ_BAND_FREQUENCIES: [f32; EQ_BANDS] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+/// Number of bands of the equalizer.
+pub const EQ_BANDS: usize = 10;
+/// Quality of the bands, about an octave wide.
+pub const EQ_BAND_Q: f64 = 1.41;
+/// Most a band can boost or cut, in dB.
+pub const MAX_EQ_GAIN: f32 = 12.0;
+
+/// Presets of the equalizer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EqPreset {
+    Flat,
+    Rock,
+    Classical,
+}
+
+/// Implement [`EqPreset`].
+impl EqPreset {
+    /// All the presets.
+    pub const ALL: [EqPreset; 3] = [EqPreset::Flat, EqPreset::Rock, EqPreset::Classical];
+
+    /// Gains of the bands of the preset, in dB.
+    #[must_use]
+    pub fn gains(self) -> [f32; EQ_BANDS] {
+        match self {
+            EqPreset::Flat => [0.0; EQ_BANDS],
+            EqPreset::Rock => [4.0, 3.0, 2.0, 0.5, -1.0, -1.0, 0.5, 2.0, 3.0, 3.5],
+            EqPreset::Classical => [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -2.5, -2.5, -2.5, -3.5],
+        }
+    }
+}
+
+/// Implement [`Display`] for [`EqPreset`].
+impl Display for EqPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EqPreset::Flat => "flat",
+            EqPreset::Rock => "rock",
+            EqPreset::Classical => "classical",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Settings of the multi-band equalizer, the gain of each band in dB.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Equalizer {
+    pub gains: [f32; EQ_BANDS],
+}
+
+/// Implement [`Equalizer`].
+impl Equalizer {
+    /// Create an [`Equalizer`] with the gains of a preset.
+    #[must_use]
+    pub fn from_preset(preset: EqPreset) -> Self {
+        Equalizer {
+            gains: preset.gains(),
+        }
+    }
+
+    /// Whether the equalizer leaves the audio untouched.
+    #[must_use]
+    pub fn is_flat(&self) -> bool {
+        self.gains.iter().all(|gain| *gain == 0.0)
+    }
+
+    /// The preset the equalizer is set to, `None` if a band was changed.
+    #[must_use]
+    pub fn preset(&self) -> Option<EqPreset> {
+        EqPreset::ALL
+            .into_iter()
+            .find(|preset| preset.gains() == self.gains)
+    }
+
+    /// Set the gain of a band, counting from 0.
+    /// # Errors
+    /// Returns an error if there is no such band or the gain is more than
+    /// [`MAX_EQ_GAIN`].
+    pub fn set_band(&mut self, band: usize, gain: f32) -> Result<(), EffectsError> {
+        if !(-MAX_EQ_GAIN..=MAX_EQ_GAIN).contains(&gain) {
+            return Err(EffectsError::GainOutOfRange { max: MAX_EQ_GAIN });
+        }
+        let slot = self.gains.get_mut(band).ok_or(EffectsError::NoSuchBand {
+            band,
+            bands: EQ_BANDS,
+        })?;
+        *slot = gain;
+        Ok(())
+    }
+
+    /// Build the filter applying the equalizer to audio at `sample_rate`.
+    /// Bands that are flat or above what the sample rate can hold are left
+    /// out.
+    #[must_use]
+    pub fn filter(&self, sample_rate: u32) -> EqualizerFilter {
+        let nyquist = sample_rate as f32 / 2.0;
+        let bands = EQ_BAND_FREQUENCIES
+            .iter()
+            .zip(self.gains)
+            .filter(|(frequency, gain)| *gain != 0.0 && **frequency < nyquist * 0.9)
+            .map(|(frequency, gain)| {
+                let coefficients = BiquadCoefficients::peaking(
+                    sample_rate,
+                    f64::from(*frequency),
+                    EQ_BAND_Q,
+                    f64::from(gain),
+                );
+                [Biquad::new(coefficients), Biquad::new(coefficients)]
+            })
+            .collect();
+        EqualizerFilter { bands }
+    }
+}
+
+/// Applies an [`Equalizer`] to stereo audio, a peaking filter per band and
+/// channel.
+#[derive(Debug)]
+pub struct EqualizerFilter {
+    bands: Vec<[Biquad; 2]>,
+}
+
+impl AudioFilter for EqualizerFilter {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(2) {
+            for [left, right] in &mut self.bands {
+                frame[0] = left.process(f64::from(frame[0])) as f32;
+                frame[1] = right.process(f64::from(frame[1])) as f32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equalizer() {
+        let mut equalizer = Equalizer::default();
+        assert!(equalizer.is_flat());
+        assert_eq!(equalizer.preset(), Some(EqPreset::Flat));
+        assert!(equalizer.filter(48_000).bands.is_empty());
+
+        let equalizer_rock = Equalizer::from_preset(EqPreset::Rock);
+        assert_eq!(equalizer_rock.preset(), Some(EqPreset::Rock));
+        assert_eq!(equalizer_rock.filter(48_000).bands.len(), EQ_BANDS);
+        // The 16 kHz band can't be played at 22.05 kHz
+        assert_eq!(equalizer_rock.filter(22_050).bands.len(), EQ_BANDS - 1);
+
+        equalizer.set_band(5, 3.0).unwrap();
+        assert_eq!(equalizer.preset(), None);
+        assert!(!equalizer.is_flat());
+        assert!(matches!(
+            equalizer.set_band(EQ_BANDS, 3.0),
+            Err(EffectsError::NoSuchBand { .. })
+        ));
+        assert!(matches!(
+            equalizer.set_band(0, 20.0),
+            Err(EffectsError::GainOutOfRange { .. })
+        ));
+
+        // Silence stays silent
+        let mut filter = equalizer_rock.filter(48_000);
+        let mut samples = vec![0.0; 64];
+        filter.process(&mut samples);
+        assert!(samples.iter().all(|sample| *sample == 0.0));
+    }
+}
@@ -1,13 +1,153 @@
+use crate::apply_gapless;
 use crate::check_msg;
+use crate::play_announced;
+use crate::CrackTrackQueue;
 use crate::Data;
-use crack_types::YoutubeDl;
+use crate::LoopMode;
+use crate::NowPlaying;
+use crate::{track_input_with_args, ResolveError, ResolvedTrack, Speed, RADIO_RECONNECT_ATTEMPTS};
+use crack_types::get_human_readable_timestamp;
 use poise::serenity_prelude as serenity;
 use serenity::all::{async_trait, ChannelId, GuildId, Http};
-use songbird::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+use songbird::tracks::{PlayMode, TrackHandle};
+use songbird::{Call, Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
 use std::sync::{
-    atomic::{AtomicBool, AtomicUsize, Ordering},
+    atomic::{AtomicUsize, Ordering},
     Arc,
 };
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// How long a stream has to play before its reconnect attempts are reset.
+const RADIO_STABLE_PLAY_TIME: Duration = Duration::from_secs(60);
+
+/// Persist a guild's queue after it changes, logging any failure.
+async fn save_queue(data: &Data, guild_id: GuildId, queue: &CrackTrackQueue) {
+    if let Err(_e) = data.queue_store.save(guild_id, queue).await {
+        #[cfg(feature = "crack-tracing")]
+        tracing::warn!("Failed to save the queue of {guild_id}: {_e}");
+    }
+}
+
+/// Refill a looping queue from its backup once it runs out.
+/// Returns `true` if the queue was restored.
+async fn restore_loop_backup(data: &Data, guild_id: GuildId, queue: &CrackTrackQueue) -> bool {
+    if !queue.is_empty().await || queue.loop_mode().await == LoopMode::Off {
+        return false;
+    }
+    let Some(backup) = data.queue_backups.get(&guild_id).map(|b| b.value().clone()) else {
+        return false;
+    };
+    if backup.is_empty() {
+        return false;
+    }
+    queue.append_vec(backup.into_iter().collect()).await;
+    true
+}
+
+/// Play only the part of the source between the track's offsets, e.g. a
+/// single chapter of a long video. The offsets are moved to match the
+/// `speed` the track plays at.
+pub fn apply_offsets(song: &TrackHandle, track: &ResolvedTrack, speed: Speed) {
+    let start = track.start_offset().unwrap_or_default();
+    if start > Duration::ZERO {
+        let _ = song.seek(speed.playback_time(start));
+    }
+    if let Some(end) = track.end_offset() {
+        let _ = song.add_event(
+            Event::Delayed(speed.playback_time(end.saturating_sub(start))),
+            ClipEndNotifier,
+        );
+    }
+}
+
+/// Play a track that just started at the volume of the guild's queue, times
+/// its own volume, and keep its handle so commands like `/volume` can change
+/// it while it plays. Its loudness is normalized and the stream of the next
+/// track is opened meanwhile, see [`crate::DataInner::normalize_track`] and
+/// [`crate::DataInner::preload_next`].
+pub fn track_started(
+    data: &Data,
+    guild_id: GuildId,
+    queue: &CrackTrackQueue,
+    song: &TrackHandle,
+    track: &ResolvedTrack,
+) {
+    let _ = song.set_volume(queue.gain() * track.gain());
+    data.current_tracks.insert(guild_id, song.clone());
+    data.normalize_track(guild_id, queue, song, track);
+    data.preload_next(guild_id, queue);
+}
+
+/// Play a track just dequeued in a guild: the one staged if it follows on
+/// gaplessly, otherwise its stream with the effects of the guild, announced
+/// first in the call if its queue has voice announcements on. A track built
+/// from search results alone is resolved fully first, see
+/// [`crate::CrackTrackClient::resolve_full`]. The
+/// announcement is synthesized before the call is locked, so it doesn't hold
+/// up other commands. Returns the handle of the track and whether it was
+/// announced.
+pub async fn play_dequeued(
+    data: &Data,
+    guild_id: GuildId,
+    handler_lock: &Mutex<Call>,
+    queue: &CrackTrackQueue,
+    track: &ResolvedTrack,
+) -> (TrackHandle, bool) {
+    if let Some(song) = data.take_staged(guild_id, track).await {
+        return (song, false);
+    }
+    let resolved;
+    let track = if track.is_lazy() {
+        match data.track_client.resolve_full(track.clone()).await {
+            Ok(full) => {
+                resolved = full;
+                &resolved
+            }
+            Err(_e) => {
+                #[cfg(feature = "crack-tracing")]
+                tracing::warn!("Failed to resolve {}: {_e}", track.get_url());
+                track
+            }
+        }
+    } else {
+        track
+    };
+    let src = data.next_input(guild_id, track);
+    let announcement = data.announcement(queue, track).await;
+    let announced = announcement.is_some();
+    let song = {
+        let mut handler = handler_lock.lock().await;
+        match announcement {
+            Some(announcement) => play_announced(&mut handler, announcement, src),
+            None => handler.play_input(src),
+        }
+    };
+    apply_offsets(&song, track, data.playback_speed(guild_id, track));
+    (song, announced)
+}
+
+/// Stop the track playing in a guild, leaving anything else in the call,
+/// like its ambience, playing. Its [`EnhancedTrackEndNotifier`] then moves
+/// the queue on. Returns `false` if no track was playing.
+pub fn stop_current(data: &Data, guild_id: GuildId) -> bool {
+    data.current_tracks
+        .get(&guild_id)
+        .is_some_and(|song| song.stop().is_ok())
+}
+
+/// Stops a track once it reaches its end offset, see [`apply_offsets`].
+pub struct ClipEndNotifier;
+
+#[async_trait]
+impl VoiceEventHandler for ClipEndNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track([(_, track)]) = ctx {
+            let _ = track.stop();
+        }
+        None
+    }
+}
 
 /// Enhanced TrackEndNotifier with better queue handling
 pub struct EnhancedTrackEndNotifier {
@@ -15,29 +155,185 @@ pub struct EnhancedTrackEndNotifier {
     pub http: Arc<Http>,
     pub guild_id: GuildId,
     pub data: Arc<Data>,
-    pub is_looping: Arc<AtomicBool>,
+    /// Times in a row an endless stream has been reconnected after dropping.
+    pub reconnects: usize,
+}
+
+impl EnhancedTrackEndNotifier {
+    /// Restart the current track if it is an endless stream whose connection
+    /// dropped, rather than moving on to the next track. Gives up after
+    /// [`RADIO_RECONNECT_ATTEMPTS`] quick drops in a row.
+    /// Returns `true` if the stream was restarted.
+    async fn reconnect_stream(&self, queue: &CrackTrackQueue, play_time: Duration) -> bool {
+        let Some(track) = queue.current().await.filter(|track| track.is_unbounded()) else {
+            return false;
+        };
+        let reconnects = if play_time >= RADIO_STABLE_PLAY_TIME {
+            0
+        } else {
+            self.reconnects
+        };
+        if reconnects >= RADIO_RECONNECT_ATTEMPTS {
+            check_msg(
+                self.chan_id
+                    .say(&self.http, "Stream keeps dropping, giving up.")
+                    .await,
+            );
+            return false;
+        }
+        let Some(handler_lock) = self.data.songbird.get(self.guild_id) else {
+            return false;
+        };
+        let mut handler = handler_lock.lock().await;
+        // Opened anew with the effects of the guild, `next_input` would take
+        // the stream preloaded for the next track
+        let input = track_input_with_args(
+            self.data.http_client.clone(),
+            &track,
+            self.data.ytdl_args.clone(),
+        );
+        let song = handler.play_input(self.data.with_effects(self.guild_id, &track, input));
+        track_started(&self.data, self.guild_id, queue, &song, &track);
+        let _ = song.add_event(
+            Event::Track(TrackEvent::End),
+            EnhancedTrackEndNotifier {
+                chan_id: self.chan_id,
+                http: self.http.clone(),
+                guild_id: self.guild_id,
+                data: self.data.clone(),
+                reconnects: reconnects + 1,
+            },
+        );
+        let _ = song.add_event(
+            Event::Track(TrackEvent::Error),
+            EnhancedTrackErrorNotifier {
+                chan_id: self.chan_id,
+                http: self.http.clone(),
+                guild_id: self.guild_id,
+                data: self.data.clone(),
+            },
+        );
+        check_msg(
+            self.chan_id
+                .say(&self.http, "Stream dropped, reconnecting...")
+                .await,
+        );
+        true
+    }
+
+    /// Play the current track again from `position` after the
+    /// [`crate::StallWatchdog`] stopped it for being stuck, with its stream
+    /// resolved anew. Returns `true` if it was played again.
+    async fn recover_stalled(&self, queue: &CrackTrackQueue, position: Duration) -> bool {
+        let Some(track) = queue.current().await.map(ResolvedTrack::with_fresh_stream) else {
+            return false;
+        };
+        let Some(handler_lock) = self.data.songbird.get(self.guild_id) else {
+            return false;
+        };
+        let mut handler = handler_lock.lock().await;
+        let input = track_input_with_args(
+            self.data.http_client.clone(),
+            &track,
+            self.data.ytdl_args.clone(),
+        );
+        let song = handler.play_input(self.data.with_effects(self.guild_id, &track, input));
+        let speed = self.data.playback_speed(self.guild_id, &track);
+        if !track.is_unbounded() && !track.is_live() {
+            let _ = song.seek(position);
+        }
+        if let Some(end) = track.end_offset() {
+            let _ = song.add_event(
+                Event::Delayed(speed.playback_time(end).saturating_sub(position)),
+                ClipEndNotifier,
+            );
+        }
+        track_started(&self.data, self.guild_id, queue, &song, &track);
+        let _ = song.add_event(
+            Event::Track(TrackEvent::End),
+            EnhancedTrackEndNotifier {
+                chan_id: self.chan_id,
+                http: self.http.clone(),
+                guild_id: self.guild_id,
+                data: self.data.clone(),
+                reconnects: 0,
+            },
+        );
+        let _ = song.add_event(
+            Event::Track(TrackEvent::Error),
+            EnhancedTrackErrorNotifier {
+                chan_id: self.chan_id,
+                http: self.http.clone(),
+                guild_id: self.guild_id,
+                data: self.data.clone(),
+            },
+        );
+        check_msg(
+            self.chan_id
+                .say(
+                    &self.http,
+                    format!(
+                        "Playback got stuck, resuming from {}...",
+                        get_human_readable_timestamp(Some(position.mul_f64(speed.rate())))
+                    ),
+                )
+                .await,
+        );
+        true
+    }
 }
 
 #[async_trait]
 impl VoiceEventHandler for EnhancedTrackEndNotifier {
-    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
         // Get the custom queue for this guild
         if let Some(queue) = self.data.guild_queues.get(&self.guild_id) {
+            // Play a track stopped for being stuck again from where it was
+            if let Some((_, position)) = self.data.stalled.remove(&self.guild_id) {
+                if self.recover_stalled(&queue, position).await {
+                    return None;
+                }
+            }
+
+            // Start the next track first thing when playing gaplessly
+            self.data.start_staged(self.guild_id, &queue).await;
+
+            // A stream that ended by itself rather than being stopped dropped
+            if let EventContext::Track([(state, _)]) = ctx {
+                if state.playing == PlayMode::End
+                    && self.reconnect_stream(&queue, state.play_time).await
+                {
+                    return None;
+                }
+            }
+
+            // The track that was playing has finished
+            queue.clear_current().await;
+
+            // Restart a looping queue from its backup once it runs out
+            if restore_loop_backup(&self.data, self.guild_id, &queue).await {
+                check_msg(
+                    self.chan_id
+                        .say(&self.http, "Queue ended. Restarting loop...")
+                        .await,
+                );
+            }
+
             // Check if there are more tracks in the queue
             if !queue.is_empty().await {
                 // Get the handler for this guild
                 if let Some(handler_lock) = self.data.songbird.get(self.guild_id) {
-                    let mut handler = handler_lock.lock().await;
-
                     // Get the next track from our custom queue
                     if let Some(track) = queue.dequeue().await {
-                        // Play the next track
-                        let src = songbird::input::Input::from(YoutubeDl::new(
-                            self.data.http_client.clone(),
-                            track.get_url(),
-                        ));
+                        save_queue(&self.data, self.guild_id, &queue).await;
 
-                        let song = handler.play_input(src);
+                        // Play the next track, announcing it first in the call unless
+                        // it follows on gaplessly
+                        let (song, announced) =
+                            play_dequeued(&self.data, self.guild_id, &handler_lock, &queue, &track)
+                                .await;
+                        track_started(&self.data, self.guild_id, &queue, &song, &track);
+                        apply_gapless(self.data.clone(), self.guild_id, &queue, &song, &track);
 
                         // Update activity timestamp directly
                         if let Some(idle_info) = self.data.idle_timeouts.get(&self.guild_id) {
@@ -57,7 +353,7 @@ impl VoiceEventHandler for EnhancedTrackEndNotifier {
                                 http: self.http.clone(),
                                 guild_id: self.guild_id,
                                 data: self.data.clone(),
-                                is_looping: self.is_looping.clone(),
+                                reconnects: 0,
                             },
                         );
 
@@ -68,47 +364,33 @@ impl VoiceEventHandler for EnhancedTrackEndNotifier {
                                 http: self.http.clone(),
                                 guild_id: self.guild_id,
                                 data: self.data.clone(),
-                                is_looping: self.is_looping.clone(),
                             },
                         );
 
-                        // Notify that the next track is playing
-                        check_msg(
-                            self.chan_id
-                                .say(&self.http, &format!("Now playing: {}", track.get_title()))
-                                .await,
-                        );
+                        // Notify that the next track is playing, unless it was
+                        // announced in the call already
+                        if !announced {
+                            let embed = NowPlaying::new(track)
+                                .with_queue_len(queue.len().await)
+                                .with_effects(&self.data.guild_effects(self.guild_id).get())
+                                .embed();
+                            check_msg(
+                                self.chan_id
+                                    .send_message(
+                                        &self.http,
+                                        serenity::CreateMessage::new().embed(embed),
+                                    )
+                                    .await,
+                            );
+                        }
                     }
                 }
             } else {
                 // Queue is empty
-                // Check if we're looping
-                if self.is_looping.load(std::sync::atomic::Ordering::Relaxed) {
-                    check_msg(
-                        self.chan_id
-                            .say(&self.http, "Queue ended. Restarting loop...")
-                            .await,
-                    );
+                save_queue(&self.data, self.guild_id, &queue).await;
 
-                    // Handle looping logic by getting the original queue backup
-                    // In a real implementation, you'd need to store this somewhere
-                    // For now, we'll just indicate that looping would happen here
-
-                    // This is where you'd restore the queue from a backup
-                    // For example:
-                    // if let Some(backup) = self.data.queue_backups.get(&self.guild_id) {
-                    //     let mut original_tracks = backup.value().clone();
-                    //     queue.append_vec(original_tracks).await;
-                    //
-                    //     // Start playing the first track
-                    //     if let Some(handler_lock) = self.data.songbird.get(self.guild_id) {
-                    //         // ... similar to the code above to play the next track
-                    //     }
-                    // }
-                } else {
-                    // Not looping, just notify queue is finished
-                    check_msg(self.chan_id.say(&self.http, "Queue finished.").await);
-                }
+                // Nothing left to play, looping or not
+                check_msg(self.chan_id.say(&self.http, "Queue finished.").await);
             }
         }
 
@@ -122,37 +404,52 @@ pub struct EnhancedTrackErrorNotifier {
     pub http: Arc<Http>,
     pub guild_id: serenity::GuildId,
     pub data: Arc<Data>,
-    pub is_looping: Arc<std::sync::atomic::AtomicBool>,
 }
 
 #[async_trait]
 impl VoiceEventHandler for EnhancedTrackErrorNotifier {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        if let EventContext::Track([(_, track)]) = ctx {
-            // Notify about the error
-            check_msg(
-                self.chan_id
-                    .say(
-                        &self.http,
-                        "Error playing track, skipping to next in queue...",
-                    )
-                    .await,
-            );
+        if let EventContext::Track([(state, track)]) = ctx {
+            // Notify about the error, with the reason if it is known
+            let reason = match &state.playing {
+                PlayMode::Errored(e) => ResolveError::classify(e),
+                _ => None,
+            };
+            let message = match reason {
+                Some(reason) => {
+                    format!("Error playing track: {reason}. Skipping to next in queue...")
+                }
+                None => "Error playing track, skipping to next in queue...".to_string(),
+            };
+            check_msg(self.chan_id.say(&self.http, message).await);
 
             // Stop the current track
             let _ = track.stop();
 
             // Get the custom queue for this guild
             if let Some(queue) = self.data.guild_queues.get(&self.guild_id) {
+                // Start the next track first thing when playing gaplessly
+                self.data.start_staged(self.guild_id, &queue).await;
+
+                // The track that failed is no longer playing
+                queue.clear_current().await;
+
+                // Same loop restore logic as in EnhancedTrackEndNotifier
+                if restore_loop_backup(&self.data, self.guild_id, &queue).await {
+                    check_msg(
+                        self.chan_id
+                            .say(&self.http, "Queue ended. Restarting loop...")
+                            .await,
+                    );
+                }
+
                 // Handle playing next track - same logic as in EnhancedTrackEndNotifier
                 // This is intentionally duplicated to make the error handler independent
                 if !queue.is_empty().await {
                     if let Some(handler_lock) = self.data.songbird.get(self.guild_id) {
-                        let mut handler = handler_lock.lock().await;
-
                         if let Some(next_track) = queue.dequeue().await {
-                            let src =
-                                YoutubeDl::new(self.data.http_client.clone(), next_track.get_url());
+                            save_queue(&self.data, self.guild_id, &queue).await;
+
                             // let src = match YoutubeDl::new(self.data.http_client.clone(), next_track.get_url()).into_input() {
                             //     Ok(input) => input,
                             //     Err(e) => {
@@ -165,7 +462,22 @@ impl VoiceEventHandler for EnhancedTrackErrorNotifier {
                             //     }
                             // };
 
-                            let song = handler.play_input(src.into());
+                            let (song, announced) = play_dequeued(
+                                &self.data,
+                                self.guild_id,
+                                &handler_lock,
+                                &queue,
+                                &next_track,
+                            )
+                            .await;
+                            track_started(&self.data, self.guild_id, &queue, &song, &next_track);
+                            apply_gapless(
+                                self.data.clone(),
+                                self.guild_id,
+                                &queue,
+                                &song,
+                                &next_track,
+                            );
 
                             // Update activity timestamp directly
                             if let Some(idle_info) = self.data.idle_timeouts.get(&self.guild_id) {
@@ -185,7 +497,7 @@ impl VoiceEventHandler for EnhancedTrackErrorNotifier {
                                     http: self.http.clone(),
                                     guild_id: self.guild_id,
                                     data: self.data.clone(),
-                                    is_looping: self.is_looping.clone(),
+                                    reconnects: 0,
                                 },
                             );
 
@@ -196,33 +508,30 @@ impl VoiceEventHandler for EnhancedTrackErrorNotifier {
                                     http: self.http.clone(),
                                     guild_id: self.guild_id,
                                     data: self.data.clone(),
-                                    is_looping: self.is_looping.clone(),
                                 },
                             );
 
-                            check_msg(
-                                self.chan_id
-                                    .say(
-                                        &self.http,
-                                        &format!("Now playing: {}", next_track.get_title()),
-                                    )
-                                    .await,
-                            );
+                            // Announced in the call already otherwise
+                            if !announced {
+                                let embed = NowPlaying::new(next_track)
+                                    .with_queue_len(queue.len().await)
+                                    .with_effects(&self.data.guild_effects(self.guild_id).get())
+                                    .embed();
+                                check_msg(
+                                    self.chan_id
+                                        .send_message(
+                                            &self.http,
+                                            serenity::CreateMessage::new().embed(embed),
+                                        )
+                                        .await,
+                                );
+                            }
                         }
                     }
                 } else {
-                    // Same loop handling logic as in EnhancedTrackEndNotifier
-                    if self.is_looping.load(std::sync::atomic::Ordering::Relaxed) {
-                        check_msg(
-                            self.chan_id
-                                .say(&self.http, "Queue ended. Restarting loop...")
-                                .await,
-                        );
+                    save_queue(&self.data, self.guild_id, &queue).await;
 
-                        // Loop handling code would go here
-                    } else {
-                        check_msg(self.chan_id.say(&self.http, "Queue finished.").await);
-                    }
+                    check_msg(self.chan_id.say(&self.http, "Queue finished.").await);
                 }
             }
         }
@@ -0,0 +1,162 @@
+use crate::{CrackTrackQueue, PersistedTrack};
+use std::fmt::Display;
+
+/// File formats a queue can be exported to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    M3u,
+    Csv,
+}
+
+impl ExportFormat {
+    /// File extension for the format, without the leading dot.
+    #[must_use]
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::M3u => "m3u8",
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    /// Suggested file name for an exported queue.
+    #[must_use]
+    pub fn file_name(&self) -> String {
+        format!("queue.{}", self.extension())
+    }
+}
+
+/// Implement [`Display`] for [`ExportFormat`].
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::M3u => write!(f, "m3u"),
+            ExportFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+/// Render the tracks as an extended M3U8 playlist.
+#[must_use]
+pub fn to_m3u(tracks: &[PersistedTrack]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for track in tracks {
+        let secs = track.duration_secs.map_or(-1, |d| d as i64);
+        // Newlines would break the line based format.
+        let title = track.title.replace(['\r', '\n'], " ");
+        out.push_str(&format!("#EXTINF:{},{}\n{}\n", secs, title, track.url));
+    }
+    out
+}
+
+/// Render the tracks as CSV with a `title,url,duration,requester` header.
+#[must_use]
+pub fn to_csv(tracks: &[PersistedTrack]) -> String {
+    let mut out = String::from("title,url,duration,requester\n");
+    for track in tracks {
+        let duration = track
+            .duration_secs
+            .map(|d| d.to_string())
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "{},{},{},{}\n",
+            csv_escape(&track.title),
+            csv_escape(&track.url),
+            duration,
+            track.user_id,
+        ));
+    }
+    out
+}
+
+/// Quote a CSV field if it contains a separator, quote or newline.
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Implement exporting for [`CrackTrackQueue`].
+impl CrackTrackQueue {
+    /// Export the queue, starting with the playing track if there is one.
+    pub async fn export(&self, format: ExportFormat) -> String {
+        let snapshot = self.snapshot().await;
+        let tracks = snapshot
+            .now_playing
+            .into_iter()
+            .chain(snapshot.tracks)
+            .collect::<Vec<_>>();
+        match format {
+            ExportFormat::M3u => to_m3u(&tracks),
+            ExportFormat::Csv => to_csv(&tracks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::all::UserId;
+
+    fn create_test_track(id: &str, title: &str) -> PersistedTrack {
+        PersistedTrack {
+            url: format!("https://www.youtube.com/watch?v={id}"),
+            title: title.to_string(),
+            duration_secs: Some(180),
+            user_id: UserId::new(42),
+            artist: None,
+            album: None,
+            channel: None,
+            date: None,
+            thumbnail: None,
+            unbounded: false,
+            live: false,
+            start_ms: None,
+            end_ms: None,
+            speed: None,
+            volume: None,
+        }
+    }
+
+    #[test]
+    fn test_to_m3u() {
+        let tracks = vec![create_test_track("1", "Track 1")];
+        assert_eq!(
+            to_m3u(&tracks),
+            "#EXTM3U\n#EXTINF:180,Track 1\nhttps://www.youtube.com/watch?v=1\n"
+        );
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let tracks = vec![
+            create_test_track("1", "Track 1"),
+            create_test_track("2", "Hello, \"World\""),
+        ];
+        assert_eq!(
+            to_csv(&tracks),
+            "title,url,duration,requester\n\
+             Track 1,https://www.youtube.com/watch?v=1,180,42\n\
+             \"Hello, \"\"World\"\"\",https://www.youtube.com/watch?v=2,180,42\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_export() {
+        let queue = CrackTrackQueue::new();
+        queue
+            .enqueue(create_test_track("2", "Track 2").into())
+            .await
+            .unwrap();
+        queue
+            .set_playing(Some(create_test_track("1", "Track 1").into()))
+            .await;
+
+        let m3u = queue.export(ExportFormat::M3u).await;
+        let first = m3u.find("watch?v=1").unwrap();
+        let second = m3u.find("watch?v=2").unwrap();
+        assert!(first < second);
+    }
+}
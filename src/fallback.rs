@@ -0,0 +1,90 @@
+use dashmap::DashMap;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+/// A backend a URL can be resolved with, tried in the order of the
+/// resolver chain of the [`crate::CrackTrackClient`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResolveBackend {
+    /// `rusty_ytdl`, fast and in process.
+    RustyYtdl,
+    /// yt-dlp, slower but keeps up with YouTube changes.
+    YtDlp,
+    /// The Invidious or Piped instances set with
+    /// [`crate::CrackTrackClient::with_mirrors`], also tried for videos
+    /// blocked in the region of the bot.
+    Mirror,
+}
+
+/// Default order backends are tried in.
+pub const DEFAULT_RESOLVER_CHAIN: [ResolveBackend; 2] =
+    [ResolveBackend::RustyYtdl, ResolveBackend::YtDlp];
+
+/// Implement [`Display`] for [`ResolveBackend`].
+impl Display for ResolveBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveBackend::RustyYtdl => write!(f, "rusty_ytdl"),
+            ResolveBackend::YtDlp => write!(f, "yt-dlp"),
+            ResolveBackend::Mirror => write!(f, "mirror"),
+        }
+    }
+}
+
+/// How often a backend served a track or failed to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BackendStats {
+    pub served: usize,
+    pub failed: usize,
+}
+
+/// Counts of tracks served and failures of each backend. Clones share the
+/// counts.
+#[derive(Clone, Debug, Default)]
+pub struct BackendTelemetry {
+    stats: Arc<DashMap<ResolveBackend, BackendStats>>,
+}
+
+/// Implement [`BackendTelemetry`].
+impl BackendTelemetry {
+    /// Count a track served by `backend`.
+    pub fn record_served(&self, backend: ResolveBackend) {
+        self.stats.entry(backend).or_default().served += 1;
+    }
+
+    /// Count a failure of `backend`.
+    pub fn record_failed(&self, backend: ResolveBackend) {
+        self.stats.entry(backend).or_default().failed += 1;
+    }
+
+    /// Get the counts of a backend.
+    #[must_use]
+    pub fn get(&self, backend: ResolveBackend) -> BackendStats {
+        self.stats
+            .get(&backend)
+            .map(|stats| *stats)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_telemetry() {
+        let telemetry = BackendTelemetry::default();
+        telemetry.record_failed(ResolveBackend::RustyYtdl);
+        telemetry.clone().record_served(ResolveBackend::YtDlp);
+        telemetry.record_served(ResolveBackend::YtDlp);
+        assert_eq!(
+            telemetry.get(ResolveBackend::RustyYtdl),
+            BackendStats {
+                served: 0,
+                failed: 1
+            }
+        );
+        assert_eq!(telemetry.get(ResolveBackend::YtDlp).served, 2);
+        assert_eq!(ResolveBackend::YtDlp.to_string(), "yt-dlp");
+    }
+}
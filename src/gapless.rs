@@ -0,0 +1,195 @@
+use crate::{apply_offsets, CrackTrackQueue, Data, DataInner, ResolvedTrack};
+use serenity::all::{async_trait, GuildId};
+use songbird::tracks::{PlayMode, Track, TrackHandle};
+use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long before the end of a track the next one is added to the call,
+/// paused, in gapless mode.
+pub const GAPLESS_LEAD_TIME: Duration = Duration::from_secs(5);
+
+/// Silence encoders add to the start of streamed audio, the pre-skip of
+/// Opus streams, which is skipped when playing gaplessly.
+pub const GAPLESS_ENCODER_DELAY: Duration = Duration::from_micros(6_500);
+
+/// The next track of a guild, added to the call paused so it starts as soon
+/// as the track before it ends.
+pub struct StagedTrack {
+    /// See [`ResolvedTrack::identity`].
+    identity: String,
+    song: TrackHandle,
+}
+
+/// How long a track plays for, from its start offset to its end offset or
+/// its duration. `None` for livestreams, endless streams and tracks of
+/// unknown length, which can't be played gaplessly.
+#[must_use]
+pub fn gapless_length(track: &ResolvedTrack) -> Option<Duration> {
+    if track.is_unbounded() || track.is_live() {
+        return None;
+    }
+    let end = track.end_offset().or_else(|| track.get_raw_duration())?;
+    let length = end.saturating_sub(track.start_offset().unwrap_or_default());
+    (length > Duration::ZERO).then_some(length)
+}
+
+/// Play the track after one that just started gaplessly if the queue is in
+/// gapless mode: shortly before the end, the next track is added to the call
+/// paused, past the padding of its encoder, and is started as soon as the
+/// current one drains, see [`DataInner::start_staged`].
+pub fn apply_gapless(
+    data: Arc<Data>,
+    guild_id: GuildId,
+    queue: &CrackTrackQueue,
+    song: &TrackHandle,
+    track: &ResolvedTrack,
+) {
+    if !queue.gapless() {
+        return;
+    }
+    let Some(length) = gapless_length(track) else {
+        return;
+    };
+    let length = data.playback_speed(guild_id, track).playback_time(length);
+    let _ = song.add_event(
+        Event::Delayed(length.saturating_sub(GAPLESS_LEAD_TIME)),
+        GaplessStager { guild_id, data },
+    );
+}
+
+/// Stages the next track of a guild, see [`apply_gapless`].
+pub struct GaplessStager {
+    pub guild_id: GuildId,
+    pub data: Arc<Data>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for GaplessStager {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let queue = self.data.guild_queues.get(&self.guild_id)?.clone();
+        let track = queue
+            .get(0)
+            .await
+            .filter(|track| !track.is_unbounded() && !track.is_live())?;
+        let handler_lock = self.data.songbird.get(self.guild_id)?;
+        let input = self.data.next_input(self.guild_id, &track);
+        let song = handler_lock.lock().await.play(
+            Track::from(input)
+                .pause()
+                .volume(queue.gain() * track.gain()),
+        );
+        // Open and probe the stream now rather than when it starts
+        let _ = song.make_playable();
+        apply_offsets(
+            &song,
+            &track,
+            self.data.playback_speed(self.guild_id, &track),
+        );
+        if track.start_offset().is_none() && track.local_path().is_none() {
+            let _ = song.seek(GAPLESS_ENCODER_DELAY);
+        }
+
+        let staged = StagedTrack {
+            identity: track.identity(),
+            song,
+        };
+        if let Some(old) = self.data.staged.insert(self.guild_id, staged) {
+            let _ = old.song.stop();
+        }
+        None
+    }
+}
+
+/// Implement handing off to staged tracks for [`DataInner`], through the
+/// tracks kept in `staged`.
+impl DataInner {
+    /// Start the track staged for a guild right away if it is still the
+    /// next one in the queue, without waiting for the queue to be updated.
+    /// A staged track that isn't next anymore is stopped.
+    pub async fn start_staged(&self, guild_id: GuildId, queue: &CrackTrackQueue) {
+        let Some(identity) = self
+            .staged
+            .get(&guild_id)
+            .map(|staged| staged.identity.clone())
+        else {
+            return;
+        };
+        if queue
+            .get(0)
+            .await
+            .is_some_and(|next| next.identity() == identity)
+        {
+            if let Some(staged) = self.staged.get(&guild_id) {
+                let _ = staged.song.play();
+            }
+        } else {
+            self.unstage(guild_id);
+        }
+    }
+
+    /// Take the handle of the track staged for a guild if it is `track` and
+    /// it wasn't stopped, e.g. by `/skip`. Another staged track is stopped.
+    pub async fn take_staged(
+        &self,
+        guild_id: GuildId,
+        track: &ResolvedTrack,
+    ) -> Option<TrackHandle> {
+        let (_, staged) = self.staged.remove(&guild_id)?;
+        if staged.identity != track.identity() {
+            let _ = staged.song.stop();
+            return None;
+        }
+        match staged.song.get_info().await {
+            Ok(info) if matches!(info.playing, PlayMode::Play | PlayMode::Pause) => {
+                let _ = staged.song.play();
+                Some(staged.song)
+            }
+            _ => None,
+        }
+    }
+
+    /// Stop and drop the track staged for a guild, if any.
+    pub fn unstage(&self, guild_id: GuildId) {
+        if let Some((_, staged)) = self.staged.remove(&guild_id) {
+            let _ = staged.song.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crack_types::{AuxMetadata, QueryType};
+
+    #[test]
+    fn test_gapless_length() {
+        let track = ResolvedTrack::new(QueryType::VideoLink(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+        ));
+        assert_eq!(gapless_length(&track), None);
+
+        let track = track.with_metadata(AuxMetadata {
+            duration: Some(Duration::from_secs(212)),
+            ..Default::default()
+        });
+        assert_eq!(gapless_length(&track), Some(Duration::from_secs(212)));
+        assert_eq!(
+            gapless_length(
+                &track
+                    .clone()
+                    .with_offsets(Some(Duration::from_secs(12)), None)
+            ),
+            Some(Duration::from_secs(200))
+        );
+        assert_eq!(
+            gapless_length(
+                &track
+                    .clone()
+                    .with_offsets(Some(Duration::from_secs(12)), Some(Duration::from_secs(42)))
+            ),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(gapless_length(&track.with_live(true)), None);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::ResolvedTrack;
+use crack_types::{AuxMetadata, Error, QueryType};
+use serde_json::Value;
+use std::time::Duration;
+
+/// File formats a queue can be imported from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImportFormat {
+    #[default]
+    M3u,
+    Json,
+}
+
+/// A single entry read from an imported file.
+#[derive(Clone, Debug)]
+pub enum ImportEntry {
+    /// The file had enough metadata to build the track directly.
+    Track(ResolvedTrack),
+    /// Only a URL was given, the track needs to be resolved.
+    Url(String),
+}
+
+/// The entries parsed from an imported file.
+#[derive(Clone, Debug, Default)]
+pub struct ParsedImport {
+    pub entries: Vec<ImportEntry>,
+    /// Number of lines (M3U) or array elements (JSON) that could not be parsed.
+    pub failed: usize,
+}
+
+/// Outcome of importing a queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Number of tracks added to the queue.
+    pub imported: usize,
+    /// Number of entries that failed to parse or resolve.
+    pub failed: usize,
+}
+
+/// Parse an imported file in the given format.
+/// # Errors
+/// Returns an error if a JSON file is not valid JSON at all.
+pub fn parse_import(bytes: &[u8], format: ImportFormat) -> Result<ParsedImport, Error> {
+    let text = String::from_utf8_lossy(bytes);
+    match format {
+        ImportFormat::M3u => Ok(parse_m3u(&text)),
+        ImportFormat::Json => parse_json(&text),
+    }
+}
+
+/// Parse an (extended) M3U playlist. `#EXTINF` lines give the title and
+/// duration of the URL that follows them, other comments are skipped.
+#[must_use]
+pub fn parse_m3u(text: &str) -> ParsedImport {
+    let mut parsed = ParsedImport::default();
+    let mut info: Option<(Option<u64>, String)> = None;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            match extinf.split_once(',') {
+                Some((secs, title)) => {
+                    // Negative durations mean unknown.
+                    let secs = secs.trim().parse::<i64>().ok();
+                    let secs = secs.and_then(|s| u64::try_from(s).ok());
+                    info = Some((secs, title.trim().to_string()));
+                }
+                None => parsed.failed += 1,
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let Ok(url) = url::Url::parse(line) else {
+            parsed.failed += 1;
+            info = None;
+            continue;
+        };
+        if !matches!(url.scheme(), "http" | "https") {
+            parsed.failed += 1;
+            info = None;
+            continue;
+        }
+        let url = url.to_string();
+        let entry = match info.take() {
+            Some((secs, title)) if !title.is_empty() => {
+                let metadata = AuxMetadata {
+                    title: Some(title),
+                    source_url: Some(url.clone()),
+                    duration: secs.map(Duration::from_secs),
+                    ..Default::default()
+                };
+                ImportEntry::Track(
+                    ResolvedTrack::new(QueryType::VideoLink(url)).with_metadata(metadata),
+                )
+            }
+            _ => ImportEntry::Url(url),
+        };
+        parsed.entries.push(entry);
+    }
+    parsed
+}
+
+/// Parse a JSON queue, either as written by [`crate::CrackTrackQueue::to_json`]
+/// or a plain array of tracks.
+/// # Errors
+/// Returns an error if the text is not valid JSON.
+pub fn parse_json(text: &str) -> Result<ParsedImport, Error> {
+    let value: Value = serde_json::from_str(text)?;
+    let items = match value {
+        Value::Array(items) => items,
+        Value::Object(mut object) => {
+            let mut items = Vec::new();
+            if let Some(now_playing) = object.remove("now_playing").filter(|v| !v.is_null()) {
+                items.push(now_playing);
+            }
+            match object.remove("tracks") {
+                Some(Value::Array(tracks)) => items.extend(tracks),
+                Some(_) => return Err("\"tracks\" is not an array".into()),
+                None => {}
+            }
+            items
+        }
+        _ => return Err("Expected a JSON array or queue object".into()),
+    };
+
+    let mut parsed = ParsedImport::default();
+    for item in items {
+        match serde_json::from_value::<ResolvedTrack>(item) {
+            Ok(track) => parsed.entries.push(ImportEntry::Track(track)),
+            Err(_) => parsed.failed += 1,
+        }
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrackTrackQueue;
+
+    #[test]
+    fn test_parse_m3u() {
+        let text = "#EXTM3U\n\
+                    #EXTINF:180,Track 1\n\
+                    https://www.youtube.com/watch?v=1\n\
+                    \n\
+                    https://www.youtube.com/watch?v=2\n\
+                    not a url\n\
+                    file:///music/song.mp3\n";
+        let parsed = parse_m3u(text);
+        assert_eq!(parsed.failed, 2);
+        assert_eq!(parsed.entries.len(), 2);
+        match &parsed.entries[0] {
+            ImportEntry::Track(track) => {
+                assert_eq!(track.get_title(), "Track 1");
+                assert_eq!(track.get_raw_duration(), Some(Duration::from_secs(180)));
+            }
+            ImportEntry::Url(_) => panic!("Expected a track"),
+        }
+        assert!(matches!(
+            &parsed.entries[1],
+            ImportEntry::Url(url) if url == "https://www.youtube.com/watch?v=2"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parse_json() {
+        let queue = CrackTrackQueue::new();
+        queue
+            .enqueue(ResolvedTrack::new(QueryType::VideoLink(
+                "https://www.youtube.com/watch?v=1".to_string(),
+            )))
+            .await
+            .unwrap();
+        let parsed = parse_json(&queue.to_json().await.unwrap()).unwrap();
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.failed, 0);
+
+        let parsed = parse_json(r#"[{"url": "https://www.youtube.com/watch?v=1"}, 5]"#).unwrap();
+        assert_eq!(parsed.entries.len(), 0);
+        assert_eq!(parsed.failed, 2);
+
+        assert!(parse_json("not json").is_err());
+    }
+}
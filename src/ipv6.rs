@@ -0,0 +1,106 @@
+use std::fmt::{self, Display};
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+/// Errors that can occur when parsing an [`Ipv6Block`].
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Ipv6BlockError {
+    #[error("Invalid IPv6 block, expected an address and prefix like 2001:db8::/48: {0}")]
+    Invalid(String),
+    #[error("IPv6 prefix must be between 1 and 128: {0}")]
+    Prefix(u8),
+}
+
+/// A block of IPv6 addresses, like a /48 routed to the host, to pick source
+/// addresses from so requests don't all share an IP and its throttling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ipv6Block {
+    network: Ipv6Addr,
+    prefix: u8,
+}
+
+/// Implement [`Ipv6Block`].
+impl Ipv6Block {
+    /// Mask of the network part of an address.
+    fn mask(&self) -> u128 {
+        u128::MAX << (128 - u32::from(self.prefix))
+    }
+
+    /// Get a random address in the block.
+    #[must_use]
+    pub fn random_address(&self) -> Ipv6Addr {
+        let network = u128::from(self.network);
+        let host = rand::random::<u128>() & !self.mask();
+        Ipv6Addr::from(network | host)
+    }
+
+    /// Check if an address is in the block.
+    #[must_use]
+    pub fn contains(&self, address: Ipv6Addr) -> bool {
+        u128::from(address) & self.mask() == u128::from(self.network)
+    }
+}
+
+/// Implement [`FromStr`] for [`Ipv6Block`], from CIDR notation. Host bits of
+/// the address are cleared.
+impl FromStr for Ipv6Block {
+    type Err = Ipv6BlockError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Ipv6BlockError::Invalid(s.to_string());
+        let (address, prefix) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let address: Ipv6Addr = address.parse().map_err(|_| invalid())?;
+        let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+        if prefix == 0 || prefix > 128 {
+            return Err(Ipv6BlockError::Prefix(prefix));
+        }
+        let mut block = Ipv6Block {
+            network: address,
+            prefix,
+        };
+        block.network = Ipv6Addr::from(u128::from(address) & block.mask());
+        Ok(block)
+    }
+}
+
+/// Implement [`Display`] for [`Ipv6Block`], in CIDR notation.
+impl Display for Ipv6Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ipv6_block() {
+        let block: Ipv6Block = "2001:db8:1234:5678::1/48".parse().unwrap();
+        assert_eq!(block.to_string(), "2001:db8:1234::/48");
+        assert_eq!(
+            "2001:db8::".parse::<Ipv6Block>(),
+            Err(Ipv6BlockError::Invalid("2001:db8::".to_string()))
+        );
+        assert_eq!(
+            "2001:db8::/0".parse::<Ipv6Block>(),
+            Err(Ipv6BlockError::Prefix(0))
+        );
+        assert!("10.0.0.0/8".parse::<Ipv6Block>().is_err());
+    }
+
+    #[test]
+    fn test_random_address() {
+        let block: Ipv6Block = "2001:db8:1234::/48".parse().unwrap();
+        let addresses: Vec<Ipv6Addr> = (0..16).map(|_| block.random_address()).collect();
+        assert!(addresses.iter().all(|address| block.contains(*address)));
+        assert!(addresses.iter().any(|address| *address != addresses[0]));
+        assert!(!block.contains("2001:db8:1235::1".parse().unwrap()));
+
+        let single: Ipv6Block = "2001:db8::1/128".parse().unwrap();
+        assert_eq!(
+            single.random_address(),
+            "2001:db8::1".parse::<Ipv6Addr>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use crate::CatalogTrack;
+use crack_types::Error;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Base URL of the Last.fm API.
+const LASTFM_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+
+/// A response of the Last.fm API, which reports errors in the body.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LastFmResponse<T> {
+    Error { message: String },
+    Ok(T),
+}
+
+#[derive(Debug, Deserialize)]
+struct LastFmArtist {
+    name: String,
+}
+
+/// A track listed by Last.fm, its duration is in seconds and often missing.
+#[derive(Debug, Deserialize)]
+struct LastFmTrack {
+    name: String,
+    artist: LastFmArtist,
+    #[serde(default)]
+    duration: Option<u64>,
+}
+
+/// Implement [`LastFmTrack`].
+impl LastFmTrack {
+    /// Convert to a [`CatalogTrack`].
+    fn into_track(self) -> CatalogTrack {
+        CatalogTrack::new(
+            self.name,
+            vec![self.artist.name],
+            None,
+            Duration::from_secs(self.duration.unwrap_or_default()),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SimilarTracksResponse {
+    similartracks: TrackList,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopTracksResponse {
+    toptracks: TrackList,
+}
+
+#[derive(Debug, Deserialize)]
+struct TrackList {
+    #[serde(default)]
+    track: Vec<LastFmTrack>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimilarArtistsResponse {
+    similarartists: ArtistList,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistList {
+    #[serde(default)]
+    artist: Vec<LastFmArtist>,
+}
+
+/// Client for the Last.fm API, for recommendations when YouTube's related
+/// videos are poor. Needs an API key.
+#[derive(Clone, Debug)]
+pub struct LastFmClient {
+    client: reqwest::Client,
+    api_key: String,
+}
+
+/// Implement [`LastFmClient`].
+impl LastFmClient {
+    /// Create a new [`LastFmClient`].
+    #[must_use]
+    pub fn new(client: reqwest::Client, api_key: impl Into<String>) -> Self {
+        LastFmClient {
+            client,
+            api_key: api_key.into(),
+        }
+    }
+
+    /// Create a new [`LastFmClient`] with the API key in `LASTFM_API_KEY`,
+    /// `None` if it isn't set.
+    #[must_use]
+    pub fn from_env(client: reqwest::Client) -> Option<Self> {
+        let api_key = std::env::var("LASTFM_API_KEY").ok()?;
+        Some(LastFmClient::new(client, api_key))
+    }
+
+    /// Get up to `limit` tracks similar to one, to search for on YouTube.
+    /// If Last.fm knows no similar tracks, the top track of each artist
+    /// similar to its artist is used instead.
+    /// # Errors
+    /// Returns an error if a request fails.
+    pub async fn similar_tracks(
+        &self,
+        artist: &str,
+        title: &str,
+        limit: usize,
+    ) -> Result<Vec<CatalogTrack>, Error> {
+        let limit_str = limit.to_string();
+        let similar: SimilarTracksResponse = self
+            .get(&[
+                ("method", "track.getsimilar"),
+                ("artist", artist),
+                ("track", title),
+                ("limit", limit_str.as_str()),
+                ("autocorrect", "1"),
+            ])
+            .await?;
+        if !similar.similartracks.track.is_empty() {
+            return Ok(similar
+                .similartracks
+                .track
+                .into_iter()
+                .take(limit)
+                .map(LastFmTrack::into_track)
+                .collect());
+        }
+
+        let artists: SimilarArtistsResponse = self
+            .get(&[
+                ("method", "artist.getsimilar"),
+                ("artist", artist),
+                ("limit", limit_str.as_str()),
+                ("autocorrect", "1"),
+            ])
+            .await?;
+        let mut tracks = Vec::new();
+        for similar_artist in artists.similarartists.artist.into_iter().take(limit) {
+            let top: TopTracksResponse = match self
+                .get(&[
+                    ("method", "artist.gettoptracks"),
+                    ("artist", similar_artist.name.as_str()),
+                    ("limit", "1"),
+                ])
+                .await
+            {
+                Ok(top) => top,
+                Err(_e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to get top tracks of {}: {_e}", similar_artist.name);
+                    continue;
+                }
+            };
+            tracks.extend(
+                top.toptracks
+                    .track
+                    .into_iter()
+                    .next()
+                    .map(LastFmTrack::into_track),
+            );
+        }
+        Ok(tracks)
+    }
+
+    /// Call a method of the API.
+    async fn get<T: DeserializeOwned>(&self, params: &[(&str, &str)]) -> Result<T, Error> {
+        let response: LastFmResponse<T> = self
+            .client
+            .get(LASTFM_API_URL)
+            .query(params)
+            .query(&[("api_key", self.api_key.as_str()), ("format", "json")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match response {
+            LastFmResponse::Ok(value) => Ok(value),
+            LastFmResponse::Error { message } => Err(format!("Last.fm: {message}").into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_similar_tracks() {
+        let json = r#"{"similartracks": {"track": [
+            {"name": "Digital Love", "artist": {"name": "Daft Punk", "mbid": ""}, "duration": 301, "match": 1.0},
+            {"name": "Music Sounds Better With You", "artist": {"name": "Stardust"}, "match": 0.8}
+        ], "@attr": {"artist": "Daft Punk"}}}"#;
+        let response: LastFmResponse<SimilarTracksResponse> = serde_json::from_str(json).unwrap();
+        let LastFmResponse::Ok(response) = response else {
+            panic!("Expected tracks");
+        };
+        let tracks = response
+            .similartracks
+            .track
+            .into_iter()
+            .map(LastFmTrack::into_track)
+            .collect::<Vec<_>>();
+        assert_eq!(tracks[0].search_query(), "Daft Punk - Digital Love");
+        assert_eq!(tracks[0].duration, Duration::from_secs(301));
+        assert_eq!(tracks[1].duration, Duration::ZERO);
+
+        let json = r#"{"error": 6, "message": "Track not found", "links": []}"#;
+        let response: LastFmResponse<SimilarTracksResponse> = serde_json::from_str(json).unwrap();
+        assert!(matches!(response, LastFmResponse::Error { .. }));
+    }
+}
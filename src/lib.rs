@@ -4,6 +4,117 @@ pub mod resolve;
 pub use resolve::*;
 pub mod event_handlers;
 pub use event_handlers::*;
+pub mod persist;
+pub use persist::*;
+pub mod export;
+pub use export::*;
+pub mod import;
+pub use import::*;
+pub mod paginator;
+pub use paginator::*;
+pub mod diff;
+pub use diff::*;
+pub mod catalog;
+pub use catalog::*;
+pub mod spotify;
+pub use spotify::*;
+pub mod apple_music;
+pub use apple_music::*;
+pub mod deezer;
+pub use deezer::*;
+pub mod radio;
+pub use radio::*;
+pub mod chapters;
+pub use chapters::*;
+pub mod local;
+pub use local::*;
+pub mod credentials;
+pub use credentials::*;
+pub mod po_token;
+pub use po_token::*;
+pub mod proxy;
+pub use proxy::*;
+pub mod ipv6;
+pub use ipv6::*;
+pub mod cache;
+pub use cache::*;
+pub mod retry;
+pub use retry::*;
+pub mod fallback;
+pub use fallback::*;
+pub mod search;
+pub use search::*;
+pub mod channel;
+pub use channel::*;
+pub mod canonical;
+pub use canonical::*;
+pub mod mirror;
+pub use mirror::*;
+pub mod autocomplete;
+pub use autocomplete::*;
+pub mod search_provider;
+pub use search_provider::*;
+pub mod lyrics;
+pub use lyrics::*;
+pub mod lrc;
+pub use lrc::*;
+pub mod related;
+pub use related::*;
+pub mod lastfm;
+pub use lastfm::*;
+pub mod opus;
+pub use opus::*;
+pub mod mixcloud;
+pub use mixcloud::*;
+pub mod vimeo;
+pub use vimeo::*;
+pub mod resolve_error;
+pub use resolve_error::*;
+pub mod playback;
+pub use playback::*;
+pub mod now_playing_embed;
+pub use now_playing_embed::*;
+pub mod preload;
+pub use preload::*;
+pub mod gapless;
+pub use gapless::*;
+pub mod dsp;
+pub use dsp::*;
+pub mod loudness;
+pub use loudness::*;
+pub mod effects;
+pub use effects::*;
+pub mod equalizer;
+pub use equalizer::*;
+pub mod bass_boost;
+pub use bass_boost::*;
+pub mod speed;
+pub use speed::*;
+pub mod auto_pan;
+pub use auto_pan::*;
+pub mod silence;
+pub use silence::*;
+
+pub mod audio_cache;
+pub use audio_cache::*;
+
+pub mod watchdog;
+pub use watchdog::*;
+
+pub mod clip;
+pub use clip::*;
+
+pub mod effects_chain;
+pub use effects_chain::*;
+
+pub mod tts;
+pub use tts::*;
+
+pub mod recorder;
+pub use recorder::*;
+
+pub mod ambience;
+pub use ambience::*;
 
 #[cfg(test)]
 pub mod test;
@@ -15,7 +126,7 @@ pub mod test;
 use crack_types::SpotifyTrackTrait;
 use crack_types::TrackResolveError;
 use crack_types::{parse_url, video_info_to_aux_metadata};
-use crack_types::{Error, QueryType, SearchResult};
+use crack_types::{AuxMetadata, Error, QueryType};
 //------------------------------------
 // External library imports
 //------------------------------------
@@ -24,12 +135,13 @@ use dashmap::DashMap;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use reqwest::Client as HttpClient;
+use rusty_ytdl::search::YouTube;
 use rusty_ytdl::search::{
     Playlist as RustyYTPlaylist, PlaylistSearchOptions as RustyYTPlaylistSearchOptions,
 };
-use rusty_ytdl::{search, search::YouTube};
-use rusty_ytdl::{RequestOptions, VideoOptions};
-use serenity::all::{AutocompleteChoice, GuildId};
+use rusty_ytdl::{RequestOptions, VideoOptions, VideoQuality, VideoSearchOptions};
+use serenity::all::{AutocompleteChoice, GuildId, UserId};
+use songbird::input::{Compose, YoutubeDl};
 use std::borrow::Cow;
 use std::sync::atomic::AtomicUsize;
 use std::sync::LazyLock;
@@ -38,19 +150,37 @@ use tracing::{debug, error, instrument};
 //------------------------------------
 // Standard library imports
 //------------------------------------
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::fmt::{self, Display};
 use std::future::Future;
+use std::net::IpAddr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 //------------------------------------
 // Constants
 //------------------------------------
 pub const CREATING: &str = "Creating";
+pub const DEFAULT_HISTORY_SIZE: usize = 50;
+pub const DEFAULT_MAX_QUEUE_LENGTH: usize = 1000;
+pub const DEFAULT_PAGE_SIZE: usize = 10;
 pub const DEFAULT_PLAYLIST_LIMIT: u64 = 50;
+pub const DEFAULT_PLAYLIST_CAP: usize = 1000;
+pub const PLAYLIST_PAGE_SIZE: u64 = 100;
+pub const DEFAULT_UNDO_SIZE: usize = 10;
+pub const DEFAULT_VOLUME: u8 = 100;
+pub const DISCORD_MESSAGE_LIMIT: usize = 2000;
 pub const EMPTY_QUEUE: &str = "Queue is empty or display not built.";
+pub const LIVE_DURATION: &str = "LIVE";
+pub const MAX_TRACK_VOLUME: u8 = 200;
+pub const MAX_VOLUME: u8 = 200;
 pub const NEW_FAILED: &str = "New failed";
+pub const QUEUE_EVENT_CAPACITY: usize = 64;
+pub const QUEUE_PAGINATOR_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+pub const RADIO_RECONNECT_ATTEMPTS: usize = 3;
 pub const REQ_CLIENT_STR: &str = "Reqwest client";
 pub const UNKNOWN_TITLE: &str = "Unknown title";
 pub const UNKNOWN_URL: &str = "";
@@ -92,11 +222,18 @@ static CRACK_TRACK_CLIENT: LazyLock<CrackTrackClient> = LazyLock::new(|| {
 /// Panics if the reqwest client cannot be built.
 #[must_use]
 pub fn build_configured_reqwest_client() -> reqwest::Client {
+    configured_reqwest_builder()
+        .build()
+        .unwrap_or_else(|_| panic!("{NEW_FAILED} {REQ_CLIENT_STR}"))
+}
+
+/// Get a reqwest client builder with the configuration of
+/// [`build_configured_reqwest_client`], to add a proxy or credentials to.
+#[must_use]
+pub fn configured_reqwest_builder() -> reqwest::ClientBuilder {
     reqwest::ClientBuilder::new()
         .use_rustls_tls()
         .cookie_store(true)
-        .build()
-        .unwrap_or_else(|_| panic!("{NEW_FAILED} {REQ_CLIENT_STR}"))
 }
 
 ///
@@ -139,9 +276,40 @@ pub struct DataInner {
     pub songbird: Arc<songbird::Songbird>,
     pub http_client: HttpClient,
     // Map of guild IDs to queues
-    pub guild_queues: dashmap::DashMap<serenity::all::GuildId, CrackTrackQueue>,
+    pub guild_queues: Arc<dashmap::DashMap<serenity::all::GuildId, CrackTrackQueue>>,
+    // Map of guild IDs to the queue to restore when a looping queue runs out
+    pub queue_backups: Arc<dashmap::DashMap<serenity::all::GuildId, VecDeque<ResolvedTrack>>>,
+    // Map of queue display message IDs to their page state
+    pub paginators: Arc<dashmap::DashMap<serenity::all::MessageId, QueuePaginator>>,
     // Map of guild IDs to idle timeout information
     pub idle_timeouts: dashmap::DashMap<serenity::all::GuildId, IdleTimeoutInfo>,
+    // On-disk storage for the guild queues
+    pub queue_store: QueueStore,
+    // Extra yt-dlp arguments for playback, like YouTube credentials
+    pub ytdl_args: Vec<String>,
+    // Map of guild IDs to the handle of the track playing in them
+    pub current_tracks:
+        Arc<dashmap::DashMap<serenity::all::GuildId, songbird::tracks::TrackHandle>>,
+    // Map of guild IDs to the stream of their next track, opened ahead of time
+    pub preloaded: Arc<dashmap::DashMap<serenity::all::GuildId, PreloadedInput>>,
+    // Map of guild IDs to their next track, added paused in gapless mode
+    pub staged: Arc<dashmap::DashMap<serenity::all::GuildId, StagedTrack>>,
+    // Map of guild IDs to the loudness normalization gain of their track
+    pub track_gains: Arc<dashmap::DashMap<serenity::all::GuildId, f32>>,
+    // Normalization gains measured for tracks, by track
+    pub loudness_cache: LruCache<f32>,
+    // Map of guild IDs to their audio effects
+    pub effects: Arc<dashmap::DashMap<serenity::all::GuildId, SharedEffects>>,
+    // Map of guild IDs to the position to resume their stuck track from
+    pub stalled: Arc<dashmap::DashMap<serenity::all::GuildId, Duration>>,
+    // On-disk cache of the audio of tracks played often, if enabled
+    pub audio_cache: Option<AudioCache>,
+    // Text-to-speech backend voicing track announcements, if set up
+    pub tts: Option<Arc<dyn TtsProvider>>,
+    // Map of guild IDs to the recorder of their voice channel, if recording
+    pub recorders: Arc<dashmap::DashMap<serenity::all::GuildId, VoiceRecorder>>,
+    // Client for resolving tracks, which also plays the ambience of guilds
+    pub track_client: CrackTrackClient,
 }
 
 impl std::ops::Deref for Data {
@@ -158,6 +326,17 @@ impl std::ops::DerefMut for Data {
     }
 }
 
+/// Options picking the audio-only format with the highest bitrate, so no
+/// bandwidth is wasted on video.
+#[must_use]
+pub fn audio_only_video_options() -> VideoOptions {
+    VideoOptions {
+        quality: VideoQuality::HighestAudio,
+        filter: VideoSearchOptions::Audio,
+        ..Default::default()
+    }
+}
+
 /// Client for resolving tracks, mostly holds other clients like reqwest and `rusty_ytdl`.
 #[allow(dead_code)]
 #[derive(Clone, Debug)]
@@ -166,6 +345,35 @@ pub struct CrackTrackClient {
     yt_client: rusty_ytdl::search::YouTube,
     video_opts: VideoOptions,
     q: Arc<DashMap<GuildId, CrackTrackQueue>>,
+    queue_backups: Arc<DashMap<GuildId, VecDeque<ResolvedTrack>>>,
+    saved_queues: Arc<DashMap<GuildId, BTreeMap<String, VecDeque<ResolvedTrack>>>>,
+    max_queue_len: usize,
+    max_user_tracks: usize,
+    spotify: Option<SpotifyClient>,
+    local_root: Option<PathBuf>,
+    split_chapters: bool,
+    credentials: Option<YoutubeCredentials>,
+    proxies: Vec<String>,
+    youtube_routes: YoutubeRoutes,
+    ipv6_block: Option<Ipv6Block>,
+    metadata_cache: MetadataCache,
+    retry_policy: RetryPolicy,
+    resolver_chain: Vec<ResolveBackend>,
+    backend_telemetry: BackendTelemetry,
+    playlist_cap: usize,
+    po_token: Option<PoToken>,
+    po_token_provider: Option<Arc<dyn PoTokenProvider>>,
+    mirrors: Vec<MirrorInstance>,
+    suggestion_cache: SuggestionCache,
+    suggestion_debouncer: SuggestionDebouncer,
+    suggestion_min_len: usize,
+    search_provider: Option<Arc<dyn SearchProvider>>,
+    lyrics_cache: LyricsCache,
+    genius_token: Option<String>,
+    lastfm: Option<LastFmClient>,
+    opus_passthrough: bool,
+    lazy_resolve: bool,
+    ambience: Arc<DashMap<GuildId, Ambience>>,
 }
 
 /// Implement [`Default`] for [`CrackTrackClient`].
@@ -179,13 +387,42 @@ impl Default for CrackTrackClient {
         };
         let video_opts = VideoOptions {
             request_options: req_options.clone(),
-            ..Default::default()
+            ..audio_only_video_options()
         };
         CrackTrackClient {
             req_client,
             yt_client,
             video_opts,
             q: Arc::new(DashMap::new()),
+            queue_backups: Arc::new(DashMap::new()),
+            saved_queues: Arc::new(DashMap::new()),
+            max_queue_len: DEFAULT_MAX_QUEUE_LENGTH,
+            max_user_tracks: 0,
+            spotify: None,
+            local_root: None,
+            split_chapters: false,
+            credentials: None,
+            proxies: Vec::new(),
+            youtube_routes: YoutubeRoutes::default(),
+            ipv6_block: None,
+            metadata_cache: MetadataCache::default(),
+            retry_policy: RetryPolicy::default(),
+            resolver_chain: DEFAULT_RESOLVER_CHAIN.to_vec(),
+            backend_telemetry: BackendTelemetry::default(),
+            playlist_cap: DEFAULT_PLAYLIST_CAP,
+            mirrors: Vec::new(),
+            suggestion_cache: SuggestionCache::default(),
+            suggestion_debouncer: SuggestionDebouncer::default(),
+            suggestion_min_len: DEFAULT_SUGGESTION_MIN_LEN,
+            search_provider: None,
+            lyrics_cache: LyricsCache::default(),
+            genius_token: None,
+            lastfm: None,
+            opus_passthrough: false,
+            lazy_resolve: false,
+            po_token: None,
+            po_token_provider: None,
+            ambience: Arc::new(DashMap::new()),
         }
     }
 }
@@ -215,13 +452,42 @@ impl<'a> CrackTrackClient {
         };
         let video_opts = VideoOptions {
             request_options: req_options.clone(),
-            ..Default::default()
+            ..audio_only_video_options()
         };
         CrackTrackClient {
             req_client,
             yt_client,
             video_opts,
             q: Arc::new(DashMap::new()),
+            queue_backups: Arc::new(DashMap::new()),
+            saved_queues: Arc::new(DashMap::new()),
+            max_queue_len: DEFAULT_MAX_QUEUE_LENGTH,
+            max_user_tracks: 0,
+            spotify: None,
+            local_root: None,
+            split_chapters: false,
+            credentials: None,
+            proxies: Vec::new(),
+            youtube_routes: YoutubeRoutes::default(),
+            ipv6_block: None,
+            metadata_cache: MetadataCache::default(),
+            retry_policy: RetryPolicy::default(),
+            resolver_chain: DEFAULT_RESOLVER_CHAIN.to_vec(),
+            backend_telemetry: BackendTelemetry::default(),
+            playlist_cap: DEFAULT_PLAYLIST_CAP,
+            mirrors: Vec::new(),
+            suggestion_cache: SuggestionCache::default(),
+            suggestion_debouncer: SuggestionDebouncer::default(),
+            suggestion_min_len: DEFAULT_SUGGESTION_MIN_LEN,
+            search_provider: None,
+            lyrics_cache: LyricsCache::default(),
+            genius_token: None,
+            lastfm: None,
+            opus_passthrough: false,
+            lazy_resolve: false,
+            po_token: None,
+            po_token_provider: None,
+            ambience: Arc::new(DashMap::new()),
         }
     }
 
@@ -237,7 +503,7 @@ impl<'a> CrackTrackClient {
         };
         let video_opts = VideoOptions {
             request_options: opts.clone(),
-            ..Default::default()
+            ..audio_only_video_options()
         };
         let yt_client = rusty_ytdl::search::YouTube::new_with_options(&opts).expect(NEW_FAILED);
 
@@ -246,44 +512,570 @@ impl<'a> CrackTrackClient {
             yt_client,
             video_opts,
             q: Arc::new(DashMap::new()),
+            queue_backups: Arc::new(DashMap::new()),
+            saved_queues: Arc::new(DashMap::new()),
+            max_queue_len: DEFAULT_MAX_QUEUE_LENGTH,
+            max_user_tracks: 0,
+            spotify: None,
+            local_root: None,
+            split_chapters: false,
+            credentials: None,
+            proxies: Vec::new(),
+            youtube_routes: YoutubeRoutes::default(),
+            ipv6_block: None,
+            metadata_cache: MetadataCache::default(),
+            retry_policy: RetryPolicy::default(),
+            resolver_chain: DEFAULT_RESOLVER_CHAIN.to_vec(),
+            backend_telemetry: BackendTelemetry::default(),
+            playlist_cap: DEFAULT_PLAYLIST_CAP,
+            mirrors: Vec::new(),
+            suggestion_cache: SuggestionCache::default(),
+            suggestion_debouncer: SuggestionDebouncer::default(),
+            suggestion_min_len: DEFAULT_SUGGESTION_MIN_LEN,
+            search_provider: None,
+            lyrics_cache: LyricsCache::default(),
+            genius_token: None,
+            lastfm: None,
+            opus_passthrough: false,
+            lazy_resolve: false,
+            po_token: None,
+            po_token_provider: None,
+            ambience: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Use the given [`SpotifyClient`] to resolve Spotify URLs.
+    #[must_use]
+    pub fn with_spotify(mut self, spotify: SpotifyClient) -> Self {
+        self.spotify = Some(spotify);
+        self
+    }
+
+    /// Resolve videos with chapters, like full album uploads, into one track
+    /// per chapter.
+    #[must_use]
+    pub fn with_split_chapters(mut self, split_chapters: bool) -> Self {
+        self.split_chapters = split_chapters;
+        self
+    }
+
+    /// Resolve a video into one track per chapter, each playing only its
+    /// part of the video. A video without chapters gives a single track.
+    /// # Errors
+    /// Returns an error if the video cannot be resolved.
+    pub async fn resolve_chapters(&self, url: &str) -> Result<Vec<ResolvedTrack>, Error> {
+        Ok(self.resolve_url(url).await?.split_chapters())
+    }
+
+    /// Send YouTube requests with the given credentials, so age-restricted
+    /// videos can be resolved. They are kept to clients of their own, so
+    /// they are never sent to other sites.
+    /// # Errors
+    /// Returns an error if the clients for them can't be built.
+    pub fn with_credentials(mut self, credentials: YoutubeCredentials) -> Result<Self, Error> {
+        self.credentials = Some(credentials);
+        self.rebuild_youtube_routes()?;
+        Ok(self)
+    }
+
+    /// Send YouTube searches, playlist fetches and streams through `proxy`.
+    /// # Errors
+    /// Returns an error if the proxy URL is invalid.
+    pub fn with_proxy(self, proxy: impl Into<String>) -> Result<Self, Error> {
+        self.with_proxies(vec![proxy.into()])
+    }
+
+    /// Spread YouTube searches, playlist fetches and streams over a pool of
+    /// proxies, each request going through the next one in turn.
+    /// # Errors
+    /// Returns an error if a proxy URL is invalid.
+    pub fn with_proxies(mut self, proxies: Vec<String>) -> Result<Self, Error> {
+        self.proxies = proxies;
+        self.rebuild_youtube_routes()?;
+        Ok(self)
+    }
+
+    /// Send each YouTube request from a random address of `block`, like
+    /// `2001:db8:1234::/48`, to avoid per-IP throttling. The block has to be
+    /// routed to the host. Takes the place of any proxies.
+    /// # Errors
+    /// Returns an error if the block isn't valid IPv6 CIDR notation.
+    pub fn with_ipv6_block(mut self, block: &str) -> Result<Self, Error> {
+        self.ipv6_block = Some(block.parse()?);
+        Ok(self)
+    }
+
+    /// Get the route for the next request to YouTube. With an IPv6 block a
+    /// new one is built from a random address of it, otherwise the next of
+    /// the routes is used, if any.
+    fn next_route(&self) -> Option<YoutubeRoute> {
+        let Some(block) = self.ipv6_block else {
+            return self.youtube_routes.next_route().cloned();
+        };
+        let address = IpAddr::V6(block.random_address());
+        match YoutubeRoute::from_local_address(address, self.credentials.as_ref()) {
+            Ok(route) => Some(route),
+            Err(_e) => {
+                #[cfg(feature = "crack-tracing")]
+                error!("Failed to build a client for {address}: {_e}");
+                self.youtube_routes.next_route().cloned()
+            }
         }
     }
 
-    /// Resolve a query to a vector of tracks.
+    /// Build the routes to YouTube for the proxies and credentials, none if
+    /// neither is set so requests go out through the shared clients.
+    fn rebuild_youtube_routes(&mut self) -> Result<(), Error> {
+        let proxies: Vec<Option<String>> = if self.proxies.is_empty() {
+            vec![None]
+        } else {
+            self.proxies.iter().cloned().map(Some).collect()
+        };
+        let routes = if self.credentials.is_none() && self.proxies.is_empty() {
+            Vec::new()
+        } else {
+            proxies
+                .into_iter()
+                .map(|proxy| YoutubeRoute::new(proxy, self.credentials.as_ref()))
+                .collect::<Result<Vec<_>, Error>>()?
+        };
+        self.youtube_routes = YoutubeRoutes::new(routes);
+        self.video_opts.request_options = self.base_request_options();
+        Ok(())
+    }
+
+    /// Get the search client for the next search.
+    fn search_client(&self) -> YouTube {
+        self.next_route()
+            .map_or_else(|| self.yt_client.clone(), |route| route.search)
+    }
+
+    /// Search and suggest with `provider` instead of YouTube through
+    /// `rusty_ytdl`.
+    #[must_use]
+    pub fn with_search_provider(mut self, provider: Arc<dyn SearchProvider>) -> Self {
+        self.search_provider = Some(provider);
+        self
+    }
+
+    /// Get the provider for the next search, the one set with
+    /// [`CrackTrackClient::with_search_provider`] or `rusty_ytdl` through the
+    /// next proxy.
+    fn search_provider(&self) -> Arc<dyn SearchProvider> {
+        self.search_provider
+            .clone()
+            .unwrap_or_else(|| Arc::new(RustyYtdlSearch::new(self.search_client())))
+    }
+
+    /// Send a PO token and its visitor data with requests to YouTube.
+    #[must_use]
+    pub fn with_po_token(mut self, po_token: PoToken) -> Self {
+        self.po_token = Some(po_token);
+        self
+    }
+
+    /// Ask `provider` for a fresh PO token before each request to YouTube.
+    /// The token set with [`CrackTrackClient::with_po_token`] is used if it
+    /// fails.
+    #[must_use]
+    pub fn with_po_token_provider(mut self, provider: Arc<dyn PoTokenProvider>) -> Self {
+        self.po_token_provider = Some(provider);
+        self
+    }
+
+    /// Get the PO token to use for the next request, from the provider if
+    /// one is set.
+    async fn current_po_token(&self) -> Option<PoToken> {
+        if let Some(provider) = &self.po_token_provider {
+            match provider.po_token().await {
+                Ok(po_token) => return Some(po_token),
+                Err(_e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    error!("Failed to get a PO token: {_e}");
+                }
+            }
+        }
+        self.po_token.clone()
+    }
+
+    /// Arguments passing the credentials, PO token and the next proxy to
+    /// yt-dlp for playback, see [`track_input_with_args`].
+    pub async fn ytdl_args(&self) -> Vec<String> {
+        let route = self.next_route();
+        self.route_ytdl_args(route.as_ref()).await
+    }
+
+    /// Arguments for yt-dlp to stream through `route`.
+    async fn route_ytdl_args(&self, route: Option<&YoutubeRoute>) -> Vec<String> {
+        let mut args = self
+            .credentials
+            .as_ref()
+            .map(YoutubeCredentials::ytdl_args)
+            .unwrap_or_default();
+        if let Some(po_token) = self.current_po_token().await {
+            args.extend(po_token.ytdl_args());
+        }
+        if let Some(route) = route {
+            args.extend(route.ytdl_args());
+        }
+        args
+    }
+
+    /// Build the songbird input for a track like [`track_input`], streaming
+    /// through the next proxy with the credentials and PO token.
+    pub async fn stream_input(&self, track: &ResolvedTrack) -> songbird::input::Input {
+        let route = self.next_route();
+        let args = self.route_ytdl_args(route.as_ref()).await;
+        let client = route.map_or_else(|| self.req_client.clone(), |route| route.client);
+        track_input_with_args(client, track, args)
+    }
+
+    /// Options for requests to YouTube through `rusty_ytdl`, through the
+    /// next proxy, with the credentials and the PO token set with
+    /// [`CrackTrackClient::with_po_token`], if any.
+    fn base_request_options(&self) -> RequestOptions {
+        let client = self
+            .next_route()
+            .map_or_else(|| self.req_client.clone(), |route| route.client);
+        RequestOptions {
+            client: Some(client),
+            cookies: self
+                .credentials
+                .as_ref()
+                .and_then(YoutubeCredentials::cookie_header),
+            po_token: self.po_token.as_ref().map(|token| token.po_token.clone()),
+            visitor_data: self
+                .po_token
+                .as_ref()
+                .map(|token| token.visitor_data.clone()),
+            ..Default::default()
+        }
+    }
+
+    /// Options for a request to YouTube through `rusty_ytdl`, like
+    /// [`CrackTrackClient::base_request_options`] with a fresh PO token
+    /// from the provider.
+    async fn request_options(&self) -> RequestOptions {
+        let mut options = self.base_request_options();
+        if self.po_token_provider.is_some() {
+            if let Some(po_token) = self.current_po_token().await {
+                options.po_token = Some(po_token.po_token);
+                options.visitor_data = Some(po_token.visitor_data);
+            }
+        }
+        options
+    }
+
+    /// Options for getting a video with `rusty_ytdl`, the format set with
+    /// [`CrackTrackClient::with_video_format`] and the request options of
+    /// [`CrackTrackClient::request_options`].
+    async fn video_options(&self) -> VideoOptions {
+        VideoOptions {
+            request_options: self.request_options().await,
+            ..self.video_opts.clone()
+        }
+    }
+
+    /// Set the format picked when getting a video, instead of the audio-only
+    /// one with the highest bitrate.
+    #[must_use]
+    pub fn with_video_format(mut self, quality: VideoQuality, filter: VideoSearchOptions) -> Self {
+        self.video_opts.quality = quality;
+        self.video_opts.filter = filter;
+        self
+    }
+
+    /// Allow local files inside `root` to be played, see
+    /// [`CrackTrackClient::resolve_local`].
+    #[must_use]
+    pub fn with_local_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.local_root = Some(root.into());
+        self
+    }
+
+    /// Resolve a local audio file, relative to the root set with
+    /// [`CrackTrackClient::with_local_root`] or absolute. Its tags and
+    /// duration are read with symphonia.
+    /// # Errors
+    /// Returns an error if no root is set, the file is outside of it or it
+    /// isn't a supported audio file.
+    pub async fn resolve_local(&self, path: impl AsRef<Path>) -> Result<ResolvedTrack, Error> {
+        let root = self.local_root.as_deref().ok_or(LocalFileError::NoRoot)?;
+        let path = resolve_local_path(root, path.as_ref())?;
+        let track = tokio::task::spawn_blocking(move || local_track(&path)).await??;
+        Ok(track)
+    }
+
+    /// Resolve a Spotify track, album or playlist URL, or the top 10 tracks of
+    /// an artist URL. Spotify only lists the tracks, each one is then searched
+    /// for on YouTube, the ones that can't be found are reported.
+    /// # Errors
+    /// Returns an error if no [`SpotifyClient`] is set, the Spotify request
+    /// fails or none of the tracks can be found on YouTube.
+    pub async fn resolve_spotify(&self, url: &str) -> Result<ResolveMany, Error> {
+        let Some(spotify) = &self.spotify else {
+            return Err("Spotify is not configured".into());
+        };
+        let tracks = spotify.tracks(url).await?;
+        self.resolve_catalog(&tracks).await.partial()
+    }
+
+    /// Resolve an Apple Music song, album or playlist URL. Apple Music only
+    /// lists the tracks, each one is then searched for on YouTube, the ones
+    /// that can't be found are reported.
+    /// # Errors
+    /// Returns an error if the Apple Music request fails or none of the
+    /// tracks can be found on YouTube.
+    pub async fn resolve_apple_music(&self, url: &str) -> Result<ResolveMany, Error> {
+        let tracks = AppleMusicClient::new(self.req_client.clone())
+            .tracks(url)
+            .await?;
+        self.resolve_catalog(&tracks).await.partial()
+    }
+
+    /// Resolve a Deezer track, album or playlist URL. Deezer only lists the
+    /// tracks, each one is then searched for on YouTube, the ones that can't
+    /// be found are reported.
+    /// # Errors
+    /// Returns an error if the Deezer request fails or none of the tracks
+    /// can be found on YouTube.
+    pub async fn resolve_deezer(&self, url: &str) -> Result<ResolveMany, Error> {
+        let tracks = DeezerClient::new(self.req_client.clone())
+            .tracks(url)
+            .await?;
+        self.resolve_catalog(&tracks).await.partial()
+    }
+
+    /// Resolve a Mixcloud show, user or playlist URL, up to
+    /// [`DEFAULT_PLAYLIST_LIMIT`] shows. The metadata comes from Mixcloud,
+    /// the shows are streamed from their pages by yt-dlp.
+    /// # Errors
+    /// Returns an error if the Mixcloud request fails.
+    pub async fn resolve_mixcloud(&self, url: &str) -> Result<Vec<ResolvedTrack>, Error> {
+        MixcloudClient::new(self.req_client.clone())
+            .tracks(url, DEFAULT_PLAYLIST_LIMIT)
+            .await
+    }
+
+    /// Resolve a Vimeo video URL, played from its progressive file or HLS
+    /// manifest.
+    /// # Errors
+    /// Returns an error if the video is private or the Vimeo request fails.
+    pub async fn resolve_vimeo(&self, url: &str) -> Result<ResolvedTrack, Error> {
+        VimeoClient::new(self.req_client.clone()).track(url).await
+    }
+
+    /// Search for the tracks listed by a music catalog on YouTube, see
+    /// [`CrackTrackClient::resolve_catalog_track`]. A track that can't be
+    /// found is reported without stopping the rest.
+    async fn resolve_catalog(&self, tracks: &[CatalogTrack]) -> ResolveMany {
+        let mut result = ResolveMany::default();
+        for track in tracks {
+            match self.resolve_catalog_track(track).await {
+                Ok(resolved) => result.tracks.push(resolved),
+                Err(e) => result
+                    .failures
+                    .push((QueryType::Keywords(track.search_query()), e)),
+            }
+        }
+        result
+    }
+
+    /// Find a catalog track on YouTube. With an ISRC, the results of
+    /// searching for it are checked for the same title and length first.
+    /// Otherwise, or if none match, the best result of searching for its
+    /// artist and title is used. How sure the match is is set on the track.
+    /// # Errors
+    /// Returns an error if the search fails, finds nothing or the video
+    /// cannot be resolved.
+    pub async fn resolve_catalog_track(
+        &self,
+        track: &CatalogTrack,
+    ) -> Result<ResolvedTrack, Error> {
+        if let Some(isrc) = &track.isrc {
+            let candidates = self
+                .search_videos(&format!("\"{isrc}\""), RANKED_SEARCH_LIMIT)
+                .await
+                .unwrap_or_default();
+            let verified = candidates
+                .into_iter()
+                .map(SearchCandidate::from)
+                .find(|candidate| track.match_confidence(candidate) == MatchConfidence::Medium);
+            if let Some(verified) = verified {
+                return Ok(self
+                    .resolve_url(&verified.url)
+                    .await?
+                    .with_match_confidence(MatchConfidence::High));
+            }
+        }
+        let query = track.search_query();
+        let expected = (!track.duration.is_zero()).then_some(track.duration);
+        let (video, best) = self.best_candidate(&query, expected).await?;
+        let confidence = track.match_confidence(&best);
+        Ok(self
+            .resolve_search_video(video)
+            .await?
+            .with_match_confidence(confidence))
+    }
+
+    /// Search for `keywords` and get the best of the results, ranked by
+    /// [`rank_candidates`] with the length of the wanted track if known.
+    async fn best_candidate(
+        &self,
+        keywords: &str,
+        expected: Option<Duration>,
+    ) -> Result<(rusty_ytdl::search::Video, SearchCandidate), Error> {
+        let videos = self.search_videos(keywords, RANKED_SEARCH_LIMIT).await?;
+        let candidates = videos.iter().cloned().map(SearchCandidate::from).collect();
+        rank_candidates(candidates, keywords, expected)
+            .into_iter()
+            .next()
+            .and_then(|best| {
+                let video = videos.into_iter().find(|video| video.url == best.url)?;
+                Some((video, best))
+            })
+            .ok_or_else(|| ResolveError::NoSearchResults(keywords.to_string()).into())
+    }
+
+    /// Resolve a video found by a search, or only build the track from the
+    /// search results with [`CrackTrackClient::with_lazy_resolve`].
+    async fn resolve_search_video(
+        &self,
+        video: rusty_ytdl::search::Video,
+    ) -> Result<ResolvedTrack, Error> {
+        if self.lazy_resolve {
+            Ok(ResolvedTrack::from(video))
+        } else {
+            self.resolve_url(&video.url).await
+        }
+    }
+
+    /// Build tracks found by searches from the search results alone, instead
+    /// of getting the details of each video too. This halves the requests
+    /// to queue a track, the details are got when the track plays, or with
+    /// [`CrackTrackClient::resolve_full`].
+    #[must_use]
+    pub fn with_lazy_resolve(mut self, lazy_resolve: bool) -> Self {
+        self.lazy_resolve = lazy_resolve;
+        self
+    }
+
+    /// Get the details of a track built from search results alone, see
+    /// [`CrackTrackClient::with_lazy_resolve`]. Other tracks are returned as
+    /// they are. The requester, offsets and match confidence are kept.
+    /// # Errors
+    /// Returns an error if the video cannot be resolved.
+    pub async fn resolve_full(&self, track: ResolvedTrack) -> Result<ResolvedTrack, Error> {
+        if !track.is_lazy() {
+            return Ok(track);
+        }
+        let mut resolved = self.resolve_url(&track.get_url()).await?;
+        resolved.user_id = track.user_id;
+        resolved.start_offset = track.start_offset;
+        resolved.end_offset = track.end_offset;
+        resolved.match_confidence = track.match_confidence;
+        Ok(resolved)
+    }
+
+    /// Search for `keywords` and resolve the best of the results, ranked by
+    /// [`rank_candidates`] with the length of the wanted track if known.
+    /// # Errors
+    /// Returns an error if the search fails, finds nothing or the video
+    /// cannot be resolved.
+    pub async fn resolve_keywords_ranked(
+        &self,
+        keywords: &str,
+        expected: Option<Duration>,
+    ) -> Result<ResolvedTrack, Error> {
+        let (video, _best) = self.best_candidate(keywords, expected).await?;
+        #[cfg(feature = "crack-tracing")]
+        tracing::info!("Resolved: {}", _best.url);
+        self.resolve_search_video(video).await
+    }
+
+    /// Resolve an internet radio stream to an endless track named after the
+    /// station, returning `None` if the URL isn't a stream.
+    /// # Errors
+    /// Returns an error if the request fails.
+    pub async fn resolve_radio(&self, url: &str) -> Result<Option<ResolvedTrack>, Error> {
+        let info = probe_radio(&self.req_client, url).await?;
+        Ok(info.map(|info| info.into_track(url)))
+    }
+
+    /// Resolve a query to a vector of tracks. For queries listing many
+    /// tracks, like a Spotify playlist, the ones that fail are reported
+    /// alongside the rest.
     ///
     /// # Errors
     /// Returns an error if:
     /// - The query type is not implemented
-    /// - The track(s) cannot be resolved
+    /// - The track(s) cannot be resolved, or none of many
     /// - The playlist cannot be resolved
-    pub async fn resolve_query_to_tracks(
-        &self,
-        query: QueryType,
-    ) -> Result<Vec<ResolvedTrack>, Error> {
-        match query {
-            QueryType::VideoLink(_) | QueryType::Keywords(_) => {
-                self.resolve_track_many(vec![query]).await
+    /// - A Spotify URL is given without a [`SpotifyClient`] set
+    /// - A local file is outside of the root set with [`CrackTrackClient::with_local_root`]
+    ///
+    /// Errors with a known reason, like a private video, are a [`ResolveError`].
+    pub async fn resolve_query_to_tracks(&self, query: QueryType) -> Result<ResolveMany, Error> {
+        let result = match query {
+            QueryType::VideoLink(ref url) | QueryType::PlaylistLink(ref url)
+                if is_spotify_url(url) =>
+            {
+                self.resolve_spotify(url).await
+            }
+            QueryType::VideoLink(ref url) | QueryType::PlaylistLink(ref url)
+                if is_apple_music_url(url) =>
+            {
+                self.resolve_apple_music(url).await
             }
-            QueryType::PlaylistLink(_) => {
-                self.resolve_playlist(&query.build_query().unwrap_or_default())
+            QueryType::VideoLink(ref url) | QueryType::PlaylistLink(ref url)
+                if is_deezer_url(url) =>
+            {
+                self.resolve_deezer(url).await
+            }
+            QueryType::VideoLink(ref url) | QueryType::PlaylistLink(ref url)
+                if is_mixcloud_url(url) =>
+            {
+                self.resolve_mixcloud(url).await.map(ResolveMany::from)
+            }
+            QueryType::VideoLink(ref url) if is_vimeo_url(url) => {
+                Ok(vec![self.resolve_vimeo(url).await?].into())
+            }
+            QueryType::VideoLink(ref url) | QueryType::PlaylistLink(ref url)
+                if is_channel_url(url) =>
+            {
+                self.resolve_channel(url, DEFAULT_PLAYLIST_LIMIT)
                     .await
+                    .map(ResolveMany::from)
+            }
+            QueryType::VideoLink(ref url) if is_file_url(url) => {
+                let path = file_url_to_path(url)?;
+                Ok(vec![self.resolve_local(path).await?].into())
+            }
+            QueryType::VideoLink(ref url) if !is_youtube_url(url) => {
+                match self.resolve_radio(url).await? {
+                    Some(track) => Ok(vec![track].into()),
+                    None => self.resolve_track_many(vec![query]).await.partial(),
+                }
             }
+            QueryType::VideoLink(ref url) if self.split_chapters => {
+                self.resolve_chapters(url).await.map(ResolveMany::from)
+            }
+            QueryType::VideoLink(_) | QueryType::Keywords(_) => {
+                self.resolve_track_many(vec![query]).await.partial()
+            }
+            QueryType::PlaylistLink(_) => self
+                .resolve_playlist(&query.build_query().unwrap_or_default())
+                .await
+                .map(ResolveMany::from),
             QueryType::KeywordList(keywords_list) => {
                 let queries = keywords_list
                     .iter()
                     .map(|x| QueryType::Keywords(x.clone()))
                     .collect::<Vec<QueryType>>();
-                self.resolve_track_many(queries).await
+                self.resolve_track_many(queries).await.partial()
             }
             QueryType::NewYoutubeDl(boxed_src_metadata) => {
-                let req_options = RequestOptions {
-                    client: Some(self.req_client.clone()),
-                    ..Default::default()
-                };
-                let video_options = VideoOptions {
-                    request_options: req_options.clone(),
-                    ..Default::default()
-                };
+                let video_options = self.video_options().await;
                 let opts = &boxed_src_metadata.1;
                 let video = rusty_ytdl::Video::new_with_options(
                     opts.clone().source_url.unwrap_or_default(),
@@ -294,7 +1086,8 @@ impl<'a> CrackTrackClient {
                 Ok(vec![ResolvedTrack::default()
                     .with_details(info.video_details)
                     .with_metadata(opts.clone())
-                    .with_video(video)])
+                    .with_video(video)]
+                .into())
             }
             QueryType::SpotifyTracks(tracks) => {
                 let queries = tracks
@@ -302,123 +1095,406 @@ impl<'a> CrackTrackClient {
                     .map(|x| QueryType::Keywords(x.build_query()))
                     .collect::<Vec<QueryType>>();
 
-                self.resolve_track_many(queries).await
+                self.resolve_track_many(queries).await.partial()
             }
             _ => {
                 error!("Query type not implemented: {query:?}");
                 Err(TrackResolveError::UnknownQueryType.into())
             }
+        };
+        result.map_err(classify_error)
+    }
+
+    /// Resolve every URL in a message, like several links pasted into one
+    /// play command, see [`extract_urls`]. Each URL is resolved like a query
+    /// on its own, so a playlist or album adds all of its tracks. URLs that
+    /// fail are reported without stopping the rest.
+    pub async fn resolve_all_urls(&self, text: &str) -> ResolveMany {
+        let mut result = ResolveMany::default();
+        for url in extract_urls(text) {
+            let query = url_query_type(&url);
+            match self.resolve_query_to_tracks(query.clone()).await {
+                Ok(resolved) => {
+                    result.tracks.extend(resolved.tracks);
+                    result.failures.extend(resolved.failures);
+                }
+                Err(e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to resolve {url}: {e}");
+                    result.failures.push((query, e));
+                }
+            }
         }
+        result
     }
 
-    /// Resolve many tracks from a `Vec` of queries.
-    /// # Errors
-    /// Returns an error if any track cannot be resolved.
-    /// # TODO: Fix this so it can deal with failures.
-    pub async fn resolve_track_many(
-        &self,
-        queries: Vec<QueryType>,
-    ) -> Result<Vec<ResolvedTrack>, Error> {
-        let mut queue = Vec::new();
+    /// Resolve many tracks from a `Vec` of queries. A query that fails
+    /// doesn't stop the rest, it is reported with its error so the tracks
+    /// that did resolve can still be used.
+    pub async fn resolve_track_many(&self, queries: Vec<QueryType>) -> ResolveMany {
+        let mut result = ResolveMany::default();
         for query in queries {
-            let track = self.resolve_track(query).await?;
-            queue.push(track);
+            match self.resolve_track(query.clone()).await {
+                Ok(track) => result.tracks.push(track),
+                Err(e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to resolve {query:?}: {e}");
+                    result.failures.push((query, e));
+                }
+            }
         }
-        Ok(queue)
+        result
     }
 
     /// Resolve a track from a query. This does not start or ready the track for playback.
+    /// Transient failures are retried, see [`CrackTrackClient::with_retry_policy`].
     /// # Errors
-    /// Returns an error if the track cannot be resolved.
+    /// Returns an error if the track cannot be resolved, a [`ResolveError`]
+    /// if the reason is known.
     #[instrument(skip(self))]
     pub async fn resolve_track(&self, query: QueryType) -> Result<ResolvedTrack, Error> {
-        match query {
+        let result = match query {
             QueryType::VideoLink(ref url) => self.resolve_url(url).await,
-            QueryType::Keywords(ref keywords) => {
-                let search_results = self.yt_client.search_one(keywords, None).await?;
-                let Some(SearchResult::Video(video)) = search_results else {
-                    return Err(TrackResolveError::NotFound.into());
-                };
-                let video_url = video.url.clone();
-                tracing::info!("Resolved: {video_url}");
-                self.resolve_url(&video_url).await
-            }
+            QueryType::Keywords(ref keywords) => self.resolve_keywords_ranked(keywords, None).await,
             _ => {
                 #[cfg(feature = "crack-tracing")]
                 error!("Query type not implemented: {query:?}");
                 Err(TrackResolveError::UnknownQueryType.into())
             }
+        };
+        result.map_err(classify_error)
+    }
+
+    /// Retry failed resolves with the given policy, instead of the default
+    /// of 3 attempts. Only transient failures, like throttling or timeouts,
+    /// are retried.
+    #[must_use]
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Keep the metadata of up to `capacity` videos for `ttl`, instead of
+    /// the defaults. A capacity of 0 disables the cache.
+    #[must_use]
+    pub fn with_metadata_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.metadata_cache = MetadataCache::new(capacity, ttl);
+        self
+    }
+
+    /// Try the backends in the given order when resolving a URL, instead of
+    /// `rusty_ytdl` and then yt-dlp.
+    #[must_use]
+    pub fn with_resolver_chain(mut self, resolver_chain: Vec<ResolveBackend>) -> Self {
+        self.resolver_chain = resolver_chain;
+        self
+    }
+
+    /// Fall back to Invidious or Piped instances, tried in order, when a
+    /// video is blocked in the region of the bot.
+    #[must_use]
+    pub fn with_mirrors(mut self, mirrors: Vec<MirrorInstance>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Resolve a video through the mirrors set with
+    /// [`CrackTrackClient::with_mirrors`], streaming from the first one that
+    /// has it.
+    /// # Errors
+    /// Returns the error of the last mirror if none of them has the video.
+    pub async fn resolve_mirror(&self, video_id: &str) -> Result<ResolvedTrack, Error> {
+        let mut last_error: Error = "No mirrors are configured".into();
+        for mirror in &self.mirrors {
+            match mirror.fetch(&self.req_client, video_id).await {
+                Ok(video) => return Ok(ResolvedTrack::from(video)),
+                Err(e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to resolve {video_id} with {mirror}: {e}");
+                    last_error = e;
+                }
+            }
         }
+        Err(last_error)
     }
 
-    /// Resolve a URL and return a single track.
+    /// Get how often a backend served a track or failed to.
+    #[must_use]
+    pub fn backend_stats(&self, backend: ResolveBackend) -> BackendStats {
+        self.backend_telemetry.get(backend)
+    }
+
+    /// Resolve a URL and return a single track, trying each backend of the
+    /// resolver chain until one succeeds. The metadata of videos is cached
+    /// by video ID, see [`CrackTrackClient::with_metadata_cache`]. YouTube
+    /// URLs are canonicalized first, see [`CanonicalUrl`], and their
+    /// timestamp becomes the start offset of the track. Videos blocked in
+    /// the region of the bot are tried with the mirrors last, see
+    /// [`CrackTrackClient::with_mirrors`].
     async fn resolve_url(&self, url: &str) -> Result<ResolvedTrack, Error> {
-        let request_options = RequestOptions {
-            client: Some(self.req_client.clone()),
-            ..Default::default()
-        };
-        let video_options = VideoOptions {
-            request_options: request_options.clone(),
-            ..Default::default()
-        };
-        let video = rusty_ytdl::Video::new_with_options(url, video_options)?;
-        let info = video.get_info().await?;
-        let metadata = video_info_to_aux_metadata(&info);
+        let canonical = CanonicalUrl::parse(url);
+        let timestamp = canonical.as_ref().and_then(|canonical| canonical.timestamp);
+        let video_id = canonical
+            .as_ref()
+            .and_then(|canonical| canonical.video_id.clone())
+            .or_else(|| extract_video_id(url));
+        let url = canonical
+            .and_then(|canonical| canonical.video_url())
+            .unwrap_or_else(|| url.to_string());
+        let url = url.as_str();
+        if let Some(cached) = video_id
+            .as_deref()
+            .and_then(|id| self.metadata_cache.get(id))
+        {
+            return Ok(ResolvedTrack::default()
+                .with_details(cached.details)
+                .with_metadata(cached.metadata)
+                .with_start_offset(timestamp));
+        }
 
-        Ok(ResolvedTrack::default()
+        let mut last_error: Error = "No resolver backends are configured".into();
+        for &backend in &self.resolver_chain {
+            let result = match backend {
+                ResolveBackend::RustyYtdl => {
+                    self.resolve_url_rusty_ytdl(url, video_id.clone()).await
+                }
+                ResolveBackend::YtDlp => self.resolve_url_ytdlp(url).await,
+                ResolveBackend::Mirror => match video_id.as_deref() {
+                    Some(id) => self.resolve_mirror(id).await,
+                    None => Err(TrackResolveError::NotFound.into()),
+                },
+            };
+            match result {
+                Ok(track) => {
+                    self.backend_telemetry.record_served(backend);
+                    return Ok(track.with_backend(backend).with_start_offset(timestamp));
+                }
+                Err(e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to resolve {url} with {backend}: {e}");
+                    self.backend_telemetry.record_failed(backend);
+                    last_error = e;
+                }
+            }
+        }
+
+        let try_mirrors = !self.mirrors.is_empty()
+            && !self.resolver_chain.contains(&ResolveBackend::Mirror)
+            && is_region_blocked(&*last_error);
+        if let Some(id) = video_id.as_deref().filter(|_| try_mirrors) {
+            match self.resolve_mirror(id).await {
+                Ok(track) => {
+                    self.backend_telemetry.record_served(ResolveBackend::Mirror);
+                    return Ok(track
+                        .with_backend(ResolveBackend::Mirror)
+                        .with_start_offset(timestamp));
+                }
+                Err(_e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to resolve {url} with the mirrors: {_e}");
+                    self.backend_telemetry.record_failed(ResolveBackend::Mirror);
+                }
+            }
+        }
+        Err(classify_error(last_error))
+    }
+
+    /// Resolve a URL with yt-dlp, which only gives its metadata.
+    async fn resolve_url_ytdlp(&self, url: &str) -> Result<ResolvedTrack, Error> {
+        let metadata = self
+            .retry_policy
+            .retry(|| async {
+                let mut ytdl = YoutubeDl::new(self.req_client.clone(), url.to_string())
+                    .user_args(self.ytdl_args().await);
+                Ok(ytdl.aux_metadata().await?)
+            })
+            .await?;
+        Ok(ResolvedTrack::new(QueryType::VideoLink(url.to_string())).with_metadata(metadata))
+    }
+
+    /// Resolve a URL with `rusty_ytdl`, caching the metadata under
+    /// `video_id`.
+    async fn resolve_url_rusty_ytdl(
+        &self,
+        url: &str,
+        video_id: Option<String>,
+    ) -> Result<ResolvedTrack, Error> {
+        let (video, info) = self
+            .retry_policy
+            .retry(|| async {
+                let video_options = self.video_options().await;
+                let video = rusty_ytdl::Video::new_with_options(url, video_options)?;
+                let info = video.get_info().await?;
+                Ok((video, info))
+            })
+            .await?;
+        let mut metadata = video_info_to_aux_metadata(&info);
+
+        // A livestream has no length yet, and is only served over HLS
+        let live = info
+            .video_details
+            .live_broadcast_details
+            .as_ref()
+            .is_some_and(|details| details.is_live_now);
+        let hls_url = info
+            .formats
+            .iter()
+            .filter(|format| live && format.is_hls && format.has_audio)
+            .min_by_key(|format| format.bitrate)
+            .map(|format| format.url.clone());
+        // The details of a video blocked in the region are there, but none
+        // of its streams
+        if !live && info.formats.is_empty() && !self.mirrors.is_empty() {
+            return Err("The video has no streams available in your region".into());
+        }
+        if live {
+            metadata.duration = None;
+        } else if let Some(id) = video_id {
+            // Livestreams aren't cached, they end and their manifest expires
+            self.metadata_cache.insert(
+                id,
+                CachedMetadata {
+                    details: info.video_details.clone(),
+                    metadata: metadata.clone(),
+                },
+            );
+        }
+
+        let opus_url = opus_format(&info.formats)
+            .filter(|_| self.opus_passthrough && !live)
+            .map(|format| format.url.clone());
+
+        let track = ResolvedTrack::default()
             .with_details(info.video_details)
             .with_metadata(metadata)
-            .with_video(video))
+            .with_video(video)
+            .with_live(live);
+        let track = match opus_url {
+            Some(opus_url) => track.with_stream_url(opus_url),
+            None => track,
+        };
+        Ok(match hls_url {
+            Some(hls_url) => track.with_hls_url(hls_url),
+            None => track,
+        })
     }
 
     /// Resolve a search query and return a single track.
     /// # Errors
     /// Returns an error if the search fails or resolve fails.
     pub async fn resolve_search_one(&self, query: &str) -> Result<ResolvedTrack, Error> {
-        let search_results = self.yt_client.search_one(query, None).await?;
-        let Some(SearchResult::Video(video)) = search_results else {
-            return Err(TrackResolveError::NotFound.into());
+        let Some(video) = self.search_provider().search_one(query).await? else {
+            return Err(ResolveError::NoSearchResults(query.to_string()).into());
         };
         let video_url = video.url.clone();
         let query = QueryType::VideoLink(video_url);
         self.resolve_track(query).await
     }
 
+    /// Search for up to `limit` videos, leaving out channels and playlists.
+    async fn search_videos(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<rusty_ytdl::search::Video>, Error> {
+        self.retry_policy
+            .retry(|| async { self.search_provider().search_many(query, limit).await })
+            .await
+    }
+
+    /// Search for up to `n` videos matching `filters`, keeping them paired
+    /// with their candidates.
+    async fn search_matching(
+        &self,
+        query: &str,
+        n: usize,
+        filters: &SearchFilters,
+    ) -> Result<Vec<(rusty_ytdl::search::Video, SearchCandidate)>, Error> {
+        let videos = self.search_videos(query, filters.search_limit(n)).await?;
+        Ok(videos
+            .into_iter()
+            .map(|video| (video.clone(), SearchCandidate::from(video)))
+            .filter(|(_, candidate)| filters.matches(candidate))
+            .take(n)
+            .collect())
+    }
+
+    /// Search for up to `n` videos without resolving them, to let the user
+    /// pick one, see [`CrackTrackClient::resolve_candidate`].
+    /// # Errors
+    /// Returns an error if the search fails.
+    pub async fn resolve_search_detailed(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<SearchCandidate>, Error> {
+        self.search_filtered(query, n, &SearchFilters::default())
+            .await
+    }
+
+    /// Search for up to `n` videos matching `filters`, like live sets over
+    /// an hour, without resolving them. More results are searched for than
+    /// asked, to make up for the ones filtered out.
+    /// # Errors
+    /// Returns an error if the search fails.
+    pub async fn search_filtered(
+        &self,
+        query: &str,
+        n: usize,
+        filters: &SearchFilters,
+    ) -> Result<Vec<SearchCandidate>, Error> {
+        let matching = self.search_matching(query, n, filters).await?;
+        Ok(matching
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .collect())
+    }
+
+    /// Search for up to `n` videos matching `filters` and return them as a
+    /// queue of tracks, see [`CrackTrackClient::search_filtered`].
+    /// # Errors
+    /// Returns an error if the search fails.
+    pub async fn resolve_search_filtered(
+        &self,
+        query: &str,
+        n: usize,
+        filters: &SearchFilters,
+    ) -> Result<Vec<ResolvedTrack>, Error> {
+        let matching = self.search_matching(query, n, filters).await?;
+        Ok(matching
+            .into_iter()
+            .map(|(video, _)| ResolvedTrack::from(video))
+            .collect())
+    }
+
+    /// Resolve a candidate of [`CrackTrackClient::resolve_search_detailed`]
+    /// into a playable track.
+    /// # Errors
+    /// Returns an error if the video cannot be resolved.
+    pub async fn resolve_candidate(
+        &self,
+        candidate: &SearchCandidate,
+    ) -> Result<ResolvedTrack, Error> {
+        self.resolve_track(candidate.query()).await
+    }
+
     /// Resolve a search query and return a queue of tracks.
     /// # Errors
     /// Returns an error if the search fails.
     pub async fn resolve_search(&self, query: &str) -> Result<Vec<ResolvedTrack>, Error> {
-        let search_options = rusty_ytdl::search::SearchOptions {
-            limit: 5,
-            ..Default::default()
-        };
-        let search_results = self.yt_client.search(query, Some(&search_options)).await?;
-        let mut queue = Vec::new();
-        for result in search_results {
-            let SearchResult::Video(video) = result else {
-                continue;
-            };
-            queue.push(video.into());
-        }
-        Ok(queue)
+        let videos = self.search_provider().search_many(query, 5).await?;
+        Ok(videos.into_iter().map(Into::into).collect())
     }
 
     /// Resolve a search query and return a queue of tracks.
     /// # Errors
     /// Returns an error if the search fails.
     pub async fn resolve_search_faster(&self, query: &str) -> Result<Vec<ResolvedTrack>, Error> {
-        let search_options = rusty_ytdl::search::SearchOptions {
-            limit: 5,
-            ..Default::default()
-        };
-        let search_results = self.yt_client.search(query, Some(&search_options)).await?;
+        let videos = self.search_provider().search_many(query, 5).await?;
         let mut queue = Vec::new();
         let mut tasks =
             FuturesUnordered::<Pin<Box<dyn Future<Output = Result<ResolvedTrack, Error>>>>>::new();
-        for result in search_results {
-            let SearchResult::Video(video) = result else {
-                continue;
-            };
+        for video in videos {
             let video_url = video.url.clone();
             let query = QueryType::VideoLink(video_url);
             let track = self.resolve_track(query);
@@ -450,14 +1526,48 @@ impl<'a> CrackTrackClient {
     //     Ok(autocomplete_choices)
     // }
 
+    /// Keep the suggestions of up to `capacity` queries for `ttl`, instead
+    /// of 256 for a minute. A capacity of 0 disables the cache.
+    #[must_use]
+    pub fn with_suggestion_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.suggestion_cache = SuggestionCache::new(capacity, ttl);
+        self
+    }
+
+    /// Wait `delay` for a newer autocomplete request of the same user before
+    /// searching, see [`CrackTrackClient::resolve_suggestion_search_debounced`].
+    #[must_use]
+    pub fn with_suggestion_debounce(mut self, delay: Duration) -> Self {
+        self.suggestion_debouncer = SuggestionDebouncer::new(delay);
+        self
+    }
+
+    /// Only search for suggestions for queries at least `min_len` characters
+    /// long, instead of 3.
+    #[must_use]
+    pub fn with_suggestion_min_len(mut self, min_len: usize) -> Self {
+        self.suggestion_min_len = min_len;
+        self
+    }
+
     /// Get a suggestion autocomplete from a search instead of the suggestion api.
+    /// Queries shorter than the minimum length get no suggestions, and the
+    /// suggestions are cached by normalized query, see
+    /// [`CrackTrackClient::with_suggestion_cache`].
     /// # Errors
     /// Returns an [`Error`] if the search fails.
     pub async fn resolve_suggestion_search(
         &self,
         query: &str,
     ) -> Result<Vec<AutocompleteChoice>, Error> {
-        let tracks = self.resolve_search(query).await?;
+        let query = normalize_query(query);
+        if query.chars().count() < self.suggestion_min_len {
+            return Ok(Vec::new());
+        }
+        if let Some(choices) = self.suggestion_cache.get(&query) {
+            return Ok(choices);
+        }
+        let tracks = self.resolve_search(&query).await?;
         let autocomplete_choices: Vec<AutocompleteChoice> = tracks
             .iter()
             .map(|track| Cow::Owned(track.clone()))
@@ -465,93 +1575,465 @@ impl<'a> CrackTrackClient {
             .into_iter()
             .map(|track| track.clone().autocomplete_option())
             .collect::<Vec<AutocompleteChoice>>();
+        self.suggestion_cache
+            .insert(query, autocomplete_choices.clone());
         Ok(autocomplete_choices)
     }
 
-    /// Resolve a playlist from a URL. Limit is set to 50 by default.
+    /// Get a suggestion autocomplete like
+    /// [`CrackTrackClient::resolve_suggestion_search`] for a user typing, only
+    /// searching once they stop. A request superseded by a newer one of the
+    /// same user gets no suggestions, Discord only shows the newest anyway.
+    /// # Errors
+    /// Returns an [`Error`] if the search fails.
+    pub async fn resolve_suggestion_search_debounced(
+        &self,
+        user: UserId,
+        query: &str,
+    ) -> Result<Vec<AutocompleteChoice>, Error> {
+        if let Some(choices) = self.suggestion_cache.get(&normalize_query(query)) {
+            return Ok(choices);
+        }
+        if !self.suggestion_debouncer.settle(user).await {
+            return Ok(Vec::new());
+        }
+        self.resolve_suggestion_search(query).await
+    }
+
+    /// Resolve a playlist from a URL. Limit is set to 50 by default.
+    /// # Errors
+    /// Returns an [`Error`] if the playlist cannot be resolved.
+    pub async fn resolve_playlist<'b>(&self, url: &'b str) -> Result<Vec<ResolvedTrack>, Error> {
+        self.resolve_playlist_limit(url, DEFAULT_PLAYLIST_LIMIT)
+            .await
+    }
+
+    /// Set the most tracks [`CrackTrackClient::resolve_playlist_all`] fetches
+    /// from one playlist, instead of 1000.
+    #[must_use]
+    pub fn with_playlist_cap(mut self, playlist_cap: usize) -> Self {
+        self.playlist_cap = playlist_cap;
+        self
+    }
+
+    /// Resolve every video of a playlist, page by page, up to the cap set
+    /// with [`CrackTrackClient::with_playlist_cap`]. `progress` is called
+    /// with the number of tracks fetched so far after each page.
+    /// # Errors
+    /// Returns an [`Error`] if the first page cannot be fetched. A later page
+    /// failing ends the playlist early instead.
+    pub async fn resolve_playlist_all(
+        &self,
+        url: &str,
+        mut progress: impl FnMut(usize),
+    ) -> Result<Vec<ResolvedTrack>, Error> {
+        let cap = self.playlist_cap as u64;
+        let search_options = RustyYTPlaylistSearchOptions {
+            limit: PLAYLIST_PAGE_SIZE.min(cap),
+            request_options: Some(self.request_options().await),
+            ..Default::default()
+        };
+        let mut playlist = RustyYTPlaylist::get(url, Some(&search_options)).await?;
+        let mut videos = std::mem::take(&mut playlist.videos);
+        progress(videos.len());
+        while (videos.len() as u64) < cap {
+            let page_limit = PLAYLIST_PAGE_SIZE.min(cap - videos.len() as u64);
+            let page = match playlist.next(Some(page_limit)).await {
+                Ok(page) if !page.is_empty() => page,
+                Ok(_) => break,
+                Err(_e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    error!("Failed to fetch the next page of {url}: {_e}");
+                    break;
+                }
+            };
+            videos.extend(page);
+            progress(videos.len());
+        }
+        videos.truncate(self.playlist_cap);
+
+        Ok(videos
+            .into_iter()
+            .map(|video| {
+                ResolvedTrack::default()
+                    .with_query(QueryType::VideoLink(video.url.clone()))
+                    .with_search_video(video)
+            })
+            .collect())
+    }
+
+    /// Resolve a playlist from a URL. Limit must be given, this is intended to be used primarily by
+    /// a helper method in the [`CrackTrackClient`].
+    /// # Errors
+    /// Returns an [`Error`] if the playlist cannot be resolved.
+    pub async fn resolve_playlist_limit<'b>(
+        &self,
+        url: &'b str,
+        limit: u64,
+    ) -> Result<Vec<ResolvedTrack>, Error> {
+        let search_options = RustyYTPlaylistSearchOptions {
+            limit,
+            request_options: Some(self.request_options().await),
+            ..Default::default()
+        };
+        let search_options = Some(&search_options);
+        let res = RustyYTPlaylist::get(url, search_options).await?;
+
+        let mut queue = Vec::new();
+
+        for video in res.videos {
+            let track = ResolvedTrack::default()
+                .with_query(QueryType::VideoLink(video.url.clone()))
+                .with_search_video(video);
+            #[cfg(feature = "crack-tracing")]
+            debug!("Resolved: {track}");
+            queue.push(track);
+        }
+        Ok(queue)
+    }
+
+    /// Resolve the uploads of a YouTube channel from a channel or handle
+    /// URL, like `youtube.com/@artist`, newest first. At most `limit` videos
+    /// are resolved.
+    /// # Errors
+    /// Returns an [`Error`] if the channel page cannot be fetched, has no
+    /// channel ID, or its uploads cannot be resolved.
+    pub async fn resolve_channel(
+        &self,
+        url: &str,
+        limit: u64,
+    ) -> Result<Vec<ResolvedTrack>, Error> {
+        let channel_id = match parse_channel_url(url) {
+            Some(ChannelUrl::Id(id)) => id,
+            Some(_) => self.fetch_channel_id(url).await?,
+            None => return Err(TrackResolveError::UnknownQueryType.into()),
+        };
+        let uploads = uploads_playlist_url(&channel_id).ok_or(TrackResolveError::NotFound)?;
+        self.resolve_playlist_limit(&uploads, limit).await
+    }
+
+    /// Get the channel ID from the page of a channel.
+    async fn fetch_channel_id(&self, url: &str) -> Result<String, Error> {
+        let client = self
+            .next_route()
+            .map_or_else(|| self.req_client.clone(), |route| route.client);
+        let mut request = client.get(url);
+        if let Some(cookies) = self
+            .credentials
+            .as_ref()
+            .and_then(YoutubeCredentials::cookie_header)
+        {
+            request = request.header(reqwest::header::COOKIE, cookies);
+        }
+        let html = request.send().await?.error_for_status()?.text().await?;
+        parse_channel_page_id(&html).ok_or_else(|| TrackResolveError::NotFound.into())
+    }
+
+    /// Get a suggestion from a query. Passthrough to [`rusty_ytdl::search::YouTube::suggestion`].
+    /// # Errors
+    /// Returns an error if the query fails.
+    pub async fn suggestion(&self, query: &str) -> Result<Vec<String>, Error> {
+        self.search_provider().suggest(query).await
+    }
+
+    /// Also search Genius for lyrics LRCLIB doesn't have, with an API token.
+    #[must_use]
+    pub fn with_genius_token(mut self, genius_token: impl Into<String>) -> Self {
+        self.genius_token = Some(genius_token.into());
+        self
+    }
+
+    /// Get the lyrics of a track from LRCLIB or Genius, see [`LyricsClient`],
+    /// cached by video ID. Returns `None` if no source has them.
+    /// # Errors
+    /// Returns an error if every source fails.
+    pub async fn lyrics(&self, track: &ResolvedTrack) -> Result<Option<Lyrics>, Error> {
+        let key = track.identity();
+        if let Some(lyrics) = self.lyrics_cache.get(&key) {
+            return Ok(lyrics);
+        }
+        let metadata = track.get_metadata().unwrap_or_else(|| AuxMetadata {
+            title: Some(track.get_title()),
+            ..Default::default()
+        });
+        let query = build_query_lyric_aux_metadata(&metadata);
+        let lyrics = LyricsClient::new(self.req_client.clone(), self.genius_token.clone())
+            .search(&query)
+            .await?;
+        self.lyrics_cache.insert(key, lyrics.clone());
+        Ok(lyrics)
+    }
+
+    /// Get the lyrics of a track as plain text, for a `/lyrics` command. See
+    /// [`CrackTrackClient::lyrics`].
+    /// # Errors
+    /// Returns an error if every source fails.
+    pub async fn lyrics_for(&self, track: &ResolvedTrack) -> Result<Option<String>, Error> {
+        Ok(self.lyrics(track).await?.map(|lyrics| lyrics.plain))
+    }
+
+    /// Get the time-synced lyrics of a track, to follow with
+    /// [`synced_lyrics_stream`]. Returns `None` if no source has them.
+    /// # Errors
+    /// Returns an error if every source fails.
+    pub async fn synced_lyrics(
+        &self,
+        track: &ResolvedTrack,
+    ) -> Result<Option<Vec<LyricLine>>, Error> {
+        Ok(self
+            .lyrics(track)
+            .await?
+            .and_then(|lyrics| lyrics.synced_lines()))
+    }
+
+    /// Get up to `n` tracks like a YouTube track, for autoplay or
+    /// recommendations: the videos YouTube lists as related to it, then
+    /// those of the mix it generates from it if there aren't enough. The
+    /// track itself, duplicates and livestreams are left out.
+    /// # Errors
+    /// Returns an error if the track isn't a YouTube video or its related
+    /// videos cannot be fetched.
+    pub async fn related_tracks(
+        &self,
+        track: &ResolvedTrack,
+        n: usize,
+    ) -> Result<Vec<ResolvedTrack>, Error> {
+        let video_id = track.video_id().ok_or(TrackResolveError::NotFound)?;
+        let url = format!("https://www.youtube.com/watch?v={video_id}");
+        let info = self
+            .retry_policy
+            .retry(|| async {
+                let video_options = self.video_options().await;
+                let video = rusty_ytdl::Video::new_with_options(&url, video_options)?;
+                Ok(video.get_info().await?)
+            })
+            .await?;
+        let mut candidates: Vec<ResolvedTrack> = info
+            .related_videos
+            .iter()
+            .map(related_video_track)
+            .collect();
+        if pick_related(&video_id, candidates.clone(), n).len() < n {
+            // Mixes are generated per user and may not be available
+            match self
+                .resolve_playlist_limit(&mix_playlist_url(&video_id), n as u64 + 1)
+                .await
+            {
+                Ok(mix) => candidates.extend(mix),
+                Err(_e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to get the mix of {video_id}: {_e}");
+                }
+            }
+        }
+        Ok(pick_related(&video_id, candidates, n))
+    }
+
+    /// Stream videos served as WebM/Opus straight from YouTube, so songbird
+    /// can pass the audio through without transcoding it, instead of
+    /// through yt-dlp. Tracks resolved from the metadata cache still go
+    /// through yt-dlp, and the stream URLs expire after a few hours.
+    #[must_use]
+    pub fn with_opus_passthrough(mut self, opus_passthrough: bool) -> Self {
+        self.opus_passthrough = opus_passthrough;
+        self
+    }
+
+    /// Get recommendations from Last.fm with an API key, see
+    /// [`CrackTrackClient::similar_tracks`].
+    #[must_use]
+    pub fn with_lastfm(mut self, api_key: impl Into<String>) -> Self {
+        self.lastfm = Some(LastFmClient::new(self.req_client.clone(), api_key));
+        self
+    }
+
+    /// Get up to `n` tracks similar to one from Last.fm, as candidates to
+    /// search for on YouTube, for when YouTube's related videos are poor.
     /// # Errors
-    /// Returns an [`Error`] if the playlist cannot be resolved.
-    pub async fn resolve_playlist<'b>(&self, url: &'b str) -> Result<Vec<ResolvedTrack>, Error> {
-        self.resolve_playlist_limit(url, DEFAULT_PLAYLIST_LIMIT)
-            .await
+    /// Returns an error if Last.fm isn't set up with
+    /// [`CrackTrackClient::with_lastfm`] or the request fails.
+    pub async fn similar_tracks(
+        &self,
+        artist: &str,
+        title: &str,
+        n: usize,
+    ) -> Result<Vec<CatalogTrack>, Error> {
+        let lastfm = self.lastfm.as_ref().ok_or("Last.fm is not configured")?;
+        lastfm.similar_tracks(artist, title, n).await
     }
 
-    /// Resolve a playlist from a URL. Limit must be given, this is intended to be used primarily by
-    /// a helper method in the [`CrackTrackClient`].
+    /// Get up to `n` tracks similar to one from Last.fm, found on YouTube.
+    /// See [`CrackTrackClient::similar_tracks`].
     /// # Errors
-    /// Returns an [`Error`] if the playlist cannot be resolved.
-    pub async fn resolve_playlist_limit<'b>(
+    /// Returns an error if Last.fm fails or none of the tracks can be found
+    /// on YouTube.
+    pub async fn resolve_similar_tracks(
         &self,
-        url: &'b str,
-        limit: u64,
+        artist: &str,
+        title: &str,
+        n: usize,
     ) -> Result<Vec<ResolvedTrack>, Error> {
-        let req_options = RequestOptions {
-            client: Some(self.req_client.clone()),
-            ..Default::default()
-        };
-        let search_options = RustyYTPlaylistSearchOptions {
-            limit,
-            request_options: Some(req_options),
-            ..Default::default()
-        };
-        let search_options = Some(&search_options);
-        let res = RustyYTPlaylist::get(url, search_options).await?;
-
-        let mut queue = Vec::new();
+        let tracks = self.similar_tracks(artist, title, n).await?;
+        self.resolve_catalog(&tracks).await.into_tracks()
+    }
 
-        for video in res.videos {
-            let track = ResolvedTrack::default()
-                .with_query(QueryType::VideoLink(video.url.clone()))
-                .with_search_video(video);
-            #[cfg(feature = "crack-tracing")]
-            debug!("Resolved: {track}");
-            queue.push(track);
-        }
-        Ok(queue)
+    /// Ensures a queue exists for a guild, and returns it.
+    pub fn ensure_queue(&self, guild: GuildId) -> CrackTrackQueue {
+        self.q
+            .entry(guild)
+            .or_insert_with(|| {
+                let q = CrackTrackQueue::new();
+                q.set_max_len(self.max_queue_len);
+                q.set_max_per_user(self.max_user_tracks);
+                q
+            })
+            .clone()
     }
 
-    /// Get a suggestion from a query. Passthrough to [`rusty_ytdl::search::YouTube::suggestion`].
-    /// # Errors
-    /// Returns an error if the query fails.
-    pub async fn suggestion(&self, query: &str) -> Result<Vec<String>, Error> {
-        suggestion_yt(self.yt_client.clone(), query).await
+    /// Drop the queue of a guild, e.g. once the bot has left it, and return it.
+    pub fn remove_queue(&self, guild: GuildId) -> Option<CrackTrackQueue> {
+        self.queue_backups.remove(&guild);
+        self.saved_queues.remove(&guild);
+        self.q.remove(&guild).map(|(_, q)| q)
     }
 
-    /// Ensures a queue exists for a guild, and returns it.
-    pub fn ensure_queue(&self, guild: GuildId) -> CrackTrackQueue {
-        if let Some(q) = self.q.get(&guild) {
-            q.clone()
-        } else {
-            let q: &mut CrackTrackQueue = Box::leak(Box::new(CrackTrackQueue::new()));
-            //let q = *q;
-            self.q.insert(guild, q.clone());
-            q.clone()
-        }
+    /// Drop the queues of every guild for which `is_active` returns `false`,
+    /// e.g. the guilds the bot is no longer in. Returns how many were dropped.
+    pub fn evict_queues<F>(&self, is_active: F) -> usize
+    where
+        F: Fn(GuildId) -> bool,
+    {
+        let before = self.q.len();
+        self.q.retain(|guild, _| is_active(*guild));
+        self.queue_backups.retain(|guild, _| is_active(*guild));
+        self.saved_queues.retain(|guild, _| is_active(*guild));
+        before - self.q.len()
     }
 
     /// Resolve a track from a query and enqueue it.
     /// # Errors
-    /// Can return an [`Error`] if the track cannot be resolved.
+    /// Can return an [`Error`] if the track cannot be resolved or the queue is full.
     pub async fn enqueue_query(
         &mut self,
         guild: GuildId,
         query: QueryType,
     ) -> Result<ResolvedTrack, Error> {
         let track = self.resolve_track(query).await?;
-        let () = self.ensure_queue(guild).push_back(track.clone()).await;
+        self.ensure_queue(guild).enqueue(track.clone()).await?;
         Ok(track)
     }
 
     /// Enqueue a track internally.
-    pub async fn enqueue_track(&mut self, guild: GuildId, track: ResolvedTrack) {
-        self.ensure_queue(guild).push_back(track.clone()).await;
+    /// # Errors
+    /// Returns [`QueueError::QueueFull`] if the queue is at its maximum length,
+    /// or [`QueueError::UserLimitReached`] if the requester is at theirs.
+    pub async fn enqueue_track(
+        &mut self,
+        guild: GuildId,
+        track: ResolvedTrack,
+    ) -> Result<(), QueueError> {
+        self.ensure_queue(guild).enqueue(track).await
     }
 
-    /// Append vec of tracks to the queue.
-    pub async fn append_queue(&mut self, guild: GuildId, tracks: Vec<ResolvedTrack>) {
-        for track in tracks {
-            let () = self.ensure_queue(guild).push_back(track).await;
-        }
+    /// Enqueue a track to play right after the currently playing one.
+    /// Playback always pulls the next track from the front of the [`CrackTrackQueue`],
+    /// so nothing else needs to be reordered.
+    /// # Errors
+    /// Returns [`QueueError::QueueFull`] if the queue is at its maximum length,
+    /// or [`QueueError::UserLimitReached`] if the requester is at theirs.
+    pub async fn enqueue_next(
+        &mut self,
+        guild: GuildId,
+        track: ResolvedTrack,
+    ) -> Result<(), QueueError> {
+        self.ensure_queue(guild).insert_after_playing(track).await
+    }
+
+    /// Append vec of tracks to the queue, shuffling them in if autoshuffle is on.
+    /// With `dedupe`, tracks whose video ID is already queued are skipped.
+    /// Returns how many tracks were added and skipped.
+    /// # Errors
+    /// Returns [`QueueError::QueueFull`] once the queue reaches its maximum length,
+    /// or [`QueueError::UserLimitReached`] once a requester reaches theirs,
+    /// the tracks appended before that are kept.
+    pub async fn append_queue(
+        &mut self,
+        guild: GuildId,
+        tracks: Vec<ResolvedTrack>,
+        dedupe: bool,
+    ) -> Result<AppendOutcome, QueueError> {
+        self.ensure_queue(guild)
+            .enqueue_batch_with(tracks, dedupe)
+            .await
+    }
+
+    /// Resolve two playlists and enqueue their tracks alternating between them,
+    /// `a1, b1, a2, b2, ...`, and return how many tracks were added.
+    /// # Errors
+    /// Returns an [`Error`] if either playlist cannot be resolved, or the queue
+    /// fills up, in which case the tracks added before that are kept.
+    pub async fn enqueue_interleaved(
+        &self,
+        guild: GuildId,
+        first_url: &str,
+        second_url: &str,
+    ) -> Result<usize, Error> {
+        let (first, second) = futures::try_join!(
+            self.resolve_playlist(first_url),
+            self.resolve_playlist(second_url)
+        )?;
+        let outcome = self.ensure_queue(guild).interleave(first, second).await?;
+        Ok(outcome.added)
+    }
+
+    /// Set the window in which played tracks can't be queued again in a guild,
+    /// `None` allows queueing them right away.
+    pub async fn set_recent_window(&self, guild: GuildId, window: Option<RecentWindow>) {
+        self.ensure_queue(guild).set_recent_window(window).await;
+    }
+
+    /// Restart the track playing in a guild: it is put back at the front of
+    /// the guild's queue to be played again from the top, see
+    /// [`CrackTrackQueue::requeue_current`]. Returns the track, `None` if
+    /// nothing is playing.
+    pub async fn restart_current(&self, guild: GuildId) -> Option<ResolvedTrack> {
+        let queue = self.q.get(&guild)?.clone();
+        queue.requeue_current().await
+    }
+
+    /// Toggle autoshuffle for the guild's queue and return the new value.
+    pub fn toggle_autoshuffle(&self, guild: GuildId) -> bool {
+        self.ensure_queue(guild).toggle_autoshuffle()
+    }
+
+    /// Set the maximum queue length used for newly created guild queues, 0 means no limit.
+    pub fn set_default_max_queue_len(&mut self, max_len: usize) {
+        self.max_queue_len = max_len;
+    }
+
+    /// Set the maximum queue length of a guild, 0 means no limit.
+    pub fn set_max_queue_len(&self, guild: GuildId, max_len: usize) {
+        self.ensure_queue(guild).set_max_len(max_len);
+    }
+
+    /// Get the maximum queue length of a guild, 0 means no limit.
+    pub fn get_max_queue_len(&self, guild: GuildId) -> usize {
+        self.ensure_queue(guild).max_len()
+    }
+
+    /// Set the per-user track limit used for newly created guild queues, 0 means no limit.
+    pub fn set_default_max_user_tracks(&mut self, max: usize) {
+        self.max_user_tracks = max;
+    }
+
+    /// Set the maximum number of tracks a user can have queued in a guild, 0 means no limit.
+    pub fn set_max_user_tracks(&self, guild: GuildId, max: usize) {
+        self.ensure_queue(guild).set_max_per_user(max);
+    }
+
+    /// Get the maximum number of tracks a user can have queued in a guild, 0 means no limit.
+    pub fn get_max_user_tracks(&self, guild: GuildId) -> usize {
+        self.ensure_queue(guild).max_per_user()
     }
 
     /// Build the display string for the queue.
@@ -568,10 +2050,259 @@ impl<'a> CrackTrackClient {
         self.ensure_queue(guild).get_display()
     }
 
+    /// Get the display string for the queue, rebuilt if the queue changed.
+    pub async fn display(&self, guild: GuildId) -> String {
+        self.ensure_queue(guild).display().await
+    }
+
+    /// Build the display pages for the queue, with at most `page_size` entries per page.
+    pub async fn build_pages(&self, guild: GuildId, page_size: usize) -> Vec<String> {
+        self.ensure_queue(guild).build_pages(page_size).await
+    }
+
+    /// Get a single display page of the queue, 0-based, using the default page size.
+    pub async fn get_page(&self, guild: GuildId, n: usize) -> Option<String> {
+        self.build_pages(guild, DEFAULT_PAGE_SIZE)
+            .await
+            .get(n)
+            .cloned()
+    }
+
     /// Get the queue.
     pub async fn get_queue(&self, guild: GuildId) -> VecDeque<ResolvedTrack> {
         self.ensure_queue(guild).get_queue().await
     }
+
+    /// Remove a contiguous range of tracks from the queue and return them.
+    pub async fn remove_range(&self, guild: GuildId, range: Range<usize>) -> Vec<ResolvedTrack> {
+        self.ensure_queue(guild).remove_range(range).await
+    }
+
+    /// Jump to the track at `index` in the queue, dropping every track before
+    /// it, and return the new head. With `record_history` the skipped tracks
+    /// are moved to the history.
+    /// # Errors
+    /// Returns a [`QueueError`] if the index is out of bounds.
+    pub async fn jump_to(
+        &self,
+        guild: GuildId,
+        index: usize,
+        record_history: bool,
+    ) -> Result<ResolvedTrack, QueueError> {
+        self.ensure_queue(guild)
+            .jump_to(index, record_history)
+            .await
+    }
+
+    /// Undo the last destructive operation on the queue.
+    /// Returns `false` if there was nothing to undo.
+    pub async fn undo(&self, guild: GuildId) -> bool {
+        self.ensure_queue(guild).undo().await
+    }
+
+    /// Get the history of played tracks, oldest first.
+    pub async fn get_history(&self, guild: GuildId) -> VecDeque<ResolvedTrack> {
+        self.ensure_queue(guild).history().await
+    }
+
+    /// Put the most recently played track back at the front of the queue.
+    pub async fn previous(&self, guild: GuildId) -> Option<ResolvedTrack> {
+        self.ensure_queue(guild).previous().await
+    }
+
+    /// Remove every track requested by the given user and return them.
+    pub async fn remove_by_user(&self, guild: GuildId, user_id: UserId) -> Vec<ResolvedTrack> {
+        self.ensure_queue(guild).remove_by_user(user_id).await
+    }
+
+    /// Remove every queued track whose requester isn't in `present_users`,
+    /// e.g. the members of the voice channel, and return them.
+    pub async fn cleanup_absent_requesters(
+        &self,
+        guild: GuildId,
+        present_users: &HashSet<UserId>,
+    ) -> Vec<ResolvedTrack> {
+        self.ensure_queue(guild)
+            .remove_absent_requesters(present_users)
+            .await
+    }
+
+    /// Total duration of the tracks queued in the guild.
+    pub async fn total_duration(&self, guild: GuildId) -> Duration {
+        self.ensure_queue(guild).total_duration().await
+    }
+
+    /// Estimated time until the track at `index` in the guild's queue starts playing.
+    pub async fn eta_for(&self, guild: GuildId, index: usize) -> Option<Duration> {
+        self.ensure_queue(guild).eta_for(index).await
+    }
+
+    /// Find the tracks in the queue whose title contains the query, with their index.
+    pub async fn search_queue(&self, guild: GuildId, query: &str) -> Vec<(usize, ResolvedTrack)> {
+        self.ensure_queue(guild).search_titles(query).await
+    }
+
+    /// Sort the queue by the given key.
+    pub async fn sort_queue(&self, guild: GuildId, key: SortKey) {
+        self.ensure_queue(guild).sort_by(key).await
+    }
+
+    /// Remove duplicate tracks from the queue, returning how many were dropped.
+    pub async fn dedup_queue(&self, guild: GuildId) -> usize {
+        self.ensure_queue(guild).dedup().await
+    }
+
+    /// Move a track in the queue from one index to another.
+    /// # Errors
+    /// Returns a [`QueueError`] if either index is out of bounds.
+    pub async fn move_track(
+        &self,
+        guild: GuildId,
+        from: usize,
+        to: usize,
+    ) -> Result<(), QueueError> {
+        self.ensure_queue(guild).move_track(from, to).await
+    }
+
+    /// Export the queue of a guild as a file in the given format.
+    pub async fn export_queue(&self, guild: GuildId, format: ExportFormat) -> Vec<u8> {
+        self.ensure_queue(guild).export(format).await.into_bytes()
+    }
+
+    /// Import a queue file and append its tracks to the guild's queue.
+    /// Entries that only have a URL are resolved first, entries that fail to
+    /// parse or resolve are skipped and counted in the report.
+    /// # Errors
+    /// Returns an error if the file cannot be parsed at all, or a [`QueueError`]
+    /// if the queue fills up.
+    pub async fn import_queue(
+        &mut self,
+        guild: GuildId,
+        bytes: &[u8],
+        format: ImportFormat,
+    ) -> Result<ImportReport, Error> {
+        let parsed = parse_import(bytes, format)?;
+        let mut failed = parsed.failed;
+        let mut tracks = Vec::with_capacity(parsed.entries.len());
+        for entry in parsed.entries {
+            match entry {
+                ImportEntry::Track(track) => tracks.push(track),
+                ImportEntry::Url(url) => {
+                    match self.resolve_track(QueryType::VideoLink(url)).await {
+                        Ok(track) => tracks.push(track),
+                        Err(e) => {
+                            tracing::warn!("Failed to resolve imported track: {e}");
+                            failed += 1;
+                        }
+                    }
+                }
+            }
+        }
+        let imported = tracks.len();
+        self.append_queue(guild, tracks, false).await?;
+        Ok(ImportReport { imported, failed })
+    }
+
+    /// Save all the guild queues to the store.
+    /// # Errors
+    /// Returns an error if any queue cannot be saved.
+    pub async fn save_queues(&self, store: &QueueStore) -> Result<(), Error> {
+        store.save_all(&self.q).await
+    }
+
+    /// Restore all the guild queues saved in the store, replacing any existing ones.
+    /// # Errors
+    /// Returns an error if the saved queues cannot be loaded.
+    pub async fn load_queues(&self, store: &QueueStore) -> Result<(), Error> {
+        for (guild, queue) in store.load_all().await? {
+            self.q.insert(guild, queue);
+        }
+        Ok(())
+    }
+
+    /// Get the loop mode of the queue.
+    pub async fn get_loop_mode(&self, guild: GuildId) -> LoopMode {
+        self.ensure_queue(guild).loop_mode().await
+    }
+
+    /// Set the loop mode of the queue. Enabling [`LoopMode::Queue`] backs up
+    /// the queue so it can be restored once it runs out.
+    pub async fn set_loop_mode(&self, guild: GuildId, loop_mode: LoopMode) {
+        let queue = self.ensure_queue(guild);
+        queue.set_loop_mode(loop_mode).await;
+        self.update_queue_backup(guild, &queue).await;
+    }
+
+    /// Toggle the loop mode of the queue to the next mode and return it.
+    pub async fn toggle_loop_mode(&self, guild: GuildId) -> LoopMode {
+        let queue = self.ensure_queue(guild);
+        let loop_mode = queue.cycle_loop_mode().await;
+        self.update_queue_backup(guild, &queue).await;
+        loop_mode
+    }
+
+    /// Back up the queue if it is looping, or drop the backup if it isn't.
+    async fn update_queue_backup(&self, guild: GuildId, queue: &CrackTrackQueue) {
+        if queue.loop_mode().await == LoopMode::Queue {
+            self.queue_backups.insert(guild, queue.backup().await);
+        } else {
+            self.queue_backups.remove(&guild);
+        }
+    }
+
+    /// Restore the backed up queue of a looping guild, if its queue is empty.
+    /// Returns `true` if the queue was restored.
+    pub async fn restore_queue_backup(&self, guild: GuildId) -> bool {
+        let queue = self.ensure_queue(guild);
+        if !queue.is_empty().await {
+            return false;
+        }
+        match self.queue_backups.get(&guild) {
+            Some(backup) if !backup.is_empty() => {
+                queue.append_vec(backup.iter().cloned().collect()).await;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Save a copy of the guild's queue, starting with the playing track,
+    /// under `name`, replacing any queue saved under the same name.
+    /// Returns the number of tracks saved.
+    pub async fn save_queue(&self, guild: GuildId, name: &str) -> usize {
+        let tracks = self.ensure_queue(guild).backup().await;
+        let len = tracks.len();
+        self.saved_queues
+            .entry(guild)
+            .or_default()
+            .insert(name.trim().to_string(), tracks);
+        len
+    }
+
+    /// Replace the guild's queue with the one saved under `name`, the replaced
+    /// queue can be brought back with [`CrackTrackClient::undo`].
+    /// Returns the number of tracks loaded, or `None` if nothing is saved under `name`.
+    pub async fn load_queue(&self, guild: GuildId, name: &str) -> Option<usize> {
+        let tracks = self.saved_queues.get(&guild)?.get(name.trim()).cloned()?;
+        let len = tracks.len();
+        self.ensure_queue(guild).replace(tracks.into(), true).await;
+        Some(len)
+    }
+
+    /// Names of the queues saved for the guild, in alphabetical order.
+    pub fn list_saved(&self, guild: GuildId) -> Vec<String> {
+        self.saved_queues
+            .get(&guild)
+            .map(|saved| saved.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Delete the queue saved under `name`, returning `true` if there was one.
+    pub fn delete_saved(&self, guild: GuildId, name: &str) -> bool {
+        self.saved_queues
+            .get_mut(&guild)
+            .is_some_and(|mut saved| saved.remove(name.trim()).is_some())
+    }
 }
 
 /// Get a suggestion from a query. Use the global static client.
@@ -594,15 +2325,7 @@ pub async fn suggestion(query: &str) -> Result<Vec<String>, Error> {
 /// # Errors
 /// Returns an error if the query fails.
 pub async fn suggestion_yt(client: YouTube, query: &str) -> Result<Vec<String>, Error> {
-    let query = query.replace('"', "");
-    if query.is_empty() {
-        return Ok(Vec::new());
-    }
-    client
-        .suggestion(query, Some(search::LanguageTags::EN))
-        .await
-        .map_err(Into::into)
-        .map(|res| res.into_iter().map(|x| x.replace('"', "")).collect())
+    RustyYtdlSearch::new(client).suggest(query).await
 }
 
 /// Args struct for the CLI.
@@ -629,7 +2352,10 @@ enum Commands {
         ip: String,
     },
     Resolve {
-        /// URL of the video / playlist to resolve.
+        /// URL of the video / playlist to resolve, or of a Spotify track, album
+        /// or playlist with `SPOTIFY_CLIENT_ID` and `SPOTIFY_CLIENT_SECRET` set,
+        /// of an Apple Music or Deezer song, album or playlist, or a `file://`
+        /// URL of an audio file in the working directory.
         #[arg(value_parser = parse_url)]
         url: url::Url,
     },
@@ -639,8 +2365,16 @@ enum Commands {
     },
 }
 
-/// Get the query type from a youtube URL. Video or playlist.
+/// Get the query type from a youtube URL. Video or playlist, with the
+/// canonical URL, see [`CanonicalUrl`]. Videos keep their timestamp.
 fn yt_url_type(url: &url::Url) -> QueryType {
+    if let Some(canonical) = CanonicalUrl::parse(url.as_str()) {
+        return if canonical.is_playlist() {
+            QueryType::PlaylistLink(canonical.url())
+        } else {
+            QueryType::VideoLink(canonical.timestamped_url())
+        };
+    }
     if url.path().contains("playlist")
         || url.query_pairs().any(|(k, _)| k == "list") && url.path().contains("watch")
     {
@@ -650,11 +2384,50 @@ fn yt_url_type(url: &url::Url) -> QueryType {
     }
 }
 
+/// Get the query type of any supported URL. YouTube URLs are a video or a
+/// playlist, see [`yt_url_type`], everything else is a video link and
+/// classified further when resolved.
+#[must_use]
+pub fn url_query_type(url: &str) -> QueryType {
+    match url::Url::parse(url) {
+        Ok(parsed) if is_youtube_url(url) => yt_url_type(&parsed),
+        _ => QueryType::VideoLink(url.to_string()),
+    }
+}
+
+/// Print the queries that failed to resolve and return the tracks that did.
+fn print_failures(resolved: ResolveMany) -> Vec<ResolvedTrack> {
+    for (query, e) in &resolved.failures {
+        println!("Failed to resolve {query:?}: {e}");
+    }
+    resolved.tracks
+}
+
 /// Match the CLI command and run the appropriate function.
 #[cfg_attr(feature = "crack-tracing", instrument())]
 async fn match_cli(cli: Cli) -> Result<String, Error> {
     let guild = GuildId::new(1);
-    let client = Box::leak(Box::new(CrackTrackClient::new()));
+    let mut client = CrackTrackClient::new();
+    if let Some(credentials) = YoutubeCredentials::from_env()? {
+        client = client.with_credentials(credentials)?;
+    }
+    if let Some(po_token) = PoToken::from_env() {
+        client = client.with_po_token(po_token);
+    }
+    if let Ok(proxies) = std::env::var("YOUTUBE_PROXIES") {
+        let proxies = proxies.split(',').map(|proxy| proxy.trim().to_string());
+        client = client.with_proxies(proxies.filter(|proxy| !proxy.is_empty()).collect())?;
+    }
+    if let Ok(block) = std::env::var("YOUTUBE_IPV6_BLOCK") {
+        client = client.with_ipv6_block(&block)?;
+    }
+    if let Ok(mirrors) = std::env::var("YOUTUBE_MIRRORS") {
+        let mirrors = mirrors
+            .split(',')
+            .filter(|mirror| !mirror.trim().is_empty());
+        client = client.with_mirrors(mirrors.map(str::parse).collect::<Result<_, _>>()?);
+    }
+    let client = Box::leak(Box::new(client));
     // let _osint_key = std::env::var("IPQS_API_KEY").map_err(|_| {
     //     tracing::error!("IPQS_API_KEY not found in environment.");
     //     CrackedError::MissingEnvVar("IPQS_API_KEY".to_string())
@@ -673,25 +2446,43 @@ async fn match_cli(cli: Cli) -> Result<String, Error> {
         // },
         Commands::Ipqs { .. } => todo!(),
         Commands::Resolve { url } => {
-            let tracks = match yt_url_type(&url) {
-                QueryType::VideoLink(url) => {
-                    vec![client.resolve_track(QueryType::VideoLink(url)).await?]
-                }
-                QueryType::PlaylistLink(url) => {
-                    let url = url.clone();
-                    client.resolve_playlist(url.as_str()).await?
-                }
-                _ => {
-                    tracing::error!("Unknown URL type: {url}");
-                    Vec::new()
+            let tracks = if url.scheme() == "file" {
+                // Only the working directory is allowed from the CLI
+                let local = client.clone().with_local_root(std::env::current_dir()?);
+                vec![local.resolve_local(file_url_to_path(url.as_str())?).await?]
+            } else if is_apple_music_url(url.as_str()) {
+                print_failures(client.resolve_apple_music(url.as_str()).await?)
+            } else if is_deezer_url(url.as_str()) {
+                print_failures(client.resolve_deezer(url.as_str()).await?)
+            } else if is_spotify_url(url.as_str()) {
+                let spotify = SpotifyClient::from_env().await?;
+                print_failures(
+                    client
+                        .clone()
+                        .with_spotify(spotify)
+                        .resolve_spotify(url.as_str())
+                        .await?,
+                )
+            } else {
+                match yt_url_type(&url) {
+                    QueryType::VideoLink(url) => {
+                        vec![client.resolve_track(QueryType::VideoLink(url)).await?]
+                    }
+                    QueryType::PlaylistLink(url) => {
+                        let url = url.clone();
+                        client.resolve_playlist(url.as_str()).await?
+                    }
+                    _ => {
+                        tracing::error!("Unknown URL type: {url}");
+                        Vec::new()
+                    }
                 }
             };
             for track in &tracks {
                 println!("{track}");
             }
-            let () = client.append_queue(guild, tracks).await;
-            client.build_display(guild).await;
-            let disp = client.get_display(guild);
+            client.append_queue(guild, tracks, false).await?;
+            let disp = client.display(guild).await;
             println!("{disp}");
         }
         Commands::Query { query } => {
@@ -699,7 +2490,7 @@ async fn match_cli(cli: Cli) -> Result<String, Error> {
             for query in queries {
                 let res = client.resolve_search_one(query).await?;
                 println!("Resolved: {res}");
-                let () = client.enqueue_track(guild, res).await;
+                client.enqueue_track(guild, res).await?;
             }
         }
     }
@@ -801,6 +2592,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_video_format() {
+        let client = CrackTrackClient::default();
+        assert!(matches!(
+            client.video_opts.quality,
+            VideoQuality::HighestAudio
+        ));
+        assert!(matches!(
+            client.video_opts.filter,
+            VideoSearchOptions::Audio
+        ));
+
+        let client = client.with_video_format(VideoQuality::Lowest, VideoSearchOptions::VideoAudio);
+        assert!(matches!(client.video_opts.quality, VideoQuality::Lowest));
+        assert!(matches!(
+            client.video_opts.filter,
+            VideoSearchOptions::VideoAudio
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_suggestion_min_len() {
+        // Too short to search for, so this doesn't need the network
+        let client = CrackTrackClient::new().with_suggestion_min_len(3);
+        let choices = client.resolve_suggestion_search("  mo  ").await.unwrap();
+        assert!(choices.is_empty());
+    }
+
     #[tokio::test]
     async fn test_suggestion2() {
         if env::var("CI").is_ok() {
@@ -887,8 +2706,7 @@ mod tests {
         for query in queries {
             if let Ok(track) = client.enqueue_query(guild, query).await {
                 println!("Enqueued: {track}");
-                client.build_display(guild).await;
-                let disp: String = client.get_display(guild);
+                let disp: String = client.display(guild).await;
                 println!("{disp}");
             } else if std::env::var("CI").is_err() {
                 panic!();
@@ -909,8 +2727,9 @@ mod tests {
             "https://www.youtube.com/watch?v=X9ukSm5gmKk",
             "https://www.youtube.com/watch?v=X9ukSm5gmKk&list=PLc1HPXyC5ookjUsyLkdfek0WUIGuGXRcP",
             "https://www.youtube.com/playlist?list=PLc1HPXyC5ookjUsyLkdfek0WUIGuGXRcP",
+            "https://youtube.com/shorts/X9ukSm5gmKk?si=abc123",
         ];
-        let want_playlist = vec![false, true, true];
+        let want_playlist = vec![false, true, true, false];
         let urls = urls
             .iter()
             .map(|x| url::Url::parse(x).expect("Failed to parse URL"))
@@ -924,5 +2743,257 @@ mod tests {
                 _ => panic!(),
             }
         }
+
+        let url = url::Url::parse("https://youtu.be/X9ukSm5gmKk?t=1m30s").unwrap();
+        assert!(matches!(
+            yt_url_type(&url),
+            QueryType::VideoLink(url) if url == "https://www.youtube.com/watch?v=X9ukSm5gmKk&t=90"
+        ));
+    }
+
+    #[test]
+    fn test_extract_urls() {
+        let text = "play <https://www.youtube.com/watch?v=X9ukSm5gmKk>, \
+            https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC and \
+            (https://www.youtube.com/playlist?list=PLc1HPXyC5ookjUsyLkdfek0WUIGuGXRcP). \
+            again https://www.youtube.com/watch?v=X9ukSm5gmKk";
+        let urls = extract_urls(text);
+        assert_eq!(
+            urls,
+            vec![
+                "https://www.youtube.com/watch?v=X9ukSm5gmKk",
+                "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+                "https://www.youtube.com/playlist?list=PLc1HPXyC5ookjUsyLkdfek0WUIGuGXRcP",
+            ]
+        );
+        let types = urls
+            .iter()
+            .map(|url| url_query_type(url))
+            .collect::<Vec<_>>();
+        assert!(matches!(types[0], QueryType::VideoLink(_)));
+        assert!(matches!(types[1], QueryType::VideoLink(_)));
+        assert!(matches!(types[2], QueryType::PlaylistLink(_)));
+        assert!(extract_urls("no links here").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_backup_restore() {
+        let guild = GuildId::new(1);
+        let mut client = CrackTrackClient::new();
+        for id in ["1", "2"] {
+            let track = ResolvedTrack::new(QueryType::VideoLink(format!(
+                "https://www.youtube.com/watch?v={id}"
+            )));
+            client.enqueue_track(guild, track).await.unwrap();
+        }
+
+        // Nothing is restored while the queue isn't looping
+        client.ensure_queue(guild).clear().await;
+        assert!(!client.restore_queue_backup(guild).await);
+        assert!(client.undo(guild).await);
+
+        assert_eq!(client.toggle_loop_mode(guild).await, LoopMode::Track);
+        assert_eq!(client.toggle_loop_mode(guild).await, LoopMode::Queue);
+        let queue = client.ensure_queue(guild);
+        queue.clear().await;
+        assert!(client.restore_queue_backup(guild).await);
+        assert_eq!(queue.len().await, 2);
+        assert!(!client.restore_queue_backup(guild).await);
+
+        // Turning looping off drops the backup
+        assert_eq!(client.toggle_loop_mode(guild).await, LoopMode::Off);
+        queue.clear().await;
+        assert!(!client.restore_queue_backup(guild).await);
+    }
+
+    #[tokio::test]
+    async fn test_saved_queues() {
+        let guild = GuildId::new(1);
+        let mut client = CrackTrackClient::new();
+        for id in ["1", "2"] {
+            let track = ResolvedTrack::new(QueryType::VideoLink(format!(
+                "https://www.youtube.com/watch?v={id}"
+            )));
+            client.enqueue_track(guild, track).await.unwrap();
+        }
+
+        assert_eq!(client.save_queue(guild, "favorites").await, 2);
+        assert_eq!(client.save_queue(guild, " empty").await, 2);
+        assert_eq!(client.list_saved(guild), vec!["empty", "favorites"]);
+        assert!(client.list_saved(GuildId::new(2)).is_empty());
+
+        let queue = client.ensure_queue(guild);
+        queue.clear().await;
+        assert_eq!(client.load_queue(guild, "favorites").await, Some(2));
+        assert_eq!(queue.len().await, 2);
+        assert_eq!(client.load_queue(guild, "missing").await, None);
+
+        assert!(client.delete_saved(guild, "empty"));
+        assert!(!client.delete_saved(guild, "empty"));
+        assert_eq!(client.list_saved(guild), vec!["favorites"]);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_queue_and_evict() {
+        let client = CrackTrackClient::new();
+        let (guild1, guild2) = (GuildId::new(1), GuildId::new(2));
+
+        // The same queue is handed out on every call
+        let queue = client.ensure_queue(guild1);
+        queue.push_back(ResolvedTrack::default()).await;
+        assert_eq!(client.ensure_queue(guild1).len().await, 1);
+        client.ensure_queue(guild2);
+
+        assert_eq!(client.evict_queues(|guild| guild == guild1), 1);
+        assert_eq!(client.q.len(), 1);
+        assert!(client.remove_queue(guild1).is_some());
+        assert!(client.remove_queue(guild1).is_none());
+    }
+
+    /// [`PoTokenProvider`] handing out a fixed token, or failing.
+    #[derive(Debug)]
+    struct FixedPoTokenProvider(Option<PoToken>);
+
+    #[serenity::async_trait]
+    impl PoTokenProvider for FixedPoTokenProvider {
+        async fn po_token(&self) -> Result<PoToken, Error> {
+            self.0.clone().ok_or_else(|| "No token".into())
+        }
+    }
+
+    #[derive(Debug)]
+    struct MockSearchProvider;
+
+    #[serenity::async_trait]
+    impl SearchProvider for MockSearchProvider {
+        async fn search_one(
+            &self,
+            _query: &str,
+        ) -> Result<Option<rusty_ytdl::search::Video>, Error> {
+            Ok(None)
+        }
+
+        async fn search_many(
+            &self,
+            _query: &str,
+            limit: usize,
+        ) -> Result<Vec<rusty_ytdl::search::Video>, Error> {
+            Ok(vec![crack_types::build_mock_search_video(); limit.min(2)])
+        }
+
+        async fn suggest(&self, query: &str) -> Result<Vec<String>, Error> {
+            Ok(vec![format!("{query} remix")])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_provider() {
+        let client = CrackTrackClient::new().with_search_provider(Arc::new(MockSearchProvider));
+        let tracks = client.resolve_search("anything").await.unwrap();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(
+            client.suggestion("song").await.unwrap(),
+            vec!["song remix".to_string()]
+        );
+        assert!(client.resolve_search_one("anything").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lazy_resolve() {
+        let client = CrackTrackClient::new()
+            .with_search_provider(Arc::new(MockSearchProvider))
+            .with_lazy_resolve(true);
+        let track = client
+            .resolve_track(QueryType::Keywords("anything".to_string()))
+            .await
+            .unwrap();
+        let video = crack_types::build_mock_search_video();
+        assert!(track.is_lazy());
+        assert_eq!(track.get_url(), video.url);
+        assert_eq!(track.get_title(), video.title);
+    }
+
+    #[tokio::test]
+    async fn test_po_token_request_options() {
+        let client = CrackTrackClient::new().with_po_token(PoToken::new("static", "visitor"));
+        let options = client.request_options().await;
+        assert_eq!(options.po_token.as_deref(), Some("static"));
+        assert_eq!(options.visitor_data.as_deref(), Some("visitor"));
+
+        // A failing provider falls back to the static token
+        let failing = client
+            .clone()
+            .with_po_token_provider(Arc::new(FixedPoTokenProvider(None)));
+        let options = failing.request_options().await;
+        assert_eq!(options.po_token.as_deref(), Some("static"));
+
+        let provided = client.with_po_token_provider(Arc::new(FixedPoTokenProvider(Some(
+            PoToken::new("fresh", "other"),
+        ))));
+        let options = provided.request_options().await;
+        assert_eq!(options.po_token.as_deref(), Some("fresh"));
+        assert!(provided
+            .ytdl_args()
+            .await
+            .contains(&"youtube:po_token=web.gvs+fresh;visitor_data=other".to_string()));
+    }
+
+    #[test]
+    fn test_youtube_routes() {
+        let client = CrackTrackClient::new();
+        assert!(client.youtube_routes.is_empty());
+        let proxied = client
+            .clone()
+            .with_proxies(vec![
+                "http://127.0.0.1:8080".to_string(),
+                "http://127.0.0.2:8080".to_string(),
+            ])
+            .unwrap();
+        assert_eq!(proxied.youtube_routes.len(), 2);
+        assert!(client.clone().with_proxy("not a proxy").is_err());
+
+        let rotated = client.with_ipv6_block("2001:db8:1234::/48").unwrap();
+        let block: Ipv6Block = "2001:db8:1234::/48".parse().unwrap();
+        let route = rotated.next_route().unwrap();
+        assert!(
+            matches!(route.local_address, Some(IpAddr::V6(address)) if block.contains(address))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_track_many_failures() {
+        let client = CrackTrackClient::new();
+        let result = client
+            .resolve_track_many(vec![QueryType::None, QueryType::None])
+            .await;
+        assert!(!result.is_complete());
+        assert_eq!(result.failures.len(), 2);
+        assert!(result.into_tracks().is_err());
+
+        let result = client.resolve_track_many(Vec::new()).await;
+        assert!(result.is_complete());
+        assert!(result.into_tracks().unwrap().is_empty());
+
+        // Any resolved track is kept despite failures
+        let result = ResolveMany {
+            tracks: vec![ResolvedTrack::default()],
+            failures: vec![(QueryType::None, "Not found".into())],
+        };
+        let result = result.partial().unwrap();
+        assert_eq!(result.failures.len(), 1);
+        assert_eq!(result.into_tracks().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_empty_resolver_chain() {
+        let client = CrackTrackClient::new().with_resolver_chain(Vec::new());
+        let result = client
+            .resolve_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+            .await;
+        assert!(result.is_err());
+        assert_eq!(
+            client.backend_stats(ResolveBackend::RustyYtdl),
+            BackendStats::default()
+        );
     }
 }
@@ -0,0 +1,219 @@
+use crate::{parse_replay_gain, ResolvedTrack};
+use crack_types::{AuxMetadata, QueryType};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::{MediaSourceStream, MediaSourceStreamOptions};
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
+use symphonia::core::probe::Hint;
+use url::Url;
+
+/// Errors that can occur when resolving a local file.
+#[derive(Debug, thiserror::Error)]
+pub enum LocalFileError {
+    #[error("Local playback is disabled, no root directory is configured")]
+    NoRoot,
+    #[error("{} is outside of the allowed directory", .0.display())]
+    OutsideRoot(PathBuf),
+    #[error("{} is not a file", .0.display())]
+    NotAFile(PathBuf),
+    #[error("Not a file URL: {0}")]
+    InvalidUrl(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Unsupported audio file: {0}")]
+    Unsupported(#[from] symphonia::core::errors::Error),
+}
+
+/// Check if a URL points to a local file.
+#[must_use]
+pub fn is_file_url(url: &str) -> bool {
+    url.starts_with("file://")
+}
+
+/// Get the path of a `file://` URL.
+/// # Errors
+/// Returns an error if the URL isn't a valid file URL.
+pub fn file_url_to_path(url: &str) -> Result<PathBuf, LocalFileError> {
+    Url::parse(url)
+        .ok()
+        .filter(|url| url.scheme() == "file")
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| LocalFileError::InvalidUrl(url.to_string()))
+}
+
+/// Resolve a path, relative to `root` or absolute, to a file inside `root`.
+/// Both are canonicalized first, so neither `..` nor symlinks can escape it.
+/// # Errors
+/// Returns an error if the path doesn't exist, isn't a file or is outside `root`.
+pub fn resolve_local_path(root: &Path, path: &Path) -> Result<PathBuf, LocalFileError> {
+    let root = root.canonicalize()?;
+    let path = root.join(path).canonicalize()?;
+    if !path.starts_with(&root) {
+        return Err(LocalFileError::OutsideRoot(path));
+    }
+    if !path.is_file() {
+        return Err(LocalFileError::NotAFile(path));
+    }
+    Ok(path)
+}
+
+/// Read the tags and duration of an audio file with symphonia. The title
+/// falls back to the file name. This blocks, so call it off the runtime.
+/// # Errors
+/// Returns an error if the file can't be opened or isn't a supported format.
+pub fn read_local_metadata(path: &Path) -> Result<AuxMetadata, LocalFileError> {
+    read_local_tags(path).map(|(metadata, _)| metadata)
+}
+
+/// Read the metadata of an audio file like [`read_local_metadata`], along
+/// with its ReplayGain track gain in dB, if it is tagged with one.
+fn read_local_tags(path: &Path) -> Result<(AuxMetadata, Option<f32>), LocalFileError> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    // Tags can be in a container header probed before the format, or in the format itself
+    let mut tags: Vec<Tag> = Vec::new();
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            tags.extend_from_slice(revision.tags());
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend_from_slice(revision.tags());
+    }
+    let tag = |key: StandardTagKey| {
+        tags.iter()
+            .find(|tag| tag.std_key == Some(key))
+            .map(|tag| tag.value.to_string())
+    };
+
+    let params = probed
+        .format
+        .default_track()
+        .map(|track| track.codec_params.clone());
+    let duration = params.as_ref().and_then(|params| {
+        let time = params.time_base?.calc_time(params.n_frames?);
+        Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+    });
+    let file_name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned());
+
+    let replay_gain = tag(StandardTagKey::ReplayGainTrackGain)
+        .as_deref()
+        .and_then(parse_replay_gain);
+
+    let metadata = AuxMetadata {
+        title: tag(StandardTagKey::TrackTitle).or(file_name),
+        artist: tag(StandardTagKey::Artist),
+        album: tag(StandardTagKey::Album),
+        date: tag(StandardTagKey::Date),
+        duration,
+        sample_rate: params.as_ref().and_then(|params| params.sample_rate),
+        channels: params
+            .as_ref()
+            .and_then(|params| params.channels)
+            .and_then(|channels| u8::try_from(channels.count()).ok()),
+        source_url: Url::from_file_path(path).ok().map(String::from),
+        ..Default::default()
+    };
+    Ok((metadata, replay_gain))
+}
+
+/// Build a [`ResolvedTrack`] for a local file, its URL is a `file://` URL.
+/// # Errors
+/// Returns an error if the file can't be read, see [`read_local_metadata`].
+pub fn local_track(path: &Path) -> Result<ResolvedTrack, LocalFileError> {
+    let (metadata, replay_gain) = read_local_tags(path)?;
+    let url = Url::from_file_path(path)
+        .map_err(|()| LocalFileError::InvalidUrl(path.display().to_string()))?;
+    Ok(ResolvedTrack::new(QueryType::VideoLink(url.into()))
+        .with_metadata(metadata)
+        .with_replay_gain(replay_gain))
+}
+
+/// Implement local files for [`ResolvedTrack`].
+impl ResolvedTrack {
+    /// Get the path of the track if it is a local file.
+    pub fn local_path(&self) -> Option<PathBuf> {
+        let url = self.get_url();
+        if is_file_url(&url) {
+            file_url_to_path(&url).ok()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create an empty directory in the temp directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cracktunes-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("Failed to create test dir");
+        dir
+    }
+
+    #[test]
+    fn test_resolve_local_path() {
+        let dir = test_dir("local");
+        let root = dir.join("music");
+        std::fs::create_dir_all(root.join("album")).unwrap();
+        std::fs::write(root.join("album/song.mp3"), b"").unwrap();
+        std::fs::write(dir.join("secret.txt"), b"").unwrap();
+
+        let path = resolve_local_path(&root, Path::new("album/song.mp3")).unwrap();
+        assert!(path.ends_with("album/song.mp3"));
+        let absolute = root.join("album/song.mp3");
+        assert_eq!(resolve_local_path(&root, &absolute).unwrap(), path);
+
+        assert!(matches!(
+            resolve_local_path(&root, Path::new("../secret.txt")),
+            Err(LocalFileError::OutsideRoot(_))
+        ));
+        assert!(matches!(
+            resolve_local_path(&root, &dir.join("secret.txt")),
+            Err(LocalFileError::OutsideRoot(_))
+        ));
+        assert!(matches!(
+            resolve_local_path(&root, Path::new("album")),
+            Err(LocalFileError::NotAFile(_))
+        ));
+        assert!(matches!(
+            resolve_local_path(&root, Path::new("missing.mp3")),
+            Err(LocalFileError::Io(_))
+        ));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_local_path() {
+        let path = std::env::temp_dir().join("song.flac");
+        let url = Url::from_file_path(&path).unwrap().to_string();
+        assert!(is_file_url(&url));
+        let track = ResolvedTrack::new(QueryType::VideoLink(url));
+        assert_eq!(track.local_path(), Some(path));
+
+        let track = ResolvedTrack::new(QueryType::VideoLink(
+            "https://www.youtube.com/watch?v=1".to_string(),
+        ));
+        assert_eq!(track.local_path(), None);
+        assert!(file_url_to_path("https://example.com/song.mp3").is_err());
+    }
+}
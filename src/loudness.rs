@@ -0,0 +1,377 @@
+use crate::{
+    db_to_gain, track_input_with_args, Biquad, BiquadCoefficients, CrackTrackQueue, DataInner,
+    LruCache, ResolvedTrack,
+};
+use crack_types::Error;
+use serenity::all::GuildId;
+use songbird::input::codecs::{get_codec_registry, get_probe};
+use songbird::input::{Input, LiveInput, Parsed};
+use songbird::tracks::TrackHandle;
+use std::f64::consts::PI;
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::errors::Error as SymphoniaError;
+
+/// Loudness tracks are normalized to, in LUFS, the same as most streaming
+/// services.
+pub const TARGET_LOUDNESS: f32 = -14.0;
+/// Loudness ReplayGain 2.0 gains are relative to, in LUFS.
+pub const REPLAY_GAIN_REFERENCE: f32 = -18.0;
+/// Most a quiet track is boosted by, in dB, louder would clip.
+pub const MAX_NORMALIZATION_BOOST: f32 = 6.0;
+/// Most a loud track is cut by, in dB.
+pub const MAX_NORMALIZATION_CUT: f32 = 20.0;
+/// How much of the start of a stream is measured when it has no
+/// ReplayGain.
+pub const LOUDNESS_MEASURE_TIME: Duration = Duration::from_secs(10);
+/// Number of tracks whose measured gain is kept.
+pub const LOUDNESS_CACHE_CAPACITY: usize = 1024;
+/// Time the measured gain of a track is kept.
+pub const LOUDNESS_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Blocks whose loudness is below this are silence, in LUFS.
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks this much quieter than the ungated loudness are ignored, in LU.
+const RELATIVE_GATE: f64 = -10.0;
+/// Gating blocks are 400 ms long and start every 100 ms, so each is made of
+/// this many steps.
+const STEPS_PER_BLOCK: usize = 4;
+
+/// The first stage of the K-weighting of ITU-R BS.1770, a high shelf
+/// modelling the head.
+fn k_weighting_shelf(sample_rate: u32) -> BiquadCoefficients {
+    let (f0, gain, q) = (
+        1681.974_450_955_533,
+        3.999_843_853_973_347,
+        0.707_175_236_955_419_6,
+    );
+    let k = (PI * f0 / f64::from(sample_rate)).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.499_666_774_154_541_6);
+    BiquadCoefficients::new(
+        vh + vb * k / q + k * k,
+        2.0 * (k * k - vh),
+        vh - vb * k / q + k * k,
+        1.0 + k / q + k * k,
+        2.0 * (k * k - 1.0),
+        1.0 - k / q + k * k,
+    )
+}
+
+/// The second stage of the K-weighting of ITU-R BS.1770, a high pass.
+fn k_weighting_high_pass(sample_rate: u32) -> BiquadCoefficients {
+    let (f0, q) = (38.135_470_876_024_44, 0.500_327_037_323_877_3);
+    let k = (PI * f0 / f64::from(sample_rate)).tan();
+    BiquadCoefficients::new(
+        1.0,
+        -2.0,
+        1.0,
+        1.0 + k / q + k * k,
+        2.0 * (k * k - 1.0),
+        1.0 - k / q + k * k,
+    )
+}
+
+/// Measures the integrated loudness of audio as in EBU R128, in LUFS.
+/// All channels are weighted the same, as for stereo.
+#[derive(Debug)]
+pub struct LoudnessMeter {
+    channels: usize,
+    /// K-weighting filters of each channel.
+    filters: Vec<[Biquad; 2]>,
+    frames_per_step: usize,
+    /// Frames in the step being measured.
+    step_frames: usize,
+    /// Energy of the step being measured.
+    step_energy: f64,
+    /// Mean square of the latest steps.
+    steps: Vec<f64>,
+    /// Mean square of every gating block.
+    blocks: Vec<f64>,
+}
+
+/// Implement [`LoudnessMeter`].
+impl LoudnessMeter {
+    /// Create a new [`LoudnessMeter`] for interleaved audio.
+    #[must_use]
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let filters = [
+            Biquad::new(k_weighting_shelf(sample_rate)),
+            Biquad::new(k_weighting_high_pass(sample_rate)),
+        ];
+        LoudnessMeter {
+            channels: channels.max(1),
+            filters: vec![filters; channels.max(1)],
+            frames_per_step: (sample_rate as usize / 10).max(1),
+            step_frames: 0,
+            step_energy: 0.0,
+            steps: Vec::with_capacity(STEPS_PER_BLOCK),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Measure more interleaved samples.
+    pub fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (sample, [shelf, high_pass]) in frame.iter().zip(self.filters.iter_mut()) {
+                let weighted = high_pass.process(shelf.process(f64::from(*sample)));
+                self.step_energy += weighted * weighted;
+            }
+            self.step_frames += 1;
+            if self.step_frames == self.frames_per_step {
+                self.end_step();
+            }
+        }
+    }
+
+    /// Finish a 100 ms step, and the block ending with it.
+    fn end_step(&mut self) {
+        if self.steps.len() == STEPS_PER_BLOCK {
+            self.steps.remove(0);
+        }
+        self.steps
+            .push(self.step_energy / self.frames_per_step as f64);
+        self.step_frames = 0;
+        self.step_energy = 0.0;
+        if self.steps.len() == STEPS_PER_BLOCK {
+            self.blocks
+                .push(self.steps.iter().sum::<f64>() / STEPS_PER_BLOCK as f64);
+        }
+    }
+
+    /// The integrated loudness so far, `None` if there isn't a full block
+    /// of audio louder than silence yet.
+    #[must_use]
+    pub fn loudness(&self) -> Option<f32> {
+        let loudness = |energy: f64| -0.691 + 10.0 * energy.log10();
+        let mean_above = |gate: f64| {
+            let gated: Vec<f64> = self
+                .blocks
+                .iter()
+                .copied()
+                .filter(|energy| loudness(*energy) > gate)
+                .collect();
+            (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+        };
+        let ungated = mean_above(ABSOLUTE_GATE)?;
+        let gate = loudness(ungated) + RELATIVE_GATE;
+        mean_above(gate.max(ABSOLUTE_GATE)).map(|energy| loudness(energy) as f32)
+    }
+}
+
+/// Gain in dB bringing audio of `loudness` LUFS to [`TARGET_LOUDNESS`],
+/// within the limits of boosting and cutting.
+#[must_use]
+pub fn normalization_gain_db(loudness: f32) -> f32 {
+    (TARGET_LOUDNESS - loudness).clamp(-MAX_NORMALIZATION_CUT, MAX_NORMALIZATION_BOOST)
+}
+
+/// Loudness in LUFS of a track with a ReplayGain 2.0 track gain in dB.
+#[must_use]
+pub fn replay_gain_loudness(replay_gain: f32) -> f32 {
+    REPLAY_GAIN_REFERENCE - replay_gain
+}
+
+/// Parse a ReplayGain tag value, like `-6.54 dB`.
+#[must_use]
+pub fn parse_replay_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|gain: &f32| gain.is_finite())
+}
+
+/// Measure the loudness of the first `duration` of an input, in LUFS.
+/// # Errors
+/// Returns an error if the input can't be opened or decoded, or is silent.
+pub async fn measure_loudness(input: Input, duration: Duration) -> Result<f32, Error> {
+    let input = input
+        .make_playable_async(get_codec_registry(), get_probe())
+        .await?;
+    let Input::Live(LiveInput::Parsed(parsed), _) = input else {
+        return Err("The input couldn't be parsed".into());
+    };
+    tokio::task::spawn_blocking(move || measure_parsed(parsed, duration)).await?
+}
+
+/// Decode the first `duration` of a parsed input into a [`LoudnessMeter`].
+/// This blocks, see [`measure_loudness`].
+fn measure_parsed(mut parsed: Parsed, duration: Duration) -> Result<f32, Error> {
+    let mut meter: Option<LoudnessMeter> = None;
+    let mut frames = 0u64;
+    loop {
+        let packet = match parsed.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(_)) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != parsed.track_id {
+            continue;
+        }
+        let decoded = match parsed.decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let spec = *decoded.spec();
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        samples.copy_interleaved_ref(decoded);
+        meter
+            .get_or_insert_with(|| LoudnessMeter::new(spec.rate, spec.channels.count()))
+            .push(samples.samples());
+        frames += (samples.len() / spec.channels.count().max(1)) as u64;
+        if frames >= duration.as_secs() * u64::from(spec.rate) {
+            break;
+        }
+    }
+    meter
+        .and_then(|meter| meter.loudness())
+        .ok_or_else(|| "The audio is silent".into())
+}
+
+/// Get the gain normalizing a track, from its ReplayGain or else by
+/// measuring the start of its stream. Measured gains are cached.
+pub async fn track_normalization_gain(
+    client: reqwest::Client,
+    ytdl_args: Vec<String>,
+    cache: &LruCache<f32>,
+    track: &ResolvedTrack,
+) -> Option<f32> {
+    if track.is_unbounded() || track.is_live() {
+        return None;
+    }
+    if let Some(replay_gain) = track.replay_gain() {
+        return Some(db_to_gain(normalization_gain_db(replay_gain_loudness(
+            replay_gain,
+        ))));
+    }
+    let identity = track.identity();
+    if let Some(gain) = cache.get(&identity) {
+        return Some(gain);
+    }
+    let input = track_input_with_args(client, track, ytdl_args);
+    match measure_loudness(input, LOUDNESS_MEASURE_TIME).await {
+        Ok(loudness) => {
+            let gain = db_to_gain(normalization_gain_db(loudness));
+            cache.insert(identity, gain);
+            Some(gain)
+        }
+        Err(_e) => {
+            #[cfg(feature = "crack-tracing")]
+            tracing::warn!(
+                "Failed to measure the loudness of {}: {_e}",
+                track.get_url()
+            );
+            None
+        }
+    }
+}
+
+/// Implement loudness normalization for [`DataInner`], the gains of the
+/// tracks playing are kept in `track_gains`.
+impl DataInner {
+    /// Get the gain of the track playing in a guild, its own volume with its
+    /// normalization gain, 1 if it has neither.
+    #[must_use]
+    pub fn track_gain(&self, guild_id: GuildId) -> f32 {
+        self.track_gains.get(&guild_id).map_or(1.0, |gain| *gain)
+    }
+
+    /// Get the volume to play the track of a guild at, the volume of its
+    /// queue with the normalization gain of the track.
+    #[must_use]
+    pub fn track_volume(&self, guild_id: GuildId, queue: &CrackTrackQueue) -> f32 {
+        queue.gain() * self.track_gain(guild_id)
+    }
+
+    /// Play the track that just started in a guild at its own volume, and
+    /// normalize its loudness if its queue has normalization on. The volume
+    /// changes once the gain is known, which is right away for preloaded
+    /// tracks.
+    pub fn normalize_track(
+        &self,
+        guild_id: GuildId,
+        queue: &CrackTrackQueue,
+        song: &TrackHandle,
+        track: &ResolvedTrack,
+    ) {
+        let volume = track.gain();
+        self.track_gains.insert(guild_id, volume);
+        if !queue.normalize() {
+            return;
+        }
+        let client = self.http_client.clone();
+        let ytdl_args = self.ytdl_args.clone();
+        let cache = self.loudness_cache.clone();
+        let track_gains = self.track_gains.clone();
+        let current_tracks = self.current_tracks.clone();
+        let queue = queue.clone();
+        let song = song.clone();
+        let track = track.clone();
+        tokio::spawn(async move {
+            let Some(gain) = track_normalization_gain(client, ytdl_args, &cache, &track).await
+            else {
+                return;
+            };
+            // Another track may have started meanwhile
+            if current_tracks
+                .get(&guild_id)
+                .is_some_and(|current| current.uuid() == song.uuid())
+            {
+                track_gains.insert(guild_id, volume * gain);
+                let _ = song.set_volume(queue.gain() * volume * gain);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interleaved stereo sine wave with the same signal in both channels.
+    fn stereo_sine(frequency: f32, amplitude: f32, sample_rate: u32, secs: u32) -> Vec<f32> {
+        (0..sample_rate * secs)
+            .flat_map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                let sample = amplitude * (2.0 * std::f32::consts::PI * frequency * t).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_loudness_meter() {
+        // A 1 kHz sine at -20 dBFS in both channels is -20 LUFS
+        let mut meter = LoudnessMeter::new(48_000, 2);
+        meter.push(&stereo_sine(1000.0, 0.1, 48_000, 3));
+        let loudness = meter.loudness().unwrap();
+        assert!((loudness + 20.0).abs() < 0.2, "{loudness}");
+
+        // Silence is gated out
+        let mut silent = LoudnessMeter::new(44_100, 2);
+        silent.push(&vec![0.0; 44_100 * 2]);
+        assert_eq!(silent.loudness(), None);
+        silent.push(&stereo_sine(1000.0, 0.1, 44_100, 2));
+        // The blocks overlapping the silence are a bit quieter
+        let loudness = silent.loudness().unwrap();
+        assert!((loudness + 20.0).abs() < 0.5, "{loudness}");
+    }
+
+    #[test]
+    fn test_normalization_gain() {
+        assert_eq!(normalization_gain_db(-14.0), 0.0);
+        assert_eq!(normalization_gain_db(-8.0), -6.0);
+        assert_eq!(normalization_gain_db(-30.0), MAX_NORMALIZATION_BOOST);
+        assert_eq!(replay_gain_loudness(-6.5), -11.5);
+        assert_eq!(parse_replay_gain("-6.54 dB"), Some(-6.54));
+        assert_eq!(parse_replay_gain("+1.20 db"), Some(1.2));
+        assert_eq!(parse_replay_gain("loud"), None);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::Lyrics;
+use futures::Stream;
+use regex::Regex;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::time::Instant;
+
+static LRC_TIMESTAMP_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[(\d+):(\d{1,2})(?:[.:](\d{1,3}))?\]").unwrap());
+static LRC_OFFSET_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^\[offset:\s*([+-]?\d+)\s*\]").unwrap());
+
+/// A line of time-synced lyrics, sung `offset` into the track.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LyricLine {
+    pub offset: Duration,
+    pub text: String,
+}
+
+/// Parse lyrics in the LRC format, like `[01:02.50]Line`, into lines sorted
+/// by offset. A line may have several timestamps, and the `[offset:ms]`
+/// tag shifts every line. Other tags like `[ar:Artist]` are ignored.
+#[must_use]
+pub fn parse_lrc(lrc: &str) -> Vec<LyricLine> {
+    // A positive offset makes the lyrics appear sooner
+    let shift_ms: i64 = LRC_OFFSET_REGEX
+        .captures(lrc)
+        .and_then(|caps| caps[1].parse().ok())
+        .unwrap_or_default();
+    let mut lines = Vec::new();
+    for line in lrc.lines() {
+        let mut rest = line.trim();
+        let mut offsets = Vec::new();
+        while let Some(caps) = LRC_TIMESTAMP_REGEX.captures(rest) {
+            let minutes: u64 = caps[1].parse().unwrap_or_default();
+            let seconds: u64 = caps[2].parse().unwrap_or_default();
+            // Hundredths in `.50`, milliseconds in `.500`
+            let fraction = caps.get(3).map_or(0, |fraction| {
+                let digits = fraction.as_str();
+                digits.parse::<u64>().unwrap_or_default() * 10u64.pow(3 - digits.len() as u32)
+            });
+            let millis = (minutes * 60 + seconds) * 1000 + fraction;
+            let shifted = i64::try_from(millis).unwrap_or(i64::MAX) - shift_ms;
+            offsets.push(Duration::from_millis(shifted.max(0).unsigned_abs()));
+            rest = &rest[caps[0].len()..];
+        }
+        let text = rest.trim();
+        lines.extend(offsets.into_iter().map(|offset| LyricLine {
+            offset,
+            text: text.to_string(),
+        }));
+    }
+    lines.sort_by_key(|line| line.offset);
+    lines
+}
+
+/// Implement [`Lyrics`].
+impl Lyrics {
+    /// Get the time-synced lines, if the lyrics have any.
+    #[must_use]
+    pub fn synced_lines(&self) -> Option<Vec<LyricLine>> {
+        let lines = parse_lrc(self.synced.as_deref()?);
+        (!lines.is_empty()).then_some(lines)
+    }
+}
+
+/// Emit each line when it is sung in a track that started playing at
+/// `track_start`, so a handler can edit a lyrics message in time. When
+/// started late, like after a seek, the line being sung is emitted right
+/// away and the ones before it are skipped.
+pub fn synced_lyrics_stream(
+    lines: Vec<LyricLine>,
+    track_start: Instant,
+) -> impl Stream<Item = LyricLine> {
+    let elapsed = track_start.elapsed();
+    let current = lines
+        .iter()
+        .rposition(|line| line.offset <= elapsed)
+        .unwrap_or(0);
+    let lines = lines.into_iter().skip(current);
+    futures::stream::unfold(lines, move |mut lines| async move {
+        let line = lines.next()?;
+        tokio::time::sleep_until(track_start + line.offset).await;
+        Some((line, lines))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[test]
+    fn test_parse_lrc() {
+        let lrc = "[ar:Artist]\n[ti:Song]\n[00:12.50]Second\n[00:01.00][01:00.00]First and chorus\n[00:30.123] Third \nno timestamp";
+        let lines = parse_lrc(lrc);
+        assert_eq!(
+            lines,
+            vec![
+                LyricLine {
+                    offset: Duration::from_secs(1),
+                    text: "First and chorus".to_string()
+                },
+                LyricLine {
+                    offset: Duration::from_millis(12_500),
+                    text: "Second".to_string()
+                },
+                LyricLine {
+                    offset: Duration::from_millis(30_123),
+                    text: "Third".to_string()
+                },
+                LyricLine {
+                    offset: Duration::from_secs(60),
+                    text: "First and chorus".to_string()
+                },
+            ]
+        );
+
+        let shifted = parse_lrc("[offset:+500]\n[00:00.20]Early\n[00:02]Later");
+        assert_eq!(shifted[0].offset, Duration::ZERO);
+        assert_eq!(shifted[1].offset, Duration::from_millis(1500));
+    }
+
+    #[tokio::test]
+    async fn test_synced_lyrics_stream() {
+        let lines = [0, 20, 40]
+            .iter()
+            .map(|ms| LyricLine {
+                offset: Duration::from_millis(*ms),
+                text: ms.to_string(),
+            })
+            .collect::<Vec<_>>();
+        // Started 25ms ago, so the line at 20ms is being sung
+        let track_start = Instant::now() - Duration::from_millis(25);
+        let emitted = synced_lyrics_stream(lines, track_start)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            emitted
+                .iter()
+                .map(|line| line.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["20", "40"]
+        );
+        assert!(track_start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::LruCache;
+use crack_types::{AuxMetadata, Error};
+use regex::Regex;
+use serde::Deserialize;
+use std::fmt::{self, Display};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Base URL of the LRCLIB API, free and without a key.
+const LRCLIB_API_URL: &str = "https://lrclib.net/api";
+/// Base URL of the Genius API, which needs a token.
+const GENIUS_API_URL: &str = "https://api.genius.com";
+/// Default number of videos whose lyrics are kept.
+pub const DEFAULT_LYRICS_CACHE_CAPACITY: usize = 256;
+/// Default time the lyrics of a video are kept.
+pub const DEFAULT_LYRICS_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+static TITLE_NOISE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s*[(\[][^)\]]*[)\]]").unwrap());
+static HTML_TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+static HTML_BREAK_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<br\s*/?>").unwrap());
+
+/// Where lyrics were found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LyricsSource {
+    Lrclib,
+    Genius,
+}
+
+/// Implement [`Display`] for [`LyricsSource`].
+impl Display for LyricsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LyricsSource::Lrclib => write!(f, "LRCLIB"),
+            LyricsSource::Genius => write!(f, "Genius"),
+        }
+    }
+}
+
+/// The lyrics of a track.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lyrics {
+    pub plain: String,
+    /// Time-synced lyrics in the LRC format, if the source has them.
+    pub synced: Option<String>,
+    pub source: LyricsSource,
+}
+
+/// Cache of video ID to its lyrics, `None` if it has none, so the sources
+/// aren't asked again.
+pub type LyricsCache = LruCache<Option<Lyrics>>;
+
+/// Implement [`Default`] for [`LyricsCache`].
+impl Default for LyricsCache {
+    fn default() -> Self {
+        LruCache::new(DEFAULT_LYRICS_CACHE_CAPACITY, DEFAULT_LYRICS_CACHE_TTL)
+    }
+}
+
+/// Build a query to search for the lyrics of a track, from its track name
+/// and artist, or from its title without the parts in brackets like
+/// `(Official Video)` if it has no track name.
+#[must_use]
+pub fn build_query_lyric_aux_metadata(aux_metadata: &AuxMetadata) -> String {
+    let track = aux_metadata
+        .track
+        .clone()
+        .or_else(|| aux_metadata.title.clone())
+        .unwrap_or_default();
+    let track = TITLE_NOISE_REGEX.replace_all(&track, "");
+    let artist = aux_metadata
+        .artist
+        .as_deref()
+        .unwrap_or_default()
+        .trim_end_matches(" - Topic")
+        .trim_end_matches("VEVO");
+    // Titles of uploads often already name the artist
+    if artist.is_empty() || track.to_lowercase().contains(&artist.to_lowercase()) {
+        track.trim().to_string()
+    } else {
+        format!("{} {artist}", track.trim())
+    }
+}
+
+/// A result of a search on LRCLIB.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibTrack {
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+    #[serde(default)]
+    instrumental: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeniusResponse {
+    response: GeniusHits,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeniusHits {
+    hits: Vec<GeniusHit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeniusHit {
+    result: GeniusSong,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeniusSong {
+    url: String,
+}
+
+/// Get the lyrics of the first LRCLIB result that has any.
+fn first_lrclib_lyrics(tracks: Vec<LrclibTrack>) -> Option<Lyrics> {
+    tracks
+        .into_iter()
+        .filter(|track| !track.instrumental)
+        .find_map(|track| {
+            let plain = track
+                .plain_lyrics
+                .filter(|plain| !plain.trim().is_empty())?;
+            Some(Lyrics {
+                plain,
+                synced: track
+                    .synced_lyrics
+                    .filter(|synced| !synced.trim().is_empty()),
+                source: LyricsSource::Lrclib,
+            })
+        })
+}
+
+/// Get the lyrics from a Genius song page, which splits them into
+/// containers with `<br>` line breaks and markup in between.
+fn genius_lyrics_from_html(html: &str) -> Option<String> {
+    const MARKER: &str = "data-lyrics-container=\"true\"";
+    let mut parts = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find(MARKER) {
+        let after = &rest[start..];
+        let Some(open_end) = after.find('>') else {
+            break;
+        };
+        // Walk to the `</div>` closing the container
+        let content = &after[open_end + 1..];
+        let mut depth = 1;
+        let mut index = 0;
+        while depth > 0 {
+            let next_open = content[index..].find("<div");
+            let Some(next_close) = content[index..].find("</div>") else {
+                index = content.len();
+                break;
+            };
+            match next_open {
+                Some(open) if open < next_close => {
+                    depth += 1;
+                    index += open + 4;
+                }
+                _ => {
+                    depth -= 1;
+                    index += next_close + 6;
+                }
+            }
+        }
+        let inner = content[..index].trim_end_matches("</div>");
+        let text = HTML_BREAK_REGEX.replace_all(inner, "\n");
+        parts.push(decode_html_entities(&HTML_TAG_REGEX.replace_all(&text, "")));
+        rest = &content[index..];
+    }
+    let lyrics = parts.join("\n").trim().to_string();
+    (!lyrics.is_empty()).then_some(lyrics)
+}
+
+/// Decode the HTML entities Genius uses in lyrics.
+fn decode_html_entities(text: &str) -> String {
+    text.replace("&amp;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+}
+
+/// Client for LRCLIB and, with a token, Genius.
+#[derive(Clone, Debug)]
+pub struct LyricsClient {
+    client: reqwest::Client,
+    genius_token: Option<String>,
+}
+
+/// Implement [`LyricsClient`].
+impl LyricsClient {
+    /// Create a new [`LyricsClient`], only asking Genius if `genius_token` is
+    /// set.
+    #[must_use]
+    pub fn new(client: reqwest::Client, genius_token: Option<String>) -> Self {
+        LyricsClient {
+            client,
+            genius_token,
+        }
+    }
+
+    /// Search for the lyrics of a track on LRCLIB, then on Genius.
+    /// # Errors
+    /// Returns an error if every source fails. Finding no lyrics is not an
+    /// error.
+    pub async fn search(&self, query: &str) -> Result<Option<Lyrics>, Error> {
+        let lrclib = self.search_lrclib(query).await;
+        if let Ok(Some(lyrics)) = lrclib {
+            return Ok(Some(lyrics));
+        }
+        if self.genius_token.is_none() {
+            return lrclib;
+        }
+        match (self.search_genius(query).await, lrclib) {
+            (Ok(Some(lyrics)), _) => Ok(Some(lyrics)),
+            (Err(e), Err(_)) => Err(e),
+            _ => Ok(None),
+        }
+    }
+
+    /// Search for the lyrics of a track on LRCLIB.
+    async fn search_lrclib(&self, query: &str) -> Result<Option<Lyrics>, Error> {
+        let tracks: Vec<LrclibTrack> = self
+            .client
+            .get(format!("{LRCLIB_API_URL}/search"))
+            .query(&[("q", query)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(first_lrclib_lyrics(tracks))
+    }
+
+    /// Search for the lyrics of a track on Genius, scraping them from the
+    /// page of the first result.
+    async fn search_genius(&self, query: &str) -> Result<Option<Lyrics>, Error> {
+        let token = self.genius_token.as_deref().unwrap_or_default();
+        let response: GeniusResponse = self
+            .client
+            .get(format!("{GENIUS_API_URL}/search"))
+            .query(&[("q", query)])
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let Some(hit) = response.response.hits.into_iter().next() else {
+            return Ok(None);
+        };
+        let html = self
+            .client
+            .get(hit.result.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(genius_lyrics_from_html(&html).map(|plain| Lyrics {
+            plain,
+            synced: None,
+            source: LyricsSource::Genius,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_query_lyric_aux_metadata() {
+        let aux_metadata = AuxMetadata {
+            artist: Some("Molly Nilsson - Topic".to_string()),
+            track: Some("1995".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_query_lyric_aux_metadata(&aux_metadata),
+            "1995 Molly Nilsson"
+        );
+
+        // The artist is already in the title
+        let aux_metadata = AuxMetadata {
+            artist: Some("Rick Astley".to_string()),
+            title: Some("Rick Astley - Never Gonna Give You Up (Official Music Video)".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_query_lyric_aux_metadata(&aux_metadata),
+            "Rick Astley - Never Gonna Give You Up"
+        );
+    }
+
+    #[test]
+    fn test_first_lrclib_lyrics() {
+        let json = r#"[
+            {"plainLyrics": null, "syncedLyrics": null, "instrumental": true},
+            {"plainLyrics": "Line one\nLine two", "syncedLyrics": "[00:01.00]Line one", "instrumental": false}
+        ]"#;
+        let lyrics = first_lrclib_lyrics(serde_json::from_str(json).unwrap()).unwrap();
+        assert_eq!(lyrics.plain, "Line one\nLine two");
+        assert_eq!(lyrics.synced.as_deref(), Some("[00:01.00]Line one"));
+        assert_eq!(lyrics.source, LyricsSource::Lrclib);
+        assert_eq!(first_lrclib_lyrics(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_genius_lyrics_from_html() {
+        let html = r#"<html><div data-lyrics-container="true" class="x">[Verse 1]<br/>Don&#x27;t <a href="/a"><span>stop</span></a> me<br>now<div class="ad"></div></div>
+            <p>Ad</p><div data-lyrics-container="true">Rock &amp; roll</div></html>"#;
+        assert_eq!(
+            genius_lyrics_from_html(html).as_deref(),
+            Some("[Verse 1]\nDon't stop me\nnow\nRock & roll")
+        );
+        assert_eq!(genius_lyrics_from_html("<html></html>"), None);
+    }
+}
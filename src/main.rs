@@ -3,11 +3,7 @@
 //!
 //! Requires the "cache", "voice", and "poise" features be enabled in your
 //! Cargo.toml.
-use std::{
-    env,
-    sync::{atomic::AtomicBool, Arc},
-    time::Duration,
-};
+use std::{env, sync::Arc, time::Duration};
 
 use poise::serenity_prelude as serenity;
 use reqwest::Client as HttpClient;
@@ -19,14 +15,25 @@ use serenity::{
 
 use cracktunes::{
     event_handlers::{
-        ChannelDurationNotifier, EnhancedTrackErrorNotifier, SongEndNotifier, SongFader,
+        play_dequeued, stop_current, track_started, ChannelDurationNotifier,
+        EnhancedTrackErrorNotifier, SongEndNotifier, SongFader,
     },
     EnhancedTrackEndNotifier,
 };
 
-use crack_types::QueryType;
-use cracktunes::{check_msg, CrackTrackQueue, Data, DataInner, ResolvedTrack};
+use crack_types::{get_human_readable_timestamp, QueryType};
+use cracktunes::{
+    apply_gapless, check_msg, format_duration_short, is_youtube_url, parse_clip_time,
+    set_voice_decoding, AudioCache, AutoPan, BassBoost, CanonicalUrl, CrackTrackClient,
+    CrackTrackQueue, Data, DataInner, EffectKind, EffectsError, EqPreset, Equalizer, ExportFormat,
+    HttpTtsProvider, LoopMode, LruCache, NowPlaying, PageAction, PoToken, QueueError,
+    QueuePaginator, QueueStore, RecentWindow, ResolvedTrack, SortKey, Speed, StallWatchdog,
+    TtsProvider, YoutubeCredentials, DEFAULT_AMBIENCE_VOLUME, DEFAULT_PAGE_SIZE,
+    DEFAULT_QUEUE_STORE_DIR, DEFAULT_RECORDING_SECS, EQ_BAND_FREQUENCIES, LOUDNESS_CACHE_CAPACITY,
+    LOUDNESS_CACHE_TTL, MAX_RECORDING_SECS, STALL_CHECK_INTERVAL,
+};
 use songbird::{input::YoutubeDl, Call, Event, TrackEvent};
+use tokio::sync::Mutex;
 // Define the context type for poise
 type Context<'a> = poise::Context<'a, Data, serenity::Error>;
 
@@ -44,21 +51,71 @@ async fn get_queue(ctx: Context<'_>) -> Result<CrackTrackQueue, String> {
     let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
     let queues = &ctx.data().guild_queues;
 
-    if !queues.contains_key(&guild_id) {
-        queues.insert(guild_id, CrackTrackQueue::new());
+    Ok(queues.entry(guild_id).or_default().clone())
+}
+
+// Helper function to persist a guild's queue after it changes
+async fn save_queue(ctx: Context<'_>, queue: &CrackTrackQueue) {
+    let Some(guild_id) = ctx.guild_id() else {
+        return;
+    };
+    if let Err(e) = ctx.data().queue_store.save(guild_id, queue).await {
+        println!("Error saving queue: {}", e);
     }
+}
+
+// Helper function to turn the page of a queue display when one of its buttons is pressed
+async fn handle_queue_page(
+    ctx: &serenity::Context,
+    data: &Data,
+    component: &serenity::ComponentInteraction,
+) -> Result<(), serenity::Error> {
+    let Some(action) = PageAction::from_custom_id(&component.data.custom_id) else {
+        return Ok(());
+    };
+    let response = {
+        let Some(mut paginator) = data.paginators.get_mut(&component.message.id) else {
+            return Ok(());
+        };
+        if paginator.is_expired() {
+            drop(paginator);
+            data.paginators.remove(&component.message.id);
+            return Ok(());
+        }
+        paginator.apply(action);
+        paginator.update_response()
+    };
+    component.create_response(&ctx.http, response).await
+}
 
-    Ok(queues.get(&guild_id).unwrap().clone())
+// Helper function to drop everything kept for a guild the bot has left
+async fn forget_guild(data: &Data, guild_id: serenity::GuildId) {
+    data.guild_queues.remove(&guild_id);
+    data.queue_backups.remove(&guild_id);
+    data.idle_timeouts.remove(&guild_id);
+    data.current_tracks.remove(&guild_id);
+    data.preloaded.remove(&guild_id);
+    data.unstage(guild_id);
+    data.track_gains.remove(&guild_id);
+    data.effects.remove(&guild_id);
+    data.stalled.remove(&guild_id);
+    data.stop_recording(guild_id);
+    data.track_client.stop_ambience(guild_id);
+    if let Err(e) = data.queue_store.remove(guild_id).await {
+        println!("Error removing saved queue: {}", e);
+    }
 }
 
 // Add this to improve the play_next_from_queue function to handle track failures
 async fn play_next_from_queue(
     ctx: Context<'_>,
     queue: CrackTrackQueue,
-    mut handler: Call,
+    handler_lock: &Mutex<Call>,
 ) -> Result<(), serenity::Error> {
     // Get the next track from our custom queue
     if let Some(track) = queue.dequeue().await {
+        save_queue(ctx, &queue).await;
+
         // Try to play it with songbird
         // let src = match YoutubeDl::new(ctx.data().http_client.clone(), track.get_url()).into_input() {
         //     Ok(input) => input,
@@ -70,13 +127,22 @@ async fn play_next_from_queue(
         //         return play_next_from_queue(ctx, queue, handler).await;
         //     }
         // };
-        let _data = Arc::new(ctx.data().clone());
-        let src = YoutubeDl::new(ctx.data().http_client.clone(), track.get_url());
+        let guild_id = ctx.guild_id().unwrap();
 
-        let song = handler.play_input(src.into());
+        // Played the same way as by the end notifier, announced first if
+        // announcements are on
+        let (song, announced) =
+            play_dequeued(ctx.data(), guild_id, handler_lock, &queue, &track).await;
+        apply_gapless(
+            Arc::new(ctx.data().clone()),
+            guild_id,
+            &queue,
+            &song,
+            &track,
+        );
+        track_started(ctx.data(), guild_id, &queue, &song, &track);
 
         // Update activity timestamp directly
-        let guild_id = ctx.guild_id().unwrap();
         if let Some(idle_info) = ctx.data().idle_timeouts.get(&guild_id) {
             let current_time = idle_info
                 .last_activity
@@ -97,7 +163,7 @@ async fn play_next_from_queue(
                 http: http.clone(),
                 guild_id: ctx.guild_id().unwrap(),
                 data: Arc::new(ctx.data().clone()),
-                is_looping: Arc::new(AtomicBool::new(false)),
+                reconnects: 0,
             },
         );
 
@@ -109,16 +175,22 @@ async fn play_next_from_queue(
                 http: http.clone(),
                 guild_id: ctx.guild_id().unwrap(),
                 data: Arc::new(ctx.data().clone()),
-                is_looping: Arc::new(AtomicBool::new(false)),
             },
         );
 
-        // Notify that the track is playing
-        check_msg(
-            chan_id
-                .say(http.clone(), &format!("Now playing: {}", track.get_title()))
-                .await,
-        );
+        // Notify that the track is playing, unless it was announced in the
+        // call already
+        if !announced {
+            let embed = NowPlaying::new(track)
+                .with_queue_len(queue.len().await)
+                .with_effects(&ctx.data().guild_effects(guild_id).get())
+                .embed();
+            check_msg(
+                chan_id
+                    .send_message(http.clone(), serenity::CreateMessage::new().embed(embed))
+                    .await,
+            );
+        }
     }
 
     Ok(())
@@ -173,6 +245,12 @@ async fn join(ctx: Context<'_>) -> Result<(), serenity::Error> {
 
         // Add the notifier as a global event
         handle.add_global_event(Event::Periodic(Duration::from_secs(60), None), notifier);
+
+        // Recover tracks that get stuck while playing
+        handle.add_global_event(
+            Event::Periodic(STALL_CHECK_INTERVAL, None),
+            StallWatchdog::new(guild_id, Arc::new(ctx.data().clone())),
+        );
     } else {
         ctx.say("Error joining the channel").await?;
     }
@@ -187,6 +265,9 @@ async fn leave(ctx: Context<'_>) -> Result<(), serenity::Error> {
     let manager = ctx.data().songbird.clone();
 
     if manager.get(guild_id).is_some() {
+        // The recording and ambience are of the call being left
+        ctx.data().stop_recording(guild_id);
+        ctx.data().track_client.stop_ambience(guild_id);
         if let Err(e) = manager.remove(guild_id).await {
             ctx.say(format!("Failed: {:?}", e)).await?;
         } else {
@@ -216,11 +297,14 @@ async fn play_url(
     if let Some(handler_lock) = data.songbird.get(guild_id) {
         let mut handler = handler_lock.lock().await;
 
+        let track = ResolvedTrack::new(QueryType::VideoLink(url.clone()));
         let src = YoutubeDl::new(data.http_client.clone(), url);
 
         // This handler object will allow you to, as needed,
         // control the audio track via events and further commands.
         let song = handler.play_input(src.into());
+        let queue = data.guild_queues.entry(guild_id).or_default().clone();
+        track_started(data, guild_id, &queue, &song, &track);
 
         // Update activity timestamp directly
         if let Some(idle_info) = ctx.data().idle_timeouts.get(&guild_id) {
@@ -268,12 +352,28 @@ async fn play_url(
 async fn queue(
     ctx: Context<'_>,
     #[description = "URL to a video or audio"] url: String,
+    #[description = "Speed to play the song at"] speed: Option<SpeedChoice>,
+    #[description = "Where to start playing, like 1:30"] start: Option<String>,
+    #[description = "Where to stop playing, like 2:00"] end: Option<String>,
+    #[description = "Volume in percent of the server's volume, e.g. 50 to play it quietly"]
+    #[max = 200]
+    volume: Option<u8>,
 ) -> Result<(), serenity::Error> {
     if !url.starts_with("http") {
         ctx.say("Must provide a valid URL").await?;
         return Ok(());
     }
 
+    // Only play part of the song, e.g. for an intro song or a soundbite
+    let parse = |time: Option<String>| time.as_deref().map(parse_clip_time).transpose();
+    let (start, end) = match (parse(start), parse(end)) {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(e), _) | (_, Err(e)) => {
+            ctx.say(e.to_string()).await?;
+            return Ok(());
+        }
+    };
+
     let guild_id = ctx.guild_id().unwrap();
     let data = ctx.data();
 
@@ -284,25 +384,60 @@ async fn queue(
     })?;
 
     if let Some(handler_lock) = data.songbird.get(guild_id) {
-        let handler = handler_lock.lock().await;
+        if let Some(index) = queue.position_of(&url).await {
+            ctx.say(format!(
+                "That song is already queued at position {}.",
+                index + 1
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        // Radio streams are played directly and never finish by themselves
+        let radio = if is_youtube_url(&url) {
+            None
+        } else {
+            CrackTrackClient::new()
+                .resolve_radio(&url)
+                .await
+                .unwrap_or_else(|e| {
+                    println!("Error probing stream: {}", e);
+                    None
+                })
+        };
 
         // Create a resolved track from the URL
-        let query = QueryType::VideoLink(url);
-        let track = ResolvedTrack::new(query).with_user_id(ctx.author().id);
+        let track = radio
+            .unwrap_or_else(|| {
+                // Start at the timestamp of links like `?t=90`
+                let timestamp = CanonicalUrl::parse(&url).and_then(|canonical| canonical.timestamp);
+                ResolvedTrack::new(QueryType::VideoLink(url)).with_start_offset(timestamp)
+            })
+            .with_user_id(ctx.author().id)
+            .with_speed(speed.map(Speed::from))
+            .with_volume(volume);
+        let track = match track.with_clip(start, end) {
+            Ok(track) => track,
+            Err(e) => {
+                ctx.say(e.to_string()).await?;
+                return Ok(());
+            }
+        };
 
         // Add to our custom queue
-        queue.enqueue(track.clone()).await;
+        if let Err(e) = queue.enqueue(track.clone()).await {
+            ctx.say(format!("Failed: {e}")).await?;
+            return Ok(());
+        }
 
         // Check if we need to start playing (if this is the first track)
         let queue_len = queue.len().await;
         if queue_len == 1 {
             // This is the first track, so start playing
-            play_next_from_queue(ctx, queue.clone(), handler.clone()).await?;
+            play_next_from_queue(ctx, queue.clone(), &handler_lock).await?;
         }
 
-        // Build the display for the queue
-        let mut queue_clone = queue.clone();
-        queue_clone.build_display().await;
+        save_queue(ctx, &queue).await;
 
         ctx.say(format!("Added song to queue: position {queue_len}",))
             .await?;
@@ -313,6 +448,111 @@ async fn queue(
     Ok(())
 }
 
+/// Adds every song of a playlist to the queue
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn queue_playlist(
+    ctx: Context<'_>,
+    #[description = "URL to a playlist or album, e.g. on YouTube or Spotify"] url: String,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let data = ctx.data();
+
+    let queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    let Some(handler_lock) = data.songbird.get(guild_id) else {
+        ctx.say("Not in a voice channel to play in").await?;
+        return Ok(());
+    };
+
+    // Resolving a large playlist can take longer than Discord waits for a reply
+    ctx.defer().await?;
+    let resolved = match CrackTrackClient::new()
+        .resolve_query_to_tracks(QueryType::PlaylistLink(url.clone()))
+        .await
+    {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            ctx.say(format!("Failed to resolve playlist: {e}")).await?;
+            return Ok(());
+        }
+    };
+    let failed = resolved.failures.len();
+    let tracks = resolved
+        .tracks
+        .into_iter()
+        .map(|track| track.with_user_id(ctx.author().id))
+        .collect();
+
+    let idle = queue.is_empty().await && queue.current().await.is_none();
+    // The whole playlist goes in under a single lock of the queue
+    let mut msg = match queue.enqueue_batch_with(tracks, true).await {
+        Ok(outcome) if outcome.skipped > 0 => format!(
+            "Added {} songs to the queue, skipped {} already queued or played recently.",
+            outcome.added, outcome.skipped
+        ),
+        Ok(outcome) => format!("Added {} songs to the queue.", outcome.added),
+        Err(e) => format!("Added part of the playlist: {e}"),
+    };
+    if failed > 0 {
+        msg.push_str(&format!(" {failed} songs couldn't be found."));
+    }
+
+    if idle && !queue.is_empty().await {
+        play_next_from_queue(ctx, queue.clone(), &handler_lock).await?;
+    }
+    save_queue(ctx, &queue).await;
+
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Adds a song to play right after the current one
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn queue_next(
+    ctx: Context<'_>,
+    #[description = "URL to a video or audio"] url: String,
+) -> Result<(), serenity::Error> {
+    if !url.starts_with("http") {
+        ctx.say("Must provide a valid URL").await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let data = ctx.data();
+
+    // Get the custom queue for this guild
+    let queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    if let Some(handler_lock) = data.songbird.get(guild_id) {
+        let query = QueryType::VideoLink(url);
+        let track = ResolvedTrack::new(query).with_user_id(ctx.author().id);
+
+        if let Err(e) = queue.insert_after_playing(track).await {
+            ctx.say(format!("Failed: {e}")).await?;
+            return Ok(());
+        }
+
+        // Nothing is playing yet, so start right away
+        if queue.get_playing().await.is_none() {
+            play_next_from_queue(ctx, queue.clone(), &handler_lock).await?;
+        }
+        save_queue(ctx, &queue).await;
+
+        ctx.say("Added song to play next.").await?;
+    } else {
+        ctx.say("Not in a voice channel to play in").await?;
+    }
+
+    Ok(())
+}
+
 /// Skips the current song
 #[poise::command(slash_command, prefix_command, guild_only)]
 async fn skip(ctx: Context<'_>) -> Result<(), serenity::Error> {
@@ -320,25 +560,35 @@ async fn skip(ctx: Context<'_>) -> Result<(), serenity::Error> {
     let manager = ctx.data().songbird.clone();
 
     if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
-
-        // Skip the current song in songbird's queue
-        handler.stop();
-
-        // Also dequeue from our custom queue
         let custom_queue = get_queue(ctx).await.map_err(|e| {
             println!("Error getting queue: {}", e);
             serenity::Error::Other("Failed to get queue")
         })?;
 
-        let _ = custom_queue.dequeue().await;
-
-        // Play the next song from our custom queue
-        if !custom_queue.is_empty().await {
-            play_next_from_queue(ctx, custom_queue.clone(), handler.clone()).await?;
+        // A looped track is queued again up front, drop it so it is actually
+        // skipped
+        let loop_mode = custom_queue.loop_mode().await;
+        if loop_mode == LoopMode::Track {
+            let _ = custom_queue.pop_front().await;
         }
 
-        let len = custom_queue.len().await;
+        // Stop only the current song, its end notifier plays the next one
+        let len = if stop_current(ctx.data(), guild_id) {
+            save_queue(ctx, &custom_queue).await;
+            let len = custom_queue.len().await;
+            match loop_mode {
+                LoopMode::Off => len.saturating_sub(1),
+                _ => len,
+            }
+        } else {
+            custom_queue.clear_current().await;
+            if !custom_queue.is_empty().await {
+                play_next_from_queue(ctx, custom_queue.clone(), &handler_lock).await?;
+            }
+            save_queue(ctx, &custom_queue).await;
+            custom_queue.len().await
+        };
+
         ctx.say(format!("Song skipped: {} in queue.", len)).await?;
     } else {
         ctx.say("Not in a voice channel to play in").await?;
@@ -347,6 +597,52 @@ async fn skip(ctx: Context<'_>) -> Result<(), serenity::Error> {
     Ok(())
 }
 
+/// Skips to a position in the queue
+#[poise::command(slash_command, prefix_command, guild_only, rename = "skipto")]
+async fn skip_to(
+    ctx: Context<'_>,
+    #[description = "Position of the track to skip to"] position: usize,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let manager = ctx.data().songbird.clone();
+
+    let Some(handler_lock) = manager.get(guild_id) else {
+        ctx.say("Not in a voice channel to play in").await?;
+        return Ok(());
+    };
+
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    // Positions are 1-based for users
+    let track = match custom_queue
+        .jump_to(position.saturating_sub(1), false)
+        .await
+    {
+        Ok(track) => track,
+        Err(e) => {
+            ctx.say(format!("Failed: {e}")).await?;
+            return Ok(());
+        }
+    };
+
+    // Stop only the current song, its end notifier plays the one jumped to
+    if stop_current(ctx.data(), guild_id) {
+        save_queue(ctx, &custom_queue).await;
+    } else {
+        custom_queue.clear_current().await;
+        play_next_from_queue(ctx, custom_queue.clone(), &handler_lock).await?;
+        save_queue(ctx, &custom_queue).await;
+    }
+
+    ctx.say(format!("Skipped to: {}", track.get_title()))
+        .await?;
+
+    Ok(())
+}
+
 /// Stops playback and clears the queue
 #[poise::command(slash_command, prefix_command, guild_only)]
 async fn stop(ctx: Context<'_>) -> Result<(), serenity::Error> {
@@ -356,8 +652,9 @@ async fn stop(ctx: Context<'_>) -> Result<(), serenity::Error> {
     if let Some(handler_lock) = manager.get(guild_id) {
         let mut handler = handler_lock.lock().await;
 
-        // Stop the songbird queue
+        // Stop the songbird queue, ambience included
         handler.stop();
+        ctx.data().track_client.stop_ambience(guild_id);
 
         // Clear our custom queue
         let custom_queue = get_queue(ctx).await.map_err(|e| {
@@ -366,6 +663,8 @@ async fn stop(ctx: Context<'_>) -> Result<(), serenity::Error> {
         })?;
 
         custom_queue.clear().await;
+        custom_queue.clear_current().await;
+        save_queue(ctx, &custom_queue).await;
 
         ctx.say("Queue cleared.").await?;
     } else {
@@ -377,21 +676,42 @@ async fn stop(ctx: Context<'_>) -> Result<(), serenity::Error> {
 
 /// Displays the current queue
 #[poise::command(slash_command, prefix_command, guild_only)]
-async fn show_queue(ctx: Context<'_>) -> Result<(), serenity::Error> {
+async fn show_queue(
+    ctx: Context<'_>,
+    #[description = "Page of the queue to show"] page: Option<usize>,
+) -> Result<(), serenity::Error> {
     let custom_queue = get_queue(ctx).await.map_err(|e| {
         println!("Error getting queue: {}", e);
         serenity::Error::Other("Failed to get queue")
     })?;
 
     let mut queue_clone = custom_queue.clone();
-    queue_clone.build_display().await;
-
-    let display = queue_clone.get_display();
+    let pages = queue_clone.build_pages(DEFAULT_PAGE_SIZE).await;
 
-    if display.is_empty() {
+    if pages.is_empty() {
         ctx.say("The queue is empty.").await?;
-    } else {
-        ctx.say(format!("**Current Queue:**\n{}", display)).await?;
+        return Ok(());
+    }
+
+    // Pages are 1-based for users
+    let page = page.unwrap_or(1).max(1) - 1;
+    let paginator = QueuePaginator::new(pages, page);
+    let total = custom_queue.total_duration().await;
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .content(format!("{} of music queued", format_duration_short(total)))
+                .embed(paginator.embed())
+                .components(paginator.components()),
+        )
+        .await?;
+
+    // Keep the page state around so the buttons can be handled
+    if paginator.num_pages() > 1 {
+        let message_id = reply.message().await?.id;
+        let paginators = &ctx.data().paginators;
+        paginators.retain(|_, paginator| !paginator.is_expired());
+        paginators.insert(message_id, paginator);
     }
 
     Ok(())
@@ -399,114 +719,854 @@ async fn show_queue(ctx: Context<'_>) -> Result<(), serenity::Error> {
 
 /// Shuffles the queue
 #[poise::command(slash_command, prefix_command, guild_only)]
-async fn shuffle(ctx: Context<'_>) -> Result<(), serenity::Error> {
-    let guild_id = ctx.guild_id().unwrap();
-    let manager = ctx.data().songbird.clone();
+async fn shuffle(
+    ctx: Context<'_>,
+    #[description = "Number of upcoming songs to keep in place"] keep: Option<usize>,
+    #[description = "Spread each user's songs evenly over the queue"] balanced: Option<bool>,
+) -> Result<(), serenity::Error> {
+    // Get our custom queue
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
+    // The playing track isn't part of the queue, so playback carries on
+    match (keep, balanced.unwrap_or(false)) {
+        (_, true) => custom_queue.shuffle_balanced().await,
+        (Some(n), false) => custom_queue.shuffle_after(n).await,
+        (None, false) => custom_queue.shuffle().await,
+    }
 
-        // Get our custom queue
-        let custom_queue = get_queue(ctx).await.map_err(|e| {
-            println!("Error getting queue: {}", e);
-            serenity::Error::Other("Failed to get queue")
-        })?;
+    save_queue(ctx, &custom_queue).await;
 
-        // Save the current playing track if there is one
-        let current_track = if !custom_queue.is_empty().await {
-            custom_queue.dequeue().await
-        } else {
-            None
-        };
+    ctx.say("Queue shuffled!").await?;
 
-        // Shuffle our custom queue
-        custom_queue.shuffle().await;
+    Ok(())
+}
 
-        // If we had a current track, put it back at the front
-        if let Some(track) = current_track {
-            custom_queue.push_front(track).await;
-        }
+/// Moves a song to a different position in the queue
+#[poise::command(slash_command, prefix_command, guild_only, rename = "move")]
+async fn move_track(
+    ctx: Context<'_>,
+    #[description = "Position of the song to move"]
+    #[min = 1]
+    from: usize,
+    #[description = "Position to move the song to"]
+    #[min = 1]
+    to: usize,
+) -> Result<(), serenity::Error> {
+    // Prefix commands aren't held to the minimum
+    if from == 0 || to == 0 {
+        ctx.say("Positions start at 1.").await?;
+        return Ok(());
+    }
 
-        // We need to rebuild the songbird queue to match our shuffled queue
-        handler.stop();
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
 
-        // Play the next track from our shuffled queue
-        if !custom_queue.is_empty().await {
-            play_next_from_queue(ctx, custom_queue.clone(), handler.clone()).await?;
+    // Positions are 1-based for users
+    match custom_queue.move_track(from - 1, to - 1).await {
+        Ok(()) => {
+            ctx.say(format!("Moved song from position {from} to {to}."))
+                .await?
         }
+        Err(QueueError::IndexOutOfBounds { index, len }) => {
+            ctx.say(format!(
+                "Failed: there is no song at position {}, the queue has {len}.",
+                index + 1
+            ))
+            .await?
+        }
+        Err(e) => ctx.say(format!("Failed: {e}")).await?,
+    };
 
-        // Build the display for the queue
-        let mut queue_clone = custom_queue.clone();
-        queue_clone.build_display().await;
+    Ok(())
+}
+
+/// Undoes the last clear, shuffle or bulk removal of the queue
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn undo(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
 
-        ctx.say("Queue shuffled!").await?;
+    if custom_queue.undo().await {
+        save_queue(ctx, &custom_queue).await;
+        let len = custom_queue.len().await;
+        ctx.say(format!("Queue restored: {} in queue.", len))
+            .await?;
     } else {
-        ctx.say("Not in a voice channel.").await?;
+        ctx.say("Nothing to undo.").await?;
     }
 
     Ok(())
 }
 
-/// Pings the bot
-#[poise::command(slash_command, prefix_command)]
-async fn ping(ctx: Context<'_>) -> Result<(), serenity::Error> {
-    ctx.say("Pong!").await?;
-    Ok(())
-}
-
-/// Mutes the bot
+/// Exports the queue as a playlist file
 #[poise::command(slash_command, prefix_command, guild_only)]
-async fn mute(ctx: Context<'_>) -> Result<(), serenity::Error> {
-    let guild_id = ctx.guild_id().unwrap();
-    let manager = ctx.data().songbird.clone();
-
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
+async fn export(
+    ctx: Context<'_>,
+    #[description = "Export as CSV instead of M3U"] csv: Option<bool>,
+) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
 
-        if handler.is_mute() {
-            ctx.say("Already muted").await?;
-        } else {
-            if let Err(e) = handler.mute(true).await {
-                ctx.say(format!("Failed: {:?}", e)).await?;
-            } else {
-                ctx.say("Now muted").await?;
-            }
-        }
+    let format = if csv.unwrap_or(false) {
+        ExportFormat::Csv
     } else {
-        ctx.say("Not in a voice channel").await?;
-    }
+        ExportFormat::M3u
+    };
+    let bytes = custom_queue.export(format).await.into_bytes();
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!("Exported queue as {format}."))
+            .attachment(serenity::CreateAttachment::bytes(bytes, format.file_name())),
+    )
+    .await?;
 
     Ok(())
 }
 
-/// Unmutes the bot
-#[poise::command(slash_command, prefix_command, guild_only)]
-async fn unmute(ctx: Context<'_>) -> Result<(), serenity::Error> {
-    let guild_id = ctx.guild_id().unwrap();
-    let manager = ctx.data().songbird.clone();
+/// What the `sort` command can sort the queue by
+#[derive(Debug, poise::ChoiceParameter)]
+enum SortChoice {
+    #[name = "Shortest first"]
+    Shortest,
+    #[name = "Longest first"]
+    Longest,
+    #[name = "Title"]
+    Title,
+    #[name = "Requester"]
+    Requester,
+}
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
-        if let Err(e) = handler.mute(false).await {
-            ctx.say(format!("Failed: {:?}", e)).await?;
-        } else {
-            ctx.say("Unmuted").await?;
+impl From<SortChoice> for SortKey {
+    fn from(choice: SortChoice) -> Self {
+        match choice {
+            SortChoice::Shortest => SortKey::DurationAsc,
+            SortChoice::Longest => SortKey::DurationDesc,
+            SortChoice::Title => SortKey::Title,
+            SortChoice::Requester => SortKey::Requester,
         }
-    } else {
-        ctx.say("Not in a voice channel to unmute in").await?;
     }
+}
 
-    Ok(())
+/// Presets of the equalizer to choose from
+#[derive(Debug, poise::ChoiceParameter)]
+enum EqPresetChoice {
+    #[name = "Flat"]
+    Flat,
+    #[name = "Rock"]
+    Rock,
+    #[name = "Classical"]
+    Classical,
 }
 
-/// Deafens the bot
+impl From<EqPresetChoice> for EqPreset {
+    fn from(choice: EqPresetChoice) -> Self {
+        match choice {
+            EqPresetChoice::Flat => EqPreset::Flat,
+            EqPresetChoice::Rock => EqPreset::Rock,
+            EqPresetChoice::Classical => EqPreset::Classical,
+        }
+    }
+}
+
+/// Shows the equalizer or sets it to a preset
 #[poise::command(slash_command, prefix_command, guild_only)]
-async fn deafen(ctx: Context<'_>) -> Result<(), serenity::Error> {
+async fn equalizer(
+    ctx: Context<'_>,
+    #[description = "Preset to set the equalizer to"] preset: Option<EqPresetChoice>,
+) -> Result<(), serenity::Error> {
     let guild_id = ctx.guild_id().unwrap();
-    let manager = ctx.data().songbird.clone();
-
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
+    let effects = ctx.data().guild_effects(guild_id);
+
+    let Some(preset) = preset else {
+        let equalizer = effects.get().chain.equalizer();
+        let bands = EQ_BAND_FREQUENCIES
+            .iter()
+            .zip(equalizer.gains)
+            .map(|(frequency, gain)| format!("{frequency} Hz: {gain:+} dB"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let name = equalizer
+            .preset()
+            .map_or_else(|| "custom".to_string(), |preset| preset.to_string());
+        ctx.say(format!("Equalizer is {name} ({bands}).")).await?;
+        return Ok(());
+    };
+    let preset = EqPreset::from(preset);
+    effects.update(|effects| effects.chain.set_equalizer(Equalizer::from_preset(preset)));
+    ctx.say(format!("Equalizer set to {preset}.")).await?;
+
+    Ok(())
+}
+
+/// Sets the gain of a band of the equalizer
+#[poise::command(slash_command, prefix_command, guild_only, rename = "eqband")]
+async fn eq_band(
+    ctx: Context<'_>,
+    #[description = "Band to change, from 1 for the lowest"]
+    #[min = 1]
+    #[max = 10]
+    band: usize,
+    #[description = "Gain in dB, 0 is unchanged"]
+    #[min = -12]
+    #[max = 12]
+    gain: f32,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let effects = ctx.data().guild_effects(guild_id);
+
+    // Bands are 1-based for users
+    let index = band.saturating_sub(1);
+    match effects.update(|effects| {
+        let mut equalizer = effects.chain.equalizer();
+        equalizer.set_band(index, gain)?;
+        effects.chain.set_equalizer(equalizer);
+        Ok::<_, EffectsError>(())
+    }) {
+        Ok(()) => {
+            let frequency = EQ_BAND_FREQUENCIES[index];
+            ctx.say(format!("Set {frequency} Hz to {gain:+} dB."))
+                .await?
+        }
+        Err(e) => ctx.say(format!("Failed: {e}")).await?,
+    };
+
+    Ok(())
+}
+
+/// Levels of the bass boost to choose from
+#[derive(Debug, poise::ChoiceParameter)]
+enum BassBoostChoice {
+    #[name = "Off"]
+    Off,
+    #[name = "Low"]
+    Low,
+    #[name = "Medium"]
+    Medium,
+    #[name = "High"]
+    High,
+    #[name = "Extreme"]
+    Extreme,
+}
+
+impl From<BassBoostChoice> for Option<BassBoost> {
+    fn from(choice: BassBoostChoice) -> Self {
+        match choice {
+            BassBoostChoice::Off => None,
+            BassBoostChoice::Low => Some(BassBoost::Low),
+            BassBoostChoice::Medium => Some(BassBoost::Medium),
+            BassBoostChoice::High => Some(BassBoost::High),
+            BassBoostChoice::Extreme => Some(BassBoost::Extreme),
+        }
+    }
+}
+
+/// Sets the bass boost, or toggles it if no level is given
+#[poise::command(slash_command, prefix_command, guild_only, rename = "bassboost")]
+async fn bass_boost(
+    ctx: Context<'_>,
+    #[description = "How much to boost the bass"] level: Option<BassBoostChoice>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let effects = ctx.data().guild_effects(guild_id);
+
+    let bass_boost = effects.update(|effects| {
+        let bass_boost = match level {
+            Some(level) => level.into(),
+            None if effects.chain.bass_boost().is_some() => None,
+            None => Some(BassBoost::default()),
+        };
+        effects.chain.set_bass_boost(bass_boost);
+        bass_boost
+    });
+    let msg = match bass_boost {
+        Some(bass_boost) => format!("Bass boost set to {bass_boost}."),
+        None => "Bass boost is now off.".to_string(),
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Speeds to choose from
+#[derive(Debug, poise::ChoiceParameter)]
+enum SpeedChoice {
+    #[name = "Normal"]
+    Normal,
+    #[name = "Nightcore"]
+    Nightcore,
+    #[name = "Daycore"]
+    Daycore,
+}
+
+impl From<SpeedChoice> for Speed {
+    fn from(choice: SpeedChoice) -> Self {
+        match choice {
+            SpeedChoice::Normal => Speed::NORMAL,
+            SpeedChoice::Nightcore => Speed::NIGHTCORE,
+            SpeedChoice::Daycore => Speed::DAYCORE,
+        }
+    }
+}
+
+/// Shows or sets the speed songs play at, their pitch changing with it
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn speed(
+    ctx: Context<'_>,
+    #[description = "Preset to set the speed to"] preset: Option<SpeedChoice>,
+    #[description = "Speed in percent, 100 is normal"]
+    #[min = 50]
+    #[max = 200]
+    percent: Option<u16>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let effects = ctx.data().guild_effects(guild_id);
+
+    let speed = match (preset, percent) {
+        (_, Some(percent)) => match Speed::new(percent) {
+            Ok(speed) => speed,
+            Err(e) => {
+                ctx.say(format!("Failed: {e}")).await?;
+                return Ok(());
+            }
+        },
+        (Some(preset), None) => preset.into(),
+        (None, None) => {
+            let speed = effects.get().chain.speed();
+            ctx.say(format!("Songs play at {speed}.")).await?;
+            return Ok(());
+        }
+    };
+    effects.update(|effects| effects.chain.set_speed(speed));
+    ctx.say(format!("Speed set to {speed}.")).await?;
+
+    Ok(())
+}
+
+/// Turns the 8D effect on or off, the audio going around the listener
+#[poise::command(slash_command, prefix_command, guild_only, rename = "8d")]
+async fn auto_pan(
+    ctx: Context<'_>,
+    #[description = "Seconds the audio takes to go around, turns it on"]
+    #[min = 2]
+    #[max = 60]
+    period: Option<u16>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let effects = ctx.data().guild_effects(guild_id);
+
+    let auto_pan = match period.map(AutoPan::new).transpose() {
+        Ok(auto_pan) => auto_pan,
+        Err(e) => {
+            ctx.say(format!("Failed: {e}")).await?;
+            return Ok(());
+        }
+    };
+    let auto_pan = effects.update(|effects| {
+        let auto_pan = match auto_pan {
+            Some(auto_pan) => Some(auto_pan),
+            None if effects.chain.auto_pan().is_some() => None,
+            None => Some(AutoPan::default()),
+        };
+        effects.chain.set_auto_pan(auto_pan);
+        auto_pan
+    });
+    let msg = match auto_pan {
+        Some(auto_pan) => format!("8D is now on, {auto_pan}."),
+        None => "8D is now off.".to_string(),
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Toggles skipping the silence at the start and end of songs
+#[poise::command(slash_command, prefix_command, guild_only, rename = "trimsilence")]
+async fn trim_silence(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let effects = ctx.data().guild_effects(guild_id);
+
+    let trim_silence = effects.update(|effects| {
+        effects.trim_silence = !effects.trim_silence;
+        effects.trim_silence
+    });
+    let msg = if trim_silence {
+        "Silence at the start and end of songs is now skipped."
+    } else {
+        "Silence at the start and end of songs is now played."
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Effects of the chain to choose from
+#[derive(Debug, poise::ChoiceParameter)]
+enum EffectChoice {
+    #[name = "Equalizer"]
+    Equalizer,
+    #[name = "Bass boost"]
+    BassBoost,
+    #[name = "Speed"]
+    Speed,
+    #[name = "8D"]
+    AutoPan,
+}
+
+impl From<EffectChoice> for EffectKind {
+    fn from(choice: EffectChoice) -> Self {
+        match choice {
+            EffectChoice::Equalizer => EffectKind::Equalizer,
+            EffectChoice::BassBoost => EffectKind::BassBoost,
+            EffectChoice::Speed => EffectKind::Speed,
+            EffectChoice::AutoPan => EffectKind::AutoPan,
+        }
+    }
+}
+
+/// Shows the audio effects in the order they apply
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn effects(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let effects = ctx.data().guild_effects(guild_id).get();
+
+    let mut lines = effects
+        .chain
+        .iter()
+        .enumerate()
+        .map(|(index, effect)| format!("{}. {effect}", index + 1))
+        .collect::<Vec<_>>();
+    if effects.trim_silence {
+        lines.push("Silence trimmed".to_string());
+    }
+    let msg = if lines.is_empty() {
+        "No effects are on.".to_string()
+    } else {
+        format!("Effects, in the order they apply:\n{}", lines.join("\n"))
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Moves an effect to another place in the order effects apply
+#[poise::command(slash_command, prefix_command, guild_only, rename = "moveeffect")]
+async fn move_effect(
+    ctx: Context<'_>,
+    #[description = "Effect to move"] effect: EffectChoice,
+    #[description = "Place to move it to, from 1 for the first"]
+    #[min = 1]
+    position: usize,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let effects = ctx.data().guild_effects(guild_id);
+
+    let kind = EffectKind::from(effect);
+    // Places are 1-based for users
+    let index = position.saturating_sub(1);
+    match effects.update(|effects| effects.chain.move_to(kind, index)) {
+        Ok(()) => ctx.say(format!("Moved the {kind} to {position}.")).await?,
+        Err(e) => ctx.say(format!("Failed: {e}")).await?,
+    };
+
+    Ok(())
+}
+
+/// Turns an effect off
+#[poise::command(slash_command, prefix_command, guild_only, rename = "removeeffect")]
+async fn remove_effect(
+    ctx: Context<'_>,
+    #[description = "Effect to turn off"] effect: EffectChoice,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let effects = ctx.data().guild_effects(guild_id);
+
+    let kind = EffectKind::from(effect);
+    let msg = match effects.update(|effects| effects.chain.remove(kind)) {
+        Some(_) => format!("The {kind} is now off."),
+        None => format!("Failed: {}", EffectsError::NotInChain(kind)),
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Turns every audio effect off
+#[poise::command(slash_command, prefix_command, guild_only, rename = "reseteffects")]
+async fn reset_effects(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let effects = ctx.data().guild_effects(guild_id);
+
+    effects.update(|effects| {
+        effects.chain.reset();
+        effects.trim_silence = false;
+    });
+    ctx.say("All effects are now off.").await?;
+
+    Ok(())
+}
+
+/// Sorts the queue
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn sort(
+    ctx: Context<'_>,
+    #[description = "What to sort the queue by"] by: SortChoice,
+) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    custom_queue.sort_by(by.into()).await;
+    save_queue(ctx, &custom_queue).await;
+    ctx.say("Queue sorted.").await?;
+
+    Ok(())
+}
+
+/// Toggles shuffling newly added playlists into the queue
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn autoshuffle(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    let enabled = custom_queue.toggle_autoshuffle();
+    save_queue(ctx, &custom_queue).await;
+    ctx.say(format!(
+        "Autoshuffle {}.",
+        if enabled { "enabled" } else { "disabled" }
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Toggles playing tracks back to back without a gap, e.g. for albums
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn gapless(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    let enabled = custom_queue.toggle_gapless();
+    save_queue(ctx, &custom_queue).await;
+    ctx.say(format!(
+        "Gapless playback {}, starting with the next track.",
+        if enabled { "enabled" } else { "disabled" }
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Toggles announcing each track in the voice channel instead of in chat
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn announce(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    let enabled = custom_queue.toggle_announce();
+    save_queue(ctx, &custom_queue).await;
+    let msg = if enabled && ctx.data().tts.is_none() {
+        "Voice announcements enabled, but no text-to-speech server is set up.".to_string()
+    } else {
+        format!(
+            "Voice announcements {}, starting with the next track.",
+            if enabled { "enabled" } else { "disabled" }
+        )
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Toggles keeping the last seconds of the voice channel to clip, of users who agree to it
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn record(
+    ctx: Context<'_>,
+    #[description = "Seconds to keep, 30 by default"]
+    #[min = 1]
+    #[max = 120]
+    seconds: Option<u32>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let data = ctx.data();
+
+    let Some(handler_lock) = data.songbird.get(guild_id) else {
+        ctx.say("Not in a voice channel").await?;
+        return Ok(());
+    };
+    let mut handler = handler_lock.lock().await;
+    if data.stop_recording(guild_id) {
+        set_voice_decoding(&mut handler, false);
+        ctx.say("Recording stopped, what was recorded is gone.")
+            .await?;
+        return Ok(());
+    }
+    let seconds = seconds
+        .unwrap_or(DEFAULT_RECORDING_SECS)
+        .min(MAX_RECORDING_SECS);
+    data.start_recording(guild_id, &mut handler, seconds);
+    ctx.say(format!(
+        "Recording the last {seconds}s of the voice channel for /clip. \
+         Only users who agree with /recordconsent are recorded."
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Toggles agreeing to have your voice recorded for clips
+#[poise::command(slash_command, prefix_command, guild_only, rename = "recordconsent")]
+async fn record_consent(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let Some(recorder) = ctx.data().recorder(guild_id) else {
+        ctx.say("Not recording.").await?;
+        return Ok(());
+    };
+    let user_id = ctx.author().id;
+    let consent = !recorder.has_consent(user_id);
+    recorder.set_consent(user_id, consent);
+    let msg = if consent {
+        "Your voice is now recorded for clips."
+    } else {
+        "Your voice is no longer recorded, what was recorded is gone."
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Posts the last seconds of the voice channel as an audio file
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn clip(
+    ctx: Context<'_>,
+    #[description = "Seconds to clip, all that is kept by default"]
+    #[min = 1]
+    #[max = 120]
+    seconds: Option<u32>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let Some(recorder) = ctx.data().recorder(guild_id) else {
+        ctx.say("Not recording, turn it on with /record.").await?;
+        return Ok(());
+    };
+    if recorder.recorded().is_zero() {
+        ctx.say("Nothing recorded yet.").await?;
+        return Ok(());
+    }
+    // Sent straight from memory so no clip is left on disk, named after the
+    // command so clips never collide
+    let clip = recorder.clip(seconds);
+    ctx.send(
+        poise::CreateReply::default()
+            .content("Here's the clip.")
+            .attachment(serenity::CreateAttachment::bytes(
+                clip,
+                format!("clip-{}.wav", ctx.id()),
+            )),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Loops a background sound like rain or a fireplace under the music
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn ambience(
+    ctx: Context<'_>,
+    #[description = "URL of the sound to loop"] url: String,
+    #[description = "Volume in percent, 15 by default"]
+    #[max = 100]
+    volume: Option<u8>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let data = ctx.data();
+
+    let Some(handler_lock) = data.songbird.get(guild_id) else {
+        ctx.say("Not in a voice channel to play in").await?;
+        return Ok(());
+    };
+    let mut handler = handler_lock.lock().await;
+    let track = ResolvedTrack::new(QueryType::VideoLink(url)).with_user_id(ctx.author().id);
+    let volume = volume.unwrap_or(DEFAULT_AMBIENCE_VOLUME);
+    match data
+        .track_client
+        .start_ambience(guild_id, &mut handler, track, volume)
+        .await
+    {
+        Ok(_) => ctx.say(format!("Ambience playing at {volume}%.")).await?,
+        Err(e) => ctx.say(format!("Failed: {e}")).await?,
+    };
+
+    Ok(())
+}
+
+/// Shows or sets the volume of the ambience, apart from the music
+#[poise::command(slash_command, prefix_command, guild_only, rename = "ambiencevolume")]
+async fn ambience_volume(
+    ctx: Context<'_>,
+    #[description = "Volume in percent"]
+    #[max = 100]
+    level: Option<u8>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let client = &ctx.data().track_client;
+
+    let Some(level) = level else {
+        let msg = match client.ambience(guild_id) {
+            Some(ambience) => format!("Ambience volume is {}%.", ambience.volume),
+            None => "No ambience is playing.".to_string(),
+        };
+        ctx.say(msg).await?;
+        return Ok(());
+    };
+    match client.set_ambience_volume(guild_id, level) {
+        Ok(()) => ctx.say(format!("Ambience volume set to {level}%.")).await?,
+        Err(e) => ctx.say(format!("Failed: {e}")).await?,
+    };
+
+    Ok(())
+}
+
+/// Stops the ambience, the music playing on
+#[poise::command(slash_command, prefix_command, guild_only, rename = "stopambience")]
+async fn stop_ambience(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let msg = match ctx.data().track_client.stop_ambience(guild_id) {
+        Some(_) => "Ambience stopped.",
+        None => "No ambience is playing.",
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Toggles normalizing the loudness of tracks so they play at the same volume
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn normalize(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    let enabled = custom_queue.toggle_normalize();
+    save_queue(ctx, &custom_queue).await;
+    ctx.say(format!(
+        "Loudness normalization {}, starting with the next track.",
+        if enabled { "enabled" } else { "disabled" }
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Cycles the loop mode of the queue (off -> track -> queue)
+#[poise::command(slash_command, prefix_command, guild_only, rename = "loop")]
+async fn loop_mode(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    let loop_mode = custom_queue.cycle_loop_mode().await;
+    save_queue(ctx, &custom_queue).await;
+
+    // Keep a copy of the queue to restore once a looping queue runs out
+    let guild_id = ctx.guild_id().unwrap();
+    if loop_mode == LoopMode::Queue {
+        ctx.data()
+            .queue_backups
+            .insert(guild_id, custom_queue.backup().await);
+    } else {
+        ctx.data().queue_backups.remove(&guild_id);
+    }
+    ctx.say(format!("Loop mode: {loop_mode}")).await?;
+
+    Ok(())
+}
+
+/// Pings the bot
+#[poise::command(slash_command, prefix_command)]
+async fn ping(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    ctx.say("Pong!").await?;
+    Ok(())
+}
+
+/// Mutes the bot
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn mute(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let manager = ctx.data().songbird.clone();
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let mut handler = handler_lock.lock().await;
+
+        if handler.is_mute() {
+            ctx.say("Already muted").await?;
+        } else {
+            if let Err(e) = handler.mute(true).await {
+                ctx.say(format!("Failed: {:?}", e)).await?;
+            } else {
+                ctx.say("Now muted").await?;
+            }
+        }
+    } else {
+        ctx.say("Not in a voice channel").await?;
+    }
+
+    Ok(())
+}
+
+/// Unmutes the bot
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn unmute(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let manager = ctx.data().songbird.clone();
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let mut handler = handler_lock.lock().await;
+        if let Err(e) = handler.mute(false).await {
+            ctx.say(format!("Failed: {:?}", e)).await?;
+        } else {
+            ctx.say("Unmuted").await?;
+        }
+    } else {
+        ctx.say("Not in a voice channel to unmute in").await?;
+    }
+
+    Ok(())
+}
+
+/// Deafens the bot
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn deafen(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let manager = ctx.data().songbird.clone();
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let mut handler = handler_lock.lock().await;
 
         if handler.is_deaf() {
             ctx.say("Already deafened").await?;
@@ -551,6 +1611,207 @@ async fn set_idle_timeout(
     Ok(())
 }
 
+/// Sets the maximum number of songs in the queue (0 = no limit)
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn set_max_queue_length(
+    ctx: Context<'_>,
+    #[description = "Maximum number of songs (0 = no limit)"] length: usize,
+) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    custom_queue.set_max_len(length);
+
+    if length == 0 {
+        ctx.say("Maximum queue length disabled.").await?;
+    } else {
+        ctx.say(format!("Maximum queue length set to {} songs.", length))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Shows the song that is playing, with the current title of a radio stream
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn now_playing(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    let Some(track) = custom_queue.current().await else {
+        ctx.say("Nothing is playing.").await?;
+        return Ok(());
+    };
+
+    let stream_title = if track.is_unbounded() {
+        cracktunes::now_playing(&ctx.data().http_client, &track.get_url())
+            .await
+            .unwrap_or_else(|e| {
+                println!("Error reading stream title: {}", e);
+                None
+            })
+    } else {
+        None
+    };
+    let guild_id = ctx.guild_id().unwrap();
+    let mut now_playing = NowPlaying::new(track)
+        .with_queue_len(custom_queue.len().await)
+        .with_stream_title(stream_title)
+        .with_effects(&ctx.data().guild_effects(guild_id).get());
+    if let Ok(state) = ctx.data().playback_state(guild_id).await {
+        now_playing = now_playing.with_elapsed(state.position);
+    }
+    ctx.send(poise::CreateReply::default().embed(now_playing.embed()))
+        .await?;
+
+    Ok(())
+}
+
+/// Sets the maximum number of songs a user can have queued (0 = no limit)
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn set_max_user_tracks(
+    ctx: Context<'_>,
+    #[description = "Maximum number of songs per user (0 = no limit)"] length: usize,
+) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    custom_queue.set_max_per_user(length);
+
+    if length == 0 {
+        ctx.say("Per-user queue limit disabled.").await?;
+    } else {
+        ctx.say(format!("Users can now queue at most {} songs.", length))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Pauses the current song
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn pause(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    match ctx.data().pause(guild_id).await {
+        Ok(state) => {
+            ctx.say(format!(
+                "Paused at {}.",
+                get_human_readable_timestamp(Some(state.position))
+            ))
+            .await?
+        }
+        Err(e) => ctx.say(format!("Failed: {e}")).await?,
+    };
+
+    Ok(())
+}
+
+/// Plays the current song again from the start
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn restart(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    match ctx.data().restart(guild_id).await {
+        Ok(track) => ctx.say(format!("Restarted {}.", track.get_title())).await?,
+        Err(e) => ctx.say(format!("Failed: {e}")).await?,
+    };
+
+    Ok(())
+}
+
+/// Resumes the paused song
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn resume(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    match ctx.data().resume(guild_id).await {
+        Ok(state) => {
+            ctx.say(format!(
+                "Resumed at {}.",
+                get_human_readable_timestamp(Some(state.position))
+            ))
+            .await?
+        }
+        Err(e) => ctx.say(format!("Failed: {e}")).await?,
+    };
+
+    Ok(())
+}
+
+/// Shows or sets the playback volume (0-200%), kept for the next songs
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn volume(
+    ctx: Context<'_>,
+    #[description = "Volume in percent, 100 is unchanged"]
+    #[max = 200]
+    level: Option<u8>,
+) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    let Some(level) = level else {
+        ctx.say(format!("Volume is {}%.", custom_queue.volume()))
+            .await?;
+        return Ok(());
+    };
+    if let Err(e) = custom_queue.set_volume(level) {
+        ctx.say(format!("Failed: {e}")).await?;
+        return Ok(());
+    }
+    save_queue(ctx, &custom_queue).await;
+
+    // Change the song playing now too
+    let guild_id = ctx.guild_id().unwrap();
+    if let Some(song) = ctx.data().current_tracks.get(&guild_id) {
+        let _ = song.set_volume(ctx.data().track_volume(guild_id, &custom_queue));
+    }
+    ctx.say(format!("Volume set to {level}%.")).await?;
+
+    Ok(())
+}
+
+/// Stops recently played songs from being queued again (no options = allow them)
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn no_repeat(
+    ctx: Context<'_>,
+    #[description = "Number of last played songs that can't be queued"] tracks: Option<usize>,
+    #[description = "Minutes before a played song can be queued again"] minutes: Option<u64>,
+) -> Result<(), serenity::Error> {
+    let custom_queue = get_queue(ctx).await.map_err(|e| {
+        println!("Error getting queue: {}", e);
+        serenity::Error::Other("Failed to get queue")
+    })?;
+
+    let window = match (tracks, minutes) {
+        (_, Some(minutes)) => Some(RecentWindow::Duration(Duration::from_secs(
+            minutes.saturating_mul(60),
+        ))),
+        (Some(tracks), None) => Some(RecentWindow::Tracks(tracks)),
+        (None, None) => None,
+    };
+    custom_queue.set_recent_window(window).await;
+
+    let msg = match window {
+        Some(RecentWindow::Duration(_)) => format!(
+            "Songs played in the last {} minutes can't be queued again.",
+            minutes.unwrap_or_default()
+        ),
+        Some(RecentWindow::Tracks(n)) => {
+            format!("The last {n} songs played can't be queued again.")
+        }
+        None => "Recently played songs can be queued again.".to_string(),
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
 /// Undeafens the bot
 #[poise::command(slash_command, prefix_command, guild_only)]
 async fn undeafen(ctx: Context<'_>) -> Result<(), serenity::Error> {
@@ -582,6 +1843,43 @@ async fn main() {
 
     let manager = songbird::Songbird::serenity();
 
+    // Restore the queues saved before the last shutdown
+    let queue_store = QueueStore::new(
+        env::var("QUEUE_STORE_DIR").unwrap_or_else(|_| DEFAULT_QUEUE_STORE_DIR.to_string()),
+    );
+    let guild_queues = Arc::new(queue_store.load_all().await.unwrap_or_else(|e| {
+        println!("Error loading saved queues: {:?}", e);
+        dashmap::DashMap::new()
+    }));
+    let guild_queues_clone = Arc::clone(&guild_queues);
+    let queue_store_clone = queue_store.clone();
+
+    // YouTube credentials for age-restricted videos and a PO token, passed to yt-dlp
+    let mut ytdl_args = match YoutubeCredentials::from_env() {
+        Ok(credentials) => credentials
+            .map(|credentials| credentials.ytdl_args())
+            .unwrap_or_default(),
+        Err(e) => {
+            println!("Error loading YouTube credentials: {:?}", e);
+            Vec::new()
+        }
+    };
+    if let Some(po_token) = PoToken::from_env() {
+        ytdl_args.extend(po_token.ytdl_args());
+    }
+
+    // Keep the audio of tracks played often on disk if a directory is set
+    let audio_cache = AudioCache::from_env();
+    if let Some(audio_cache) = &audio_cache {
+        if let Err(e) = audio_cache.load().await {
+            println!("Error loading the audio cache: {:?}", e);
+        }
+    }
+
+    // Voice track announcements if a text-to-speech server is set
+    let tts = HttpTtsProvider::from_env(HttpClient::new())
+        .map(|tts| Arc::new(tts) as Arc<dyn TtsProvider>);
+
     let manager_clone = Arc::clone(&manager);
     // Set up the poise framework
     let framework = poise::Framework::builder()
@@ -592,16 +1890,70 @@ async fn main() {
                 leave(),
                 play_url(),
                 queue(),
+                queue_next(),
+                queue_playlist(),
                 skip(),
+                skip_to(),
                 stop(),
                 show_queue(),
+                now_playing(),
                 shuffle(),
+                move_track(),
+                undo(),
+                sort(),
+                export(),
+                autoshuffle(),
+                gapless(),
+                normalize(),
+                announce(),
+                record(),
+                record_consent(),
+                clip(),
+                ambience(),
+                ambience_volume(),
+                stop_ambience(),
+                equalizer(),
+                eq_band(),
+                bass_boost(),
+                speed(),
+                auto_pan(),
+                trim_silence(),
+                effects(),
+                move_effect(),
+                remove_effect(),
+                reset_effects(),
+                loop_mode(),
                 mute(),
                 unmute(),
                 deafen(),
                 undeafen(),
                 set_idle_timeout(),
+                set_max_queue_length(),
+                set_max_user_tracks(),
+                volume(),
+                pause(),
+                resume(),
+                restart(),
+                no_repeat(),
             ],
+            event_handler: |ctx, event, _framework, data| {
+                Box::pin(async move {
+                    match event {
+                        // Drop the state of guilds the bot was removed from, an
+                        // unavailable guild is only an outage.
+                        serenity::FullEvent::GuildDelete { incomplete, .. } => {
+                            if !incomplete.unavailable {
+                                forget_guild(data, incomplete.id).await;
+                            }
+                        }
+                        serenity::FullEvent::InteractionCreate {
+                            interaction: serenity::Interaction::Component(component),
+                        } => handle_queue_page(ctx, data, component).await?,
+                        _ => {}
+                    }
+                    Ok(())
+                })
+            },
             // Maybe one day
             // prefix_options: poise::PrefixFrameworkOptions {
             //     prefix: Some("~".into()),
@@ -615,8 +1967,23 @@ async fn main() {
                 Ok(Data(DataInner {
                     songbird: Arc::clone(&manager_clone),
                     http_client: HttpClient::new(),
-                    guild_queues: dashmap::DashMap::new(),
+                    guild_queues: guild_queues_clone,
+                    queue_backups: Arc::new(dashmap::DashMap::new()),
+                    paginators: Arc::new(dashmap::DashMap::new()),
                     idle_timeouts: dashmap::DashMap::new(),
+                    queue_store: queue_store_clone,
+                    ytdl_args,
+                    current_tracks: Arc::new(dashmap::DashMap::new()),
+                    preloaded: Arc::new(dashmap::DashMap::new()),
+                    staged: Arc::new(dashmap::DashMap::new()),
+                    track_gains: Arc::new(dashmap::DashMap::new()),
+                    effects: Arc::new(dashmap::DashMap::new()),
+                    stalled: Arc::new(dashmap::DashMap::new()),
+                    loudness_cache: LruCache::new(LOUDNESS_CACHE_CAPACITY, LOUDNESS_CACHE_TTL),
+                    audio_cache,
+                    tts,
+                    recorders: Arc::new(dashmap::DashMap::new()),
+                    track_client: CrackTrackClient::new(),
                 }))
             })
         })
@@ -629,6 +1996,19 @@ async fn main() {
         .await
         .expect("Error creating client");
 
+    // Save the queues and shut down cleanly on ctrl-c
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            println!("Could not register ctrl-c handler");
+            return;
+        }
+        if let Err(e) = queue_store.save_all(&guild_queues).await {
+            println!("Error saving queues: {:?}", e);
+        }
+        shard_manager.shutdown_all().await;
+    });
+
     let _ = client
         .start()
         .await
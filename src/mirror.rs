@@ -0,0 +1,333 @@
+use crate::ResolvedTrack;
+use crack_types::{AuxMetadata, Error, QueryType};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Parts of error messages of videos that can't be played in the region of
+/// the bot, which a mirror elsewhere may be able to play.
+const REGION_BLOCKED_MARKERS: &[&str] = &[
+    "not available in your country",
+    "blocked it in your country",
+    "not made this video available in your country",
+    "available in your region",
+    "geo restricted",
+    "geo-restricted",
+];
+
+/// Check if an error is from a video being blocked in the region of the bot.
+#[must_use]
+pub fn is_region_blocked(error: &(dyn std::error::Error + 'static)) -> bool {
+    let message = error.to_string().to_lowercase();
+    REGION_BLOCKED_MARKERS
+        .iter()
+        .any(|marker| message.contains(marker))
+}
+
+/// Kind of YouTube frontend a [`MirrorInstance`] runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MirrorKind {
+    Invidious,
+    Piped,
+}
+
+/// An Invidious or Piped instance, which fetches and proxies videos from its
+/// own region, so it can play videos blocked in the region of the bot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MirrorInstance {
+    pub kind: MirrorKind,
+    /// Base URL of the instance, for Piped of its API.
+    pub base_url: String,
+}
+
+/// A video as listed by a mirror, with a stream proxied by the mirror.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MirrorVideo {
+    pub video_id: String,
+    pub title: String,
+    pub artist: String,
+    pub duration: Duration,
+    pub thumbnail: Option<String>,
+    pub stream_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InvidiousVideo {
+    title: String,
+    author: String,
+    length_seconds: u64,
+    #[serde(default)]
+    video_thumbnails: Vec<InvidiousThumbnail>,
+    #[serde(default)]
+    adaptive_formats: Vec<InvidiousFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InvidiousThumbnail {
+    url: String,
+}
+
+/// A format of an Invidious video. Some instances give the numbers as
+/// strings.
+#[derive(Debug, Deserialize)]
+struct InvidiousFormat {
+    itag: Value,
+    #[serde(rename = "type")]
+    mime_type: String,
+    #[serde(default)]
+    bitrate: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PipedStreams {
+    title: String,
+    uploader: String,
+    duration: u64,
+    thumbnail_url: Option<String>,
+    #[serde(default)]
+    audio_streams: Vec<PipedStream>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PipedStream {
+    url: String,
+    #[serde(default)]
+    bitrate: u64,
+}
+
+/// Get a number that may be given as a string.
+fn number(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+/// Implement [`MirrorInstance`].
+impl MirrorInstance {
+    /// Create a new [`MirrorInstance`] of an Invidious instance.
+    #[must_use]
+    pub fn invidious(base_url: impl Into<String>) -> Self {
+        MirrorInstance {
+            kind: MirrorKind::Invidious,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Create a new [`MirrorInstance`] of the API of a Piped instance.
+    #[must_use]
+    pub fn piped(api_url: impl Into<String>) -> Self {
+        MirrorInstance {
+            kind: MirrorKind::Piped,
+            base_url: api_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Get a video and the best audio stream of it from the mirror.
+    /// # Errors
+    /// Returns an error if the request fails or the video has no audio.
+    pub async fn fetch(
+        &self,
+        client: &reqwest::Client,
+        video_id: &str,
+    ) -> Result<MirrorVideo, Error> {
+        match self.kind {
+            MirrorKind::Invidious => {
+                let url = format!("{}/api/v1/videos/{video_id}", self.base_url);
+                let video: InvidiousVideo = client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                self.invidious_video(video_id, video)
+            }
+            MirrorKind::Piped => {
+                let url = format!("{}/streams/{video_id}", self.base_url);
+                let streams: PipedStreams = client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Self::piped_video(video_id, streams)
+            }
+        }
+    }
+
+    /// Build a [`MirrorVideo`] from an Invidious video. The stream is
+    /// proxied through the instance, as the URLs of the formats are only
+    /// valid in its region.
+    fn invidious_video(&self, video_id: &str, video: InvidiousVideo) -> Result<MirrorVideo, Error> {
+        let itag = video
+            .adaptive_formats
+            .iter()
+            .filter(|format| format.mime_type.starts_with("audio/"))
+            .max_by_key(|format| number(&format.bitrate).unwrap_or_default())
+            .and_then(|format| number(&format.itag))
+            .ok_or("Invidious: the video has no audio formats")?;
+        let thumbnail = video.video_thumbnails.into_iter().next().map(|thumbnail| {
+            if thumbnail.url.starts_with('/') {
+                format!("{}{}", self.base_url, thumbnail.url)
+            } else {
+                thumbnail.url
+            }
+        });
+        Ok(MirrorVideo {
+            video_id: video_id.to_string(),
+            title: video.title,
+            artist: video.author,
+            duration: Duration::from_secs(video.length_seconds),
+            thumbnail,
+            stream_url: format!(
+                "{}/latest_version?id={video_id}&itag={itag}&local=true",
+                self.base_url
+            ),
+        })
+    }
+
+    /// Build a [`MirrorVideo`] from the streams of a Piped video, which are
+    /// already proxied.
+    fn piped_video(video_id: &str, streams: PipedStreams) -> Result<MirrorVideo, Error> {
+        let stream = streams
+            .audio_streams
+            .into_iter()
+            .max_by_key(|stream| stream.bitrate)
+            .ok_or("Piped: the video has no audio streams")?;
+        Ok(MirrorVideo {
+            video_id: video_id.to_string(),
+            title: streams.title,
+            artist: streams.uploader,
+            duration: Duration::from_secs(streams.duration),
+            thumbnail: streams.thumbnail_url,
+            stream_url: stream.url,
+        })
+    }
+}
+
+/// Implement [`FromStr`] for [`MirrorInstance`], parsing `invidious:<url>` or
+/// `piped:<api url>`.
+impl FromStr for MirrorInstance {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(':') {
+            Some(("invidious", url)) => Ok(MirrorInstance::invidious(url)),
+            Some(("piped", url)) => Ok(MirrorInstance::piped(url)),
+            _ => Err(format!("Invalid mirror {s}, expected invidious:<url> or piped:<url>").into()),
+        }
+    }
+}
+
+/// Implement [`Display`] for [`MirrorInstance`].
+impl Display for MirrorInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            MirrorKind::Invidious => write!(f, "invidious:{}", self.base_url),
+            MirrorKind::Piped => write!(f, "piped:{}", self.base_url),
+        }
+    }
+}
+
+/// Implement [`From<MirrorVideo>`] for [`ResolvedTrack`], streaming from the
+/// mirror instead of through yt-dlp.
+impl From<MirrorVideo> for ResolvedTrack {
+    fn from(video: MirrorVideo) -> Self {
+        let url = format!("https://www.youtube.com/watch?v={}", video.video_id);
+        let metadata = AuxMetadata {
+            title: Some(video.title),
+            artist: Some(video.artist.clone()),
+            channel: Some(video.artist),
+            duration: Some(video.duration),
+            thumbnail: video.thumbnail,
+            source_url: Some(url.clone()),
+            ..Default::default()
+        };
+        ResolvedTrack::new(QueryType::VideoLink(url))
+            .with_metadata(metadata)
+            .with_stream_url(video.stream_url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_region_blocked() {
+        let error: Error =
+            "Video unavailable: The uploader has not made this video available in your country"
+                .into();
+        assert!(is_region_blocked(&*error));
+        let error: Error = "Video is private".into();
+        assert!(!is_region_blocked(&*error));
+    }
+
+    #[test]
+    fn test_parse_mirrors() {
+        let mirror: MirrorInstance = "invidious:https://yewtu.be/".parse().unwrap();
+        assert_eq!(mirror, MirrorInstance::invidious("https://yewtu.be"));
+        assert_eq!(mirror.to_string(), "invidious:https://yewtu.be");
+        let mirror: MirrorInstance = "piped:https://pipedapi.kavin.rocks".parse().unwrap();
+        assert_eq!(mirror.kind, MirrorKind::Piped);
+        assert!("https://yewtu.be".parse::<MirrorInstance>().is_err());
+    }
+
+    #[test]
+    fn test_invidious_video() {
+        let json = r#"{
+            "title": "Song",
+            "author": "Artist",
+            "lengthSeconds": 212,
+            "videoThumbnails": [{"url": "/vi/abc/maxres.jpg"}],
+            "adaptiveFormats": [
+                {"itag": "137", "type": "video/mp4", "bitrate": "4000000"},
+                {"itag": "140", "type": "audio/mp4", "bitrate": "130000"},
+                {"itag": 251, "type": "audio/webm", "bitrate": 160000}
+            ]
+        }"#;
+        let mirror = MirrorInstance::invidious("https://yewtu.be");
+        let video = mirror
+            .invidious_video("abc", serde_json::from_str(json).unwrap())
+            .unwrap();
+        assert_eq!(
+            video.stream_url,
+            "https://yewtu.be/latest_version?id=abc&itag=251&local=true"
+        );
+        assert_eq!(
+            video.thumbnail.as_deref(),
+            Some("https://yewtu.be/vi/abc/maxres.jpg")
+        );
+
+        let track = ResolvedTrack::from(video);
+        assert_eq!(track.get_title(), "Song");
+        assert_eq!(track.get_url(), "https://www.youtube.com/watch?v=abc");
+        assert!(track.stream_url().is_some());
+    }
+
+    #[test]
+    fn test_piped_video() {
+        let json = r#"{
+            "title": "Song",
+            "uploader": "Artist",
+            "duration": 212,
+            "thumbnailUrl": "https://proxy.example/vi/abc.jpg",
+            "audioStreams": [
+                {"url": "https://proxy.example/low", "bitrate": 48000},
+                {"url": "https://proxy.example/high", "bitrate": 160000}
+            ]
+        }"#;
+        let video =
+            MirrorInstance::piped_video("abc", serde_json::from_str(json).unwrap()).unwrap();
+        assert_eq!(video.stream_url, "https://proxy.example/high");
+        assert_eq!(video.duration, Duration::from_secs(212));
+        let json = r#"{"title": "Song", "uploader": "Artist", "duration": 1}"#;
+        assert!(MirrorInstance::piped_video("abc", serde_json::from_str(json).unwrap()).is_err());
+    }
+}
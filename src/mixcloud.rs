@@ -0,0 +1,273 @@
+use crate::ResolvedTrack;
+use crack_types::{AuxMetadata, Error, QueryType};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Base URL of the public Mixcloud API.
+const MIXCLOUD_API_URL: &str = "https://api.mixcloud.com";
+
+static MIXCLOUD_URL_REGEX_STR: &str = r"^(?:https?://)?(?:www\.|m\.)?mixcloud\.com/([^/?#]+)(?:/([^/?#]+))?(?:/([^/?#]+))?/?(?:[?#].*)?$";
+static MIXCLOUD_URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(MIXCLOUD_URL_REGEX_STR).unwrap());
+
+/// First parts of Mixcloud paths that aren't users.
+const RESERVED_PATHS: [&str; 7] = [
+    "discover",
+    "categories",
+    "live",
+    "upload",
+    "settings",
+    "select",
+    "search",
+];
+
+/// What a Mixcloud URL points to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MixcloudUrl {
+    /// A single show, or mix, of a user.
+    Cloudcast { user: String, slug: String },
+    /// The shows a user uploaded.
+    User(String),
+    /// A playlist of shows of a user.
+    Playlist { user: String, slug: String },
+}
+
+/// Implement [`MixcloudUrl`].
+impl MixcloudUrl {
+    /// URL of the API for the item, a list for users and playlists.
+    fn api_url(&self) -> String {
+        match self {
+            MixcloudUrl::Cloudcast { user, slug } => format!("{MIXCLOUD_API_URL}/{user}/{slug}/"),
+            MixcloudUrl::User(user) => format!("{MIXCLOUD_API_URL}/{user}/cloudcasts/"),
+            MixcloudUrl::Playlist { user, slug } => {
+                format!("{MIXCLOUD_API_URL}/{user}/playlists/{slug}/cloudcasts/")
+            }
+        }
+    }
+}
+
+/// Parse a Mixcloud URL, returning `None` if it isn't one or points to
+/// something other than shows, like the discover pages.
+#[must_use]
+pub fn parse_mixcloud_url(url: &str) -> Option<MixcloudUrl> {
+    let caps = MIXCLOUD_URL_REGEX.captures(url.trim())?;
+    let user = caps[1].to_string();
+    if RESERVED_PATHS.contains(&user.as_str()) {
+        return None;
+    }
+    match (caps.get(2).map(|m| m.as_str()), caps.get(3)) {
+        (None | Some("uploads"), None) => Some(MixcloudUrl::User(user)),
+        (Some("playlists"), Some(slug)) => Some(MixcloudUrl::Playlist {
+            user,
+            slug: slug.as_str().to_string(),
+        }),
+        (Some("playlists"), None) => None,
+        (Some(slug), None) => Some(MixcloudUrl::Cloudcast {
+            user,
+            slug: slug.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Check if a URL points to shows on Mixcloud.
+#[must_use]
+pub fn is_mixcloud_url(url: &str) -> bool {
+    parse_mixcloud_url(url).is_some()
+}
+
+/// A response of the Mixcloud API, which reports errors in the body.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MixcloudResponse<T> {
+    Error { error: MixcloudApiError },
+    Ok(T),
+}
+
+/// An error reported by the Mixcloud API.
+#[derive(Debug, Deserialize)]
+struct MixcloudApiError {
+    message: String,
+}
+
+/// A page of a Mixcloud list, with the URL of the next one.
+#[derive(Debug, Deserialize)]
+struct MixcloudPage<T> {
+    data: Vec<T>,
+    #[serde(default)]
+    paging: Option<MixcloudPaging>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MixcloudPaging {
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MixcloudUser {
+    name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MixcloudPictures {
+    large: Option<String>,
+    extra_large: Option<String>,
+}
+
+/// A show of the Mixcloud API, its length is in seconds.
+#[derive(Debug, Deserialize)]
+struct Cloudcast {
+    name: String,
+    url: String,
+    #[serde(default)]
+    audio_length: Option<u64>,
+    user: MixcloudUser,
+    #[serde(default)]
+    pictures: MixcloudPictures,
+}
+
+/// Implement [`Cloudcast`].
+impl Cloudcast {
+    /// Convert to a [`ResolvedTrack`], streamed from its page by yt-dlp.
+    fn into_track(self) -> ResolvedTrack {
+        let metadata = AuxMetadata {
+            title: Some(self.name),
+            artist: Some(self.user.name.clone()),
+            channel: Some(self.user.name),
+            duration: self.audio_length.map(Duration::from_secs),
+            thumbnail: self.pictures.extra_large.or(self.pictures.large),
+            source_url: Some(self.url.clone()),
+            ..Default::default()
+        };
+        ResolvedTrack::new(QueryType::VideoLink(self.url)).with_metadata(metadata)
+    }
+}
+
+/// Client for Mixcloud links using the public Mixcloud API for the
+/// metadata, no credentials are needed. The audio is streamed by yt-dlp.
+#[derive(Clone, Debug)]
+pub struct MixcloudClient {
+    client: reqwest::Client,
+}
+
+/// Implement [`MixcloudClient`].
+impl MixcloudClient {
+    /// Create a new [`MixcloudClient`].
+    #[must_use]
+    pub fn new(client: reqwest::Client) -> Self {
+        MixcloudClient { client }
+    }
+
+    /// Get the shows a Mixcloud show, user or playlist URL points to, up to
+    /// `limit` of them, newest first for users.
+    /// # Errors
+    /// Returns an error if the URL isn't a Mixcloud URL or a request fails.
+    pub async fn tracks(&self, url: &str, limit: u64) -> Result<Vec<ResolvedTrack>, Error> {
+        let Some(parsed) = parse_mixcloud_url(url) else {
+            return Err(format!("Not a Mixcloud URL: {url}").into());
+        };
+        match parsed {
+            MixcloudUrl::Cloudcast { .. } => {
+                let cloudcast: Cloudcast = self.get(&parsed.api_url()).await?;
+                Ok(vec![cloudcast.into_track()])
+            }
+            MixcloudUrl::User(_) | MixcloudUrl::Playlist { .. } => {
+                let cloudcasts = self.get_all(&parsed.api_url(), limit).await?;
+                Ok(cloudcasts.into_iter().map(Cloudcast::into_track).collect())
+            }
+        }
+    }
+
+    /// Get an object from the API.
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        let response: MixcloudResponse<T> = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match response {
+            MixcloudResponse::Ok(value) => Ok(value),
+            MixcloudResponse::Error { error } => Err(format!("Mixcloud: {}", error.message).into()),
+        }
+    }
+
+    /// Get up to `limit` shows of a list from the API, following its pages.
+    async fn get_all(&self, url: &str, limit: u64) -> Result<Vec<Cloudcast>, Error> {
+        let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+        let mut items = Vec::new();
+        let mut next = Some(url.to_string());
+        while let Some(url) = next.filter(|_| items.len() < limit) {
+            let page: MixcloudPage<Cloudcast> = self.get(&url).await?;
+            items.extend(page.data);
+            next = page.paging.and_then(|paging| paging.next);
+        }
+        items.truncate(limit);
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mixcloud_url() {
+        assert_eq!(
+            parse_mixcloud_url("https://www.mixcloud.com/spartacus/party-time/"),
+            Some(MixcloudUrl::Cloudcast {
+                user: "spartacus".to_string(),
+                slug: "party-time".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_mixcloud_url("https://m.mixcloud.com/spartacus/"),
+            Some(MixcloudUrl::User("spartacus".to_string()))
+        );
+        assert_eq!(
+            parse_mixcloud_url("mixcloud.com/spartacus/uploads/?order=latest"),
+            Some(MixcloudUrl::User("spartacus".to_string()))
+        );
+        assert_eq!(
+            parse_mixcloud_url("https://www.mixcloud.com/spartacus/playlists/best-of/"),
+            Some(MixcloudUrl::Playlist {
+                user: "spartacus".to_string(),
+                slug: "best-of".to_string(),
+            })
+        );
+        assert!(!is_mixcloud_url("https://www.mixcloud.com/discover/house/"));
+        assert!(!is_mixcloud_url("https://www.youtube.com/watch?v=abc"));
+    }
+
+    #[test]
+    fn test_mixcloud_response() {
+        let json = r#"{"data": [
+            {"key": "/spartacus/party-time/", "name": "Party Time", "audio_length": 3600,
+             "url": "https://www.mixcloud.com/spartacus/party-time/",
+             "user": {"name": "Spartacus", "username": "spartacus"},
+             "pictures": {"large": "https://thumbnailer.mixcloud.com/large.jpg"}}
+        ], "paging": {"next": "https://api.mixcloud.com/spartacus/cloudcasts/?offset=20"}}"#;
+        let response: MixcloudResponse<MixcloudPage<Cloudcast>> =
+            serde_json::from_str(json).unwrap();
+        let MixcloudResponse::Ok(page) = response else {
+            panic!("Expected a page");
+        };
+        assert!(page.paging.and_then(|paging| paging.next).is_some());
+        let track = page.data.into_iter().next().unwrap().into_track();
+        assert_eq!(track.get_title(), "Party Time");
+        assert_eq!(
+            track.get_url(),
+            "https://www.mixcloud.com/spartacus/party-time/"
+        );
+        assert_eq!(track.get_raw_duration(), Some(Duration::from_secs(3600)));
+
+        let json = r#"{"error": {"type": "NotFound", "message": "Not found"}}"#;
+        let response: MixcloudResponse<Cloudcast> = serde_json::from_str(json).unwrap();
+        assert!(matches!(response, MixcloudResponse::Error { .. }));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{AudioEffects, ResolvedTrack, Speed, LIVE_DURATION};
+use crack_types::get_human_readable_timestamp;
+use serenity::all::{CreateEmbed, CreateEmbedFooter};
+use std::time::Duration;
+
+/// Number of segments of the progress bar of a [`NowPlaying`] embed.
+pub const PROGRESS_BAR_WIDTH: usize = 20;
+
+/// Render how far into a track playback is as a text bar, like
+/// `▬▬▬▬🔘▬▬▬▬▬`, `width` segments long.
+#[must_use]
+pub fn progress_bar(elapsed: Duration, total: Duration, width: usize) -> String {
+    let width = width.max(1);
+    let position = if total.is_zero() {
+        0
+    } else {
+        let ratio = elapsed.as_secs_f64() / total.as_secs_f64();
+        ((ratio.clamp(0.0, 1.0) * width as f64) as usize).min(width - 1)
+    };
+    let mut bar = "▬".repeat(position);
+    bar.push('🔘');
+    bar.push_str(&"▬".repeat(width - 1 - position));
+    bar
+}
+
+/// Builder of the embed showing the track playing in a guild: its
+/// thumbnail, title and artist, or the song on air for a radio station,
+/// requester, elapsed time with a progress bar, the part played of clips,
+/// the audio effects on and the tracks left in the queue.
+#[derive(Clone, Debug)]
+pub struct NowPlaying {
+    track: ResolvedTrack,
+    elapsed: Option<Duration>,
+    queue_len: Option<usize>,
+    stream_title: Option<String>,
+    effects: Vec<String>,
+    speed: Speed,
+}
+
+/// Implement [`NowPlaying`].
+impl NowPlaying {
+    /// Create a new [`NowPlaying`] for a track that just started.
+    #[must_use]
+    pub fn new(track: ResolvedTrack) -> Self {
+        NowPlaying {
+            track,
+            elapsed: None,
+            queue_len: None,
+            stream_title: None,
+            effects: Vec::new(),
+            speed: Speed::NORMAL,
+        }
+    }
+
+    /// Set how far into the track playback is.
+    #[must_use]
+    pub fn with_elapsed(mut self, elapsed: Duration) -> Self {
+        self.elapsed = Some(elapsed);
+        self
+    }
+
+    /// Set the number of tracks left in the queue.
+    #[must_use]
+    pub fn with_queue_len(mut self, queue_len: usize) -> Self {
+        self.queue_len = Some(queue_len);
+        self
+    }
+
+    /// Set the title of the song an internet radio station is playing.
+    #[must_use]
+    pub fn with_stream_title(mut self, stream_title: Option<String>) -> Self {
+        self.stream_title = stream_title;
+        self
+    }
+
+    /// Set the audio effects of the guild, which the track plays with at
+    /// its own speed if it has one.
+    #[must_use]
+    pub fn with_effects(mut self, effects: &AudioEffects) -> Self {
+        let effects = effects.clone().for_track(self.track.speed());
+        self.effects = effects.describe();
+        self.speed = effects.chain.speed();
+        self
+    }
+
+    /// Render the elapsed and total time, with a progress bar if the length
+    /// of the track is known. The total is how long the track takes at the
+    /// speed it plays at.
+    #[must_use]
+    pub fn time_line(&self) -> String {
+        let elapsed = self.elapsed.unwrap_or_default();
+        let elapsed_str = get_human_readable_timestamp(Some(elapsed));
+        match self.track.get_raw_duration() {
+            _ if self.track.is_unbounded() => format!("🔴 {LIVE_DURATION} • {elapsed_str}"),
+            Some(total) if !total.is_zero() => {
+                let total = self.speed.playback_time(total);
+                format!(
+                    "{} {elapsed_str} / {}",
+                    progress_bar(elapsed, total, PROGRESS_BAR_WIDTH),
+                    get_human_readable_timestamp(Some(total))
+                )
+            }
+            _ => format!("{elapsed_str} / {}", self.track.get_duration()),
+        }
+    }
+
+    /// Build the embed.
+    #[must_use]
+    pub fn embed(&self) -> CreateEmbed {
+        let mut description = format!("[{}]({})", self.track.get_title(), self.track.get_url());
+        if let Some(stream_title) = &self.stream_title {
+            description = format!("{stream_title}\non {description}");
+        } else if let Some(artist) = self.track.get_artist() {
+            description.push_str(&format!("\nby {artist}"));
+        }
+        let mut embed = CreateEmbed::new()
+            .title("Now Playing")
+            .description(description)
+            .field("Requested by", self.track.requester_mention(), true)
+            .field("Time", self.time_line(), false);
+        if let Some(clip) = self.track.clip_description() {
+            embed = embed.field("Clip", clip, true);
+        }
+        if !self.effects.is_empty() {
+            embed = embed.field("Effects", self.effects.join("\n"), false);
+        }
+        if let Some(thumbnail) = self.track.get_thumbnail() {
+            embed = embed.thumbnail(thumbnail);
+        }
+        if let Some(queue_len) = self.queue_len {
+            let footer = match queue_len {
+                0 => "Nothing else is queued".to_string(),
+                1 => "1 song left in the queue".to_string(),
+                n => format!("{n} songs left in the queue"),
+            };
+            embed = embed.footer(CreateEmbedFooter::new(footer));
+        }
+        embed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crack_types::{AuxMetadata, QueryType};
+
+    #[test]
+    fn test_progress_bar() {
+        let total = Duration::from_secs(100);
+        assert_eq!(progress_bar(Duration::ZERO, total, 5), "🔘▬▬▬▬");
+        assert_eq!(progress_bar(Duration::from_secs(50), total, 5), "▬▬🔘▬▬");
+        assert_eq!(progress_bar(Duration::from_secs(200), total, 5), "▬▬▬▬🔘");
+        assert_eq!(
+            progress_bar(Duration::from_secs(5), Duration::ZERO, 3),
+            "🔘▬▬"
+        );
+    }
+
+    #[test]
+    fn test_time_line() {
+        let track = ResolvedTrack::new(QueryType::VideoLink(
+            "https://www.youtube.com/watch?v=abc".to_string(),
+        ))
+        .with_metadata(AuxMetadata {
+            title: Some("Song".to_string()),
+            duration: Some(Duration::from_secs(200)),
+            ..Default::default()
+        });
+        let now_playing = NowPlaying::new(track.clone()).with_elapsed(Duration::from_secs(100));
+        let time_line = now_playing.time_line();
+        assert!(time_line.starts_with(&progress_bar(
+            Duration::from_secs(100),
+            Duration::from_secs(200),
+            PROGRESS_BAR_WIDTH
+        )));
+        assert!(time_line.ends_with(&format!(
+            "{} / {}",
+            get_human_readable_timestamp(Some(Duration::from_secs(100))),
+            get_human_readable_timestamp(Some(Duration::from_secs(200)))
+        )));
+
+        // A nightcore track is over sooner
+        let nightcore = NowPlaying::new(track.clone().with_speed(Some(Speed::NIGHTCORE)))
+            .with_effects(&AudioEffects::default());
+        assert!(nightcore.time_line().ends_with(&format!(
+            "/ {}",
+            get_human_readable_timestamp(Some(Duration::from_secs(160)))
+        )));
+
+        let radio = NowPlaying::new(track.with_unbounded(true));
+        assert!(radio.time_line().starts_with("🔴 LIVE"));
+    }
+}
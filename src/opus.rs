@@ -0,0 +1,37 @@
+use rusty_ytdl::VideoFormat;
+
+/// Whether a stream is Opus audio in a WebM container, which songbird
+/// passes through to Discord without decoding and re-encoding it, as long as
+/// the volume is untouched and no other track is mixed in.
+#[must_use]
+pub fn is_opus_stream(container: &str, codecs: &[String]) -> bool {
+    container.eq_ignore_ascii_case("webm")
+        && codecs
+            .iter()
+            .any(|codec| codec.trim().eq_ignore_ascii_case("opus"))
+}
+
+/// Pick the audio-only WebM/Opus format with the highest bitrate, if the
+/// video has any.
+#[must_use]
+pub fn opus_format(formats: &[VideoFormat]) -> Option<&VideoFormat> {
+    formats
+        .iter()
+        .filter(|format| format.has_audio && !format.has_video && !format.is_hls)
+        .filter(|format| is_opus_stream(&format.mime_type.container, &format.mime_type.codecs))
+        .max_by_key(|format| format.audio_bitrate.unwrap_or(format.bitrate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_opus_stream() {
+        assert!(is_opus_stream("webm", &["opus".to_string()]));
+        assert!(is_opus_stream("WebM", &[" Opus".to_string()]));
+        assert!(!is_opus_stream("webm", &["vorbis".to_string()]));
+        assert!(!is_opus_stream("mp4", &["mp4a.40.2".to_string()]));
+        assert!(!is_opus_stream("webm", &[]));
+    }
+}
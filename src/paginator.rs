@@ -0,0 +1,212 @@
+use crate::QUEUE_PAGINATOR_TIMEOUT;
+use serenity::all::{
+    ButtonStyle, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter,
+    CreateInteractionResponse, CreateInteractionResponseMessage,
+};
+use std::time::Instant;
+
+/// Prefix of the custom IDs of the queue paginator buttons.
+pub const QUEUE_PAGE_BUTTON_PREFIX: &str = "queue_page";
+
+/// A button of a [`QueuePaginator`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageAction {
+    First,
+    Prev,
+    Next,
+    Last,
+}
+
+/// Implement [`PageAction`].
+impl PageAction {
+    const ALL: [PageAction; 4] = [
+        PageAction::First,
+        PageAction::Prev,
+        PageAction::Next,
+        PageAction::Last,
+    ];
+
+    /// Name of the action, used in the button's custom ID.
+    fn name(self) -> &'static str {
+        match self {
+            PageAction::First => "first",
+            PageAction::Prev => "prev",
+            PageAction::Next => "next",
+            PageAction::Last => "last",
+        }
+    }
+
+    /// Label shown on the button.
+    fn label(self) -> &'static str {
+        match self {
+            PageAction::First => "⏮",
+            PageAction::Prev => "◀",
+            PageAction::Next => "▶",
+            PageAction::Last => "⏭",
+        }
+    }
+
+    /// Custom ID of the button for this action.
+    #[must_use]
+    pub fn custom_id(self) -> String {
+        format!("{QUEUE_PAGE_BUTTON_PREFIX}:{}", self.name())
+    }
+
+    /// Parse the custom ID of a component interaction, returning `None` if it
+    /// isn't one of the queue paginator buttons.
+    #[must_use]
+    pub fn from_custom_id(custom_id: &str) -> Option<Self> {
+        let name = custom_id
+            .strip_prefix(QUEUE_PAGE_BUTTON_PREFIX)?
+            .strip_prefix(':')?;
+        PageAction::ALL
+            .into_iter()
+            .find(|action| action.name() == name)
+    }
+}
+
+/// Page state of a queue display message, browsed with buttons.
+#[derive(Clone, Debug)]
+pub struct QueuePaginator {
+    pages: Vec<String>,
+    page: usize,
+    created: Instant,
+}
+
+/// Implement [`QueuePaginator`].
+impl QueuePaginator {
+    /// Create a new [`QueuePaginator`] showing the given page, 0-based.
+    #[must_use]
+    pub fn new(pages: Vec<String>, page: usize) -> Self {
+        let page = page.min(pages.len().saturating_sub(1));
+        QueuePaginator {
+            pages,
+            page,
+            created: Instant::now(),
+        }
+    }
+
+    /// The page currently shown, 0-based.
+    #[must_use]
+    pub fn page(&self) -> usize {
+        self.page
+    }
+
+    /// Number of pages.
+    #[must_use]
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Whether the paginator is older than [`QUEUE_PAGINATOR_TIMEOUT`] and
+    /// its buttons should no longer be handled.
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.created.elapsed() > QUEUE_PAGINATOR_TIMEOUT
+    }
+
+    /// Apply a button press, returning `true` if the page changed.
+    pub fn apply(&mut self, action: PageAction) -> bool {
+        let last = self.pages.len().saturating_sub(1);
+        let page = match action {
+            PageAction::First => 0,
+            PageAction::Prev => self.page.saturating_sub(1),
+            PageAction::Next => (self.page + 1).min(last),
+            PageAction::Last => last,
+        };
+        let changed = page != self.page;
+        self.page = page;
+        changed
+    }
+
+    /// Build the embed for the current page.
+    #[must_use]
+    pub fn embed(&self) -> CreateEmbed {
+        let description = self
+            .pages
+            .get(self.page)
+            .map_or("The queue is empty.", String::as_str);
+        CreateEmbed::new()
+            .title("Current Queue")
+            .description(description)
+            .footer(CreateEmbedFooter::new(format!(
+                "Page {}/{}",
+                self.page + 1,
+                self.pages.len().max(1)
+            )))
+    }
+
+    /// Build the navigation buttons, disabling the ones that would not move.
+    #[must_use]
+    pub fn components(&self) -> Vec<CreateActionRow> {
+        if self.pages.len() <= 1 {
+            return Vec::new();
+        }
+        let at_start = self.page == 0;
+        let at_end = self.page + 1 >= self.pages.len();
+        let buttons = PageAction::ALL
+            .into_iter()
+            .map(|action| {
+                let disabled = match action {
+                    PageAction::First | PageAction::Prev => at_start,
+                    PageAction::Next | PageAction::Last => at_end,
+                };
+                CreateButton::new(action.custom_id())
+                    .label(action.label())
+                    .style(ButtonStyle::Secondary)
+                    .disabled(disabled)
+            })
+            .collect();
+        vec![CreateActionRow::Buttons(buttons)]
+    }
+
+    /// Build the response updating the paginated message to the current page.
+    #[must_use]
+    pub fn update_response(&self) -> CreateInteractionResponse {
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .embed(self.embed())
+                .components(self.components()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_action_custom_id() {
+        for action in PageAction::ALL {
+            assert_eq!(
+                PageAction::from_custom_id(&action.custom_id()),
+                Some(action)
+            );
+        }
+        assert_eq!(PageAction::from_custom_id("queue_page:middle"), None);
+        assert_eq!(PageAction::from_custom_id("something_else:next"), None);
+    }
+
+    #[test]
+    fn test_paginator_apply() {
+        let pages = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+        let mut paginator = QueuePaginator::new(pages, 10);
+        assert_eq!(paginator.page(), 2);
+        assert_eq!(paginator.num_pages(), 3);
+
+        assert!(!paginator.apply(PageAction::Next));
+        assert!(paginator.apply(PageAction::Prev));
+        assert_eq!(paginator.page(), 1);
+        assert!(paginator.apply(PageAction::First));
+        assert_eq!(paginator.page(), 0);
+        assert!(!paginator.apply(PageAction::Prev));
+        assert!(paginator.apply(PageAction::Last));
+        assert_eq!(paginator.page(), 2);
+        assert!(!paginator.is_expired());
+        assert_eq!(paginator.components().len(), 1);
+
+        // A single page needs no buttons
+        let paginator = QueuePaginator::new(vec!["1".to_string()], 0);
+        assert!(paginator.components().is_empty());
+    }
+}
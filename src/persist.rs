@@ -0,0 +1,433 @@
+use crate::{CrackTrackQueue, LoopMode, ResolvedTrack, Speed, MAX_VOLUME};
+use crack_types::{AuxMetadata, Error, QueryType};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, UserId};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Default directory the [`QueueStore`] writes to.
+pub const DEFAULT_QUEUE_STORE_DIR: &str = "queues";
+
+/// The serialized form of a [`ResolvedTrack`], used by its [`Serialize`] and
+/// [`Deserialize`] implementations. The live video handles are not kept, the
+/// track is re-resolved on playback.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedTrack {
+    pub url: String,
+    pub title: String,
+    pub duration_secs: Option<u64>,
+    pub user_id: UserId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unbounded: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub live: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<Speed>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<u8>,
+}
+
+/// Convert an offset to whole milliseconds for storage.
+fn duration_millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Implement [`From`] for [`&ResolvedTrack`] to [`PersistedTrack`].
+impl From<&ResolvedTrack> for PersistedTrack {
+    fn from(track: &ResolvedTrack) -> Self {
+        let metadata = track.get_metadata().unwrap_or_default();
+        PersistedTrack {
+            url: track.get_url(),
+            title: track.get_title(),
+            duration_secs: track.get_raw_duration().map(|d| d.as_secs()),
+            user_id: track.get_requesting_user(),
+            artist: metadata.artist,
+            album: metadata.album,
+            channel: metadata.channel,
+            date: metadata.date,
+            thumbnail: metadata.thumbnail,
+            unbounded: track.is_unbounded(),
+            live: track.is_live(),
+            start_ms: track.start_offset().map(duration_millis),
+            end_ms: track.end_offset().map(duration_millis),
+            speed: track.speed(),
+            volume: track.volume(),
+        }
+    }
+}
+
+/// Implement [`From`] for [`ResolvedTrack`] to [`PersistedTrack`].
+impl From<ResolvedTrack> for PersistedTrack {
+    fn from(track: ResolvedTrack) -> Self {
+        PersistedTrack::from(&track)
+    }
+}
+
+/// Implement [`From`] for [`PersistedTrack`] to [`ResolvedTrack`].
+impl From<PersistedTrack> for ResolvedTrack {
+    fn from(track: PersistedTrack) -> Self {
+        let metadata = AuxMetadata {
+            title: Some(track.title),
+            source_url: Some(track.url.clone()),
+            duration: track.duration_secs.map(Duration::from_secs),
+            artist: track.artist,
+            album: track.album,
+            channel: track.channel,
+            date: track.date,
+            thumbnail: track.thumbnail,
+            ..Default::default()
+        };
+        ResolvedTrack::new(QueryType::VideoLink(track.url))
+            .with_metadata(metadata)
+            .with_user_id(track.user_id)
+            .with_unbounded(track.unbounded)
+            .with_live(track.live)
+            .with_offsets(
+                track.start_ms.map(Duration::from_millis),
+                track.end_ms.map(Duration::from_millis),
+            )
+            .with_speed(track.speed)
+            .with_volume(track.volume)
+    }
+}
+
+/// A serializable snapshot of the state of a [`CrackTrackQueue`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    pub now_playing: Option<PersistedTrack>,
+    pub tracks: Vec<PersistedTrack>,
+    #[serde(default)]
+    pub loop_mode: LoopMode,
+    #[serde(default)]
+    pub autoshuffle: bool,
+    #[serde(default)]
+    pub gapless: bool,
+    #[serde(default)]
+    pub normalize: bool,
+    #[serde(default)]
+    pub announce: bool,
+    /// Volume in percent, `None` in snapshots from before it was saved.
+    #[serde(default)]
+    pub volume: Option<u8>,
+}
+
+impl QueueSnapshot {
+    /// Whether there is anything in the snapshot worth keeping.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.now_playing.is_none() && self.tracks.is_empty()
+    }
+}
+
+/// Implement snapshotting for [`CrackTrackQueue`].
+impl CrackTrackQueue {
+    /// Take a [`QueueSnapshot`] of the queue.
+    pub async fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            now_playing: self.get_playing().await.as_ref().map(PersistedTrack::from),
+            tracks: self
+                .read_with(|queue| queue.iter().map(PersistedTrack::from).collect())
+                .await,
+            loop_mode: self.loop_mode().await,
+            autoshuffle: self.autoshuffle(),
+            gapless: self.gapless(),
+            normalize: self.normalize(),
+            announce: self.announce(),
+            volume: Some(self.volume()),
+        }
+    }
+
+    /// Serialize the queue to JSON, including the playing track and loop mode.
+    /// # Errors
+    /// Returns an error if the queue cannot be serialized.
+    pub async fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self.snapshot().await)
+    }
+
+    /// Create a new [`CrackTrackQueue`] from JSON produced by [`CrackTrackQueue::to_json`].
+    /// # Errors
+    /// Returns an error if the JSON is not a valid queue.
+    pub async fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let snapshot: QueueSnapshot = serde_json::from_str(json)?;
+        Ok(CrackTrackQueue::from_snapshot(snapshot).await)
+    }
+
+    /// Create a new [`CrackTrackQueue`] from a [`QueueSnapshot`].
+    /// The track that was playing is put back at the front of the queue so
+    /// playback resumes with it.
+    pub async fn from_snapshot(snapshot: QueueSnapshot) -> Self {
+        let mut tracks = snapshot
+            .tracks
+            .into_iter()
+            .map(ResolvedTrack::from)
+            .collect::<VecDeque<_>>();
+        if let Some(now_playing) = snapshot.now_playing {
+            tracks.push_front(now_playing.into());
+        }
+        let queue = CrackTrackQueue::with_queue(tracks);
+        queue.set_loop_mode(snapshot.loop_mode).await;
+        queue.set_autoshuffle(snapshot.autoshuffle);
+        queue.set_gapless(snapshot.gapless);
+        queue.set_normalize(snapshot.normalize);
+        queue.set_announce(snapshot.announce);
+        if let Some(volume) = snapshot.volume {
+            let _ = queue.set_volume(volume.min(MAX_VOLUME));
+        }
+        queue
+    }
+}
+
+/// Stores guild queues on disk as one JSON file per guild.
+#[derive(Clone, Debug)]
+pub struct QueueStore {
+    dir: PathBuf,
+}
+
+/// Implement [`Default`] for [`QueueStore`].
+impl Default for QueueStore {
+    fn default() -> Self {
+        QueueStore::new(DEFAULT_QUEUE_STORE_DIR)
+    }
+}
+
+impl QueueStore {
+    /// Create a new [`QueueStore`] writing to the given directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        QueueStore { dir: dir.into() }
+    }
+
+    /// Get the directory the store writes to.
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the file holding the queue of a guild.
+    fn guild_path(&self, guild: GuildId) -> PathBuf {
+        self.dir.join(format!("{guild}.json"))
+    }
+
+    /// Save the queue of a guild. Empty queues remove the guild's file.
+    /// # Errors
+    /// Returns an error if the snapshot cannot be serialized or written.
+    pub async fn save(&self, guild: GuildId, queue: &CrackTrackQueue) -> Result<(), Error> {
+        let snapshot = queue.snapshot().await;
+        let path = self.guild_path(guild);
+        if snapshot.is_empty() {
+            return self.remove(guild).await;
+        }
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let json = serde_json::to_vec_pretty(&snapshot)?;
+        // Write to a temporary file first so a crash never leaves a truncated queue.
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, json).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    /// Remove the saved queue of a guild, if there is one.
+    /// # Errors
+    /// Returns an error if the file exists but cannot be removed.
+    pub async fn remove(&self, guild: GuildId) -> Result<(), Error> {
+        match tokio::fs::remove_file(self.guild_path(guild)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Load the queue of a guild, if one was saved.
+    /// # Errors
+    /// Returns an error if the file exists but cannot be read or parsed.
+    pub async fn load(&self, guild: GuildId) -> Result<Option<CrackTrackQueue>, Error> {
+        let json = match tokio::fs::read(self.guild_path(guild)).await {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let snapshot: QueueSnapshot = serde_json::from_slice(&json)?;
+        Ok(Some(CrackTrackQueue::from_snapshot(snapshot).await))
+    }
+
+    /// Save every queue in the map.
+    /// # Errors
+    /// Returns the first error encountered while saving.
+    pub async fn save_all(&self, queues: &DashMap<GuildId, CrackTrackQueue>) -> Result<(), Error> {
+        // Clone out of the map so no shard lock is held across the awaits.
+        let queues = queues
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        for (guild, queue) in queues {
+            self.save(guild, &queue).await?;
+        }
+        Ok(())
+    }
+
+    /// Load every saved queue into a new map.
+    /// # Errors
+    /// Returns an error if the store directory cannot be read or a queue cannot be loaded.
+    pub async fn load_all(&self) -> Result<DashMap<GuildId, CrackTrackQueue>, Error> {
+        let queues = DashMap::new();
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(queues),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(guild) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+                .filter(|id| *id != 0)
+                .map(GuildId::new)
+            else {
+                continue;
+            };
+            if let Some(queue) = self.load(guild).await? {
+                queues.insert(guild, queue);
+            }
+        }
+        Ok(queues)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_track(id: &str, user: u64) -> ResolvedTrack {
+        PersistedTrack {
+            url: format!("https://www.youtube.com/watch?v={id}"),
+            title: format!("Track {id}"),
+            duration_secs: Some(180),
+            user_id: UserId::new(user),
+            artist: None,
+            album: None,
+            channel: None,
+            date: None,
+            thumbnail: None,
+            unbounded: false,
+            live: false,
+            start_ms: None,
+            end_ms: None,
+            speed: None,
+            volume: None,
+        }
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let queue = CrackTrackQueue::new();
+        queue.enqueue(create_test_track("1", 1)).await.unwrap();
+        queue
+            .enqueue(
+                create_test_track("2", 2)
+                    .with_speed(Some(Speed::NIGHTCORE))
+                    .with_volume(Some(50)),
+            )
+            .await
+            .unwrap();
+        queue.set_playing(Some(create_test_track("0", 3))).await;
+        queue.set_loop_mode(LoopMode::Queue).await;
+        queue.set_volume(60).unwrap();
+        queue.set_gapless(true);
+        queue.set_normalize(true);
+        queue.set_announce(true);
+
+        let snapshot = queue.snapshot().await;
+        assert_eq!(snapshot.tracks.len(), 2);
+        assert_eq!(snapshot.now_playing.as_ref().unwrap().title, "Track 0");
+        assert_eq!(snapshot.tracks[1].user_id, UserId::new(2));
+        assert_eq!(snapshot.tracks[1].duration_secs, Some(180));
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let parsed: QueueSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, snapshot);
+
+        // The playing track is restored at the front of the queue
+        let restored = CrackTrackQueue::from_snapshot(parsed).await;
+        assert_eq!(restored.len().await, 3);
+        assert_eq!(restored.get(0).await.unwrap().get_title(), "Track 0");
+        assert_eq!(restored.get(2).await.unwrap().get_title(), "Track 2");
+        assert_eq!(
+            restored.get(2).await.unwrap().speed(),
+            Some(Speed::NIGHTCORE)
+        );
+        assert_eq!(restored.get(2).await.unwrap().volume(), Some(50));
+        assert_eq!(restored.loop_mode().await, LoopMode::Queue);
+        assert_eq!(restored.volume(), 60);
+        assert!(restored.gapless());
+        assert!(restored.normalize());
+        assert!(restored.announce());
+    }
+
+    #[tokio::test]
+    async fn test_queue_json_round_trip() {
+        let queue = CrackTrackQueue::new();
+        queue.enqueue(create_test_track("1", 1)).await.unwrap();
+        queue.enqueue(create_test_track("2", 2)).await.unwrap();
+
+        let json = queue.to_json().await.unwrap();
+        assert!(json.contains("https://www.youtube.com/watch?v=2"));
+
+        let restored = CrackTrackQueue::from_json(&json).await.unwrap();
+        assert_eq!(restored.len().await, 2);
+        let track = restored.get(1).await.unwrap();
+        assert_eq!(track.get_title(), "Track 2");
+        assert_eq!(track.get_requesting_user(), UserId::new(2));
+
+        assert!(CrackTrackQueue::from_json("not json").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queue_store_save_load() {
+        let dir = std::env::temp_dir().join(format!("cracktunes-queues-{}", std::process::id()));
+        let store = QueueStore::new(&dir);
+        let guild = GuildId::new(42);
+
+        let queue = CrackTrackQueue::new();
+        queue.enqueue(create_test_track("1", 1)).await.unwrap();
+        queue.enqueue(create_test_track("2", 1)).await.unwrap();
+        store.save(guild, &queue).await.unwrap();
+
+        let loaded = store.load(guild).await.unwrap().unwrap();
+        assert_eq!(loaded.len().await, 2);
+        assert_eq!(
+            loaded.get(1).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=2"
+        );
+
+        let all = store.load_all().await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert!(all.contains_key(&guild));
+
+        // Saving an empty queue removes the file
+        queue.clear().await;
+        store.save(guild, &queue).await.unwrap();
+        assert!(store.load(guild).await.unwrap().is_none());
+        assert!(store.load(GuildId::new(7)).await.unwrap().is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{DataInner, ResolvedTrack};
+use serenity::all::GuildId;
+use songbird::error::ControlError;
+use songbird::tracks::{PlayMode, TrackHandle};
+use std::time::Duration;
+
+/// Errors that can occur when controlling the track playing in a guild.
+#[derive(Debug, thiserror::Error)]
+pub enum PlaybackError {
+    #[error("Nothing is playing")]
+    NothingPlaying,
+    #[error("Playback is already paused")]
+    AlreadyPaused,
+    #[error("Playback isn't paused")]
+    NotPaused,
+    #[error("Failed to control the track: {0}")]
+    Control(ControlError),
+}
+
+/// Implement [`From`] for [`ControlError`] to [`PlaybackError`], a track
+/// that finished means nothing is playing.
+impl From<ControlError> for PlaybackError {
+    fn from(error: ControlError) -> Self {
+        match error {
+            ControlError::Finished => PlaybackError::NothingPlaying,
+            error => PlaybackError::Control(error),
+        }
+    }
+}
+
+/// Whether the track playing in a guild is paused, and how far into it
+/// playback is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlaybackState {
+    pub paused: bool,
+    pub position: Duration,
+}
+
+/// Implement controlling playback for [`DataInner`], through the handles of
+/// the tracks playing kept in `current_tracks`.
+impl DataInner {
+    /// Get the handle of the track playing in a guild.
+    fn current_track(&self, guild_id: GuildId) -> Result<TrackHandle, PlaybackError> {
+        self.current_tracks
+            .get(&guild_id)
+            .map(|song| song.clone())
+            .ok_or(PlaybackError::NothingPlaying)
+    }
+
+    /// Get the state of the track playing in a guild.
+    /// # Errors
+    /// Returns [`PlaybackError::NothingPlaying`] if no track is playing or
+    /// paused.
+    pub async fn playback_state(&self, guild_id: GuildId) -> Result<PlaybackState, PlaybackError> {
+        let info = self.current_track(guild_id)?.get_info().await?;
+        let paused = match info.playing {
+            PlayMode::Play => false,
+            PlayMode::Pause => true,
+            _ => return Err(PlaybackError::NothingPlaying),
+        };
+        Ok(PlaybackState {
+            paused,
+            position: info.position,
+        })
+    }
+
+    /// Pause the track playing in a guild.
+    /// # Errors
+    /// Returns [`PlaybackError::AlreadyPaused`] if it is paused already, or
+    /// an error if nothing is playing.
+    pub async fn pause(&self, guild_id: GuildId) -> Result<PlaybackState, PlaybackError> {
+        let state = self.playback_state(guild_id).await?;
+        if state.paused {
+            return Err(PlaybackError::AlreadyPaused);
+        }
+        self.current_track(guild_id)?.pause()?;
+        Ok(PlaybackState {
+            paused: true,
+            ..state
+        })
+    }
+
+    /// Resume the paused track of a guild.
+    /// # Errors
+    /// Returns [`PlaybackError::NotPaused`] if it is playing already, or an
+    /// error if nothing is playing.
+    pub async fn resume(&self, guild_id: GuildId) -> Result<PlaybackState, PlaybackError> {
+        let state = self.playback_state(guild_id).await?;
+        if !state.paused {
+            return Err(PlaybackError::NotPaused);
+        }
+        self.current_track(guild_id)?.play()?;
+        Ok(PlaybackState {
+            paused: false,
+            ..state
+        })
+    }
+
+    /// Restart the track playing in a guild from the top, leaving the rest of
+    /// its queue as it is. The track is rewound if it can be, otherwise it is
+    /// put back at the front of the queue and stopped, so it is played again
+    /// from scratch, see [`crate::CrackTrackQueue::requeue_current`].
+    /// # Errors
+    /// Returns [`PlaybackError::NothingPlaying`] if no track is playing.
+    pub async fn restart(&self, guild_id: GuildId) -> Result<ResolvedTrack, PlaybackError> {
+        let song = self.current_track(guild_id)?;
+        let queue = self
+            .guild_queues
+            .get(&guild_id)
+            .map(|queue| queue.clone())
+            .ok_or(PlaybackError::NothingPlaying)?;
+        let track = queue.current().await.ok_or(PlaybackError::NothingPlaying)?;
+
+        // Streams can't be rewound, and the events ending clips or staging
+        // gapless tracks count the time played, so they'd go off too soon
+        let rewind = !track.is_unbounded()
+            && !track.is_live()
+            && track.end_offset().is_none()
+            && !queue.gapless();
+        if rewind {
+            let start = self
+                .playback_speed(guild_id, &track)
+                .playback_time(track.start_offset().unwrap_or_default());
+            match song.seek_async(start).await {
+                Ok(_) => return Ok(track),
+                Err(ControlError::Finished) => return Err(PlaybackError::NothingPlaying),
+                Err(_e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to rewind {}: {_e}", track.get_url());
+                }
+            }
+        }
+        queue.requeue_current().await;
+        song.stop()?;
+        Ok(track)
+    }
+}
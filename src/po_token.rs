@@ -0,0 +1,125 @@
+use crack_types::Error;
+use serde::Deserialize;
+use serenity::async_trait;
+use std::fmt::Debug;
+
+/// A proof of origin token, with the visitor data it was generated for.
+/// YouTube blocks more and more requests without one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoToken {
+    pub po_token: String,
+    pub visitor_data: String,
+}
+
+/// Implement [`PoToken`].
+impl PoToken {
+    /// Create a new [`PoToken`].
+    #[must_use]
+    pub fn new(po_token: impl Into<String>, visitor_data: impl Into<String>) -> Self {
+        PoToken {
+            po_token: po_token.into(),
+            visitor_data: visitor_data.into(),
+        }
+    }
+
+    /// Load a token from `YOUTUBE_PO_TOKEN` and `YOUTUBE_VISITOR_DATA`.
+    /// Returns `None` unless both are set.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let po_token = std::env::var("YOUTUBE_PO_TOKEN").ok()?;
+        let visitor_data = std::env::var("YOUTUBE_VISITOR_DATA").ok()?;
+        Some(PoToken::new(po_token, visitor_data))
+    }
+
+    /// Arguments passing the token to yt-dlp's YouTube extractor.
+    #[must_use]
+    pub fn ytdl_args(&self) -> Vec<String> {
+        vec![
+            "--extractor-args".to_string(),
+            format!(
+                "youtube:po_token=web.gvs+{};visitor_data={}",
+                self.po_token, self.visitor_data
+            ),
+        ]
+    }
+}
+
+/// Hook for an external source of fresh PO tokens, asked before each
+/// request to YouTube.
+#[async_trait]
+pub trait PoTokenProvider: Debug + Send + Sync {
+    /// Get a token to use for the next request.
+    async fn po_token(&self) -> Result<PoToken, Error>;
+}
+
+/// Response of a PO token server.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PoTokenResponse {
+    po_token: String,
+    content_binding: String,
+}
+
+/// [`PoTokenProvider`] asking a PO token server speaking the bgutil HTTP
+/// API, like `bgutil-ytdlp-pot-provider`, for a token.
+#[derive(Clone, Debug)]
+pub struct HttpPoTokenProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+/// Implement [`HttpPoTokenProvider`].
+impl HttpPoTokenProvider {
+    /// Create a new [`HttpPoTokenProvider`] for the server at `url`, e.g.
+    /// `http://127.0.0.1:4416`.
+    #[must_use]
+    pub fn new(client: reqwest::Client, url: impl Into<String>) -> Self {
+        HttpPoTokenProvider {
+            client,
+            url: url.into(),
+        }
+    }
+}
+
+/// Implement [`PoTokenProvider`] for [`HttpPoTokenProvider`].
+#[async_trait]
+impl PoTokenProvider for HttpPoTokenProvider {
+    async fn po_token(&self) -> Result<PoToken, Error> {
+        let response: PoTokenResponse = self
+            .client
+            .post(format!("{}/get_pot", self.url.trim_end_matches('/')))
+            .json(&serde_json::json!({}))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // The content binding of a token not bound to a video is its visitor data
+        Ok(PoToken::new(response.po_token, response.content_binding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_po_token_response() {
+        let json = r#"{"poToken": "abc", "contentBinding": "CgtWaXNpdG9y", "expiresAt": "2026-10-16T12:00:00Z"}"#;
+        let response: PoTokenResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.po_token, "abc");
+        assert_eq!(response.content_binding, "CgtWaXNpdG9y");
+    }
+
+    #[test]
+    fn test_ytdl_args() {
+        let token = PoToken::new("abc", "def");
+        assert_eq!(
+            token.ytdl_args(),
+            vec![
+                "--extractor-args".to_string(),
+                "youtube:po_token=web.gvs+abc;visitor_data=def".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{
+    track_input_with_args, track_normalization_gain, CrackTrackQueue, DataInner, ResolvedTrack,
+};
+use serenity::all::GuildId;
+use songbird::input::{File, Input};
+
+/// The stream of the next track of a guild, opened while the track before it
+/// plays.
+pub struct PreloadedInput {
+    /// See [`ResolvedTrack::identity`].
+    identity: String,
+    input: Input,
+}
+
+/// Whether a track is worth opening ahead of time. Endless streams and
+/// livestreams would be behind by the time they play, and local files open
+/// instantly anyway.
+#[must_use]
+pub fn should_preload(track: &ResolvedTrack) -> bool {
+    !track.is_unbounded() && !track.is_live() && track.local_path().is_none()
+}
+
+/// Implement preloading the next track for [`DataInner`], through the
+/// streams kept in `preloaded`.
+impl DataInner {
+    /// Open the stream of the next track in the queue of a guild in the
+    /// background, so it can start as soon as the current one ends. At most
+    /// one stream is kept per guild, replacing any older one, and it is only
+    /// read from once it plays, so memory stays bounded. With normalization
+    /// on, the loudness of the track is measured beforehand.
+    pub fn preload_next(&self, guild_id: GuildId, queue: &CrackTrackQueue) {
+        let preloaded = self.preloaded.clone();
+        let client = self.http_client.clone();
+        let ytdl_args = self.ytdl_args.clone();
+        let loudness_cache = self.loudness_cache.clone();
+        let audio_cache = self.audio_cache.clone();
+        let queue = queue.clone();
+        tokio::spawn(async move {
+            let Some(track) = queue.get(0).await.filter(should_preload).filter(|track| {
+                // Cached audio opens as fast as a local file
+                !audio_cache
+                    .as_ref()
+                    .is_some_and(|cache| cache.get(track).is_some())
+            }) else {
+                preloaded.remove(&guild_id);
+                return;
+            };
+            // Measure its loudness now too so it is normalized as it starts
+            if queue.normalize() {
+                let _ = track_normalization_gain(
+                    client.clone(),
+                    ytdl_args.clone(),
+                    &loudness_cache,
+                    &track,
+                )
+                .await;
+            }
+            let identity = track.identity();
+            if preloaded
+                .get(&guild_id)
+                .is_some_and(|preload| preload.identity == identity)
+            {
+                return;
+            }
+            let input = match track_input_with_args(client, &track, ytdl_args)
+                .make_live_async()
+                .await
+            {
+                Ok(input) => input,
+                Err(_e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to preload {}: {_e}", track.get_url());
+                    return;
+                }
+            };
+            // The queue may have changed while the stream was opening
+            if queue
+                .get(0)
+                .await
+                .is_some_and(|next| next.same_track_as(&track))
+            {
+                preloaded.insert(guild_id, PreloadedInput { identity, input });
+            }
+        });
+    }
+
+    /// Take the preloaded stream of a guild if it is the stream of `track`.
+    /// A preloaded stream of another track is dropped.
+    pub fn take_preloaded(&self, guild_id: GuildId, track: &ResolvedTrack) -> Option<Input> {
+        let (_, preload) = self.preloaded.remove(&guild_id)?;
+        (preload.identity == track.identity()).then_some(preload.input)
+    }
+
+    /// Open the audio of a track from the [`crate::AudioCache`] if it is
+    /// cached. Otherwise the play is counted, and the audio of tracks played
+    /// often enough is downloaded in the background for their next play.
+    pub fn cached_input(&self, track: &ResolvedTrack) -> Option<Input> {
+        let audio_cache = self.audio_cache.as_ref()?;
+        if let Some(path) = audio_cache.get(track) {
+            return Some(File::new(path).into());
+        }
+        if audio_cache.record_play(track) {
+            let audio_cache = audio_cache.clone();
+            let track = track.clone();
+            tokio::spawn(async move {
+                if let Err(_e) = audio_cache.store(&track).await {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Failed to cache the audio of {}: {_e}", track.get_url());
+                }
+            });
+        }
+        None
+    }
+
+    /// Build the songbird input for the track about to play in a guild,
+    /// using its preloaded stream or cached audio if there is one, with the
+    /// audio effects of the guild.
+    pub fn next_input(&self, guild_id: GuildId, track: &ResolvedTrack) -> Input {
+        let input = self
+            .cached_input(track)
+            .or_else(|| self.take_preloaded(guild_id, track))
+            .unwrap_or_else(|| {
+                track_input_with_args(self.http_client.clone(), track, self.ytdl_args.clone())
+            });
+        self.with_effects(guild_id, track, input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crack_types::QueryType;
+
+    #[test]
+    fn test_should_preload() {
+        let track = ResolvedTrack::new(QueryType::VideoLink(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+        ));
+        assert!(should_preload(&track));
+        assert!(!should_preload(&track.clone().with_live(true)));
+        assert!(!should_preload(&track.with_unbounded(true)));
+    }
+}
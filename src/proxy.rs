@@ -0,0 +1,170 @@
+use crate::{configured_reqwest_builder, YoutubeCredentials};
+use crack_types::Error;
+use rusty_ytdl::search::YouTube;
+use rusty_ytdl::RequestOptions;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Build a configured reqwest client sending every request through `proxy`,
+/// an `http://` or `https://` URL.
+/// # Errors
+/// Returns an error if the proxy URL is invalid or the client can't be built.
+pub fn build_proxied_reqwest_client(proxy: &str) -> Result<reqwest::Client, Error> {
+    Ok(configured_reqwest_builder()
+        .proxy(reqwest::Proxy::all(proxy)?)
+        .build()?)
+}
+
+/// A way to send requests to YouTube: an HTTP client, with the credentials
+/// and through a proxy or from a source address if any are set, and a search
+/// client using it.
+#[derive(Clone, Debug)]
+pub struct YoutubeRoute {
+    pub client: reqwest::Client,
+    pub search: YouTube,
+    pub proxy: Option<String>,
+    pub local_address: Option<IpAddr>,
+}
+
+/// Implement [`YoutubeRoute`].
+impl YoutubeRoute {
+    /// Build a route through `proxy`, or a direct one.
+    /// # Errors
+    /// Returns an error if the proxy URL is invalid or a client can't be built.
+    pub fn new(
+        proxy: Option<String>,
+        credentials: Option<&YoutubeCredentials>,
+    ) -> Result<Self, Error> {
+        YoutubeRoute::build(proxy, None, credentials)
+    }
+
+    /// Build a direct route sending requests from `local_address`.
+    /// # Errors
+    /// Returns an error if a client can't be built.
+    pub fn from_local_address(
+        local_address: IpAddr,
+        credentials: Option<&YoutubeCredentials>,
+    ) -> Result<Self, Error> {
+        YoutubeRoute::build(None, Some(local_address), credentials)
+    }
+
+    /// Build a route.
+    fn build(
+        proxy: Option<String>,
+        local_address: Option<IpAddr>,
+        credentials: Option<&YoutubeCredentials>,
+    ) -> Result<Self, Error> {
+        let mut builder = configured_reqwest_builder().local_address(local_address);
+        if let Some(proxy) = &proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        if let Some(credentials) = credentials {
+            builder = credentials.apply(builder)?;
+        }
+        let client = builder.build()?;
+        let search = YouTube::new_with_options(&RequestOptions {
+            client: Some(client.clone()),
+            cookies: credentials.and_then(YoutubeCredentials::cookie_header),
+            ..Default::default()
+        })?;
+        Ok(YoutubeRoute {
+            client,
+            search,
+            proxy,
+            local_address,
+        })
+    }
+
+    /// Arguments sending yt-dlp through the same proxy or from the same
+    /// source address.
+    #[must_use]
+    pub fn ytdl_args(&self) -> Vec<String> {
+        let proxy = self
+            .proxy
+            .iter()
+            .flat_map(|proxy| ["--proxy".to_string(), proxy.clone()]);
+        let local_address = self
+            .local_address
+            .iter()
+            .flat_map(|address| ["--source-address".to_string(), address.to_string()]);
+        proxy.chain(local_address).collect()
+    }
+}
+
+/// Routes to YouTube handed out in turn, so requests are spread over a pool
+/// of proxies. Clones share the rotation.
+#[derive(Clone, Debug, Default)]
+pub struct YoutubeRoutes {
+    routes: Arc<Vec<YoutubeRoute>>,
+    next: Arc<AtomicUsize>,
+}
+
+/// Implement [`YoutubeRoutes`].
+impl YoutubeRoutes {
+    /// Create a new [`YoutubeRoutes`] over `routes`.
+    #[must_use]
+    pub fn new(routes: Vec<YoutubeRoute>) -> Self {
+        YoutubeRoutes {
+            routes: Arc::new(routes),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Get the route for the next request, `None` if there are none.
+    #[must_use]
+    pub fn next_route(&self) -> Option<&YoutubeRoute> {
+        if self.routes.is_empty() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.routes.len();
+        self.routes.get(index)
+    }
+
+    /// Number of routes.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Whether there are no routes, so requests go out directly.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_youtube_routes_rotate() {
+        let proxies = ["http://127.0.0.1:8080", "http://127.0.0.2:8080"];
+        let routes = YoutubeRoutes::new(
+            proxies
+                .iter()
+                .map(|proxy| YoutubeRoute::new(Some((*proxy).to_string()), None).unwrap())
+                .collect(),
+        );
+        assert_eq!(routes.len(), 2);
+        let picked: Vec<_> = (0..4)
+            .map(|_| routes.next_route().unwrap().proxy.clone().unwrap())
+            .collect();
+        assert_eq!(picked, vec![proxies[0], proxies[1], proxies[0], proxies[1]]);
+        assert_eq!(
+            routes.next_route().unwrap().ytdl_args(),
+            vec!["--proxy".to_string(), proxies[0].to_string()]
+        );
+
+        assert!(YoutubeRoutes::default().next_route().is_none());
+        let address: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_eq!(
+            YoutubeRoute::from_local_address(address, None)
+                .unwrap()
+                .ytdl_args(),
+            vec!["--source-address".to_string(), "2001:db8::1".to_string()]
+        );
+        assert!(YoutubeRoute::new(Some("not a proxy".to_string()), None).is_err());
+    }
+}
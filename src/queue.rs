@@ -1,26 +1,317 @@
+use crate::extract_video_id;
+use crate::CanonicalUrl;
 use crate::ResolvedTrack;
+use crate::DEFAULT_HISTORY_SIZE;
+use crate::DEFAULT_MAX_QUEUE_LENGTH;
+use crate::DEFAULT_PAGE_SIZE;
+use crate::DEFAULT_UNDO_SIZE;
+use crate::DEFAULT_VOLUME;
+use crate::DISCORD_MESSAGE_LIMIT;
 use crate::EMPTY_QUEUE;
+use crate::MAX_VOLUME;
+use crate::QUEUE_EVENT_CAPACITY;
 
 use rand::seq::SliceRandom;
-use std::collections::VecDeque;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serenity::all::UserId;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex, RwLock, RwLockWriteGuard};
+use tokio::task::JoinHandle;
+
+/// Errors that can occur when operating on a [`CrackTrackQueue`].
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum QueueError {
+    #[error("Index {index} is out of bounds for a queue of length {len}")]
+    IndexOutOfBounds { index: usize, len: usize },
+    #[error("The queue is full, it can hold at most {max} tracks")]
+    QueueFull { max: usize },
+    #[error("<@{user}> already has {max} tracks queued, the most a user can have")]
+    UserLimitReached { user: UserId, max: usize },
+    #[error("{title} was played too recently to queue it again")]
+    RecentlyPlayed { title: String },
+    #[error("Volume {volume}% is too loud, the most is {max}%")]
+    VolumeTooLoud { volume: u8, max: u8 },
+}
+
+/// Window in which a track that was played can't be queued again, see
+/// [`CrackTrackQueue::set_recent_window`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecentWindow {
+    /// The last `n` tracks played.
+    Tracks(usize),
+    /// The tracks played within the duration.
+    Duration(Duration),
+}
+
+/// Loop mode for a [`CrackTrackQueue`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoopMode {
+    /// Tracks are dropped from the queue once they are dequeued.
+    #[default]
+    Off,
+    /// The dequeued track is put back at the front of the queue.
+    Track,
+    /// The dequeued track is put back at the end of the queue.
+    Queue,
+}
+
+/// Implement [`LoopMode`].
+impl LoopMode {
+    /// Get the next loop mode in the cycle `Off -> Track -> Queue -> Off`.
+    #[must_use]
+    pub fn next(self) -> Self {
+        match self {
+            LoopMode::Off => LoopMode::Track,
+            LoopMode::Track => LoopMode::Queue,
+            LoopMode::Queue => LoopMode::Off,
+        }
+    }
+}
+
+/// Implement [`Display`] for [`LoopMode`].
+impl Display for LoopMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopMode::Off => write!(f, "off"),
+            LoopMode::Track => write!(f, "track"),
+            LoopMode::Queue => write!(f, "queue"),
+        }
+    }
+}
+
+/// Format a duration for humans, e.g. `2h 13m` or `35m`.
+#[must_use]
+pub fn format_duration_short(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    let (hours, minutes) = (minutes / 60, minutes % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+/// A change to a [`CrackTrackQueue`], sent to its subscribers.
+#[derive(Clone, Debug)]
+pub enum QueueEvent {
+    /// Tracks were added to the queue.
+    Enqueued(Vec<ResolvedTrack>),
+    /// A track was taken from the front of the queue to be played.
+    Dequeued(ResolvedTrack),
+    /// Tracks were removed from the queue without being played.
+    Removed(Vec<ResolvedTrack>),
+    /// The queue was cleared.
+    Cleared,
+    /// The order of the queue changed, e.g. by a shuffle, sort or move.
+    Reordered,
+    /// The queue was restored to an earlier state by an undo.
+    Restored,
+    /// The whole queue was replaced by a new list of tracks.
+    Replaced,
+}
+
+/// How many tracks of a batch were added to a [`CrackTrackQueue`], and how
+/// many were skipped as duplicates or recently played.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AppendOutcome {
+    pub added: usize,
+    pub skipped: usize,
+}
+
+/// Key identifying a track when looking for duplicates, see
+/// [`ResolvedTrack::identity`].
+fn dedup_key(track: &ResolvedTrack) -> String {
+    let key = track.identity();
+    // Chapters of the same video are different tracks
+    match track.start_offset() {
+        Some(start) => format!("{key}@{}", start.as_millis()),
+        None => key,
+    }
+}
+
+/// Key of a URL or bare video ID: the video ID of any form of YouTube URL,
+/// see [`CanonicalUrl`], or the URL itself.
+fn url_key(url_or_id: &str) -> String {
+    CanonicalUrl::parse(url_or_id)
+        .and_then(|canonical| canonical.video_id)
+        .or_else(|| extract_video_id(url_or_id))
+        .unwrap_or_else(|| url_or_id.to_string())
+}
+
+/// Alternate the tracks of two lists, `a1, b1, a2, b2, ...`, with the rest of
+/// the longer one at the end.
+fn interleave_tracks(first: Vec<ResolvedTrack>, second: Vec<ResolvedTrack>) -> Vec<ResolvedTrack> {
+    let mut tracks = Vec::with_capacity(first.len() + second.len());
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (a, b) => tracks.extend(a.into_iter().chain(b)),
+        }
+    }
+    tracks
+}
+
+/// Split the entries of the tracks into pages of at most `page_size` entries,
+/// none of them over Discord's message length limit.
+fn paginate<'a>(
+    tracks: impl IntoIterator<Item = &'a ResolvedTrack>,
+    page_size: usize,
+) -> Vec<String> {
+    let page_size = page_size.max(1);
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut entries = 0;
+    for (index, track) in tracks.into_iter().enumerate() {
+        let entry = track.queue_entry(index + 1);
+        if !page.is_empty()
+            && (entries == page_size || page.len() + entry.len() + 1 > DISCORD_MESSAGE_LIMIT)
+        {
+            pages.push(std::mem::take(&mut page));
+            entries = 0;
+        }
+        if !page.is_empty() {
+            page.push('\n');
+        }
+        page.push_str(&entry);
+        entries += 1;
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// Key to sort a [`CrackTrackQueue`] by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// Shortest tracks first, tracks with an unknown duration last.
+    DurationAsc,
+    /// Longest tracks first, tracks with an unknown duration last.
+    DurationDesc,
+    /// Alphabetical by title, ignoring case.
+    Title,
+    /// Group tracks by the user who requested them, keeping their order.
+    Requester,
+}
+
+/// Copy of the queue length and head, kept up to date on every write so the
+/// `try_` accessors have something to return while the queue is locked.
+/// `version` is bumped by every mutation, a display built at an older
+/// version is dirty.
+#[derive(Debug, Default)]
+struct QueueCache {
+    len: AtomicUsize,
+    head: std::sync::Mutex<Option<ResolvedTrack>>,
+    version: AtomicU64,
+}
+
+impl QueueCache {
+    fn update(&self, queue: &VecDeque<ResolvedTrack>) {
+        self.len.store(queue.len(), Ordering::Relaxed);
+        if let Ok(mut head) = self.head.lock() {
+            *head = queue.front().cloned();
+        }
+        self.touch();
+    }
+
+    /// Mark the displays of the queue as dirty.
+    fn touch(&self) {
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+}
+
+/// Write guard for the tracks of a [`CrackTrackQueue`], updating its
+/// [`QueueCache`] when dropped.
+struct QueueWriteGuard<'a> {
+    queue: RwLockWriteGuard<'a, VecDeque<ResolvedTrack>>,
+    cache: &'a QueueCache,
+}
+
+impl Deref for QueueWriteGuard<'_> {
+    type Target = VecDeque<ResolvedTrack>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.queue
+    }
+}
+
+impl DerefMut for QueueWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.queue
+    }
+}
+
+impl Drop for QueueWriteGuard<'_> {
+    fn drop(&mut self) {
+        self.cache.update(&self.queue);
+    }
+}
 
 /// A [`CrackTrackQueue`] queue of tracks to be played.
 #[derive(Clone, Debug)]
 pub struct CrackTrackQueue {
     //inner: Arc<DashMap<GuildId, VecDeque<ResolvedTrack>>>,
-    inner: Arc<Mutex<VecDeque<ResolvedTrack>>>,
+    inner: Arc<RwLock<VecDeque<ResolvedTrack>>>,
+    playing: Arc<RwLock<Option<ResolvedTrack>>>,
+    loop_mode: Arc<RwLock<LoopMode>>,
+    history: Arc<RwLock<VecDeque<(ResolvedTrack, Instant)>>>,
+    history_max: Arc<AtomicUsize>,
+    recent_window: Arc<RwLock<Option<RecentWindow>>>,
+    undo_stack: Arc<Mutex<Vec<VecDeque<ResolvedTrack>>>>,
+    max_len: Arc<AtomicUsize>,
+    max_per_user: Arc<AtomicUsize>,
+    autoshuffle: Arc<AtomicBool>,
+    gapless: Arc<AtomicBool>,
+    normalize: Arc<AtomicBool>,
+    announce: Arc<AtomicBool>,
+    volume: Arc<AtomicU8>,
+    events: broadcast::Sender<QueueEvent>,
+    cache: Arc<QueueCache>,
     pub(crate) display: String,
+    pub(crate) pages: Vec<String>,
+    /// Version of the queue `display` was built at, `None` if never built.
+    display_version: Option<u64>,
+    /// The tracks `display` was built from.
+    pub(crate) display_tracks: Vec<ResolvedTrack>,
 }
 
 /// Implement [`Default`] for [`CrackTrackQueue`].
 impl Default for CrackTrackQueue {
     fn default() -> Self {
         CrackTrackQueue {
-            inner: Arc::new(Mutex::new(VecDeque::new())),
+            inner: Arc::new(RwLock::new(VecDeque::new())),
+            playing: Arc::new(RwLock::new(None)),
+            loop_mode: Arc::new(RwLock::new(LoopMode::Off)),
+            history: Arc::new(RwLock::new(VecDeque::new())),
+            history_max: Arc::new(AtomicUsize::new(DEFAULT_HISTORY_SIZE)),
+            recent_window: Arc::new(RwLock::new(None)),
+            undo_stack: Arc::new(Mutex::new(Vec::new())),
+            max_len: Arc::new(AtomicUsize::new(DEFAULT_MAX_QUEUE_LENGTH)),
+            max_per_user: Arc::new(AtomicUsize::new(0)),
+            autoshuffle: Arc::new(AtomicBool::new(false)),
+            gapless: Arc::new(AtomicBool::new(false)),
+            normalize: Arc::new(AtomicBool::new(false)),
+            announce: Arc::new(AtomicBool::new(false)),
+            volume: Arc::new(AtomicU8::new(DEFAULT_VOLUME)),
+            events: broadcast::channel(QUEUE_EVENT_CAPACITY).0,
+            cache: Arc::new(QueueCache::default()),
             display: EMPTY_QUEUE.to_string(),
+            pages: Vec::new(),
+            display_version: None,
+            display_tracks: Vec::new(),
         }
     }
 }
@@ -36,97 +327,1010 @@ impl<'a> CrackTrackQueue {
     /// Create a new [`CrackTrackQueue`] with a given [`VecDeque`] of [`ResolvedTrack`].
     #[must_use]
     pub fn with_queue(queue: VecDeque<ResolvedTrack>) -> Self {
+        let cache = QueueCache::default();
+        cache.update(&queue);
         CrackTrackQueue {
-            inner: Arc::new(Mutex::new(queue)),
-            display: EMPTY_QUEUE.to_string(),
+            inner: Arc::new(RwLock::new(queue)),
+            cache: Arc::new(cache),
+            ..Default::default()
+        }
+    }
+
+    /// Lock the tracks for writing, the cache is updated once the guard is dropped.
+    async fn write(&self) -> QueueWriteGuard<'_> {
+        QueueWriteGuard {
+            queue: self.inner.write().await,
+            cache: &self.cache,
         }
     }
 
-    /// Get the queue.
+    /// Get the length of the queue without waiting, falling back to the
+    /// length after the last write if the queue is locked for writing.
+    #[must_use]
+    pub fn try_len(&self) -> usize {
+        self.inner
+            .try_read()
+            .map(|queue| queue.len())
+            .unwrap_or_else(|_| self.cache.len.load(Ordering::Relaxed))
+    }
+
+    /// Check if the queue is empty without waiting, see [`CrackTrackQueue::try_len`].
+    #[must_use]
+    pub fn try_is_empty(&self) -> bool {
+        self.try_len() == 0
+    }
+
+    /// Get the first track in the queue without waiting, falling back to the
+    /// first track after the last write if the queue is locked for writing.
+    #[must_use]
+    pub fn try_peek(&self) -> Option<ResolvedTrack> {
+        match self.inner.try_read() {
+            Ok(queue) => queue.front().cloned(),
+            Err(_) => self.cache.head.lock().ok().and_then(|head| head.clone()),
+        }
+    }
+
+    /// Subscribe to the [`QueueEvent`]s of the queue. A subscriber that falls
+    /// more than [`QUEUE_EVENT_CAPACITY`] events behind misses the oldest ones.
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<QueueEvent> {
+        self.events.subscribe()
+    }
+
+    /// Call `callback` for every [`QueueEvent`] of the queue from a background
+    /// task, until the queue is dropped or the returned handle is aborted.
+    pub fn on_event<F, Fut>(&self, callback: F) -> JoinHandle<()>
+    where
+        F: Fn(QueueEvent) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let mut events = self.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => callback(event).await,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    /// Send an event to the subscribers, if there are any.
+    fn emit(&self, event: QueueEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Get a copy of the queue. Prefer [`CrackTrackQueue::read_with`] when a
+    /// copy isn't needed.
     pub async fn get_queue(&self) -> VecDeque<ResolvedTrack> {
-        self.inner.lock().await.clone()
+        self.inner.read().await.clone()
+    }
+
+    /// Run `f` on the queued tracks under a read lock, without copying them.
+    /// Readers don't block each other, only writers.
+    pub async fn read_with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&VecDeque<ResolvedTrack>) -> R,
+    {
+        f(&*self.inner.read().await)
     }
 
     /// Enqueue a track.
-    pub async fn enqueue(&self, track: ResolvedTrack) {
-        self.push_back(track).await;
+    ///
+    /// # Errors
+    /// Returns [`QueueError::QueueFull`] if the queue is at its maximum length,
+    /// [`QueueError::UserLimitReached`] if the requester is at theirs, or
+    /// [`QueueError::RecentlyPlayed`] if the track was played within the recent window.
+    pub async fn enqueue(&self, track: ResolvedTrack) -> Result<(), QueueError> {
+        self.check_recently_played(&track).await?;
+        let max = self.max_len.load(Ordering::Relaxed);
+        let mut queue = self.write().await;
+        if max != 0 && queue.len() >= max {
+            return Err(QueueError::QueueFull { max });
+        }
+        self.check_user_limit(&queue, &track)?;
+        queue.push_back(track.clone());
+        self.emit(QueueEvent::Enqueued(vec![track]));
+        Ok(())
+    }
+
+    /// Insert a track so it plays immediately after the currently playing one.
+    /// Normally this is the front of the queue, but with [`LoopMode::Track`] the
+    /// playing track is kept at the front, so the new track goes right behind it.
+    ///
+    /// # Errors
+    /// Returns [`QueueError::QueueFull`] if the queue is at its maximum length,
+    /// [`QueueError::UserLimitReached`] if the requester is at theirs, or
+    /// [`QueueError::RecentlyPlayed`] if the track was played within the recent window.
+    pub async fn insert_after_playing(&self, track: ResolvedTrack) -> Result<(), QueueError> {
+        self.check_recently_played(&track).await?;
+        let max = self.max_len.load(Ordering::Relaxed);
+        let looping_track =
+            *self.loop_mode.read().await == LoopMode::Track && self.playing.read().await.is_some();
+        let mut queue = self.write().await;
+        if max != 0 && queue.len() >= max {
+            return Err(QueueError::QueueFull { max });
+        }
+        self.check_user_limit(&queue, &track)?;
+        let index = if looping_track { queue.len().min(1) } else { 0 };
+        queue.insert(index, track.clone());
+        self.emit(QueueEvent::Enqueued(vec![track]));
+        Ok(())
+    }
+
+    /// Enqueue a batch of tracks, e.g. a playlist. With autoshuffle on, each
+    /// track is inserted at a random position instead of at the back. The
+    /// playing track is kept at the front with [`LoopMode::Track`]. Tracks
+    /// played within the recent window are skipped.
+    ///
+    /// # Errors
+    /// Returns [`QueueError::QueueFull`] once the queue reaches its maximum length,
+    /// or [`QueueError::UserLimitReached`] once a requester reaches theirs,
+    /// the tracks added before that are kept.
+    pub async fn enqueue_batch(&self, tracks: Vec<ResolvedTrack>) -> Result<(), QueueError> {
+        self.enqueue_batch_with(tracks, false).await.map(|_| ())
     }
 
-    /// Dequeue a track.
+    /// Enqueue a batch of tracks like [`CrackTrackQueue::enqueue_batch`]. With
+    /// `dedupe`, tracks whose video ID is already in the queue, or earlier in
+    /// the batch, are skipped. Tracks played within the recent window are
+    /// always skipped.
+    ///
+    /// # Errors
+    /// Returns [`QueueError::QueueFull`] once the queue reaches its maximum length,
+    /// or [`QueueError::UserLimitReached`] once a requester reaches theirs,
+    /// the tracks added before that are kept.
+    pub async fn enqueue_batch_with(
+        &self,
+        tracks: Vec<ResolvedTrack>,
+        dedupe: bool,
+    ) -> Result<AppendOutcome, QueueError> {
+        let max = self.max_len.load(Ordering::Relaxed);
+        let max_per_user = self.max_per_user.load(Ordering::Relaxed);
+        let autoshuffle = self.autoshuffle.load(Ordering::Relaxed);
+        let looping_track =
+            *self.loop_mode.read().await == LoopMode::Track && self.playing.read().await.is_some();
+        let recent = self.recent_keys().await;
+        let mut queue = self.write().await;
+        let mut seen = if dedupe {
+            queue.iter().map(dedup_key).collect()
+        } else {
+            HashSet::new()
+        };
+        let mut per_user = HashMap::<UserId, usize>::new();
+        if max_per_user != 0 {
+            for track in queue.iter() {
+                *per_user.entry(track.user_id).or_default() += 1;
+            }
+        }
+        let mut rng = rand::rng();
+        let mut added = Vec::with_capacity(tracks.len());
+        let mut skipped = 0;
+        let mut full = None;
+        for track in tracks {
+            let key = dedup_key(&track);
+            if recent.contains(&key) || (dedupe && !seen.insert(key)) {
+                skipped += 1;
+                continue;
+            }
+            if max != 0 && queue.len() >= max {
+                full = Some(QueueError::QueueFull { max });
+                break;
+            }
+            if max_per_user != 0 {
+                let count = per_user.entry(track.user_id).or_default();
+                if *count >= max_per_user {
+                    full = Some(QueueError::UserLimitReached {
+                        user: track.user_id,
+                        max: max_per_user,
+                    });
+                    break;
+                }
+                *count += 1;
+            }
+            if autoshuffle {
+                let start = if looping_track { queue.len().min(1) } else { 0 };
+                let index = rng.random_range(start..=queue.len());
+                queue.insert(index, track.clone());
+            } else {
+                queue.push_back(track.clone());
+            }
+            added.push(track);
+        }
+        let outcome = AppendOutcome {
+            added: added.len(),
+            skipped,
+        };
+        if !added.is_empty() {
+            self.emit(QueueEvent::Enqueued(added));
+        }
+        match full {
+            Some(e) => Err(e),
+            None => Ok(outcome),
+        }
+    }
+
+    /// Whether newly enqueued batches are shuffled into the queue.
+    #[must_use]
+    pub fn autoshuffle(&self) -> bool {
+        self.autoshuffle.load(Ordering::Relaxed)
+    }
+
+    /// Set whether newly enqueued batches are shuffled into the queue.
+    pub fn set_autoshuffle(&self, autoshuffle: bool) {
+        self.autoshuffle.store(autoshuffle, Ordering::Relaxed);
+    }
+
+    /// Enqueue two lists of tracks alternating between them, `a1, b1, a2, b2, ...`,
+    /// e.g. to mix two playlists. The order is kept even with autoshuffle on.
+    /// Tracks played within the recent window are skipped. Returns how many
+    /// tracks were added and how many were skipped.
+    ///
+    /// # Errors
+    /// Returns [`QueueError::QueueFull`] once the queue reaches its maximum length,
+    /// or [`QueueError::UserLimitReached`] once a requester reaches theirs,
+    /// the tracks added before that are kept.
+    pub async fn interleave(
+        &self,
+        first: Vec<ResolvedTrack>,
+        second: Vec<ResolvedTrack>,
+    ) -> Result<AppendOutcome, QueueError> {
+        let max = self.max_len.load(Ordering::Relaxed);
+        let recent = self.recent_keys().await;
+        let mut queue = self.write().await;
+        let mut added = Vec::new();
+        let mut skipped = 0;
+        let mut result = Ok(());
+        for track in interleave_tracks(first, second) {
+            if recent.contains(&dedup_key(&track)) {
+                skipped += 1;
+                continue;
+            }
+            if max != 0 && queue.len() >= max {
+                result = Err(QueueError::QueueFull { max });
+                break;
+            }
+            if let Err(e) = self.check_user_limit(&queue, &track) {
+                result = Err(e);
+                break;
+            }
+            queue.push_back(track.clone());
+            added.push(track);
+        }
+        let outcome = AppendOutcome {
+            added: added.len(),
+            skipped,
+        };
+        if !added.is_empty() {
+            self.emit(QueueEvent::Enqueued(added));
+        }
+        result.map(|()| outcome)
+    }
+
+    /// Toggle autoshuffle and return the new value.
+    pub fn toggle_autoshuffle(&self) -> bool {
+        !self.autoshuffle.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Whether tracks are played back to back without a gap, e.g. for
+    /// albums, see [`crate::apply_gapless`].
+    #[must_use]
+    pub fn gapless(&self) -> bool {
+        self.gapless.load(Ordering::Relaxed)
+    }
+
+    /// Set whether tracks are played back to back without a gap.
+    pub fn set_gapless(&self, gapless: bool) {
+        self.gapless.store(gapless, Ordering::Relaxed);
+    }
+
+    /// Toggle gapless playback and return the new value.
+    pub fn toggle_gapless(&self) -> bool {
+        !self.gapless.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Whether the loudness of tracks is normalized, see
+    /// [`crate::DataInner::normalize_track`].
+    #[must_use]
+    pub fn normalize(&self) -> bool {
+        self.normalize.load(Ordering::Relaxed)
+    }
+
+    /// Set whether the loudness of tracks is normalized.
+    pub fn set_normalize(&self, normalize: bool) {
+        self.normalize.store(normalize, Ordering::Relaxed);
+    }
+
+    /// Toggle loudness normalization and return the new value.
+    pub fn toggle_normalize(&self) -> bool {
+        !self.normalize.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Whether tracks are announced in the call before they play, see
+    /// [`crate::DataInner::announcement`].
+    #[must_use]
+    pub fn announce(&self) -> bool {
+        self.announce.load(Ordering::Relaxed)
+    }
+
+    /// Set whether tracks are announced in the call.
+    pub fn set_announce(&self, announce: bool) {
+        self.announce.store(announce, Ordering::Relaxed);
+    }
+
+    /// Toggle voice announcements and return the new value.
+    pub fn toggle_announce(&self) -> bool {
+        !self.announce.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Get the maximum length of the queue, 0 means no limit.
+    #[must_use]
+    pub fn max_len(&self) -> usize {
+        self.max_len.load(Ordering::Relaxed)
+    }
+
+    /// Set the maximum length of the queue, 0 means no limit.
+    /// Tracks already in the queue are kept even if it is over the new limit.
+    pub fn set_max_len(&self, max_len: usize) {
+        self.max_len.store(max_len, Ordering::Relaxed);
+    }
+
+    /// Get the maximum number of tracks a single user can have queued, 0 means no limit.
+    #[must_use]
+    pub fn max_per_user(&self) -> usize {
+        self.max_per_user.load(Ordering::Relaxed)
+    }
+
+    /// Set the maximum number of tracks a single user can have queued, 0 means no limit.
+    /// Tracks already in the queue are kept even if a user is over the new limit.
+    pub fn set_max_per_user(&self, max_per_user: usize) {
+        self.max_per_user.store(max_per_user, Ordering::Relaxed);
+    }
+
+    /// Get the volume tracks are played at, in percent.
+    #[must_use]
+    pub fn volume(&self) -> u8 {
+        self.volume.load(Ordering::Relaxed)
+    }
+
+    /// Set the volume tracks are played at, in percent, up to
+    /// [`MAX_VOLUME`]. It is kept for the next tracks and saved with the
+    /// queue.
+    ///
+    /// # Errors
+    /// Returns [`QueueError::VolumeTooLoud`] if `volume` is over [`MAX_VOLUME`].
+    pub fn set_volume(&self, volume: u8) -> Result<(), QueueError> {
+        if volume > MAX_VOLUME {
+            return Err(QueueError::VolumeTooLoud {
+                volume,
+                max: MAX_VOLUME,
+            });
+        }
+        self.volume.store(volume, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Get the volume as the gain songbird plays a track with, 1.0 being
+    /// unchanged.
+    #[must_use]
+    pub fn gain(&self) -> f32 {
+        f32::from(self.volume()) / 100.0
+    }
+
+    /// Check that the requester of `track` can queue another track.
+    fn check_user_limit(
+        &self,
+        queue: &VecDeque<ResolvedTrack>,
+        track: &ResolvedTrack,
+    ) -> Result<(), QueueError> {
+        let max = self.max_per_user.load(Ordering::Relaxed);
+        if max == 0 {
+            return Ok(());
+        }
+        let count = queue.iter().filter(|t| t.user_id == track.user_id).count();
+        if count >= max {
+            return Err(QueueError::UserLimitReached {
+                user: track.user_id,
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    /// Dequeue a track, respecting the [`LoopMode`] of the queue.
+    /// With [`LoopMode::Track`] the track stays at the front, with
+    /// [`LoopMode::Queue`] it is moved to the back.
+    /// The dequeued track becomes the [`CrackTrackQueue::current`] track and
+    /// is recorded in the history.
     pub async fn dequeue(&self) -> Option<ResolvedTrack> {
-        self.pop_front().await
+        let loop_mode = *self.loop_mode.read().await;
+        let track = {
+            let mut queue = self.write().await;
+            let track = queue.pop_front()?;
+            match loop_mode {
+                LoopMode::Off => {}
+                LoopMode::Track => queue.push_front(track.clone()),
+                LoopMode::Queue => queue.push_back(track.clone()),
+            }
+            track
+        };
+        *self.playing.write().await = Some(track.clone());
+        self.push_history(track.clone()).await;
+        self.emit(QueueEvent::Dequeued(track.clone()));
+        Some(track)
+    }
+
+    /// Record a track in the history, dropping the oldest entries past the max size.
+    pub async fn push_history(&self, track: ResolvedTrack) {
+        let max = self.history_max.load(Ordering::Relaxed);
+        let mut history = self.history.write().await;
+        history.push_back((track, Instant::now()));
+        while history.len() > max {
+            history.pop_front();
+        }
+    }
+
+    /// Get the history of played tracks, oldest first.
+    pub async fn history(&self) -> VecDeque<ResolvedTrack> {
+        self.history
+            .read()
+            .await
+            .iter()
+            .map(|(track, _)| track.clone())
+            .collect()
+    }
+
+    /// Take the track played before the current one out of the history and
+    /// put it back at the front of the queue. Returns the track, if there
+    /// was one.
+    pub async fn previous(&self) -> Option<ResolvedTrack> {
+        let current = self.current().await;
+        let track = {
+            let mut history = self.history.write().await;
+            // Tracks are recorded as they start, so the last one may be the
+            // track playing now
+            let playing = history.back().is_some_and(|(last, _)| {
+                current
+                    .as_ref()
+                    .is_some_and(|current| last.same_track_as(current))
+            });
+            let index = history.len().checked_sub(if playing { 2 } else { 1 })?;
+            history.remove(index)?.0
+        };
+        self.push_front(track.clone()).await;
+        Some(track)
+    }
+
+    /// Clear the history of played tracks.
+    pub async fn clear_history(&self) {
+        self.history.write().await.clear();
     }
 
-    /// Return the display string for the queue.
+    /// Get the maximum number of tracks kept in the history.
+    #[must_use]
+    pub fn history_max(&self) -> usize {
+        self.history_max.load(Ordering::Relaxed)
+    }
+
+    /// Set the maximum number of tracks kept in the history, truncating it if needed.
+    pub async fn set_history_max(&self, max: usize) {
+        self.history_max.store(max, Ordering::Relaxed);
+        let mut history = self.history.write().await;
+        while history.len() > max {
+            history.pop_front();
+        }
+    }
+
+    /// Get the window in which played tracks can't be queued again, if any.
+    pub async fn recent_window(&self) -> Option<RecentWindow> {
+        *self.recent_window.read().await
+    }
+
+    /// Set the window in which played tracks can't be queued again, `None`
+    /// allows queueing them right away. Only tracks still in the history are
+    /// checked, see [`CrackTrackQueue::set_history_max`].
+    pub async fn set_recent_window(&self, window: Option<RecentWindow>) {
+        *self.recent_window.write().await = window;
+    }
+
+    /// Keys of the tracks played within the recent window.
+    async fn recent_keys(&self) -> HashSet<String> {
+        let Some(window) = self.recent_window().await else {
+            return HashSet::new();
+        };
+        let history = self.history.read().await;
+        let recent = history.iter().rev();
+        match window {
+            RecentWindow::Tracks(n) => recent.take(n).map(|(t, _)| dedup_key(t)).collect(),
+            RecentWindow::Duration(duration) => recent
+                .take_while(|(_, played_at)| played_at.elapsed() <= duration)
+                .map(|(t, _)| dedup_key(t))
+                .collect(),
+        }
+    }
+
+    /// Whether the track was played within the recent window.
+    pub async fn played_recently(&self, track: &ResolvedTrack) -> bool {
+        self.recent_keys().await.contains(&dedup_key(track))
+    }
+
+    /// Check that the track wasn't played within the recent window.
+    async fn check_recently_played(&self, track: &ResolvedTrack) -> Result<(), QueueError> {
+        if self.played_recently(track).await {
+            return Err(QueueError::RecentlyPlayed {
+                title: track.get_title(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Get the track that is currently playing, if any.
+    pub async fn get_playing(&self) -> Option<ResolvedTrack> {
+        self.playing.read().await.clone()
+    }
+
+    /// Set the track that is currently playing.
+    pub async fn set_playing(&self, track: Option<ResolvedTrack>) {
+        *self.playing.write().await = track;
+        self.cache.touch();
+    }
+
+    /// Get the track that is currently playing, the last one dequeued.
+    pub async fn current(&self) -> Option<ResolvedTrack> {
+        self.get_playing().await
+    }
+
+    /// Put the current track back at the front of the queue so it is
+    /// dequeued again, to play it from the top, and return it. With
+    /// [`LoopMode::Track`] it is already there, and with [`LoopMode::Queue`]
+    /// its copy at the back is moved, so the rest of the queue is unchanged.
+    pub async fn requeue_current(&self) -> Option<ResolvedTrack> {
+        let track = self.current().await?;
+        let loop_mode = *self.loop_mode.read().await;
+        let added = {
+            let mut queue = self.write().await;
+            let moved = loop_mode == LoopMode::Queue
+                && queue.back().is_some_and(|last| last.same_track_as(&track));
+            if moved {
+                queue.pop_back();
+            }
+            let pushed = loop_mode != LoopMode::Track
+                || !queue
+                    .front()
+                    .is_some_and(|first| first.same_track_as(&track));
+            if pushed {
+                queue.push_front(track.clone());
+            }
+            pushed && !moved
+        };
+        if added {
+            self.emit(QueueEvent::Enqueued(vec![track.clone()]));
+        } else {
+            self.emit(QueueEvent::Reordered);
+        }
+        Some(track)
+    }
+
+    /// Clear the currently playing track, e.g. once it has finished, and return it.
+    pub async fn clear_current(&self) -> Option<ResolvedTrack> {
+        let previous = self.playing.write().await.take();
+        self.cache.touch();
+        previous
+    }
+
+    /// Copy of the playing track followed by the queued tracks, used to
+    /// restore a looping queue once it runs out.
+    pub async fn backup(&self) -> VecDeque<ResolvedTrack> {
+        let mut backup = self.get_queue().await;
+        if let Some(playing) = self.get_playing().await {
+            backup.push_front(playing);
+        }
+        backup
+    }
+
+    /// Get the loop mode of the queue.
+    pub async fn loop_mode(&self) -> LoopMode {
+        *self.loop_mode.read().await
+    }
+
+    /// Set the loop mode of the queue.
+    pub async fn set_loop_mode(&self, loop_mode: LoopMode) {
+        *self.loop_mode.write().await = loop_mode;
+    }
+
+    /// Advance the loop mode to the next one in the cycle and return it.
+    pub async fn cycle_loop_mode(&self) -> LoopMode {
+        let mut loop_mode = self.loop_mode.write().await;
+        *loop_mode = loop_mode.next();
+        *loop_mode
+    }
+
+    /// Return the display string for the queue as last built, which may be
+    /// stale. Prefer [`CrackTrackQueue::display`].
     #[must_use]
     pub fn get_display(&self) -> String {
         self.display.clone()
     }
 
-    /// Build the display string for the queue.
-    /// This *must* be called before displaying the queue.
-    ///
-    /// # Errors
-    /// Returns an error if the display string cannot be built.
+    /// Whether the queue changed since the display was last built.
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        self.display_version != Some(self.cache.version())
+    }
+
+    /// Return the display string for the queue, rebuilding it first if the
+    /// queue changed since it was last built.
+    pub async fn display(&mut self) -> String {
+        if self.is_dirty() {
+            self.build_display().await;
+        }
+        self.display.clone()
+    }
+
+    /// Build the display string for the queue, this is the first page of
+    /// [`CrackTrackQueue::build_pages`] with the default page size.
     pub async fn build_display(&mut self) {
-        self.display = {
-            let queue = self.inner.lock().await.clone();
-            queue
-                .iter()
-                .map(ToString::to_string)
-                .collect::<Vec<String>>()
-                .join("\n")
+        // Read the version first, so a mutation during the build leaves it dirty.
+        let version = self.cache.version();
+        let tracks = self
+            .read_with(|queue| queue.iter().cloned().collect::<Vec<_>>())
+            .await;
+        self.pages = paginate(&tracks, DEFAULT_PAGE_SIZE);
+        self.display_tracks = tracks;
+        let page = self.pages.first().cloned().unwrap_or_default();
+        self.display = match self.current().await {
+            Some(track) => format!("Now Playing: {track}\n\n{page}"),
+            None => page,
         };
+        self.display_version = Some(version);
+    }
+
+    /// Build the pages for displaying the queue, with at most `page_size`
+    /// entries per page. Each entry shows the position, duration and requester
+    /// of the track. Pages are also split so that none of them
+    /// goes over Discord's message length limit.
+    pub async fn build_pages(&mut self, page_size: usize) -> Vec<String> {
+        let pages = self.read_with(|queue| paginate(queue, page_size)).await;
+        self.pages = pages;
+        self.pages.clone()
+    }
+
+    /// Get a page built by [`CrackTrackQueue::build_pages`], 0-based.
+    #[must_use]
+    pub fn get_page(&self, n: usize) -> Option<String> {
+        self.pages.get(n).cloned()
+    }
+
+    /// Get the number of pages built by [`CrackTrackQueue::build_pages`].
+    #[must_use]
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
     }
 
     /// Clear the queue in place.
     pub async fn clear(&self) {
-        self.inner.lock().await.clear();
+        let previous = std::mem::take(&mut *self.write().await);
+        if !previous.is_empty() {
+            self.push_undo(previous).await;
+            self.emit(QueueEvent::Cleared);
+        }
+    }
+
+    /// Push a previous state of the queue onto the undo stack,
+    /// dropping the oldest state past [`DEFAULT_UNDO_SIZE`].
+    async fn push_undo(&self, previous: VecDeque<ResolvedTrack>) {
+        let mut undo_stack = self.undo_stack.lock().await;
+        undo_stack.push(previous);
+        if undo_stack.len() > DEFAULT_UNDO_SIZE {
+            undo_stack.remove(0);
+        }
+    }
+
+    /// Restore the queue to the state before the last destructive operation
+    /// (e.g. `clear`, `replace`, `shuffle`, `sort_by`, `jump_to`, `remove_range`,
+    /// `remove_by_user`, `remove_absent_requesters` or `dedup`).
+    /// Returns `false` if there was nothing to undo.
+    pub async fn undo(&self) -> bool {
+        let Some(previous) = self.undo_stack.lock().await.pop() else {
+            return false;
+        };
+        *self.write().await = previous;
+        self.emit(QueueEvent::Restored);
+        true
+    }
+
+    /// Replace the whole queue with `tracks` under a single lock, so it is
+    /// never seen empty or half built. With `keep_playing` the playing track
+    /// stays current, and stays at the front if it is looped with
+    /// [`LoopMode::Track`], otherwise it is cleared. The replaced tracks are
+    /// returned and can be brought back with [`CrackTrackQueue::undo`].
+    pub async fn replace(
+        &self,
+        tracks: Vec<ResolvedTrack>,
+        keep_playing: bool,
+    ) -> VecDeque<ResolvedTrack> {
+        let looping_track = keep_playing
+            && *self.loop_mode.read().await == LoopMode::Track
+            && self.playing.read().await.is_some();
+        let previous = {
+            let mut queue = self.write().await;
+            let mut new = VecDeque::from(tracks);
+            if looping_track {
+                if let Some(playing) = queue.front() {
+                    new.push_front(playing.clone());
+                }
+            }
+            std::mem::replace(&mut *queue, new)
+        };
+        if !keep_playing {
+            self.clear_current().await;
+        }
+        self.push_undo(previous.clone()).await;
+        self.emit(QueueEvent::Replaced);
+        previous
+    }
+
+    /// Check if there is anything to undo.
+    pub async fn can_undo(&self) -> bool {
+        !self.undo_stack.lock().await.is_empty()
     }
 
     /// Get the length of the queue.
     pub async fn len(&self) -> usize {
-        self.inner.lock().await.len()
+        self.inner.read().await.len()
     }
 
     /// Check if the queue is empty.
     pub async fn is_empty(&self) -> bool {
-        self.inner.lock().await.is_empty()
+        self.inner.read().await.is_empty()
     }
 
     /// Get the element at the given index in the queue.
     pub async fn get(&self, index: usize) -> Option<ResolvedTrack> {
-        self.inner.lock().await.get(index).cloned()
+        self.inner.read().await.get(index).cloned()
+    }
+
+    /// Get the first `n` tracks of the queue, or all of them if there are
+    /// fewer, taking the lock only once.
+    pub async fn peek_n(&self, n: usize) -> Vec<ResolvedTrack> {
+        self.inner.read().await.iter().take(n).cloned().collect()
     }
 
     /// Remove the element at the given index in the queue.
     pub async fn remove(&self, index: usize) -> Option<ResolvedTrack> {
-        self.inner.lock().await.remove(index)
+        let track = self.write().await.remove(index)?;
+        self.emit(QueueEvent::Removed(vec![track.clone()]));
+        Some(track)
+    }
+
+    /// Remove a contiguous range of tracks from the queue and return them.
+    /// The range is clamped to the length of the queue.
+    pub async fn remove_range(&self, range: Range<usize>) -> Vec<ResolvedTrack> {
+        let (previous, removed) = {
+            let mut queue = self.write().await;
+            let end = range.end.min(queue.len());
+            if range.start >= end {
+                return Vec::new();
+            }
+            let previous = queue.clone();
+            (previous, queue.drain(range.start..end).collect::<Vec<_>>())
+        };
+        self.push_undo(previous).await;
+        self.emit(QueueEvent::Removed(removed.clone()));
+        removed
+    }
+
+    /// Jump to the track at `index` by dropping every track before it, and
+    /// return the new head of the queue. With `record_history` the skipped
+    /// tracks are moved to the history instead of being dropped.
+    ///
+    /// # Errors
+    /// Returns [`QueueError::IndexOutOfBounds`] if there is no track at `index`.
+    pub async fn jump_to(
+        &self,
+        index: usize,
+        record_history: bool,
+    ) -> Result<ResolvedTrack, QueueError> {
+        let (previous, skipped, head) = {
+            let mut queue = self.write().await;
+            let len = queue.len();
+            if index >= len {
+                return Err(QueueError::IndexOutOfBounds { index, len });
+            }
+            let previous = queue.clone();
+            let skipped = queue.drain(..index).collect::<Vec<_>>();
+            (previous, skipped, queue[0].clone())
+        };
+        if index > 0 {
+            self.push_undo(previous).await;
+            self.emit(QueueEvent::Removed(skipped.clone()));
+        }
+        if record_history {
+            for track in skipped {
+                self.push_history(track).await;
+            }
+        }
+        Ok(head)
+    }
+
+    /// Remove every track requested by the given user and return them.
+    pub async fn remove_by_user(&self, user_id: UserId) -> Vec<ResolvedTrack> {
+        self.remove_where(|track| track.get_requesting_user() == user_id)
+            .await
+    }
+
+    /// Remove every track whose requester isn't in `present_users`, e.g. the
+    /// members still in the voice channel, and return them.
+    pub async fn remove_absent_requesters(
+        &self,
+        present_users: &HashSet<UserId>,
+    ) -> Vec<ResolvedTrack> {
+        self.remove_where(|track| !present_users.contains(&track.get_requesting_user()))
+            .await
+    }
+
+    /// Remove every track matching the predicate and return them.
+    async fn remove_where<F>(&self, predicate: F) -> Vec<ResolvedTrack>
+    where
+        F: Fn(&ResolvedTrack) -> bool,
+    {
+        let (previous, removed) = {
+            let mut queue = self.write().await;
+            let previous = queue.clone();
+            let (removed, kept): (Vec<_>, Vec<_>) =
+                queue.drain(..).partition(|track| predicate(track));
+            queue.extend(kept);
+            (previous, removed)
+        };
+        if !removed.is_empty() {
+            self.push_undo(previous).await;
+            self.emit(QueueEvent::Removed(removed.clone()));
+        }
+        removed
+    }
+
+    /// Total duration of the queued tracks, tracks with an unknown duration count as zero.
+    pub async fn total_duration(&self) -> Duration {
+        self.inner
+            .read()
+            .await
+            .iter()
+            .filter_map(ResolvedTrack::get_raw_duration)
+            .sum()
+    }
+
+    /// Estimated time until the track at `index` starts playing: the playing
+    /// track plus every track queued before it. Returns `None` if the index
+    /// is out of bounds.
+    pub async fn eta_for(&self, index: usize) -> Option<Duration> {
+        let playing = self
+            .playing
+            .read()
+            .await
+            .as_ref()
+            .and_then(ResolvedTrack::get_raw_duration)
+            .unwrap_or_default();
+        let queue = self.inner.read().await;
+        if index >= queue.len() {
+            return None;
+        }
+        let queued: Duration = queue
+            .iter()
+            .take(index)
+            .filter_map(ResolvedTrack::get_raw_duration)
+            .sum();
+        Some(playing + queued)
+    }
+
+    /// Find every track matching the predicate, with its index in the queue.
+    pub async fn find<F>(&self, predicate: F) -> Vec<(usize, ResolvedTrack)>
+    where
+        F: Fn(&ResolvedTrack) -> bool,
+    {
+        self.inner
+            .read()
+            .await
+            .iter()
+            .enumerate()
+            .filter(|(_, track)| predicate(track))
+            .map(|(index, track)| (index, track.clone()))
+            .collect()
+    }
+
+    /// Get the index of the first queued track matching a URL or bare video ID.
+    /// YouTube URLs are compared by video ID, so any of their forms match.
+    pub async fn position_of(&self, url_or_id: &str) -> Option<usize> {
+        let url_or_id = url_or_id.trim();
+        let key = url_key(url_or_id);
+        self.inner
+            .read()
+            .await
+            .iter()
+            .position(|track| dedup_key(track) == key)
+    }
+
+    /// Find every track whose title contains the query, ignoring case.
+    pub async fn search_titles(&self, query: &str) -> Vec<(usize, ResolvedTrack)> {
+        let query = query.to_lowercase();
+        self.find(|track| track.get_title().to_lowercase().contains(&query))
+            .await
+    }
+
+    /// Remove duplicate tracks from the queue, comparing them by video ID
+    /// (falling back to the URL) and keeping the earliest occurrence.
+    /// Returns the number of tracks that were dropped.
+    pub async fn dedup(&self) -> usize {
+        let (previous, removed) = {
+            let mut queue = self.write().await;
+            let previous = queue.clone();
+            let mut seen = HashSet::new();
+            let mut removed = Vec::new();
+            queue.retain(|track| {
+                let keep = seen.insert(dedup_key(track));
+                if !keep {
+                    removed.push(track.clone());
+                }
+                keep
+            });
+            (previous, removed)
+        };
+        let dropped = removed.len();
+        if dropped > 0 {
+            self.push_undo(previous).await;
+            self.emit(QueueEvent::Removed(removed));
+        }
+        dropped
+    }
+
+    /// Move the track at index `from` to index `to`, shifting the tracks in between.
+    ///
+    /// # Errors
+    /// Returns [`QueueError::IndexOutOfBounds`] if either index is out of bounds.
+    pub async fn move_track(&self, from: usize, to: usize) -> Result<(), QueueError> {
+        let mut queue = self.write().await;
+        let len = queue.len();
+        if from >= len || to >= len {
+            let index = if from >= len { from } else { to };
+            return Err(QueueError::IndexOutOfBounds { index, len });
+        }
+        if let Some(track) = queue.remove(from) {
+            queue.insert(to, track);
+        }
+        self.emit(QueueEvent::Reordered);
+        Ok(())
     }
 
     /// Add a track to the back of the queue.
     pub async fn push_back(&self, track: ResolvedTrack) {
-        self.inner.lock().await.push_back(track);
+        self.write().await.push_back(track.clone());
+        self.emit(QueueEvent::Enqueued(vec![track]));
     }
 
     /// Add a track to the front of the queue.
     pub async fn push_front(&self, track: ResolvedTrack) {
-        self.inner.lock().await.push_front(track);
+        self.write().await.push_front(track.clone());
+        self.emit(QueueEvent::Enqueued(vec![track]));
     }
 
     /// Remove the last track from the queue.
     pub async fn pop_back(&self) -> Option<ResolvedTrack> {
-        self.inner.lock().await.pop_back()
+        let track = self.write().await.pop_back()?;
+        self.emit(QueueEvent::Removed(vec![track.clone()]));
+        Some(track)
     }
 
     /// Remove the first track from the queue.
     pub async fn pop_front(&self) -> Option<ResolvedTrack> {
-        self.inner.lock().await.pop_front()
+        let track = self.write().await.pop_front()?;
+        self.emit(QueueEvent::Removed(vec![track.clone()]));
+        Some(track)
     }
 
     /// Insert a track at the given index in the queue.
     pub async fn insert(&self, index: usize, track: ResolvedTrack) {
-        self.inner.lock().await.insert(index, track);
+        self.write().await.insert(index, track.clone());
+        self.emit(QueueEvent::Enqueued(vec![track]));
     }
 
     /// Append a vector of tracks to the end of the queue.
@@ -136,21 +1340,102 @@ impl<'a> CrackTrackQueue {
 
     /// Append another queue to the end of this queue.
     pub async fn append(&self, other: &mut VecDeque<ResolvedTrack>) {
-        self.inner.lock().await.append(other);
+        if other.is_empty() {
+            return;
+        }
+        let added = other.iter().cloned().collect();
+        self.write().await.append(other);
+        self.emit(QueueEvent::Enqueued(added));
     }
 
     /// Shuffle the queue.
     pub async fn shuffle(&self) {
-        self.inner
-            .lock()
-            .await
-            .make_contiguous()
-            .shuffle(&mut rand::rng());
+        let previous = {
+            let mut queue = self.write().await;
+            let previous = queue.clone();
+            queue.make_contiguous().shuffle(&mut rand::rng());
+            previous
+        };
+        self.push_undo(previous).await;
+        self.emit(QueueEvent::Reordered);
+    }
+
+    /// Shuffle the queue, leaving the first `n` tracks in place.
+    pub async fn shuffle_after(&self, n: usize) {
+        let previous = {
+            let mut queue = self.write().await;
+            if queue.len() <= n.saturating_add(1) {
+                return;
+            }
+            let previous = queue.clone();
+            queue.make_contiguous()[n..].shuffle(&mut rand::rng());
+            previous
+        };
+        self.push_undo(previous).await;
+        self.emit(QueueEvent::Reordered);
+    }
+
+    /// Shuffle the queue while spreading each requester's tracks evenly over
+    /// it, so no one's tracks end up bunched together. Each user's tracks are
+    /// shuffled, then the `i`th of a user's `k` tracks is placed around `i / k`
+    /// of the way through the queue.
+    pub async fn shuffle_balanced(&self) {
+        let previous = {
+            let mut queue = self.write().await;
+            let previous = queue.clone();
+            let mut rng = rand::rng();
+            let mut by_user = HashMap::<UserId, Vec<ResolvedTrack>>::new();
+            for track in queue.drain(..) {
+                by_user.entry(track.user_id).or_default().push(track);
+            }
+            let mut keyed = Vec::with_capacity(previous.len());
+            for (_, mut tracks) in by_user {
+                tracks.shuffle(&mut rng);
+                let k = tracks.len() as f64;
+                for (i, track) in tracks.into_iter().enumerate() {
+                    let key = (i as f64 + rng.random::<f64>()) / k;
+                    keyed.push((key, track));
+                }
+            }
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            queue.extend(keyed.into_iter().map(|(_, track)| track));
+            previous
+        };
+        self.push_undo(previous).await;
+        self.emit(QueueEvent::Reordered);
+    }
+
+    /// Sort the queue by the given key. The sort is stable, so tracks that
+    /// compare equal keep their relative order.
+    pub async fn sort_by(&self, key: SortKey) {
+        let previous = {
+            let mut queue = self.write().await;
+            let previous = queue.clone();
+            let tracks = queue.make_contiguous();
+            match key {
+                SortKey::DurationAsc => tracks.sort_by_key(|track| {
+                    (track.get_raw_duration().is_none(), track.get_raw_duration())
+                }),
+                SortKey::DurationDesc => tracks.sort_by_key(|track| {
+                    (
+                        track.get_raw_duration().is_none(),
+                        std::cmp::Reverse(track.get_raw_duration()),
+                    )
+                }),
+                SortKey::Title => {
+                    tracks.sort_by_cached_key(|track| track.get_title().to_lowercase())
+                }
+                SortKey::Requester => tracks.sort_by_key(|track| track.get_requesting_user()),
+            }
+            previous
+        };
+        self.push_undo(previous).await;
+        self.emit(QueueEvent::Reordered);
     }
 
     /// Append a copy of this queue to another queue.
     pub async fn append_self_to_other(&self, other: &mut VecDeque<ResolvedTrack>) {
-        other.append(&mut self.inner.lock().await.clone());
+        other.extend(self.inner.read().await.iter().cloned());
     }
 }
 
@@ -0,0 +1,240 @@
+use crate::ResolvedTrack;
+use crack_types::{AuxMetadata, Error, QueryType};
+use regex::Regex;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE};
+use songbird::input::{File, HlsRequest, HttpRequest, Input, YoutubeDl};
+use std::sync::LazyLock;
+
+/// Request header asking an Icecast or SHOUTcast server to interleave
+/// metadata blocks with the audio.
+pub const ICY_METADATA_HEADER: &str = "Icy-MetaData";
+
+/// Largest possible metadata block, its length is a single byte times 16.
+const MAX_ICY_METADATA_LEN: usize = 255 * 16;
+
+static STREAM_TITLE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"StreamTitle='(.*?)';").unwrap());
+
+/// What an internet radio server tells about its stream in the `icy-*`
+/// response headers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RadioInfo {
+    pub name: Option<String>,
+    pub genre: Option<String>,
+    pub content_type: Option<String>,
+    /// Number of audio bytes between two metadata blocks, if the server
+    /// sends any.
+    pub metaint: Option<usize>,
+}
+
+/// Implement [`RadioInfo`].
+impl RadioInfo {
+    /// Read the stream information from response headers, returning `None`
+    /// if the response doesn't look like an endless audio stream. Audio with
+    /// a known length is a plain file, not a stream.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let content_type = header(CONTENT_TYPE.as_str());
+        let is_icy = headers.keys().any(|name| name.as_str().starts_with("icy-"));
+        let is_audio = content_type.as_deref().is_some_and(is_stream_content_type);
+        if !is_icy && !(is_audio && !headers.contains_key(CONTENT_LENGTH)) {
+            return None;
+        }
+        Some(RadioInfo {
+            name: header("icy-name"),
+            genre: header("icy-genre"),
+            content_type,
+            metaint: header("icy-metaint").and_then(|value| value.parse().ok()),
+        })
+    }
+
+    /// Build an endless [`ResolvedTrack`] playing the stream at `url`,
+    /// named after the station.
+    #[must_use]
+    pub fn into_track(self, url: &str) -> ResolvedTrack {
+        let metadata = AuxMetadata {
+            title: Some(self.name.unwrap_or_else(|| url.to_string())),
+            source_url: Some(url.to_string()),
+            ..Default::default()
+        };
+        ResolvedTrack::new(QueryType::VideoLink(url.to_string()))
+            .with_metadata(metadata)
+            .with_unbounded(true)
+    }
+}
+
+/// Whether a content type is audio that can be played as it's received,
+/// playlists like M3U or PLS are not.
+fn is_stream_content_type(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    (mime.starts_with("audio/") && !mime.contains("mpegurl") && !mime.contains("scpls"))
+        || mime == "application/ogg"
+}
+
+/// Extract the now-playing title from an ICY metadata block like
+/// `StreamTitle='Artist - Title';StreamUrl='';`.
+#[must_use]
+pub fn parse_icy_metadata(metadata: &str) -> Option<String> {
+    STREAM_TITLE_REGEX
+        .captures(metadata)
+        .map(|caps| caps[1].trim().to_string())
+        .filter(|title| !title.is_empty())
+}
+
+/// Find the first metadata block in the start of a stream with `metaint`
+/// audio bytes per block, returning `None` if more bytes are needed. A
+/// block without metadata is returned as an empty slice.
+fn first_metadata_block(buf: &[u8], metaint: usize) -> Option<&[u8]> {
+    let len = usize::from(*buf.get(metaint)?) * 16;
+    buf.get(metaint + 1..metaint + 1 + len)
+}
+
+/// Check if a URL is an internet radio stream.
+/// # Errors
+/// Returns an error if the request fails.
+pub async fn probe_radio(client: &reqwest::Client, url: &str) -> Result<Option<RadioInfo>, Error> {
+    let response = client
+        .get(url)
+        .header(ICY_METADATA_HEADER, "1")
+        .send()
+        .await?
+        .error_for_status()?;
+    // Dropping the response closes the stream without reading it
+    Ok(RadioInfo::from_headers(response.headers()))
+}
+
+/// Get the title a radio stream is currently playing, if it sends one.
+/// # Errors
+/// Returns an error if the request fails.
+pub async fn now_playing(client: &reqwest::Client, url: &str) -> Result<Option<String>, Error> {
+    let mut response = client
+        .get(url)
+        .header(ICY_METADATA_HEADER, "1")
+        .send()
+        .await?
+        .error_for_status()?;
+    let Some(metaint) = RadioInfo::from_headers(response.headers()).and_then(|info| info.metaint)
+    else {
+        return Ok(None);
+    };
+
+    let mut buf = Vec::with_capacity(metaint + 1 + MAX_ICY_METADATA_LEN);
+    while let Some(chunk) = response.chunk().await? {
+        buf.extend_from_slice(&chunk);
+        if let Some(block) = first_metadata_block(&buf, metaint) {
+            let metadata = String::from_utf8_lossy(block);
+            return Ok(parse_icy_metadata(metadata.trim_end_matches('\0')));
+        }
+    }
+    Ok(None)
+}
+
+/// Build the songbird input for a track. Local files are decoded directly,
+/// endless streams are read directly over HTTP, livestreams are read from
+/// their HLS manifest and everything else goes through yt-dlp.
+#[must_use]
+pub fn track_input(client: reqwest::Client, track: &ResolvedTrack) -> Input {
+    track_input_with_args(client, track, Vec::new())
+}
+
+/// Build the songbird input for a track like [`track_input`], passing extra
+/// arguments to yt-dlp, like the credentials of
+/// [`crate::CrackTrackClient::ytdl_args`].
+#[must_use]
+pub fn track_input_with_args(
+    client: reqwest::Client,
+    track: &ResolvedTrack,
+    ytdl_args: Vec<String>,
+) -> Input {
+    if let Some(path) = track.local_path() {
+        File::new(path).into()
+    } else if let Some(hls_url) = track.hls_url() {
+        HlsRequest::new(client, hls_url.to_string()).into()
+    } else if let Some(stream_url) = track.stream_url() {
+        HttpRequest::new(client, stream_url.to_string()).into()
+    } else if track.is_unbounded() {
+        HttpRequest::new(client, track.get_url()).into()
+    } else {
+        YoutubeDl::new(client, track.get_url())
+            .user_args(ytdl_args)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_parse_icy_metadata() {
+        assert_eq!(
+            parse_icy_metadata("StreamTitle='Daft Punk - One More Time';StreamUrl='';"),
+            Some("Daft Punk - One More Time".to_string())
+        );
+        assert_eq!(
+            parse_icy_metadata("StreamTitle='Guns N' Roses - Patience';"),
+            Some("Guns N' Roses - Patience".to_string())
+        );
+        assert_eq!(parse_icy_metadata("StreamTitle='';"), None);
+        assert_eq!(parse_icy_metadata("StreamUrl='http://example.com';"), None);
+    }
+
+    #[test]
+    fn test_first_metadata_block() {
+        let mut buf = vec![0u8; 4];
+        assert_eq!(first_metadata_block(&buf, 4), None);
+        buf.push(1);
+        // Blocks are padded with zeroes to a multiple of 16 bytes
+        buf.extend_from_slice(b"StreamTitle='A';\0");
+        assert_eq!(
+            first_metadata_block(&buf[..10], 4),
+            None,
+            "Incomplete block"
+        );
+        assert_eq!(
+            first_metadata_block(&buf, 4),
+            Some(&b"StreamTitle='A';\0"[..])
+        );
+        assert_eq!(first_metadata_block(&[0, 0, 0], 2), Some(&[][..]));
+    }
+
+    #[test]
+    fn test_radio_info_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        assert_eq!(RadioInfo::from_headers(&headers), None);
+
+        headers.insert("icy-name", HeaderValue::from_static("Crack Radio"));
+        headers.insert("icy-metaint", HeaderValue::from_static("16000"));
+        let info = RadioInfo::from_headers(&headers).unwrap();
+        assert_eq!(info.name.as_deref(), Some("Crack Radio"));
+        assert_eq!(info.metaint, Some(16000));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("audio/mpeg"));
+        assert!(RadioInfo::from_headers(&headers).is_some());
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("3000000"));
+        assert_eq!(RadioInfo::from_headers(&headers), None);
+        headers.remove(CONTENT_LENGTH);
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("audio/x-mpegurl"));
+        assert_eq!(RadioInfo::from_headers(&headers), None);
+
+        let track = RadioInfo::default().into_track("https://radio.example.com/live");
+        assert!(track.is_unbounded());
+        assert_eq!(track.get_title(), "https://radio.example.com/live");
+        assert_eq!(track.get_url(), "https://radio.example.com/live");
+    }
+}
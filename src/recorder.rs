@@ -0,0 +1,307 @@
+use crate::DataInner;
+use serenity::all::{async_trait, GuildId, UserId};
+use songbird::driver::{DecodeConfig, DecodeMode};
+use songbird::{Call, CoreEvent, Event, EventContext, EventHandler as VoiceEventHandler};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Seconds of the voice channel kept by default.
+pub const DEFAULT_RECORDING_SECS: u32 = 30;
+/// Most seconds of the voice channel that can be kept.
+pub const MAX_RECORDING_SECS: u32 = 120;
+/// Sample rate of the voice audio received, in Hz.
+pub const RECORDING_SAMPLE_RATE: u32 = 48_000;
+/// Channels of the voice audio received, interleaved.
+pub const RECORDING_CHANNELS: u16 = 2;
+/// Samples of voice audio received every 20ms tick, over all channels.
+const TICK_SAMPLES: usize = (RECORDING_SAMPLE_RATE / 50) as usize * RECORDING_CHANNELS as usize;
+
+/// What a [`VoiceRecorder`] holds.
+#[derive(Debug, Default)]
+struct RecorderState {
+    /// Whether it is still recording, it unregisters itself from the call
+    /// once it isn't.
+    active: bool,
+    max_samples: usize,
+    /// The last seconds of the voice channel, mixed down.
+    buffer: VecDeque<i16>,
+    /// Users who agreed to be recorded, only their voice is kept.
+    consented: HashSet<UserId>,
+    /// Users speaking in the call by the SSRC of their audio.
+    ssrcs: HashMap<u32, UserId>,
+}
+
+/// Keeps the last seconds of what is said in the voice channel of a guild,
+/// so a moment can be clipped right after it happened. Recording is opt-in
+/// twice over: it has to be turned on for the guild, and only users who
+/// agreed to it are recorded, see [`VoiceRecorder::set_consent`]. Clones
+/// share the recording.
+#[derive(Clone, Debug)]
+pub struct VoiceRecorder {
+    state: Arc<Mutex<RecorderState>>,
+}
+
+/// Implement [`VoiceRecorder`].
+impl VoiceRecorder {
+    /// Create a new [`VoiceRecorder`] keeping the last `seconds` of voice,
+    /// at most [`MAX_RECORDING_SECS`].
+    #[must_use]
+    pub fn new(seconds: u32) -> Self {
+        let seconds = seconds.clamp(1, MAX_RECORDING_SECS) as usize;
+        VoiceRecorder {
+            state: Arc::new(Mutex::new(RecorderState {
+                active: true,
+                max_samples: seconds * TICK_SAMPLES * 50,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Run `f` on the state of the recorder.
+    fn with_state<R>(&self, f: impl FnOnce(&mut RecorderState) -> R) -> R {
+        f(&mut self.state.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Whether it is still recording.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.with_state(|state| state.active)
+    }
+
+    /// Stop recording and drop what was recorded.
+    pub fn stop(&self) {
+        self.with_state(|state| {
+            state.active = false;
+            state.buffer.clear();
+        });
+    }
+
+    /// Set whether a user agrees to be recorded. What was recorded is
+    /// dropped when a user withdraws, as their voice may be mixed into it.
+    pub fn set_consent(&self, user_id: UserId, consent: bool) {
+        self.with_state(|state| {
+            if consent {
+                state.consented.insert(user_id);
+            } else if state.consented.remove(&user_id) {
+                state.buffer.clear();
+            }
+        });
+    }
+
+    /// Whether a user agreed to be recorded.
+    #[must_use]
+    pub fn has_consent(&self, user_id: UserId) -> bool {
+        self.with_state(|state| state.consented.contains(&user_id))
+    }
+
+    /// Note the user whose audio comes with an SSRC.
+    pub fn map_ssrc(&self, ssrc: u32, user_id: UserId) {
+        self.with_state(|state| state.ssrcs.insert(ssrc, user_id));
+    }
+
+    /// Add a 20ms tick of decoded voice by SSRC to the recording, mixing
+    /// the users who agreed to be recorded. A tick without any adds
+    /// silence, so the recording keeps time.
+    pub fn push_tick<'a>(&self, voices: impl IntoIterator<Item = (u32, &'a [i16])>) {
+        self.with_state(|state| {
+            let mut mixed = [0i16; TICK_SAMPLES];
+            for (ssrc, voice) in voices {
+                let consented = state
+                    .ssrcs
+                    .get(&ssrc)
+                    .is_some_and(|user_id| state.consented.contains(user_id));
+                if !consented {
+                    continue;
+                }
+                for (out, sample) in mixed.iter_mut().zip(voice) {
+                    *out = out.saturating_add(*sample);
+                }
+            }
+            state.buffer.extend(mixed);
+            let excess = state.buffer.len().saturating_sub(state.max_samples);
+            state.buffer.drain(..excess);
+        });
+    }
+
+    /// How much of the voice channel is recorded.
+    #[must_use]
+    pub fn recorded(&self) -> Duration {
+        let samples = self.with_state(|state| state.buffer.len());
+        samples_duration(samples)
+    }
+
+    /// The last `seconds` recorded, all of it if `None`, as a WAV file.
+    #[must_use]
+    pub fn clip(&self, seconds: Option<u32>) -> Vec<u8> {
+        self.with_state(|state| {
+            let len = state.buffer.len();
+            let wanted = seconds.map_or(len, |seconds| seconds as usize * TICK_SAMPLES * 50);
+            let samples = state
+                .buffer
+                .iter()
+                .skip(len.saturating_sub(wanted))
+                .copied()
+                .collect::<Vec<_>>();
+            wav(&samples)
+        })
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for VoiceRecorder {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if !self.is_active() {
+            return Some(Event::Cancel);
+        }
+        match ctx {
+            EventContext::SpeakingStateUpdate(speaking) => {
+                if let Some(user_id) = speaking.user_id {
+                    self.map_ssrc(speaking.ssrc, UserId::new(user_id.0));
+                }
+            }
+            EventContext::VoiceTick(tick) => {
+                self.push_tick(tick.speaking.iter().filter_map(|(ssrc, data)| {
+                    data.decoded_voice.as_deref().map(|voice| (*ssrc, voice))
+                }));
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// How long `samples` interleaved samples of voice last.
+fn samples_duration(samples: usize) -> Duration {
+    let frames = samples / RECORDING_CHANNELS as usize;
+    Duration::from_millis(frames as u64 * 1000 / u64::from(RECORDING_SAMPLE_RATE))
+}
+
+/// Encode interleaved 16-bit voice samples as a WAV file.
+fn wav(samples: &[i16]) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let block_align = RECORDING_CHANNELS * 2;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&RECORDING_CHANNELS.to_le_bytes());
+    bytes.extend_from_slice(&RECORDING_SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(RECORDING_SAMPLE_RATE * u32::from(block_align)).to_le_bytes());
+    bytes.extend_from_slice(&block_align.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    bytes.extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+    bytes
+}
+
+/// Set whether the voice a call receives is decoded, which recording needs
+/// but costs CPU for every user speaking.
+pub fn set_voice_decoding(call: &mut Call, decode: bool) {
+    let mode = if decode {
+        DecodeMode::Decode(DecodeConfig::default())
+    } else {
+        DecodeMode::Decrypt
+    };
+    let config = call.config().clone().decode_mode(mode);
+    call.set_config(config);
+}
+
+/// Implement recording voice channels for [`DataInner`], kept in
+/// `recorders`.
+impl DataInner {
+    /// Start recording the voice channel of a guild in its call, keeping the
+    /// last `seconds`. Returns the recorder already running if there is one.
+    pub fn start_recording(
+        &self,
+        guild_id: GuildId,
+        call: &mut Call,
+        seconds: u32,
+    ) -> VoiceRecorder {
+        if let Some(recorder) = self.recorder(guild_id) {
+            return recorder;
+        }
+        let recorder = VoiceRecorder::new(seconds);
+        set_voice_decoding(call, true);
+        call.add_global_event(
+            Event::Core(CoreEvent::SpeakingStateUpdate),
+            recorder.clone(),
+        );
+        call.add_global_event(Event::Core(CoreEvent::VoiceTick), recorder.clone());
+        self.recorders.insert(guild_id, recorder.clone());
+        recorder
+    }
+
+    /// Stop recording the voice channel of a guild, dropping what was
+    /// recorded. Returns whether it was recording.
+    pub fn stop_recording(&self, guild_id: GuildId) -> bool {
+        match self.recorders.remove(&guild_id) {
+            Some((_, recorder)) => {
+                recorder.stop();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the recorder of a guild, if it is recording.
+    pub fn recorder(&self, guild_id: GuildId) -> Option<VoiceRecorder> {
+        self.recorders
+            .get(&guild_id)
+            .map(|recorder| recorder.clone())
+            .filter(VoiceRecorder::is_active)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_recorder() {
+        let recorder = VoiceRecorder::new(1);
+        let alice = UserId::new(1);
+        let bob = UserId::new(2);
+        recorder.map_ssrc(10, alice);
+        recorder.map_ssrc(20, bob);
+        recorder.set_consent(alice, true);
+
+        // Only the voice of users who agreed is kept
+        let voice = [100i16; TICK_SAMPLES];
+        recorder.push_tick([(10, &voice[..]), (20, &voice[..])]);
+        let clip = recorder.clip(None);
+        assert_eq!(clip.len(), 44 + TICK_SAMPLES * 2);
+        assert_eq!(&clip[44..46], &100i16.to_le_bytes());
+        assert_eq!(recorder.recorded(), Duration::from_millis(20));
+
+        // Only the last second is kept
+        for _ in 0..60 {
+            recorder.push_tick([]);
+        }
+        assert_eq!(recorder.recorded(), Duration::from_secs(1));
+        assert_eq!(recorder.clip(Some(0)).len(), 44);
+
+        // Withdrawing drops the recording
+        recorder.set_consent(alice, false);
+        assert_eq!(recorder.recorded(), Duration::ZERO);
+        assert!(!recorder.has_consent(alice));
+
+        recorder.stop();
+        assert!(!recorder.is_active());
+    }
+
+    #[test]
+    fn test_wav() {
+        let wav = wav(&[1, -1]);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[4..8], &40u32.to_le_bytes());
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(&wav[24..28], &RECORDING_SAMPLE_RATE.to_le_bytes());
+        assert_eq!(&wav[40..44], &4u32.to_le_bytes());
+        assert_eq!(&wav[44..], &[1, 0, 0xff, 0xff]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::ResolvedTrack;
+use crack_types::{AuxMetadata, QueryType};
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Get the URL of the mix YouTube generates from a video, a playlist of
+/// videos like it.
+#[must_use]
+pub fn mix_playlist_url(video_id: &str) -> String {
+    format!("https://www.youtube.com/watch?v={video_id}&list=RD{video_id}")
+}
+
+/// Build a track from a video listed as related to another one.
+#[must_use]
+pub fn related_video_track(video: &rusty_ytdl::RelatedVideo) -> ResolvedTrack {
+    let url = format!("https://www.youtube.com/watch?v={}", video.id);
+    let artist = video.author.as_ref().map(|author| author.name.clone());
+    let metadata = AuxMetadata {
+        title: Some(video.title.clone()),
+        artist: artist.clone(),
+        channel: artist,
+        duration: (!video.is_live).then(|| Duration::from_secs(video.length_seconds)),
+        thumbnail: video
+            .thumbnails
+            .iter()
+            .max_by_key(|thumbnail| thumbnail.width)
+            .map(|thumbnail| thumbnail.url.clone()),
+        source_url: Some(url.clone()),
+        ..Default::default()
+    };
+    ResolvedTrack::new(QueryType::VideoLink(url)).with_metadata(metadata)
+}
+
+/// Pick up to `n` candidates, leaving out the seed video, duplicates and
+/// livestreams, in order.
+#[must_use]
+pub fn pick_related(seed_id: &str, candidates: Vec<ResolvedTrack>, n: usize) -> Vec<ResolvedTrack> {
+    let mut seen = HashSet::from([seed_id.to_string()]);
+    candidates
+        .into_iter()
+        .filter(|track| !track.is_live() && track.get_raw_duration().is_some())
+        .filter(|track| seen.insert(track.identity()))
+        .take(n)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(id: &str, secs: Option<u64>) -> ResolvedTrack {
+        let url = format!("https://youtu.be/{id}");
+        ResolvedTrack::new(QueryType::VideoLink(url.clone())).with_metadata(AuxMetadata {
+            title: Some(id.to_string()),
+            duration: secs.map(Duration::from_secs),
+            source_url: Some(url),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_pick_related() {
+        let candidates = vec![
+            track("seed", Some(200)),
+            track("a", Some(180)),
+            track("live", None),
+            track("a", Some(180)),
+            track("b", Some(240)),
+            track("c", Some(300)),
+        ];
+        let picked = pick_related("seed", candidates, 2);
+        assert_eq!(
+            picked
+                .iter()
+                .map(ResolvedTrack::get_title)
+                .collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            mix_playlist_url("abc"),
+            "https://www.youtube.com/watch?v=abc&list=RDabc"
+        );
+    }
+}
@@ -1,7 +1,9 @@
-use crate::{UNKNOWN_DURATION, UNKNOWN_TITLE, UNKNOWN_URL};
-use crack_types::{get_human_readable_timestamp, AuxMetadata, QueryType};
+use crate::{CanonicalUrl, MatchConfidence, PersistedTrack, ResolveBackend, Speed};
+use crate::{LIVE_DURATION, MAX_TRACK_VOLUME, UNKNOWN_DURATION, UNKNOWN_TITLE, UNKNOWN_URL};
+use crack_types::{get_human_readable_timestamp, AuxMetadata, Error, QueryType};
 use regex::Regex;
 use rusty_ytdl::{search, VideoDetails};
+use serde::{Deserialize, Serialize};
 use serenity::all::{AutocompleteChoice, UserId};
 use std::{
     borrow::Cow,
@@ -16,14 +18,51 @@ static YOUTUBE_URL_REGEX_STR: &str = r"((?:https?:)?\/\/)?(?:youtube(-nocookie)?
 static YOUTUBE_URL_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(YOUTUBE_URL_REGEX_STR).unwrap());
 
+static YOUTUBE_VIDEO_ID_REGEX_STR: &str =
+    r"(?:[?&]v=|youtu\.be/|/embed/|/v/|/shorts/|/live/)([\w\-]+)";
+static YOUTUBE_VIDEO_ID_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(YOUTUBE_VIDEO_ID_REGEX_STR).unwrap());
+
 pub fn is_youtube_url(url: &str) -> bool {
     let regex = YOUTUBE_URL_REGEX.clone();
     regex.is_match(url)
 }
 
+/// Extract the video ID from any of the common YouTube URL forms
+/// (`watch?v=`, `youtu.be/`, `embed/`, `shorts/`, ...).
+pub fn extract_video_id(url: &str) -> Option<String> {
+    YOUTUBE_VIDEO_ID_REGEX
+        .captures(url)
+        .and_then(|caps| caps.get(1))
+        .map(|id| id.as_str().to_string())
+}
+
+static MESSAGE_URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:https?|file)://[^\s<>]+").unwrap());
+
+/// Extract every URL in a message, in order and without duplicates.
+/// Trailing punctuation and the `<>` Discord uses to suppress embeds are
+/// left out.
+#[must_use]
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for url in MESSAGE_URL_REGEX.find_iter(text) {
+        let url = url
+            .as_str()
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '}', '\'', '"']);
+        if !urls.iter().any(|seen| seen == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
 /// [`ResolvedTrack`] struct for holding resolved track information, this
 /// should be enough to play the track or enqueue it with the bot.
-#[derive(Clone, Debug)]
+/// It (de)serializes through [`PersistedTrack`], so only the metadata, URL,
+/// duration and requester are kept, not the live video handles.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "PersistedTrack", from = "PersistedTrack")]
 pub struct ResolvedTrack {
     // FIXME One of these three has the possibility of returning
     // the video id instead of the full URL. Need to figure out
@@ -38,6 +77,31 @@ pub struct ResolvedTrack {
     #[allow(dead_code)]
     // requesting user
     pub user_id: UserId,
+    /// Whether the track is an endless stream, like an internet radio station.
+    pub unbounded: bool,
+    /// Whether the track is a YouTube livestream that is still live.
+    pub live: bool,
+    /// HLS manifest to stream the track from, like the one of a livestream,
+    /// only valid for a few hours.
+    pub hls_url: Option<String>,
+    /// Audio stream proxied by a mirror, like an Invidious instance, for
+    /// videos blocked in the region of the bot.
+    pub stream_url: Option<String>,
+    /// Where to start playing, e.g. the start of a chapter.
+    pub start_offset: Option<Duration>,
+    /// Where to stop playing, e.g. the end of a chapter.
+    pub end_offset: Option<Duration>,
+    /// Backend the track was resolved with.
+    pub backend: Option<ResolveBackend>,
+    /// How sure the match of a catalog track, like a Spotify track, to this
+    /// video is.
+    pub match_confidence: Option<MatchConfidence>,
+    /// ReplayGain of the track in dB, from the tags of local files.
+    pub replay_gain: Option<f32>,
+    /// Speed to play the track at, over the speed of the guild.
+    pub speed: Option<Speed>,
+    /// Volume to play the track at, in percent of the volume of the guild.
+    pub volume: Option<u8>,
 }
 
 impl Default for ResolvedTrack {
@@ -50,6 +114,17 @@ impl Default for ResolvedTrack {
             search_video: None,
             video: None,
             queued: false,
+            unbounded: false,
+            live: false,
+            hls_url: None,
+            stream_url: None,
+            start_offset: None,
+            end_offset: None,
+            backend: None,
+            match_confidence: None,
+            replay_gain: None,
+            speed: None,
+            volume: None,
         }
     }
 }
@@ -121,6 +196,102 @@ impl ResolvedTrack {
         self
     }
 
+    /// Set the part of the source to play, from `start` until `end`.
+    #[must_use]
+    pub fn with_offsets(mut self, start: Option<Duration>, end: Option<Duration>) -> Self {
+        self.start_offset = start;
+        self.end_offset = end;
+        self
+    }
+
+    /// Set where to start playing, keeping where to stop. `None` keeps the
+    /// start offset the track has.
+    #[must_use]
+    pub fn with_start_offset(mut self, start: Option<Duration>) -> Self {
+        if start.is_some() {
+            self.start_offset = start;
+        }
+        self
+    }
+
+    /// Mark the track as a livestream, which has no duration.
+    #[must_use]
+    pub fn with_live(mut self, live: bool) -> Self {
+        self.live = live;
+        self
+    }
+
+    /// Set the HLS manifest to play the track from, like a livestream.
+    #[must_use]
+    pub fn with_hls_url(mut self, hls_url: String) -> Self {
+        self.hls_url = Some(hls_url);
+        self
+    }
+
+    /// Set the audio stream of a mirror to play the track from.
+    #[must_use]
+    pub fn with_stream_url(mut self, stream_url: String) -> Self {
+        self.stream_url = Some(stream_url);
+        self
+    }
+
+    /// Drop the stream URLs the video was resolved with, which expire or may
+    /// be what got stuck, so its stream is resolved again through yt-dlp
+    /// as it plays. Livestreams and streams of mirrors are kept, they can't
+    /// be played otherwise.
+    #[must_use]
+    pub fn with_fresh_stream(mut self) -> Self {
+        if self.video_id().is_some() && !self.live && self.backend != Some(ResolveBackend::Mirror) {
+            self.hls_url = None;
+            self.stream_url = None;
+        }
+        self
+    }
+
+    /// Mark the track as an endless stream.
+    #[must_use]
+    pub fn with_unbounded(mut self, unbounded: bool) -> Self {
+        self.unbounded = unbounded;
+        self
+    }
+
+    /// Set the backend the track was resolved with.
+    #[must_use]
+    pub fn with_backend(mut self, backend: ResolveBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Set how sure the match of a catalog track to the track is.
+    #[must_use]
+    pub fn with_match_confidence(mut self, match_confidence: MatchConfidence) -> Self {
+        self.match_confidence = Some(match_confidence);
+        self
+    }
+
+    /// Set the ReplayGain of the track in dB.
+    #[must_use]
+    pub fn with_replay_gain(mut self, replay_gain: Option<f32>) -> Self {
+        self.replay_gain = replay_gain;
+        self
+    }
+
+    /// Set the speed to play the track at.
+    #[must_use]
+    pub fn with_speed(mut self, speed: Option<Speed>) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Set the volume to play the track at, in percent of the volume of the
+    /// guild, e.g. to play a loud one quietly. It is capped at
+    /// [`MAX_TRACK_VOLUME`].
+    #[must_use]
+    pub fn with_volume(mut self, volume: Option<u8>) -> Self {
+        self.volume = volume.map(|volume| volume.min(MAX_TRACK_VOLUME));
+        self
+    }
+
     // ----------------- Getters ----------------- //
 
     /// Get the title of the track.
@@ -136,6 +307,46 @@ impl ResolvedTrack {
         }
     }
 
+    /// Get the artist of the track, or the channel that uploaded it.
+    pub fn get_artist(&self) -> Option<String> {
+        if let Some(metadata) = &self.metadata {
+            metadata.artist.clone().or_else(|| metadata.channel.clone())
+        } else if let Some(search_video) = &self.search_video {
+            Some(search_video.channel.name.clone())
+        } else {
+            self.details
+                .as_ref()
+                .and_then(|details| details.author.as_ref())
+                .map(|author| author.name.clone())
+        }
+        .filter(|artist| !artist.is_empty())
+    }
+
+    /// Get the URL of the largest thumbnail of the track, if it has one.
+    pub fn get_thumbnail(&self) -> Option<String> {
+        if let Some(thumbnail) = self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.thumbnail.clone())
+        {
+            return Some(thumbnail);
+        }
+        if let Some(search_video) = &self.search_video {
+            return search_video
+                .thumbnails
+                .iter()
+                .max_by_key(|thumbnail| thumbnail.width)
+                .map(|thumbnail| thumbnail.url.clone());
+        }
+        self.details.as_ref().and_then(|details| {
+            details
+                .thumbnails
+                .iter()
+                .max_by_key(|thumbnail| thumbnail.width)
+                .map(|thumbnail| thumbnail.url.clone())
+        })
+    }
+
     /// Get the URL of the track.
     pub fn get_url(&self) -> String {
         let url = if let Some(search_video) = &self.search_video {
@@ -153,16 +364,45 @@ impl ResolvedTrack {
             return UNKNOWN_URL.to_string();
         };
 
-        if YOUTUBE_URL_REGEX.is_match(&url) {
+        // Bare video IDs are expanded, full URLs of other sources are kept
+        if YOUTUBE_URL_REGEX.is_match(&url) || url.contains("://") {
             url
         } else {
             format!("https://www.youtube.com/watch?v={url}")
         }
     }
 
+    /// Get the YouTube video ID of the track, from any form of its URL, see
+    /// [`CanonicalUrl`]. `None` for other sources.
+    #[must_use]
+    pub fn video_id(&self) -> Option<String> {
+        let url = self.get_url();
+        CanonicalUrl::parse(&url)
+            .and_then(|canonical| canonical.video_id)
+            .or_else(|| extract_video_id(&url))
+    }
+
+    /// Key identifying the track: its video ID, or its URL for other
+    /// sources. Tracks with the same key are the same track.
+    #[must_use]
+    pub fn identity(&self) -> String {
+        self.video_id().unwrap_or_else(|| self.get_url())
+    }
+
+    /// Whether both tracks are the same video, or have the same URL for
+    /// other sources, regardless of the form of their URLs.
+    #[must_use]
+    pub fn same_track_as(&self, other: &ResolvedTrack) -> bool {
+        self.identity() == other.identity()
+    }
+
     /// Get the duration of the track.
     pub fn get_duration(&self) -> String {
-        if let Some(metadata) = &self.metadata {
+        if self.unbounded {
+            LIVE_DURATION.to_string()
+        } else if self.live {
+            UNKNOWN_DURATION.to_string()
+        } else if let Some(metadata) = &self.metadata {
             get_human_readable_timestamp(metadata.duration)
         } else if let Some(details) = &self.details {
             let duration =
@@ -176,6 +416,99 @@ impl ResolvedTrack {
         }
     }
 
+    /// Get the duration of the track as a [`Duration`], if it is known.
+    pub fn get_raw_duration(&self) -> Option<Duration> {
+        if self.live {
+            None
+        } else if let Some(metadata) = &self.metadata {
+            metadata.duration
+        } else if let Some(details) = &self.details {
+            details
+                .length_seconds
+                .parse::<u64>()
+                .ok()
+                .map(Duration::from_secs)
+        } else {
+            self.search_video
+                .as_ref()
+                .map(|search_video| Duration::from_millis(search_video.duration))
+        }
+    }
+
+    /// Whether the track is an endless stream that has no duration and
+    /// shouldn't be considered finished when its connection drops.
+    pub fn is_unbounded(&self) -> bool {
+        self.unbounded
+    }
+
+    /// Whether the track was only built from search results, without the
+    /// details of the video, see [`crate::CrackTrackClient::resolve_full`].
+    #[must_use]
+    pub fn is_lazy(&self) -> bool {
+        self.search_video.is_some() && self.details.is_none() && self.metadata.is_none()
+    }
+
+    /// Whether the track is a livestream that is still live.
+    pub fn is_live(&self) -> bool {
+        self.live
+    }
+
+    /// Get the HLS manifest of the track, if it was resolved with one.
+    pub fn hls_url(&self) -> Option<&str> {
+        self.hls_url.as_deref()
+    }
+
+    /// Get the audio stream of a mirror, if the track was resolved through
+    /// one.
+    pub fn stream_url(&self) -> Option<&str> {
+        self.stream_url.as_deref()
+    }
+
+    /// Get the backend the track was resolved with, `None` if it wasn't
+    /// resolved from a URL or came from the cache.
+    pub fn backend(&self) -> Option<ResolveBackend> {
+        self.backend
+    }
+
+    /// Get how sure the match of a catalog track to the track is, `None` if
+    /// it wasn't matched from a catalog.
+    pub fn match_confidence(&self) -> Option<MatchConfidence> {
+        self.match_confidence
+    }
+
+    /// Get the ReplayGain of the track in dB, if it has one.
+    pub fn replay_gain(&self) -> Option<f32> {
+        self.replay_gain
+    }
+
+    /// Get the speed to play the track at, if it has its own.
+    pub fn speed(&self) -> Option<Speed> {
+        self.speed
+    }
+
+    /// Get the volume to play the track at, if it has its own.
+    #[must_use]
+    pub fn volume(&self) -> Option<u8> {
+        self.volume
+    }
+
+    /// Get the gain the volume of the guild is multiplied with for the
+    /// track, 1 unless it has its own volume.
+    #[must_use]
+    pub fn gain(&self) -> f32 {
+        self.volume.map_or(1.0, |volume| f32::from(volume) / 100.0)
+    }
+
+    /// Where to start playing the source, if not at the beginning.
+    pub fn start_offset(&self) -> Option<Duration> {
+        self.start_offset
+    }
+
+    /// Where to stop playing the source, if not at the end.
+    pub fn end_offset(&self) -> Option<Duration> {
+        self.end_offset
+    }
+
     /// Get the metadata of the track.
     pub fn get_metadata(&self) -> Option<AuxMetadata> {
         self.metadata.clone()
@@ -186,6 +519,17 @@ impl ResolvedTrack {
         self.user_id
     }
 
+    /// Get a mention of the user who requested the track.
+    pub fn requester_mention(&self) -> String {
+        format!("<@{}>", self.user_id)
+    }
+
+    /// Format the track as an entry of the queue display, with its 1-based
+    /// position, duration and requester.
+    pub fn queue_entry(&self, position: usize) -> String {
+        format!("{position}. {self} • {}", self.requester_mention())
+    }
+
     /// Get the video object if it exists.
     pub fn get_video(&self) -> Option<rusty_ytdl::Video> {
         self.video.clone()
@@ -255,6 +599,55 @@ impl Display for ResolvedTrack {
     }
 }
 
+/// Outcome of resolving many queries: the tracks that resolved, in order,
+/// and the queries that failed with their errors.
+#[derive(Debug, Default)]
+pub struct ResolveMany {
+    pub tracks: Vec<ResolvedTrack>,
+    pub failures: Vec<(QueryType, Error)>,
+}
+
+/// Implement [`ResolveMany`].
+impl ResolveMany {
+    /// Whether every query resolved.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Keep the tracks that resolved along with the failures, unless
+    /// nothing resolved at all.
+    /// # Errors
+    /// Returns the first error if every query failed.
+    pub fn partial(self) -> Result<Self, Error> {
+        if !self.tracks.is_empty() {
+            return Ok(self);
+        }
+        match self.failures.into_iter().next() {
+            Some((_, error)) => Err(error),
+            None => Ok(ResolveMany::default()),
+        }
+    }
+
+    /// Get the tracks that resolved, ignoring failures unless nothing
+    /// resolved at all.
+    /// # Errors
+    /// Returns the first error if every query failed.
+    pub fn into_tracks(self) -> Result<Vec<ResolvedTrack>, Error> {
+        self.partial().map(|result| result.tracks)
+    }
+}
+
+/// Implement [`From`] for [`ResolveMany`], for tracks that all resolved.
+impl From<Vec<ResolvedTrack>> for ResolveMany {
+    fn from(tracks: Vec<ResolvedTrack>) -> Self {
+        ResolveMany {
+            tracks,
+            failures: Vec::new(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -316,6 +709,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_with_volume() {
+        let track = ResolvedTrack::default().with_volume(Some(50));
+        assert_eq!(track.volume(), Some(50));
+        assert!((track.gain() - 0.5).abs() < f32::EPSILON);
+
+        // Louder volumes, e.g. from a prefix command or a saved queue, are capped
+        let track = track.with_volume(Some(u8::MAX));
+        assert_eq!(track.volume(), Some(MAX_TRACK_VOLUME));
+        assert_eq!(ResolvedTrack::default().with_volume(None).volume(), None);
+    }
+
     #[test]
     fn test_get_title_priority() {
         let search_video = create_mock_search_video();
@@ -446,6 +851,109 @@ mod tests {
         //assert!(display.contains("youtube.com"));
     }
 
+    #[test]
+    fn test_queue_entry() {
+        let track = ResolvedTrack::default()
+            .with_metadata(create_mock_aux_metadata())
+            .with_user_id(UserId::new(123));
+        assert_eq!(track.requester_mention(), "<@123>");
+        let entry = track.queue_entry(3);
+        assert!(entry.starts_with("3. [Metadata Test Video]("));
+        assert!(entry.contains(&track.get_duration()));
+        assert!(entry.ends_with(" • <@123>"));
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let track = ResolvedTrack::default()
+            .with_metadata(create_mock_aux_metadata())
+            .with_user_id(UserId::new(123));
+        let json = serde_json::to_string(&track).expect("Failed to serialize");
+        let parsed: ResolvedTrack = serde_json::from_str(&json).expect("Failed to deserialize");
+
+        assert_eq!(parsed.get_title(), "Metadata Test Video");
+        assert_eq!(parsed.get_url(), "https://www.youtube.com/watch?v=meta123");
+        assert_eq!(parsed.get_raw_duration(), Some(Duration::from_secs(300)));
+        assert_eq!(parsed.get_requesting_user(), UserId::new(123));
+        assert!(!parsed.is_unbounded());
+
+        let stream = ResolvedTrack::new(QueryType::VideoLink(
+            "https://radio.example.com/stream.mp3".to_string(),
+        ))
+        .with_unbounded(true);
+        let json = serde_json::to_string(&stream).expect("Failed to serialize");
+        let parsed: ResolvedTrack = serde_json::from_str(&json).expect("Failed to deserialize");
+        assert!(parsed.is_unbounded());
+        assert_eq!(parsed.get_url(), "https://radio.example.com/stream.mp3");
+        assert_eq!(parsed.get_duration(), LIVE_DURATION);
+
+        let live = ResolvedTrack::default()
+            .with_metadata(create_mock_aux_metadata())
+            .with_live(true)
+            .with_hls_url("https://manifest.googlevideo.com/live.m3u8".to_string());
+        assert_eq!(live.get_duration(), UNKNOWN_DURATION);
+        assert_eq!(live.get_raw_duration(), None);
+        let json = serde_json::to_string(&live).expect("Failed to serialize");
+        let parsed: ResolvedTrack = serde_json::from_str(&json).expect("Failed to deserialize");
+        assert!(parsed.is_live());
+        // The manifest expires, so it isn't kept
+        assert_eq!(parsed.hls_url(), None);
+    }
+
+    #[test]
+    fn test_with_fresh_stream() {
+        let track = ResolvedTrack::new(QueryType::VideoLink(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+        ))
+        .with_stream_url("https://rr1---sn.googlevideo.com/videoplayback".to_string());
+        assert_eq!(track.clone().with_fresh_stream().stream_url(), None);
+        // Mirrors are what the video can be played through
+        let mirrored = track.with_backend(ResolveBackend::Mirror);
+        assert!(mirrored.with_fresh_stream().stream_url().is_some());
+    }
+
+    #[test]
+    fn test_extract_video_id() {
+        let urls = [
+            "https://www.youtube.com/watch?v=DFYRQ_zQ-gk&feature=featured",
+            "https://www.youtube.com/watch?feature=featured&v=DFYRQ_zQ-gk",
+            "https://m.youtube.com/watch?v=DFYRQ_zQ-gk",
+            "https://youtu.be/DFYRQ_zQ-gk?t=120",
+            "https://www.youtube.com/embed/DFYRQ_zQ-gk?autoplay=1",
+            "https://www.youtube.com/v/DFYRQ_zQ-gk?fs=1&hl=en_US",
+            "https://www.youtube.com/shorts/DFYRQ_zQ-gk",
+            "https://www.youtube-nocookie.com/embed/DFYRQ_zQ-gk",
+        ];
+        for url in urls {
+            assert_eq!(extract_video_id(url), Some("DFYRQ_zQ-gk".to_string()));
+        }
+        assert_eq!(extract_video_id("https://www.youtube.com/"), None);
+    }
+
+    #[test]
+    fn test_video_id_and_same_track() {
+        let short = ResolvedTrack::new(QueryType::VideoLink(
+            "https://youtu.be/DFYRQ_zQ-gk?si=abc".to_string(),
+        ));
+        let watch = ResolvedTrack::new(QueryType::VideoLink(
+            "https://www.youtube.com/watch?v=DFYRQ_zQ-gk&t=30".to_string(),
+        ));
+        let other = ResolvedTrack::new(QueryType::VideoLink(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+        ));
+        assert_eq!(short.video_id().as_deref(), Some("DFYRQ_zQ-gk"));
+        assert!(short.same_track_as(&watch));
+        assert!(!short.same_track_as(&other));
+
+        let radio = ResolvedTrack::default().with_metadata(AuxMetadata {
+            source_url: Some("https://radio.example.com/live".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(radio.video_id(), None);
+        assert_eq!(radio.identity(), "https://radio.example.com/live");
+        assert!(radio.same_track_as(&radio.clone()));
+    }
+
     #[test]
     fn test_regex1() {
         //let regex = Regex::new(r"(?im)^((?:https?:)?\/\/)?((?:www|m)\.)?((?:youtube(-nocookie)?\.com|youtu.be))(\/(?:[\w\-]+\?v=|embed\/|v\/)?)([\w\-]+)(\S+)?$").unwrap();
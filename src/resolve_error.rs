@@ -0,0 +1,167 @@
+use crate::is_region_blocked;
+use crack_types::Error;
+use reqwest::StatusCode;
+
+/// Parts of error messages of private videos.
+const PRIVATE_MARKERS: &[&str] = &["private video", "video is private", "is private"];
+
+/// Parts of error messages of videos that were deleted or never existed.
+const DELETED_MARKERS: &[&str] = &[
+    "video unavailable",
+    "has been removed",
+    "no longer available",
+    "does not exist",
+    "account associated with this video has been terminated",
+    "deleted",
+];
+
+/// Parts of error messages of videos that need a signed in adult.
+const AGE_RESTRICTED_MARKERS: &[&str] = &[
+    "age-restricted",
+    "age restricted",
+    "confirm your age",
+    "sign in to confirm your age",
+    "inappropriate for some users",
+];
+
+/// Parts of error messages of throttling, including YouTube asking to prove
+/// the bot isn't one.
+const RATE_LIMITED_MARKERS: &[&str] = &[
+    "429",
+    "too many requests",
+    "rate limit",
+    "sign in to confirm you're not a bot",
+    "sign in to confirm you’re not a bot",
+];
+
+/// Parts of error messages of requests that took too long.
+const TIMEOUT_MARKERS: &[&str] = &["timed out", "timeout", "deadline has elapsed"];
+
+/// Why a track couldn't be resolved, for messages the user can act on. Other
+/// errors are surfaced as they are.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ResolveError {
+    #[error("The video is private")]
+    Private,
+    #[error("The video was deleted or doesn't exist")]
+    Deleted,
+    #[error("The video is age-restricted and can't be played without signing in")]
+    AgeRestricted,
+    #[error("The video isn't available in the region of the bot")]
+    RegionBlocked,
+    #[error("YouTube is rate limiting the bot, try again in a few minutes")]
+    RateLimited,
+    #[error("The request timed out, try again")]
+    Timeout,
+    #[error("No results found for {0}")]
+    NoSearchResults(String),
+}
+
+/// Implement [`ResolveError`].
+impl ResolveError {
+    /// Work out why a request failed, from the errors of `reqwest` in its
+    /// chain or the messages of YouTube, `rusty_ytdl` and yt-dlp. `None` if
+    /// it isn't one of the known reasons.
+    #[must_use]
+    pub fn classify(error: &(dyn std::error::Error + 'static)) -> Option<ResolveError> {
+        let mut current = Some(error);
+        while let Some(e) = current {
+            if let Some(e) = e.downcast_ref::<ResolveError>() {
+                return Some(e.clone());
+            }
+            if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+                if e.is_timeout() {
+                    return Some(ResolveError::Timeout);
+                }
+                if e.status() == Some(StatusCode::TOO_MANY_REQUESTS) {
+                    return Some(ResolveError::RateLimited);
+                }
+            }
+            current = e.source();
+        }
+
+        let message = error.to_string().to_lowercase();
+        let has = |markers: &[&str]| markers.iter().any(|marker| message.contains(marker));
+        // Checked from the most specific, YouTube says a private or blocked
+        // video is unavailable too
+        if is_region_blocked(error) {
+            Some(ResolveError::RegionBlocked)
+        } else if has(AGE_RESTRICTED_MARKERS) {
+            Some(ResolveError::AgeRestricted)
+        } else if has(PRIVATE_MARKERS) {
+            Some(ResolveError::Private)
+        } else if has(RATE_LIMITED_MARKERS) {
+            Some(ResolveError::RateLimited)
+        } else if has(TIMEOUT_MARKERS) {
+            Some(ResolveError::Timeout)
+        } else if has(DELETED_MARKERS) {
+            Some(ResolveError::Deleted)
+        } else {
+            None
+        }
+    }
+}
+
+/// Replace an error with the [`ResolveError`] it stands for, if any, so
+/// commands and notifiers can explain it. Other errors are kept as they are.
+#[must_use]
+pub fn classify_error(error: Error) -> Error {
+    match ResolveError::classify(&*error) {
+        Some(kind) => {
+            #[cfg(feature = "crack-tracing")]
+            tracing::debug!("Classified {error} as {kind:?}");
+            kind.into()
+        }
+        None => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(message: &str) -> Option<ResolveError> {
+        let error: Error = message.into();
+        ResolveError::classify(&*error)
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            classify("Video unavailable: This is a private video"),
+            Some(ResolveError::Private)
+        );
+        assert_eq!(
+            classify("Video unavailable: This video has been removed by the uploader"),
+            Some(ResolveError::Deleted)
+        );
+        assert_eq!(
+            classify(
+                "Sign in to confirm your age. This video may be inappropriate for some users."
+            ),
+            Some(ResolveError::AgeRestricted)
+        );
+        assert_eq!(
+            classify("The uploader has not made this video available in your country"),
+            Some(ResolveError::RegionBlocked)
+        );
+        assert_eq!(
+            classify("HTTP error 429: Too Many Requests"),
+            Some(ResolveError::RateLimited)
+        );
+        assert_eq!(classify("operation timed out"), Some(ResolveError::Timeout));
+        assert_eq!(classify("Failed to parse the player"), None);
+
+        // Already classified errors are kept
+        let error: Error = ResolveError::NoSearchResults("abc".to_string()).into();
+        assert_eq!(
+            ResolveError::classify(&*error),
+            Some(ResolveError::NoSearchResults("abc".to_string()))
+        );
+        assert_eq!(
+            classify_error("Video is private".into()).to_string(),
+            "The video is private"
+        );
+        assert_eq!(classify_error("Unknown".into()).to_string(), "Unknown");
+    }
+}
@@ -0,0 +1,205 @@
+use crack_types::Error;
+use reqwest::StatusCode;
+use std::future::Future;
+use std::time::Duration;
+
+/// Parts of error messages of failures worth retrying, like throttling and
+/// network hiccups.
+const TRANSIENT_MARKERS: &[&str] = &[
+    "429",
+    "too many requests",
+    "timed out",
+    "timeout",
+    "connection reset",
+    "connection refused",
+    "connection closed",
+    "temporarily",
+    "502",
+    "503",
+    "504",
+];
+
+/// Parts of error messages of failures that retrying won't fix.
+const PERMANENT_MARKERS: &[&str] = &[
+    "private",
+    "not found",
+    "unavailable",
+    "removed",
+    "deleted",
+    "copyright",
+];
+
+/// Check if an error is transient, like a timeout or a 429, so the request
+/// may succeed when retried. Errors like a private or deleted video are
+/// permanent, as is anything not known to be transient.
+#[must_use]
+pub fn is_transient(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() || e.is_connect() {
+                return true;
+            }
+            if let Some(status) = e.status() {
+                return status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            }
+        }
+        current = e.source();
+    }
+    let message = error.to_string().to_lowercase();
+    !PERMANENT_MARKERS
+        .iter()
+        .any(|marker| message.contains(marker))
+        && TRANSIENT_MARKERS
+            .iter()
+            .any(|marker| message.contains(marker))
+}
+
+/// How often and how long to wait before retrying a failed resolve. The
+/// delay doubles with every attempt, with jitter so retries of many tracks
+/// don't all hit at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub base_delay: Duration,
+    /// Longest delay between attempts.
+    pub max_delay: Duration,
+}
+
+/// Implement [`Default`] for [`RetryPolicy`].
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+        }
+    }
+}
+
+/// Implement [`RetryPolicy`].
+impl RetryPolicy {
+    /// A policy that never retries.
+    #[must_use]
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Get the delay before retry number `retry`, counting from 0. It is
+    /// between half and all of the exponential backoff.
+    #[must_use]
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let millis = u64::try_from(backoff.as_millis()).unwrap_or(u64::MAX);
+        Duration::from_millis(rand::random_range(millis / 2..=millis))
+    }
+
+    /// Run `f` until it succeeds, fails with a permanent error or runs out
+    /// of attempts, waiting between attempts.
+    /// # Errors
+    /// Returns the error of the last attempt.
+    pub async fn retry<T, F, Fut>(&self, mut f: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut retry = 0;
+        loop {
+            match f().await {
+                Ok(value) => return Ok(value),
+                Err(e) if retry + 1 < self.max_attempts && is_transient(&*e) => {
+                    #[cfg(feature = "crack-tracing")]
+                    tracing::warn!("Retrying after transient error: {e}");
+                    tokio::time::sleep(self.delay(retry)).await;
+                    retry += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_is_transient() {
+        let error: Error = "HTTP 429 Too Many Requests".into();
+        assert!(is_transient(&*error));
+        let error: Error = "Request timed out".into();
+        assert!(is_transient(&*error));
+        let error: Error = "Video is private".into();
+        assert!(!is_transient(&*error));
+        let error: Error = "Video not found (503)".into();
+        assert!(!is_transient(&*error));
+        let error: Error = "Something else".into();
+        assert!(!is_transient(&*error));
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::default();
+        for retry in 0..10 {
+            let delay = policy.delay(retry);
+            assert!(delay <= policy.max_delay);
+        }
+        let first = policy.delay(0);
+        assert!(first >= policy.base_delay / 2 && first <= policy.base_delay);
+        assert!(policy.delay(10) >= policy.max_delay / 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        };
+
+        // Transient errors are retried until the attempts run out
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), Error> = policy
+            .retry(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("429 Too Many Requests".into())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // Permanent errors aren't
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), Error> = policy
+            .retry(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("Video is private".into())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // A success after a transient error is returned
+        let attempts = AtomicU32::new(0);
+        let result = policy
+            .retry(|| async {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err("Connection reset by peer".into())
+                } else {
+                    Ok(42)
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(RetryPolicy::none().max_attempts, 1);
+    }
+}
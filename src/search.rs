@@ -0,0 +1,396 @@
+use crack_types::{get_human_readable_timestamp, QueryType};
+use regex::Regex;
+use rusty_ytdl::search;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime};
+
+/// Longest label or description of a Discord select menu option.
+pub const SELECT_OPTION_MAX_LEN: usize = 100;
+
+/// Number of results ranked to pick the best match for keywords.
+pub const RANKED_SEARCH_LIMIT: usize = 5;
+
+/// Most results searched for to fill a filtered search.
+const FILTERED_SEARCH_MAX: usize = 50;
+
+static RELATIVE_AGE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(\d+)\s+(second|minute|hour|day|week|month|year)s?\s+ago").unwrap()
+});
+
+/// Parse a relative upload date of a search result, like `3 years ago` or
+/// `Streamed 2 days ago`, into the approximate age.
+#[must_use]
+pub fn parse_relative_age(text: &str) -> Option<Duration> {
+    let caps = RELATIVE_AGE_REGEX.captures(&text.to_lowercase())?;
+    let count: u64 = caps[1].parse().ok()?;
+    let unit_secs = match &caps[2] {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        _ => 365 * 24 * 60 * 60,
+    };
+    Some(Duration::from_secs(count.saturating_mul(unit_secs)))
+}
+
+/// Shorten `text` to at most `max` characters, ending it with `…` if cut.
+fn truncate_chars(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// A search result that hasn't been resolved yet, with enough to show it in
+/// a select menu. Only the chosen one has to be resolved, see
+/// [`crate::CrackTrackClient::resolve_candidate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchCandidate {
+    pub url: String,
+    pub title: String,
+    pub channel: String,
+    pub channel_id: String,
+    /// Length of the video, `None` for a livestream.
+    pub duration: Option<Duration>,
+    pub thumbnail: Option<String>,
+    /// When it was uploaded, relative like `3 years ago`.
+    pub uploaded_at: Option<String>,
+    pub views: u64,
+}
+
+/// Implement [`SearchCandidate`].
+impl SearchCandidate {
+    /// Label for a select menu option, the title.
+    #[must_use]
+    pub fn label(&self) -> String {
+        truncate_chars(&self.title, SELECT_OPTION_MAX_LEN)
+    }
+
+    /// Description for a select menu option, the channel and duration.
+    #[must_use]
+    pub fn description(&self) -> String {
+        let duration = get_human_readable_timestamp(self.duration);
+        truncate_chars(
+            &format!("{} • {duration}", self.channel),
+            SELECT_OPTION_MAX_LEN,
+        )
+    }
+
+    /// Query to resolve the candidate with.
+    #[must_use]
+    pub fn query(&self) -> QueryType {
+        QueryType::VideoLink(self.url.clone())
+    }
+}
+
+/// Implement [`From`] for [`search::Video`] to [`SearchCandidate`].
+impl From<search::Video> for SearchCandidate {
+    fn from(video: search::Video) -> Self {
+        let thumbnail = video
+            .thumbnails
+            .iter()
+            .max_by_key(|thumbnail| thumbnail.width)
+            .map(|thumbnail| thumbnail.url.clone());
+        SearchCandidate {
+            url: video.url,
+            title: video.title,
+            channel: video.channel.name,
+            channel_id: video.channel.id,
+            duration: (video.duration > 0).then(|| Duration::from_millis(video.duration)),
+            thumbnail,
+            uploaded_at: video.uploaded_at,
+            views: video.views,
+        }
+    }
+}
+
+/// Words in a title marking another version than the studio recording,
+/// unless the query asks for them.
+const OTHER_VERSION_MARKERS: &[&str] = &[
+    "live",
+    "cover",
+    "remix",
+    "karaoke",
+    "instrumental",
+    "sped up",
+    "slowed",
+    "nightcore",
+    "8d",
+    "reaction",
+];
+
+/// Score a search result as a match for `query`, higher is better. Topic
+/// channels and official audio or videos are preferred, other versions like
+/// covers and live recordings aren't, and when the length of the wanted
+/// track is known, results closer to it are preferred.
+#[must_use]
+pub fn rank_score(candidate: &SearchCandidate, query: &str, expected: Option<Duration>) -> i64 {
+    let title = candidate.title.to_lowercase();
+    let query = query.to_lowercase();
+    let mut score = 0;
+    if candidate.channel.ends_with(" - Topic") {
+        score += 30;
+    }
+    if title.contains("official audio") {
+        score += 20;
+    } else if title.contains("official video") || title.contains("official music video") {
+        score += 10;
+    }
+    score -= 25
+        * OTHER_VERSION_MARKERS
+            .iter()
+            .filter(|marker| title.contains(*marker) && !query.contains(*marker))
+            .count() as i64;
+    if let (Some(expected), Some(duration)) = (expected, candidate.duration) {
+        let diff = expected.abs_diff(duration).as_secs();
+        score += match diff {
+            0..=3 => 25,
+            4..=10 => 15,
+            11..=30 => 5,
+            31..=60 => 0,
+            _ => -20,
+        };
+    }
+    score
+}
+
+/// Sort search results by [`rank_score`], best first. The search order
+/// still counts for a little, so ties keep it.
+#[must_use]
+pub fn rank_candidates(
+    candidates: Vec<SearchCandidate>,
+    query: &str,
+    expected: Option<Duration>,
+) -> Vec<SearchCandidate> {
+    let mut scored: Vec<(i64, SearchCandidate)> = candidates
+        .into_iter()
+        .enumerate()
+        .map(|(index, candidate)| {
+            let score = rank_score(&candidate, query, expected) - 2 * index as i64;
+            (score, candidate)
+        })
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+/// Filters applied to search results, like only videos over an hour from
+/// the last month. Results missing what a filter checks, like the length of
+/// a livestream, don't match it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchFilters {
+    pub min_duration: Option<Duration>,
+    pub max_duration: Option<Duration>,
+    pub uploaded_after: Option<SystemTime>,
+    /// Name or ID of the channel, the name compared ignoring case.
+    pub channel: Option<String>,
+}
+
+/// Implement [`SearchFilters`].
+impl SearchFilters {
+    /// Only match videos at least `duration` long.
+    #[must_use]
+    pub fn with_min_duration(mut self, duration: Duration) -> Self {
+        self.min_duration = Some(duration);
+        self
+    }
+
+    /// Only match videos at most `duration` long.
+    #[must_use]
+    pub fn with_max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    /// Only match videos uploaded after `time`.
+    #[must_use]
+    pub fn with_uploaded_after(mut self, time: SystemTime) -> Self {
+        self.uploaded_after = Some(time);
+        self
+    }
+
+    /// Only match videos uploaded within `age` of now.
+    #[must_use]
+    pub fn with_uploaded_within(self, age: Duration) -> Self {
+        let time = SystemTime::now()
+            .checked_sub(age)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        self.with_uploaded_after(time)
+    }
+
+    /// Only match videos of a channel, by name or ID.
+    #[must_use]
+    pub fn with_channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = Some(channel.into());
+        self
+    }
+
+    /// Whether no filter is set.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == SearchFilters::default()
+    }
+
+    /// Number of results to search for to find `n` matching ones.
+    #[must_use]
+    pub fn search_limit(&self, n: usize) -> usize {
+        if self.is_empty() {
+            n
+        } else {
+            n.saturating_mul(4).clamp(n, FILTERED_SEARCH_MAX.max(n))
+        }
+    }
+
+    /// Check if a search result matches the filters.
+    #[must_use]
+    pub fn matches(&self, candidate: &SearchCandidate) -> bool {
+        if self.min_duration.is_some() || self.max_duration.is_some() {
+            let Some(duration) = candidate.duration else {
+                return false;
+            };
+            if self.min_duration.is_some_and(|min| duration < min)
+                || self.max_duration.is_some_and(|max| duration > max)
+            {
+                return false;
+            }
+        }
+        if let Some(after) = self.uploaded_after {
+            let uploaded = candidate
+                .uploaded_at
+                .as_deref()
+                .and_then(parse_relative_age)
+                .and_then(|age| SystemTime::now().checked_sub(age));
+            if !uploaded.is_some_and(|uploaded| uploaded >= after) {
+                return false;
+            }
+        }
+        if let Some(channel) = &self.channel {
+            if !candidate.channel.eq_ignore_ascii_case(channel) && candidate.channel_id != *channel
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_candidate_option() {
+        let candidate = SearchCandidate {
+            url: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+            title: "x".repeat(120),
+            channel: "Rick Astley".to_string(),
+            channel_id: "UCuAXFkgsw1L7xaCfnd5JJOw".to_string(),
+            duration: Some(Duration::from_secs(213)),
+            thumbnail: None,
+            uploaded_at: None,
+            views: 0,
+        };
+        let label = candidate.label();
+        assert_eq!(label.chars().count(), SELECT_OPTION_MAX_LEN);
+        assert!(label.ends_with('…'));
+        assert!(candidate.description().starts_with("Rick Astley • "));
+        assert!(matches!(candidate.query(), QueryType::VideoLink(url) if url == candidate.url));
+        assert_eq!(truncate_chars("short", 10), "short");
+    }
+
+    #[test]
+    fn test_parse_relative_age() {
+        assert_eq!(
+            parse_relative_age("3 years ago"),
+            Some(Duration::from_secs(3 * 365 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_relative_age("Streamed 1 day ago"),
+            Some(Duration::from_secs(24 * 60 * 60))
+        );
+        assert_eq!(parse_relative_age("yesterday"), None);
+    }
+
+    #[test]
+    fn test_search_filters() {
+        let candidate = SearchCandidate {
+            url: "https://www.youtube.com/watch?v=1".to_string(),
+            title: "Live set".to_string(),
+            channel: "Boiler Room".to_string(),
+            channel_id: "UCGBpxWJr9FNOcFYA5GkKrMg".to_string(),
+            duration: Some(Duration::from_secs(2 * 60 * 60)),
+            thumbnail: None,
+            uploaded_at: Some("2 weeks ago".to_string()),
+            views: 0,
+        };
+        let hour = Duration::from_secs(60 * 60);
+        assert!(SearchFilters::default().matches(&candidate));
+        assert!(SearchFilters::default()
+            .with_min_duration(hour)
+            .with_uploaded_within(Duration::from_secs(30 * 24 * 60 * 60))
+            .with_channel("boiler room")
+            .matches(&candidate));
+        assert!(SearchFilters::default()
+            .with_channel("UCGBpxWJr9FNOcFYA5GkKrMg")
+            .matches(&candidate));
+        assert!(!SearchFilters::default()
+            .with_max_duration(hour)
+            .matches(&candidate));
+        assert!(!SearchFilters::default()
+            .with_uploaded_within(Duration::from_secs(24 * 60 * 60))
+            .matches(&candidate));
+        assert!(!SearchFilters::default()
+            .with_channel("Other")
+            .matches(&candidate));
+
+        // A livestream has no length to compare
+        let live = SearchCandidate {
+            duration: None,
+            ..candidate
+        };
+        assert!(!SearchFilters::default()
+            .with_min_duration(hour)
+            .matches(&live));
+        assert_eq!(SearchFilters::default().search_limit(5), 5);
+        assert_eq!(
+            SearchFilters::default()
+                .with_min_duration(hour)
+                .search_limit(5),
+            20
+        );
+    }
+
+    #[test]
+    fn test_rank_candidates() {
+        let candidate = |title: &str, channel: &str, secs: u64| SearchCandidate {
+            url: format!("https://www.youtube.com/watch?v={title}"),
+            title: title.to_string(),
+            channel: channel.to_string(),
+            channel_id: String::new(),
+            duration: Some(Duration::from_secs(secs)),
+            thumbnail: None,
+            uploaded_at: None,
+            views: 0,
+        };
+        let candidates = vec![
+            candidate("Song (Live at Wembley)", "Band", 260),
+            candidate("Song (Official Video)", "BandVEVO", 245),
+            candidate("Song", "Band - Topic", 213),
+        ];
+        let expected = Some(Duration::from_secs(212));
+        let ranked = rank_candidates(candidates.clone(), "Band - Song", expected);
+        assert_eq!(ranked[0].channel, "Band - Topic");
+        assert_eq!(ranked[1].title, "Song (Official Video)");
+        assert_eq!(ranked[2].title, "Song (Live at Wembley)");
+
+        // Asking for a live version doesn't penalize it
+        assert!(
+            rank_score(&candidates[0], "Band - Song live", None)
+                > rank_score(&candidates[0], "Band - Song", None)
+        );
+    }
+}
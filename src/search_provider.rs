@@ -0,0 +1,75 @@
+use crack_types::{Error, SearchResult};
+use rusty_ytdl::search::{self, LanguageTags, SearchOptions, SearchType, YouTube};
+use serenity::async_trait;
+use std::fmt::Debug;
+
+/// A backend searching for videos and suggesting queries, like YouTube
+/// through `rusty_ytdl`, an Invidious instance or a mock in tests.
+#[async_trait]
+pub trait SearchProvider: Debug + Send + Sync {
+    /// Get the first video found for a query, if any.
+    async fn search_one(&self, query: &str) -> Result<Option<search::Video>, Error>;
+
+    /// Get up to `limit` videos found for a query, best first.
+    async fn search_many(&self, query: &str, limit: usize) -> Result<Vec<search::Video>, Error>;
+
+    /// Get queries completing a partial one.
+    async fn suggest(&self, query: &str) -> Result<Vec<String>, Error>;
+}
+
+/// Search YouTube with `rusty_ytdl`, the default [`SearchProvider`].
+#[derive(Clone, Debug)]
+pub struct RustyYtdlSearch {
+    client: YouTube,
+}
+
+/// Implement [`RustyYtdlSearch`].
+impl RustyYtdlSearch {
+    /// Create a new [`RustyYtdlSearch`] searching with `client`.
+    #[must_use]
+    pub fn new(client: YouTube) -> Self {
+        RustyYtdlSearch { client }
+    }
+}
+
+/// Implement [`SearchProvider`] for [`RustyYtdlSearch`].
+#[async_trait]
+impl SearchProvider for RustyYtdlSearch {
+    async fn search_one(&self, query: &str) -> Result<Option<search::Video>, Error> {
+        match self.client.search_one(query, None).await? {
+            Some(SearchResult::Video(video)) => Ok(Some(video)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn search_many(&self, query: &str, limit: usize) -> Result<Vec<search::Video>, Error> {
+        let search_options = SearchOptions {
+            limit: limit as u64,
+            search_type: SearchType::Video,
+            ..Default::default()
+        };
+        let search_results = self.client.search(query, Some(&search_options)).await?;
+        Ok(search_results
+            .into_iter()
+            .filter_map(|result| match result {
+                SearchResult::Video(video) => Some(video),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn suggest(&self, query: &str) -> Result<Vec<String>, Error> {
+        let query = query.replace('"', "");
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let suggestions = self
+            .client
+            .suggestion(query, Some(LanguageTags::EN))
+            .await?;
+        Ok(suggestions
+            .into_iter()
+            .map(|x| x.replace('"', ""))
+            .collect())
+    }
+}
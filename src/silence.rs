@@ -0,0 +1,97 @@
+/// Level under which audio counts as silence, in dBFS.
+pub const SILENCE_THRESHOLD_DB: f32 = -60.0;
+/// Most silence played at once when silence in the middle of a track is let
+/// through, in frames, so it isn't held in memory all at once.
+pub const SILENCE_CHUNK_FRAMES: u64 = 4096;
+
+/// Whether interleaved samples are silent, their RMS level under
+/// [`SILENCE_THRESHOLD_DB`].
+#[must_use]
+pub fn is_silent(samples: &[f32]) -> bool {
+    if samples.is_empty() {
+        return true;
+    }
+    let power = samples
+        .iter()
+        .map(|sample| f64::from(*sample).powi(2))
+        .sum::<f64>()
+        / samples.len() as f64;
+    10.0 * power.log10() < f64::from(SILENCE_THRESHOLD_DB)
+}
+
+/// What to do with a block of audio going through a [`SilenceGate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GateAction {
+    /// Drop the block, it is silence at the start of the track. Holds the
+    /// frames dropped.
+    Skip(u64),
+    /// Hold the block back, it is silence that may last to the end of the
+    /// track.
+    Hold,
+    /// Play the block, after this many frames of silence held before it.
+    Pass(u64),
+}
+
+/// Trims the silence at the start and end of a track, like the silent
+/// intros and outros of album rips. Silence in the middle of a track is only
+/// counted while it lasts and played back once audio comes after it, so
+/// silence that runs to the end is never played.
+#[derive(Clone, Debug)]
+pub struct SilenceGate {
+    /// Whether nothing but silence went through yet.
+    leading: bool,
+    /// Frames of silence held back.
+    held: u64,
+}
+
+/// Implement [`SilenceGate`].
+impl SilenceGate {
+    /// Create a new [`SilenceGate`], at the start of a track if `at_start`.
+    #[must_use]
+    pub fn new(at_start: bool) -> Self {
+        SilenceGate {
+            leading: at_start,
+            held: 0,
+        }
+    }
+
+    /// Decide what to do with the next block of interleaved stereo samples.
+    pub fn process(&mut self, samples: &[f32]) -> GateAction {
+        let frames = (samples.len() / 2) as u64;
+        if !is_silent(samples) {
+            self.leading = false;
+            return GateAction::Pass(std::mem::take(&mut self.held));
+        }
+        if self.leading {
+            GateAction::Skip(frames)
+        } else {
+            self.held += frames;
+            GateAction::Hold
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_silence_gate() {
+        assert!(is_silent(&[0.0; 8]));
+        assert!(is_silent(&[0.0001, -0.0001]));
+        assert!(!is_silent(&[0.1, -0.1]));
+
+        let silence = [0.0; 8];
+        let audio = [0.5; 8];
+        let mut gate = SilenceGate::new(true);
+        assert_eq!(gate.process(&silence), GateAction::Skip(4));
+        assert_eq!(gate.process(&audio), GateAction::Pass(0));
+        assert_eq!(gate.process(&silence), GateAction::Hold);
+        assert_eq!(gate.process(&silence), GateAction::Hold);
+        assert_eq!(gate.process(&audio), GateAction::Pass(8));
+
+        // After a seek into the middle, silence is kept
+        let mut gate = SilenceGate::new(false);
+        assert_eq!(gate.process(&silence), GateAction::Hold);
+    }
+}
@@ -7,7 +7,7 @@ use crack_types::CrackedError;
 use crack_types::QueryType;
 use rusty_ytdl::stream::Stream;
 use rusty_ytdl::RequestOptions;
-use rusty_ytdl::VideoOptions;
+use rusty_ytdl::{VideoOptions, VideoQuality, VideoSearchOptions};
 use rusty_ytdl::{
     search::{SearchResult, YouTube},
     Video, VideoInfo,
@@ -189,6 +189,8 @@ impl Compose for RustyYoutubeSearch<'_> {
                 client: Some(http_utils::get_client().clone()),
                 ..Default::default()
             },
+            quality: VideoQuality::HighestAudio,
+            filter: VideoSearchOptions::Audio,
             ..Default::default()
         };
         let url = self.url.as_ref().unwrap();
@@ -754,7 +756,7 @@ use crack_types::{
     metadata::{search_result_to_aux_metadata, video_info_to_aux_metadata},
     CrackedError, NewAuxMetadata, QueryType,
 };
-use rusty_ytdl::{RequestOptions, Video, VideoOptions};
+use rusty_ytdl::{RequestOptions, Video, VideoOptions, VideoQuality, VideoSearchOptions};
 use songbird::input::{AuxMetadata, Compose, Input as SongbirdInput, YoutubeDl};
 
 /// Get the source and metadata from a video link. Return value is a vector due
@@ -770,6 +772,8 @@ pub async fn get_rusty_search(
     };
     let video_options = VideoOptions {
         request_options: request_options.clone(),
+        quality: VideoQuality::HighestAudio,
+        filter: VideoSearchOptions::Audio,
         ..Default::default()
     };
     let video = Video::new_with_options(url.clone(), video_options)?;
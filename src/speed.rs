@@ -0,0 +1,186 @@
+use crate::EffectsError;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// Slowest a track can play, in percent of its speed.
+pub const MIN_SPEED_PERCENT: u16 = 50;
+/// Fastest a track can play, in percent of its speed.
+pub const MAX_SPEED_PERCENT: u16 = 200;
+
+/// How fast tracks play, raising their pitch along with it like a record
+/// played faster. Kept in whole percent so it can be compared and stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Speed {
+    percent: u16,
+}
+
+/// Implement [`Speed`].
+impl Speed {
+    /// Tracks as they are.
+    pub const NORMAL: Speed = Speed { percent: 100 };
+    /// Faster and higher, like nightcore edits.
+    pub const NIGHTCORE: Speed = Speed { percent: 125 };
+    /// Slower and lower, like daycore edits.
+    pub const DAYCORE: Speed = Speed { percent: 80 };
+
+    /// Create a new [`Speed`] from a percentage of the speed of tracks.
+    /// # Errors
+    /// Returns an error if the speed is below [`MIN_SPEED_PERCENT`] or above
+    /// [`MAX_SPEED_PERCENT`].
+    pub fn new(percent: u16) -> Result<Self, EffectsError> {
+        if (MIN_SPEED_PERCENT..=MAX_SPEED_PERCENT).contains(&percent) {
+            Ok(Speed { percent })
+        } else {
+            Err(EffectsError::SpeedOutOfRange {
+                min: MIN_SPEED_PERCENT,
+                max: MAX_SPEED_PERCENT,
+            })
+        }
+    }
+
+    /// The speed in percent.
+    #[must_use]
+    pub fn percent(self) -> u16 {
+        self.percent
+    }
+
+    /// How many seconds of a track play per second.
+    #[must_use]
+    pub fn rate(self) -> f64 {
+        f64::from(self.percent.clamp(MIN_SPEED_PERCENT, MAX_SPEED_PERCENT)) / 100.0
+    }
+
+    /// How long a stretch of a track takes to play at this speed.
+    #[must_use]
+    pub fn playback_time(self, track_time: Duration) -> Duration {
+        track_time.div_f64(self.rate())
+    }
+
+    /// Whether tracks play as they are.
+    #[must_use]
+    pub fn is_normal(self) -> bool {
+        self == Speed::NORMAL
+    }
+}
+
+/// Implement [`Default`] for [`Speed`].
+impl Default for Speed {
+    fn default() -> Self {
+        Speed::NORMAL
+    }
+}
+
+/// Implement [`Display`] for [`Speed`].
+impl Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Speed::NIGHTCORE => write!(f, "nightcore ({}%)", self.percent),
+            Speed::DAYCORE => write!(f, "daycore ({}%)", self.percent),
+            _ => write!(f, "{}%", self.percent),
+        }
+    }
+}
+
+/// Changes the speed of stereo audio by resampling it, interpolating
+/// between frames. Unlike an [`crate::AudioFilter`] it changes how many
+/// samples there are, so it runs as its own stage after the filters.
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    rate: f64,
+    /// Where the next output frame is, in frames of input counted from
+    /// `last`, the last frame of the input before.
+    position: f64,
+    last: [f32; 2],
+}
+
+/// Implement [`Resampler`].
+impl Resampler {
+    /// Create a new [`Resampler`] playing audio at `speed`.
+    #[must_use]
+    pub fn new(speed: Speed) -> Self {
+        Resampler {
+            rate: speed.rate(),
+            // The first output frame is the first frame of input
+            position: 1.0,
+            last: [0.0; 2],
+        }
+    }
+
+    /// Change the speed, carrying on from where the audio is.
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.rate = speed.rate();
+    }
+
+    /// Resample interleaved stereo samples into `out`.
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        out.clear();
+        let frames = samples.len() / 2;
+        if frames == 0 {
+            return;
+        }
+        let frame = |index: usize| match index {
+            0 => self.last,
+            _ => [samples[2 * index - 2], samples[2 * index - 1]],
+        };
+        while self.position < frames as f64 {
+            let index = self.position as usize;
+            let t = (self.position - index as f64) as f32;
+            let [left_a, right_a] = frame(index);
+            let [left_b, right_b] = frame(index + 1);
+            out.push(left_a + (left_b - left_a) * t);
+            out.push(right_a + (right_b - right_a) * t);
+            self.position += self.rate;
+        }
+        let last = frame(frames);
+        self.position -= frames as f64;
+        self.last = last;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speed() {
+        assert!(Speed::default().is_normal());
+        assert_eq!(Speed::new(125).unwrap(), Speed::NIGHTCORE);
+        assert!(matches!(
+            Speed::new(300),
+            Err(EffectsError::SpeedOutOfRange { .. })
+        ));
+        assert_eq!(Speed::DAYCORE.rate(), 0.8);
+        assert_eq!(
+            Speed::NIGHTCORE.playback_time(Duration::from_secs(10)),
+            Duration::from_secs(8)
+        );
+        assert_eq!(Speed::NIGHTCORE.to_string(), "nightcore (125%)");
+        assert_eq!(Speed::new(110).unwrap().to_string(), "110%");
+    }
+
+    #[test]
+    fn test_resampler() {
+        // A ramp stays a ramp, with as many frames as the speed leaves
+        let ramp = |frames: usize| -> Vec<f32> {
+            (0..frames)
+                .flat_map(|frame| [frame as f32, -(frame as f32)])
+                .collect()
+        };
+        let mut resampler = Resampler::new(Speed::new(200).unwrap());
+        let mut out = Vec::new();
+        resampler.process(&ramp(8), &mut out);
+        assert_eq!(out, vec![0.0, -0.0, 2.0, -2.0, 4.0, -4.0, 6.0, -6.0]);
+
+        let mut resampler = Resampler::new(Speed::new(50).unwrap());
+        let mut total = Vec::new();
+        for chunk in ramp(4).chunks(4) {
+            resampler.process(chunk, &mut out);
+            total.extend_from_slice(&out);
+        }
+        let left: Vec<f32> = total.iter().step_by(2).copied().collect();
+        // The last frame is held back until the audio after it comes in
+        assert_eq!(left, vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+    }
+}
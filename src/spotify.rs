@@ -0,0 +1,217 @@
+use crate::CatalogTrack;
+use crack_types::Error;
+use futures::TryStreamExt;
+use regex::Regex;
+use rspotify::model::{
+    AlbumId, ArtistId, Country, FullTrack, Market, PlayableItem, PlaylistId, SimplifiedArtist,
+    TrackId,
+};
+use rspotify::prelude::BaseClient;
+use rspotify::{ClientCredsSpotify, Config, Credentials};
+use std::sync::{Arc, LazyLock};
+
+static SPOTIFY_URL_REGEX_STR: &str = r"(?:open\.spotify\.com/(?:intl-[\w\-]+/)?|spotify:)(track|album|playlist|artist)[/:]([A-Za-z0-9]+)";
+static SPOTIFY_URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(SPOTIFY_URL_REGEX_STR).unwrap());
+
+/// Kind of item a Spotify URL points to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpotifyMediaType {
+    Track,
+    Album,
+    Playlist,
+    Artist,
+}
+
+/// A parsed `open.spotify.com` URL or `spotify:` URI.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpotifyUrl {
+    pub media_type: SpotifyMediaType,
+    pub id: String,
+}
+
+/// Parse a Spotify URL or URI, returning `None` if it isn't one.
+#[must_use]
+pub fn parse_spotify_url(url: &str) -> Option<SpotifyUrl> {
+    let caps = SPOTIFY_URL_REGEX.captures(url)?;
+    let media_type = match &caps[1] {
+        "track" => SpotifyMediaType::Track,
+        "album" => SpotifyMediaType::Album,
+        "playlist" => SpotifyMediaType::Playlist,
+        "artist" => SpotifyMediaType::Artist,
+        _ => return None,
+    };
+    Some(SpotifyUrl {
+        media_type,
+        id: caps[2].to_string(),
+    })
+}
+
+/// Check if a URL points to Spotify.
+#[must_use]
+pub fn is_spotify_url(url: &str) -> bool {
+    SPOTIFY_URL_REGEX.is_match(url)
+}
+
+/// Convert a Spotify track's artists to their names.
+fn artist_names(artists: &[SimplifiedArtist]) -> Vec<String> {
+    artists.iter().map(|artist| artist.name.clone()).collect()
+}
+
+/// Implement [`From<FullTrack>`] for [`CatalogTrack`].
+impl From<FullTrack> for CatalogTrack {
+    fn from(track: FullTrack) -> Self {
+        CatalogTrack::new(
+            track.name,
+            artist_names(&track.artists),
+            Some(track.album.name),
+            track.duration.to_std().unwrap_or_default(),
+        )
+        .with_isrc(track.external_ids.get("isrc").cloned())
+    }
+}
+
+/// Client for the Spotify Web API, authenticated with the client credentials flow.
+#[derive(Clone, Debug)]
+pub struct SpotifyClient {
+    inner: Arc<ClientCredsSpotify>,
+}
+
+/// Implement [`SpotifyClient`].
+impl SpotifyClient {
+    /// Create a new [`SpotifyClient`] and request its first token, which is
+    /// refreshed automatically once it expires.
+    /// # Errors
+    /// Returns an error if Spotify rejects the credentials.
+    pub async fn new(creds: Credentials) -> Result<Self, Error> {
+        let config = Config {
+            token_refreshing: true,
+            ..Default::default()
+        };
+        let spotify = ClientCredsSpotify::with_config(creds, config);
+        spotify.request_token().await?;
+        Ok(SpotifyClient {
+            inner: Arc::new(spotify),
+        })
+    }
+
+    /// Create a new [`SpotifyClient`] with the credentials in the
+    /// `SPOTIFY_CLIENT_ID` and `SPOTIFY_CLIENT_SECRET` environment variables.
+    /// # Errors
+    /// Returns an error if a variable is missing or Spotify rejects the credentials.
+    pub async fn from_env() -> Result<Self, Error> {
+        let id = std::env::var("SPOTIFY_CLIENT_ID").map_err(|_| "SPOTIFY_CLIENT_ID is not set")?;
+        let secret = std::env::var("SPOTIFY_CLIENT_SECRET")
+            .map_err(|_| "SPOTIFY_CLIENT_SECRET is not set")?;
+        SpotifyClient::new(Credentials::new(&id, &secret)).await
+    }
+
+    /// Get the tracks a Spotify track, album or playlist URL points to, in
+    /// order, or the top tracks of an artist.
+    /// # Errors
+    /// Returns an error if the URL isn't a supported Spotify URL or the request fails.
+    pub async fn tracks(&self, url: &str) -> Result<Vec<CatalogTrack>, Error> {
+        let Some(SpotifyUrl { media_type, id }) = parse_spotify_url(url) else {
+            return Err(format!("Not a Spotify URL: {url}").into());
+        };
+        match media_type {
+            SpotifyMediaType::Track => Ok(vec![self.track(&id).await?]),
+            SpotifyMediaType::Album => self.album_tracks(&id).await,
+            SpotifyMediaType::Playlist => self.playlist_tracks(&id).await,
+            SpotifyMediaType::Artist => self.artist_top_tracks(&id).await,
+        }
+    }
+
+    /// Get a single track.
+    /// # Errors
+    /// Returns an error if the ID is invalid or the request fails.
+    pub async fn track(&self, id: &str) -> Result<CatalogTrack, Error> {
+        let track = self.inner.track(TrackId::from_id(id)?, None).await?;
+        Ok(track.into())
+    }
+
+    /// Get every track of an album.
+    /// # Errors
+    /// Returns an error if the ID is invalid or a request fails.
+    pub async fn album_tracks(&self, id: &str) -> Result<Vec<CatalogTrack>, Error> {
+        let album_id = AlbumId::from_id(id)?;
+        let album = self.inner.album(album_id.clone(), None).await?;
+        let tracks = self
+            .inner
+            .album_track(album_id, None)
+            .map_ok(|track| {
+                CatalogTrack::new(
+                    track.name,
+                    artist_names(&track.artists),
+                    Some(album.name.clone()),
+                    track.duration.to_std().unwrap_or_default(),
+                )
+            })
+            .try_collect()
+            .await?;
+        Ok(tracks)
+    }
+
+    /// Get the top tracks of an artist in the US, at most 10.
+    /// # Errors
+    /// Returns an error if the ID is invalid or the request fails.
+    pub async fn artist_top_tracks(&self, id: &str) -> Result<Vec<CatalogTrack>, Error> {
+        let market = Market::Country(Country::UnitedStates);
+        let tracks = self
+            .inner
+            .artist_top_tracks(ArtistId::from_id(id)?, Some(market))
+            .await?;
+        Ok(tracks.into_iter().map(CatalogTrack::from).collect())
+    }
+
+    /// Get every track of a playlist, skipping podcast episodes and local files.
+    /// # Errors
+    /// Returns an error if the ID is invalid or a request fails.
+    pub async fn playlist_tracks(&self, id: &str) -> Result<Vec<CatalogTrack>, Error> {
+        let items = self
+            .inner
+            .playlist_items(PlaylistId::from_id(id)?, None, None)
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(items
+            .into_iter()
+            .filter(|item| !item.is_local)
+            .filter_map(|item| match item.track {
+                Some(PlayableItem::Track(track)) => Some(track.into()),
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spotify_url() {
+        assert_eq!(
+            parse_spotify_url("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc"),
+            Some(SpotifyUrl {
+                media_type: SpotifyMediaType::Track,
+                id: "4uLU6hMCjMI75M1A2tKUQC".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_spotify_url("https://open.spotify.com/intl-de/album/1DFixLWuPkv3KT3TnV35m3")
+                .map(|url| url.media_type),
+            Some(SpotifyMediaType::Album)
+        );
+        assert_eq!(
+            parse_spotify_url("spotify:playlist:37i9dQZF1DXcBWIGoYBM5M").map(|url| url.id),
+            Some("37i9dQZF1DXcBWIGoYBM5M".to_string())
+        );
+        assert!(parse_spotify_url("https://www.youtube.com/watch?v=1").is_none());
+        assert_eq!(
+            parse_spotify_url("https://open.spotify.com/artist/0gxyHStUsqpMadRV0Di1Qt")
+                .map(|url| url.media_type),
+            Some(SpotifyMediaType::Artist)
+        );
+        assert!(!is_spotify_url("https://open.spotify.com/show/1"));
+    }
+}
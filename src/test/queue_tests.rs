@@ -1,10 +1,14 @@
 #[cfg(test)]
 mod queue_tests {
     use std::collections::VecDeque;
+    use std::time::Duration;
 
     use tokio;
 
-    use crate::{CrackTrackQueue, ResolvedTrack, EMPTY_QUEUE};
+    use crate::{
+        AppendOutcome, CrackTrackQueue, LoopMode, QueueError, QueueEvent, RecentWindow,
+        ResolvedTrack, SortKey, DEFAULT_VOLUME, EMPTY_QUEUE, MAX_VOLUME,
+    };
     use crack_types::{QueryType, UserId};
 
     // Helper function to create a test track
@@ -32,11 +36,11 @@ mod queue_tests {
         let track1 = create_test_track("1");
         let track2 = create_test_track("2");
 
-        queue.enqueue(track1.clone()).await;
+        queue.enqueue(track1.clone()).await.unwrap();
         assert_eq!(queue.len().await, 1);
         assert!(!queue.is_empty().await);
 
-        queue.enqueue(track2.clone()).await;
+        queue.enqueue(track2.clone()).await.unwrap();
         assert_eq!(queue.len().await, 2);
 
         // Dequeue tracks (FIFO order)
@@ -55,9 +59,9 @@ mod queue_tests {
         let queue = CrackTrackQueue::new();
 
         // Add tracks
-        queue.enqueue(create_test_track("1")).await;
-        queue.enqueue(create_test_track("2")).await;
-        queue.enqueue(create_test_track("3")).await;
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+        queue.enqueue(create_test_track("3")).await.unwrap();
 
         assert_eq!(queue.len().await, 3);
 
@@ -76,9 +80,9 @@ mod queue_tests {
         let track2 = create_test_track("2");
         let track3 = create_test_track("3");
 
-        queue.enqueue(track1.clone()).await;
-        queue.enqueue(track2.clone()).await;
-        queue.enqueue(track3.clone()).await;
+        queue.enqueue(track1.clone()).await.unwrap();
+        queue.enqueue(track2.clone()).await.unwrap();
+        queue.enqueue(track3.clone()).await.unwrap();
 
         // Get track at index
         let get_track2 = queue.get(1).await.unwrap();
@@ -128,8 +132,8 @@ mod queue_tests {
         let queue = CrackTrackQueue::new();
 
         // Add tracks
-        queue.enqueue(create_test_track("1")).await;
-        queue.enqueue(create_test_track("3")).await;
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("3")).await.unwrap();
 
         // Insert in the middle
         let track2 = create_test_track("2");
@@ -155,8 +159,8 @@ mod queue_tests {
         let queue = CrackTrackQueue::new();
 
         // Add initial tracks
-        queue.enqueue(create_test_track("1")).await;
-        queue.enqueue(create_test_track("2")).await;
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
 
         // Create a vector of tracks to append
         let tracks = vec![create_test_track("3"), create_test_track("4")];
@@ -200,7 +204,10 @@ mod queue_tests {
 
         // Add a bunch of tracks
         for i in 1..11 {
-            queue.enqueue(create_test_track(&i.to_string())).await;
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
         }
 
         // Get the original order
@@ -253,8 +260,8 @@ mod queue_tests {
         assert_eq!(queue.get_display(), "");
 
         // Add tracks
-        queue.enqueue(create_test_track("1")).await;
-        queue.enqueue(create_test_track("2")).await;
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
 
         // Display still empty until built
         assert_eq!(queue.display, EMPTY_QUEUE);
@@ -275,8 +282,8 @@ mod queue_tests {
         let queue = CrackTrackQueue::new();
 
         // Add tracks
-        queue.enqueue(create_test_track("1")).await;
-        queue.enqueue(create_test_track("2")).await;
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
 
         // Clone the queue
         let queue_clone = queue.clone();
@@ -285,7 +292,7 @@ mod queue_tests {
         assert_eq!(queue.len().await, queue_clone.len().await);
 
         // Modifying one *should* affect the other
-        queue.enqueue(create_test_track("3")).await;
+        queue.enqueue(create_test_track("3")).await.unwrap();
         assert_eq!(queue.len().await, 3);
         assert_eq!(queue_clone.len().await, 3);
 
@@ -299,4 +306,1130 @@ mod queue_tests {
             "https://www.youtube.com/watch?v=2"
         );
     }
+
+    #[tokio::test]
+    async fn test_queue_loop_modes() {
+        let queue = CrackTrackQueue::new();
+        assert_eq!(queue.loop_mode().await, LoopMode::Off);
+
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+
+        // Looping a track keeps returning the same one
+        queue.set_loop_mode(LoopMode::Track).await;
+        for _ in 0..3 {
+            let track = queue.dequeue().await.unwrap();
+            assert_eq!(track.get_url(), "https://www.youtube.com/watch?v=1");
+        }
+        assert_eq!(queue.len().await, 2);
+
+        // Looping the queue rotates through all the tracks
+        queue.set_loop_mode(LoopMode::Queue).await;
+        let urls = [
+            "https://www.youtube.com/watch?v=1",
+            "https://www.youtube.com/watch?v=2",
+            "https://www.youtube.com/watch?v=1",
+        ];
+        for url in urls {
+            assert_eq!(queue.dequeue().await.unwrap().get_url(), url);
+        }
+        assert_eq!(queue.len().await, 2);
+
+        // Back to off, tracks are dropped again
+        assert_eq!(queue.cycle_loop_mode().await, LoopMode::Off);
+        queue.dequeue().await;
+        assert_eq!(queue.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_queue_requeue_current() {
+        let queue = CrackTrackQueue::new();
+        assert!(queue.requeue_current().await.is_none());
+
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+        queue.dequeue().await;
+        let track = queue.requeue_current().await.unwrap();
+        assert_eq!(track.get_url(), "https://www.youtube.com/watch?v=1");
+        assert_eq!(queue.len().await, 2);
+        assert_eq!(queue.dequeue().await.unwrap().get_url(), track.get_url());
+
+        // A looped track is already at the front
+        queue.set_loop_mode(LoopMode::Track).await;
+        queue.dequeue().await;
+        queue.requeue_current().await;
+        assert_eq!(queue.len().await, 1);
+
+        // A looped queue keeps a single copy of the track
+        queue.enqueue(create_test_track("3")).await.unwrap();
+        queue.set_loop_mode(LoopMode::Queue).await;
+        queue.dequeue().await;
+        queue.requeue_current().await;
+        assert_eq!(queue.len().await, 2);
+        assert_eq!(
+            queue.get(0).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=2"
+        );
+        assert_eq!(
+            queue.get(1).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_move_track() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..5 {
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
+        }
+
+        // Move the last track to the front
+        queue.move_track(3, 0).await.unwrap();
+        let urls: Vec<String> = queue
+            .get_queue()
+            .await
+            .iter()
+            .map(|t| t.get_url())
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://www.youtube.com/watch?v=4",
+                "https://www.youtube.com/watch?v=1",
+                "https://www.youtube.com/watch?v=2",
+                "https://www.youtube.com/watch?v=3",
+            ]
+        );
+
+        // Move the first track to the middle
+        queue.move_track(0, 2).await.unwrap();
+        assert_eq!(
+            queue.get(2).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=4"
+        );
+
+        // Out of bounds is rejected and leaves the queue untouched
+        assert_eq!(
+            queue.move_track(0, 4).await,
+            Err(QueueError::IndexOutOfBounds { index: 4, len: 4 })
+        );
+        assert_eq!(queue.len().await, 4);
+    }
+
+    #[tokio::test]
+    async fn test_queue_remove_range() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..11 {
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
+        }
+
+        // Remove tracks 3 through 6
+        let removed = queue.remove_range(2..6).await;
+        assert_eq!(removed.len(), 4);
+        assert_eq!(removed[0].get_url(), "https://www.youtube.com/watch?v=3");
+        assert_eq!(removed[3].get_url(), "https://www.youtube.com/watch?v=6");
+        assert_eq!(queue.len().await, 6);
+        assert_eq!(
+            queue.get(2).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=7"
+        );
+
+        // The end of the range is clamped to the queue length
+        let removed = queue.remove_range(4..100).await;
+        assert_eq!(removed.len(), 2);
+        assert_eq!(queue.len().await, 4);
+
+        // Empty or out of bounds ranges remove nothing
+        assert!(queue.remove_range(10..20).await.is_empty());
+        assert!(queue.remove_range(1..1).await.is_empty());
+        assert_eq!(queue.len().await, 4);
+    }
+
+    #[tokio::test]
+    async fn test_queue_dedup() {
+        let queue = CrackTrackQueue::new();
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+        queue
+            .enqueue(ResolvedTrack::new(QueryType::VideoLink(
+                "https://youtu.be/1".to_string(),
+            )))
+            .await;
+        queue.enqueue(create_test_track("3")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+
+        assert_eq!(queue.dedup().await, 2);
+        assert_eq!(queue.len().await, 3);
+
+        // The earliest occurrence of each track is kept, in order
+        let urls: Vec<String> = queue
+            .get_queue()
+            .await
+            .iter()
+            .map(|t| t.get_url())
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://www.youtube.com/watch?v=1",
+                "https://www.youtube.com/watch?v=2",
+                "https://www.youtube.com/watch?v=3",
+            ]
+        );
+
+        // Nothing left to drop
+        assert_eq!(queue.dedup().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_queue_history_previous() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..4 {
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
+        }
+        assert!(queue.history().await.is_empty());
+
+        queue.dequeue().await;
+        queue.dequeue().await;
+        let history = queue.history().await;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].get_url(), "https://www.youtube.com/watch?v=1");
+        assert_eq!(history[1].get_url(), "https://www.youtube.com/watch?v=2");
+
+        // Going back puts the track before the playing one at the front of
+        // the queue
+        let prev = queue.previous().await.unwrap();
+        assert_eq!(prev.get_url(), "https://www.youtube.com/watch?v=1");
+        assert_eq!(queue.len().await, 2);
+        assert_eq!(
+            queue.get(0).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=1"
+        );
+        let history = queue.history().await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].get_url(), "https://www.youtube.com/watch?v=2");
+
+        // Once nothing plays, the last played track is the previous one
+        queue.clear_current().await;
+        let prev = queue.previous().await.unwrap();
+        assert_eq!(prev.get_url(), "https://www.youtube.com/watch?v=2");
+        assert!(queue.previous().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_queue_history_max() {
+        let queue = CrackTrackQueue::new();
+        queue.set_history_max(2).await;
+        assert_eq!(queue.history_max(), 2);
+
+        for i in 1..6 {
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
+        }
+        while queue.dequeue().await.is_some() {}
+
+        // Only the two most recent tracks are kept
+        let history = queue.history().await;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].get_url(), "https://www.youtube.com/watch?v=4");
+        assert_eq!(history[1].get_url(), "https://www.youtube.com/watch?v=5");
+
+        queue.set_history_max(1).await;
+        assert_eq!(queue.history().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_queue_remove_by_user() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..7 {
+            let user = if i % 2 == 0 { 2 } else { 1 };
+            queue
+                .enqueue(create_test_track(&i.to_string()).with_user_id(UserId::new(user)))
+                .await;
+        }
+
+        let removed = queue.remove_by_user(UserId::new(2)).await;
+        assert_eq!(removed.len(), 3);
+        assert!(removed
+            .iter()
+            .all(|t| t.get_requesting_user() == UserId::new(2)));
+
+        // The remaining tracks keep their order
+        let urls: Vec<String> = queue
+            .get_queue()
+            .await
+            .iter()
+            .map(|t| t.get_url())
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://www.youtube.com/watch?v=1",
+                "https://www.youtube.com/watch?v=3",
+                "https://www.youtube.com/watch?v=5",
+            ]
+        );
+
+        // Nothing to remove for an unknown user
+        assert!(queue.remove_by_user(UserId::new(3)).await.is_empty());
+        assert_eq!(queue.len().await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_queue_undo() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..6 {
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
+        }
+        let original: Vec<String> = queue
+            .get_queue()
+            .await
+            .iter()
+            .map(|t| t.get_url())
+            .collect();
+        assert!(!queue.can_undo().await);
+        assert!(!queue.undo().await);
+
+        // Undo a clear
+        queue.clear().await;
+        assert!(queue.is_empty().await);
+        assert!(queue.undo().await);
+        assert_eq!(queue.len().await, 5);
+
+        // Undo a range removal followed by a shuffle, in reverse order
+        queue.remove_range(0..2).await;
+        queue.shuffle().await;
+        assert!(queue.undo().await);
+        assert_eq!(
+            queue.get(0).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=3"
+        );
+        assert!(queue.undo().await);
+        let restored: Vec<String> = queue
+            .get_queue()
+            .await
+            .iter()
+            .map(|t| t.get_url())
+            .collect();
+        assert_eq!(restored, original);
+        assert!(!queue.can_undo().await);
+
+        // Operations that change nothing don't push an undo state
+        queue.remove_range(10..20).await;
+        queue.dedup().await;
+        assert!(!queue.can_undo().await);
+    }
+
+    #[tokio::test]
+    async fn test_queue_max_len() {
+        let queue = CrackTrackQueue::new();
+        queue.set_max_len(2);
+        assert_eq!(queue.max_len(), 2);
+
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+        assert_eq!(
+            queue.enqueue(create_test_track("3")).await,
+            Err(QueueError::QueueFull { max: 2 })
+        );
+        assert_eq!(queue.len().await, 2);
+
+        // Room frees up once a track is dequeued
+        queue.dequeue().await;
+        assert!(queue.enqueue(create_test_track("3")).await.is_ok());
+
+        // No limit
+        queue.set_max_len(0);
+        for i in 4..10 {
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
+        }
+        assert_eq!(queue.len().await, 8);
+    }
+
+    #[tokio::test]
+    async fn test_queue_insert_after_playing() {
+        let queue = CrackTrackQueue::new();
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+
+        // Nothing special going on, the track goes to the front
+        queue
+            .insert_after_playing(create_test_track("3"))
+            .await
+            .unwrap();
+        assert_eq!(
+            queue.get(0).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=3"
+        );
+
+        // When looping a playing track, it stays ahead of the new track
+        queue.set_loop_mode(LoopMode::Track).await;
+        let playing = queue.dequeue().await.unwrap();
+        queue.set_playing(Some(playing)).await;
+        queue
+            .insert_after_playing(create_test_track("4"))
+            .await
+            .unwrap();
+        assert_eq!(
+            queue.get(0).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=3"
+        );
+        assert_eq!(
+            queue.get(1).await.unwrap().get_url(),
+            "https://www.youtube.com/watch?v=4"
+        );
+
+        // The maximum length is respected
+        queue.set_max_len(4);
+        assert_eq!(
+            queue.insert_after_playing(create_test_track("5")).await,
+            Err(QueueError::QueueFull { max: 4 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_build_pages() {
+        let mut queue = CrackTrackQueue::new();
+        assert!(queue.build_pages(10).await.is_empty());
+        assert_eq!(queue.num_pages(), 0);
+
+        for i in 1..26 {
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
+        }
+
+        let pages = queue.build_pages(10).await;
+        assert_eq!(pages.len(), 3);
+        assert_eq!(queue.num_pages(), 3);
+        assert_eq!(pages[0].lines().count(), 10);
+        assert_eq!(pages[2].lines().count(), 5);
+
+        // Entries are numbered across pages
+        assert!(pages[0].starts_with("1. "));
+        assert!(pages[1].starts_with("11. "));
+        assert!(pages[2].contains("youtube.com/watch?v=25"));
+        assert_eq!(queue.get_page(1), Some(pages[1].clone()));
+        assert_eq!(queue.get_page(3), None);
+
+        // Every page fits in a Discord message
+        let pages = queue.build_pages(1000).await;
+        assert!(pages.iter().all(|page| page.len() <= 2000));
+    }
+
+    #[tokio::test]
+    async fn test_queue_autoshuffle() {
+        let queue = CrackTrackQueue::new();
+        assert!(!queue.autoshuffle());
+
+        // Without autoshuffle a batch is appended in order
+        let batch = (1..4).map(|i| create_test_track(&i.to_string())).collect();
+        queue.enqueue_batch(batch).await.unwrap();
+        let urls: Vec<String> = queue
+            .get_queue()
+            .await
+            .iter()
+            .map(|t| t.get_url())
+            .collect();
+        assert!(urls[0].ends_with("v=1"));
+        assert!(urls[2].ends_with("v=3"));
+
+        // With autoshuffle every track still ends up in the queue
+        assert!(queue.toggle_autoshuffle());
+        let batch = (4..54).map(|i| create_test_track(&i.to_string())).collect();
+        queue.enqueue_batch(batch).await.unwrap();
+        assert_eq!(queue.len().await, 53);
+
+        // The playing track stays at the front when looping it
+        queue.set_loop_mode(LoopMode::Track).await;
+        queue.set_playing(Some(create_test_track("0"))).await;
+        queue.push_front(create_test_track("0")).await;
+        let batch = (54..104)
+            .map(|i| create_test_track(&i.to_string()))
+            .collect();
+        queue.enqueue_batch(batch).await.unwrap();
+        assert!(queue.get(0).await.unwrap().get_url().ends_with("v=0"));
+
+        // The maximum length is respected
+        queue.set_max_len(105);
+        let batch = (104..110)
+            .map(|i| create_test_track(&i.to_string()))
+            .collect();
+        assert_eq!(
+            queue.enqueue_batch(batch).await,
+            Err(QueueError::QueueFull { max: 105 })
+        );
+        assert_eq!(queue.len().await, 105);
+    }
+
+    #[tokio::test]
+    async fn test_queue_find() {
+        let queue = CrackTrackQueue::new();
+        for (id, title) in [
+            ("1", "Never Gonna Give You Up"),
+            ("2", "Sandstorm"),
+            ("3", "Give It Away"),
+        ] {
+            let metadata = crack_types::AuxMetadata {
+                title: Some(title.to_string()),
+                source_url: Some(format!("https://www.youtube.com/watch?v={id}")),
+                ..Default::default()
+            };
+            queue
+                .enqueue(create_test_track(id).with_metadata(metadata))
+                .await
+                .unwrap();
+        }
+
+        let found = queue.search_titles("GIVE").await;
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].0, 0);
+        assert_eq!(found[1].0, 2);
+        assert_eq!(found[1].1.get_title(), "Give It Away");
+        assert!(queue.search_titles("darude").await.is_empty());
+
+        let found = queue.find(|track| track.get_url().ends_with("v=2")).await;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, 1);
+    }
+
+    #[tokio::test]
+    async fn test_queue_sort_by() {
+        let queue = CrackTrackQueue::new();
+        for (id, title, secs, user) in [
+            ("1", "charlie", Some(300), 2),
+            ("2", "Alpha", None, 1),
+            ("3", "bravo", Some(100), 2),
+            ("4", "delta", Some(200), 1),
+        ] {
+            let metadata = crack_types::AuxMetadata {
+                title: Some(title.to_string()),
+                source_url: Some(format!("https://www.youtube.com/watch?v={id}")),
+                duration: secs.map(std::time::Duration::from_secs),
+                ..Default::default()
+            };
+            queue
+                .enqueue(
+                    create_test_track(id)
+                        .with_metadata(metadata)
+                        .with_user_id(UserId::new(user)),
+                )
+                .await
+                .unwrap();
+        }
+        let order = |queue: VecDeque<ResolvedTrack>| {
+            queue
+                .iter()
+                .map(|track| track.get_url().chars().last().unwrap())
+                .collect::<String>()
+        };
+
+        queue.sort_by(SortKey::DurationAsc).await;
+        assert_eq!(order(queue.get_queue().await), "3412");
+        queue.sort_by(SortKey::DurationDesc).await;
+        assert_eq!(order(queue.get_queue().await), "1432");
+        queue.sort_by(SortKey::Title).await;
+        assert_eq!(order(queue.get_queue().await), "2314");
+        queue.sort_by(SortKey::Requester).await;
+        assert_eq!(order(queue.get_queue().await), "2431");
+
+        // Sorting can be undone
+        assert!(queue.undo().await);
+        assert_eq!(order(queue.get_queue().await), "2314");
+    }
+
+    #[tokio::test]
+    async fn test_queue_total_duration_and_eta() {
+        let track = |id: &str, secs: Option<u64>| {
+            let metadata = crack_types::AuxMetadata {
+                source_url: Some(format!("https://www.youtube.com/watch?v={id}")),
+                duration: secs.map(std::time::Duration::from_secs),
+                ..Default::default()
+            };
+            create_test_track(id).with_metadata(metadata)
+        };
+        let queue = CrackTrackQueue::new();
+        assert_eq!(queue.total_duration().await, std::time::Duration::ZERO);
+        assert_eq!(queue.eta_for(0).await, None);
+
+        queue.enqueue(track("1", Some(120))).await.unwrap();
+        queue.enqueue(track("2", None)).await.unwrap();
+        queue.enqueue(track("3", Some(60))).await.unwrap();
+        assert_eq!(queue.total_duration().await.as_secs(), 180);
+
+        queue.set_playing(Some(track("0", Some(300)))).await;
+        assert_eq!(queue.eta_for(0).await.map(|d| d.as_secs()), Some(300));
+        assert_eq!(queue.eta_for(2).await.map(|d| d.as_secs()), Some(420));
+        assert_eq!(queue.eta_for(3).await, None);
+
+        assert_eq!(
+            crate::format_duration_short(std::time::Duration::from_secs(7980)),
+            "2h 13m"
+        );
+        assert_eq!(
+            crate::format_duration_short(std::time::Duration::from_secs(2100)),
+            "35m"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_jump_to() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..6 {
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            queue.jump_to(5, false).await.unwrap_err(),
+            QueueError::IndexOutOfBounds { index: 5, len: 5 }
+        );
+
+        // The skipped tracks never played, so they aren't history
+        let head = queue.jump_to(2, false).await.unwrap();
+        assert!(head.get_url().ends_with("v=3"));
+        assert_eq!(queue.len().await, 3);
+        assert!(queue.history().await.is_empty());
+        assert!(queue.previous().await.is_none());
+
+        // Unless asked to record them
+        let head = queue.jump_to(1, true).await.unwrap();
+        assert!(head.get_url().ends_with("v=4"));
+        assert_eq!(queue.len().await, 2);
+        assert_eq!(queue.history().await.len(), 1);
+
+        // Jumping can be undone
+        assert!(queue.undo().await);
+        assert_eq!(queue.len().await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_queue_events() {
+        let queue = CrackTrackQueue::new();
+        let mut events = queue.subscribe();
+
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue
+            .enqueue_batch(vec![create_test_track("2"), create_test_track("3")])
+            .await
+            .unwrap();
+        queue.dequeue().await;
+        queue.shuffle().await;
+        queue.remove(0).await;
+        queue.clear().await;
+        queue.undo().await;
+
+        assert!(matches!(events.recv().await, Ok(QueueEvent::Enqueued(t)) if t.len() == 1));
+        assert!(matches!(events.recv().await, Ok(QueueEvent::Enqueued(t)) if t.len() == 2));
+        assert!(
+            matches!(events.recv().await, Ok(QueueEvent::Dequeued(t)) if t.get_url().ends_with("v=1"))
+        );
+        assert!(matches!(events.recv().await, Ok(QueueEvent::Reordered)));
+        assert!(matches!(events.recv().await, Ok(QueueEvent::Removed(t)) if t.len() == 1));
+        assert!(matches!(events.recv().await, Ok(QueueEvent::Cleared)));
+        assert!(matches!(events.recv().await, Ok(QueueEvent::Restored)));
+        assert!(events.try_recv().is_err());
+
+        // Clones share the same subscribers
+        let clone = queue.clone();
+        clone.push_back(create_test_track("4")).await;
+        assert!(matches!(events.recv().await, Ok(QueueEvent::Enqueued(_))));
+    }
+
+    #[tokio::test]
+    async fn test_queue_on_event() {
+        let queue = CrackTrackQueue::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handle = queue.on_event(move |event| {
+            let tx = tx.clone();
+            async move {
+                let _ = tx.send(event);
+            }
+        });
+
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        assert!(matches!(rx.recv().await, Some(QueueEvent::Enqueued(_))));
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_queue_concurrent_readers() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..4 {
+            queue
+                .enqueue(create_test_track(&i.to_string()))
+                .await
+                .unwrap();
+        }
+
+        // Readers don't exclude each other
+        let (len, first) = tokio::join!(
+            queue.read_with(|tracks| tracks.len()),
+            queue.read_with(|tracks| tracks.front().map(ResolvedTrack::get_url)),
+        );
+        assert_eq!(len, 3);
+        assert!(first.unwrap().ends_with("v=1"));
+
+        let readers = (0..8).map(|_| {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.len().await })
+        });
+        let writer = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.enqueue(create_test_track("4")).await })
+        };
+        for reader in readers {
+            assert!(reader.await.unwrap() >= 3);
+        }
+        writer.await.unwrap().unwrap();
+        assert_eq!(queue.len().await, 4);
+    }
+
+    #[tokio::test]
+    async fn test_queue_try_accessors() {
+        let queue = CrackTrackQueue::new();
+        assert_eq!(queue.try_len(), 0);
+        assert!(queue.try_is_empty());
+        assert!(queue.try_peek().is_none());
+
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+        assert_eq!(queue.try_len(), 2);
+        assert!(!queue.try_is_empty());
+        assert!(queue.try_peek().unwrap().get_url().ends_with("v=1"));
+
+        // Readers don't get in the way
+        let peeked = queue.read_with(|_| queue.try_peek()).await;
+        assert!(peeked.unwrap().get_url().ends_with("v=1"));
+
+        // Queues built from a list of tracks start with the right values
+        let queue = CrackTrackQueue::with_queue(VecDeque::from(vec![create_test_track("3")]));
+        assert_eq!(queue.try_len(), 1);
+        assert!(queue.try_peek().unwrap().get_url().ends_with("v=3"));
+    }
+
+    #[tokio::test]
+    async fn test_queue_current() {
+        let mut queue = CrackTrackQueue::new();
+        assert!(queue.current().await.is_none());
+
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+
+        // Dequeuing promotes the track to the current one
+        queue.dequeue().await;
+        assert!(queue.current().await.unwrap().get_url().ends_with("v=1"));
+        queue.build_display().await;
+        assert!(queue.get_display().starts_with("Now Playing: "));
+
+        queue.dequeue().await;
+        assert!(queue.current().await.unwrap().get_url().ends_with("v=2"));
+
+        // Clearing it once it finishes
+        let finished = queue.clear_current().await.unwrap();
+        assert!(finished.get_url().ends_with("v=2"));
+        assert!(queue.current().await.is_none());
+        queue.build_display().await;
+        assert_eq!(queue.get_display(), "");
+    }
+
+    #[tokio::test]
+    async fn test_queue_append_dedupe() {
+        let queue = CrackTrackQueue::new();
+        queue.enqueue(create_test_track("1")).await.unwrap();
+
+        let batch = vec![
+            create_test_track("1"),
+            create_test_track("2"),
+            create_test_track("2"),
+            create_test_track("3"),
+        ];
+        let outcome = queue.enqueue_batch_with(batch.clone(), true).await.unwrap();
+        assert_eq!(
+            outcome,
+            AppendOutcome {
+                added: 2,
+                skipped: 2
+            }
+        );
+        assert_eq!(queue.len().await, 3);
+
+        // Without the option everything is added
+        let outcome = queue
+            .enqueue_batch_with(batch.clone(), false)
+            .await
+            .unwrap();
+        assert_eq!(outcome.added, 4);
+        assert_eq!(queue.len().await, 7);
+
+        let queue = CrackTrackQueue::new();
+        queue.push_back(create_test_track("3")).await;
+        let outcome = queue.enqueue_batch_with(batch, true).await.unwrap();
+        assert_eq!(
+            outcome,
+            AppendOutcome {
+                added: 2,
+                skipped: 2
+            }
+        );
+        assert_eq!(queue.len().await, 3);
+
+        // Chapters of the same video aren't duplicates
+        let chapter = |start: u64| {
+            create_test_track("4").with_offsets(Some(Duration::from_secs(start)), None)
+        };
+        let outcome = queue
+            .enqueue_batch_with(vec![chapter(0), chapter(60), chapter(60)], true)
+            .await
+            .unwrap();
+        assert_eq!(outcome.added, 2);
+
+        // Deduplicating still keeps to the maximum length
+        queue.set_max_len(6);
+        assert_eq!(
+            queue
+                .enqueue_batch_with(
+                    vec![
+                        create_test_track("5"),
+                        create_test_track("6"),
+                        create_test_track("7")
+                    ],
+                    true
+                )
+                .await,
+            Err(QueueError::QueueFull { max: 6 })
+        );
+        assert_eq!(queue.len().await, 6);
+    }
+
+    #[tokio::test]
+    async fn test_queue_display_lazy_rebuild() {
+        let mut queue = CrackTrackQueue::new();
+        assert!(queue.is_dirty());
+        assert_eq!(queue.display().await, "");
+        assert!(!queue.is_dirty());
+
+        // A mutation through another handle marks the display dirty
+        let other = queue.clone();
+        other.enqueue(create_test_track("1")).await.unwrap();
+        assert!(queue.is_dirty());
+        let display = queue.display().await;
+        assert!(display.contains("watch?v=1"));
+        assert!(!queue.is_dirty());
+
+        other.dequeue().await;
+        assert!(queue.is_dirty());
+        assert!(queue.display().await.starts_with("Now Playing: "));
+
+        other.clear_current().await;
+        assert_eq!(queue.display().await, "");
+    }
+
+    #[tokio::test]
+    async fn test_queue_max_per_user() {
+        let queue = CrackTrackQueue::new();
+        assert_eq!(queue.max_per_user(), 0);
+        queue.set_max_per_user(2);
+
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue
+            .insert_after_playing(create_test_track("2"))
+            .await
+            .unwrap();
+        assert_eq!(
+            queue.enqueue(create_test_track("3")).await,
+            Err(QueueError::UserLimitReached {
+                user: UserId::new(1),
+                max: 2
+            })
+        );
+
+        // Other users are not affected
+        let other = create_test_track("4").with_user_id(UserId::new(2));
+        queue.enqueue(other).await.unwrap();
+
+        // A batch stops at the limit, keeping what was added before it
+        queue.dequeue().await;
+        let batch = (5..8).map(|i| create_test_track(&i.to_string())).collect();
+        assert_eq!(
+            queue.enqueue_batch(batch).await,
+            Err(QueueError::UserLimitReached {
+                user: UserId::new(1),
+                max: 2
+            })
+        );
+        assert_eq!(queue.len().await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_queue_shuffle_after() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..21 {
+            queue.push_back(create_test_track(&i.to_string())).await;
+        }
+        let original = queue.get_queue().await;
+
+        queue.shuffle_after(3).await;
+        let shuffled = queue.get_queue().await;
+        assert_eq!(shuffled.len(), original.len());
+        for i in 0..3 {
+            assert_eq!(shuffled[i].get_url(), original[i].get_url());
+        }
+        for track in &original {
+            assert!(shuffled.iter().any(|t| t.get_url() == track.get_url()));
+        }
+        assert!(queue.can_undo().await);
+
+        // Nothing to shuffle when everything is kept in place
+        let queue = CrackTrackQueue::new();
+        queue.push_back(create_test_track("1")).await;
+        queue.shuffle_after(3).await;
+        assert!(!queue.can_undo().await);
+
+        // A huge offset keeps everything in place instead of overflowing
+        queue.push_back(create_test_track("2")).await;
+        queue.shuffle_after(usize::MAX).await;
+        assert!(!queue.can_undo().await);
+        assert_eq!(queue.len().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_queue_shuffle_balanced() {
+        let queue = CrackTrackQueue::new();
+        // One user queued a whole album, two others a few tracks each
+        for i in 1..13 {
+            queue.push_back(create_test_track(&i.to_string())).await;
+        }
+        for i in 13..19 {
+            let user = if i % 2 == 0 { 2 } else { 3 };
+            let track = create_test_track(&i.to_string()).with_user_id(UserId::new(user));
+            queue.push_back(track).await;
+        }
+
+        queue.shuffle_balanced().await;
+        let mut shuffled = queue.get_queue().await;
+        assert_eq!(shuffled.len(), 18);
+
+        // Every third of the queue holds a fair share of each user's tracks
+        for third in shuffled.make_contiguous().chunks(6) {
+            for user in 1..4 {
+                let count = third
+                    .iter()
+                    .filter(|track| track.user_id == UserId::new(user))
+                    .count();
+                assert!(count >= 1, "user {user} missing from a third of the queue");
+            }
+        }
+        assert!(queue.can_undo().await);
+    }
+
+    #[tokio::test]
+    async fn test_queue_position_of() {
+        let queue = CrackTrackQueue::new();
+        for id in ["dQw4w9WgXcQ", "9bZkp7q19f0"] {
+            queue.push_back(create_test_track(id)).await;
+        }
+
+        assert_eq!(queue.position_of("9bZkp7q19f0").await, Some(1));
+        assert_eq!(
+            queue.position_of("https://youtu.be/dQw4w9WgXcQ").await,
+            Some(0)
+        );
+        assert_eq!(
+            queue
+                .position_of("https://www.youtube.com/watch?v=9bZkp7q19f0&t=42")
+                .await,
+            Some(1)
+        );
+        assert_eq!(queue.position_of("kJQP7kiw5Fk").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_queue_peek_n() {
+        let queue = CrackTrackQueue::new();
+        assert!(queue.peek_n(3).await.is_empty());
+
+        for i in 1..6 {
+            queue.push_back(create_test_track(&i.to_string())).await;
+        }
+        let next = queue.peek_n(3).await;
+        assert_eq!(next.len(), 3);
+        assert_eq!(next[0].get_url(), "https://www.youtube.com/watch?v=1");
+        assert_eq!(next[2].get_url(), "https://www.youtube.com/watch?v=3");
+        assert_eq!(queue.peek_n(10).await.len(), 5);
+        assert_eq!(queue.len().await, 5);
+    }
+
+    #[tokio::test]
+    async fn test_queue_replace() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..4 {
+            queue.push_back(create_test_track(&i.to_string())).await;
+        }
+        queue.dequeue().await;
+
+        let new = vec![create_test_track("4"), create_test_track("5")];
+        let previous = queue.replace(new, true).await;
+        assert_eq!(previous.len(), 2);
+        assert_eq!(queue.len().await, 2);
+        assert!(queue.current().await.unwrap().get_url().ends_with("v=1"));
+
+        // The looped playing track stays at the front
+        queue.set_loop_mode(LoopMode::Track).await;
+        queue.dequeue().await;
+        queue.replace(vec![create_test_track("6")], true).await;
+        assert_eq!(queue.len().await, 2);
+        assert!(queue.get(0).await.unwrap().get_url().ends_with("v=4"));
+        assert!(queue.get(1).await.unwrap().get_url().ends_with("v=6"));
+
+        // Without keeping it, the playing track is cleared
+        queue.replace(vec![create_test_track("7")], false).await;
+        assert_eq!(queue.len().await, 1);
+        assert!(queue.current().await.is_none());
+
+        // The replaced tracks can be brought back
+        assert!(queue.undo().await);
+        assert_eq!(queue.len().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_queue_interleave() {
+        let queue = CrackTrackQueue::new();
+        queue.push_back(create_test_track("0")).await;
+
+        let first = (1..4)
+            .map(|i| create_test_track(&format!("a{i}")))
+            .collect();
+        let second = vec![create_test_track("b1")];
+        let outcome = queue.interleave(first, second).await.unwrap();
+        assert_eq!(outcome.added, 4);
+
+        let ids = queue
+            .get_queue()
+            .await
+            .iter()
+            .map(|track| track.get_url().rsplit('=').next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["0", "a1", "b1", "a2", "a3"]);
+
+        // The maximum length is respected
+        queue.set_max_len(6);
+        let first = vec![create_test_track("c1"), create_test_track("c2")];
+        assert_eq!(
+            queue.interleave(first, Vec::new()).await,
+            Err(QueueError::QueueFull { max: 6 })
+        );
+        assert_eq!(queue.len().await, 6);
+    }
+
+    #[tokio::test]
+    async fn test_queue_remove_absent_requesters() {
+        let queue = CrackTrackQueue::new();
+        for i in 1..7 {
+            let track = create_test_track(&i.to_string()).with_user_id(UserId::new(i % 3 + 1));
+            queue.push_back(track).await;
+        }
+
+        let present = [UserId::new(1), UserId::new(2)].into_iter().collect();
+        let removed = queue.remove_absent_requesters(&present).await;
+        assert_eq!(removed.len(), 2);
+        assert!(removed.iter().all(|t| t.user_id == UserId::new(3)));
+        assert_eq!(queue.len().await, 4);
+
+        // Nothing left to remove
+        assert!(queue.remove_absent_requesters(&present).await.is_empty());
+        assert!(queue.undo().await);
+        assert_eq!(queue.len().await, 6);
+    }
+
+    #[tokio::test]
+    async fn test_queue_recent_window() {
+        let queue = CrackTrackQueue::new();
+        queue.enqueue(create_test_track("1")).await.unwrap();
+        queue.enqueue(create_test_track("2")).await.unwrap();
+        queue.dequeue().await;
+        queue.dequeue().await;
+
+        // Without a window played tracks can be queued again
+        assert!(!queue.played_recently(&create_test_track("1")).await);
+
+        queue.set_recent_window(Some(RecentWindow::Tracks(1))).await;
+        assert!(queue.enqueue(create_test_track("1")).await.is_ok());
+        assert!(matches!(
+            queue.enqueue(create_test_track("2")).await,
+            Err(QueueError::RecentlyPlayed { .. })
+        ));
+
+        queue
+            .set_recent_window(Some(RecentWindow::Duration(Duration::from_secs(60))))
+            .await;
+        assert!(queue.played_recently(&create_test_track("1")).await);
+        let batch = vec![create_test_track("1"), create_test_track("3")];
+        let outcome = queue.enqueue_batch_with(batch, false).await.unwrap();
+        assert_eq!(
+            outcome,
+            AppendOutcome {
+                added: 1,
+                skipped: 1
+            }
+        );
+        let len = queue.len().await;
+        let outcome = queue
+            .interleave(vec![create_test_track("1")], vec![create_test_track("4")])
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            AppendOutcome {
+                added: 1,
+                skipped: 1
+            }
+        );
+        assert_eq!(queue.len().await, len + 1);
+
+        queue
+            .set_recent_window(Some(RecentWindow::Duration(Duration::ZERO)))
+            .await;
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(queue.enqueue(create_test_track("2")).await.is_ok());
+    }
+
+    #[test]
+    fn test_queue_volume() {
+        let queue = CrackTrackQueue::new();
+        assert_eq!(queue.volume(), DEFAULT_VOLUME);
+        assert!((queue.gain() - 1.0).abs() < f32::EPSILON);
+
+        queue.set_volume(50).unwrap();
+        assert!((queue.gain() - 0.5).abs() < f32::EPSILON);
+        assert_eq!(
+            queue.set_volume(MAX_VOLUME + 1),
+            Err(QueueError::VolumeTooLoud {
+                volume: MAX_VOLUME + 1,
+                max: MAX_VOLUME
+            })
+        );
+        // Clones share the volume, like the rest of the queue
+        assert_eq!(queue.clone().volume(), 50);
+    }
 }
@@ -0,0 +1,188 @@
+use crate::{CrackTrackQueue, DataInner, ResolvedTrack};
+use crack_types::Error;
+use serenity::all::async_trait;
+use songbird::input::{AudioStream, Input, LiveInput};
+use songbird::tracks::{Track, TrackHandle};
+use songbird::{Call, Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+use std::fmt::Debug;
+use std::io::Cursor;
+use std::time::Duration;
+use symphonia::core::io::MediaSource;
+
+/// Longest text read out for a track, longer titles are cut short.
+pub const MAX_ANNOUNCEMENT_CHARS: usize = 200;
+/// How long to wait for an announcement to be synthesized before the track
+/// plays without one.
+pub const TTS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A text-to-speech backend voicing announcements, like a local Piper or
+/// Coqui server or a mock in tests.
+#[async_trait]
+pub trait TtsProvider: Debug + Send + Sync {
+    /// Synthesize speech for `text`, as audio in a format symphonia can
+    /// decode, like WAV or MP3.
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>, Error>;
+}
+
+/// [`TtsProvider`] getting speech from an HTTP server taking the text in a
+/// `text` query parameter, like the Piper HTTP server or `/api/tts` of a
+/// Coqui TTS server.
+#[derive(Clone, Debug)]
+pub struct HttpTtsProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+/// Implement [`HttpTtsProvider`].
+impl HttpTtsProvider {
+    /// Create a new [`HttpTtsProvider`] for the server at `url`, e.g.
+    /// `http://127.0.0.1:5000`.
+    #[must_use]
+    pub fn new(client: reqwest::Client, url: impl Into<String>) -> Self {
+        HttpTtsProvider {
+            client,
+            url: url.into(),
+        }
+    }
+
+    /// Create a [`HttpTtsProvider`] for the server at `TTS_URL`, `None` if
+    /// it isn't set.
+    #[must_use]
+    pub fn from_env(client: reqwest::Client) -> Option<Self> {
+        let url = std::env::var("TTS_URL").ok()?;
+        Some(HttpTtsProvider::new(client, url))
+    }
+}
+
+/// Implement [`TtsProvider`] for [`HttpTtsProvider`].
+#[async_trait]
+impl TtsProvider for HttpTtsProvider {
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>, Error> {
+        let audio = self
+            .client
+            .get(&self.url)
+            .query(&[("text", text)])
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(audio.to_vec())
+    }
+}
+
+/// The text announcing a track, like `Now playing: Song by Artist`.
+#[must_use]
+pub fn announcement_text(track: &ResolvedTrack) -> String {
+    let text = match track.get_artist().filter(|artist| !artist.is_empty()) {
+        Some(artist) => format!("Now playing: {} by {artist}", track.get_title()),
+        None => format!("Now playing: {}", track.get_title()),
+    };
+    text.chars().take(MAX_ANNOUNCEMENT_CHARS).collect()
+}
+
+/// Play a synthesized announcement, then the track it announces. The track
+/// is added paused so its stream opens while the announcement plays.
+pub fn play_announced(handler: &mut Call, announcement: Vec<u8>, input: Input) -> TrackHandle {
+    let stream = AudioStream {
+        input: Box::new(Cursor::new(announcement)) as Box<dyn MediaSource>,
+        hint: None,
+    };
+    let voice = handler.play_input(Input::Live(LiveInput::Raw(stream), None));
+    let song = handler.play(Track::from(input).pause());
+    // Play the track whether the announcement finishes or fails
+    for event in [TrackEvent::End, TrackEvent::Error] {
+        let _ = voice.add_event(
+            Event::Track(event),
+            AnnouncementEndNotifier { song: song.clone() },
+        );
+    }
+    song
+}
+
+/// Starts the track announced once its announcement is over, see
+/// [`play_announced`].
+struct AnnouncementEndNotifier {
+    song: TrackHandle,
+}
+
+#[async_trait]
+impl VoiceEventHandler for AnnouncementEndNotifier {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let _ = self.song.play();
+        None
+    }
+}
+
+/// Implement voice announcements of tracks for [`DataInner`], voiced by
+/// `tts`.
+impl DataInner {
+    /// Synthesize the announcement of a track about to play, if its queue
+    /// has voice announcements on and a TTS backend is set up. Returns
+    /// `None` if it fails or takes longer than [`TTS_TIMEOUT`], so the track
+    /// plays without one.
+    pub async fn announcement(
+        &self,
+        queue: &CrackTrackQueue,
+        track: &ResolvedTrack,
+    ) -> Option<Vec<u8>> {
+        if !queue.announce() {
+            return None;
+        }
+        let tts = self.tts.as_ref()?;
+        let text = announcement_text(track);
+        match tokio::time::timeout(TTS_TIMEOUT, tts.synthesize(&text)).await {
+            Ok(Ok(audio)) => Some(audio),
+            Ok(Err(_e)) => {
+                #[cfg(feature = "crack-tracing")]
+                tracing::warn!("Failed to synthesize \"{text}\": {_e}");
+                None
+            }
+            Err(_) => {
+                #[cfg(feature = "crack-tracing")]
+                tracing::warn!("Timed out synthesizing \"{text}\"");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crack_types::{AuxMetadata, QueryType};
+
+    #[test]
+    fn test_announcement_text() {
+        let track = ResolvedTrack::new(QueryType::VideoLink(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+        ));
+        let with_artist = track.clone().with_metadata(AuxMetadata {
+            title: Some("Never Gonna Give You Up".to_string()),
+            artist: Some("Rick Astley".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            announcement_text(&with_artist),
+            "Now playing: Never Gonna Give You Up by Rick Astley"
+        );
+
+        let without_artist = track.clone().with_metadata(AuxMetadata {
+            title: Some("Never Gonna Give You Up".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            announcement_text(&without_artist),
+            "Now playing: Never Gonna Give You Up"
+        );
+
+        let long = track.with_metadata(AuxMetadata {
+            title: Some("é".repeat(300)),
+            ..Default::default()
+        });
+        assert_eq!(
+            announcement_text(&long).chars().count(),
+            MAX_ANNOUNCEMENT_CHARS
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use crate::ResolvedTrack;
+use crack_types::{AuxMetadata, Error, QueryType};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Base URL of the Vimeo player, which serves the config of a video.
+const VIMEO_PLAYER_URL: &str = "https://player.vimeo.com/video";
+
+static VIMEO_URL_REGEX_STR: &str = r"^(?:https?://)?(?:www\.)?vimeo\.com/(?:channels/[^/?#]+/|groups/[^/?#]+/videos/|album/\d+/video/)?(\d+)(?:/([0-9a-f]+))?/?(?:[?#].*)?$";
+static VIMEO_URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(VIMEO_URL_REGEX_STR).unwrap());
+
+static VIMEO_PLAYER_URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:https?://)?player\.vimeo\.com/video/(\d+)(?:\?(?:.*&)?h=([0-9a-f]+))?")
+        .unwrap()
+});
+
+/// A parsed Vimeo video URL. Unlisted videos have a hash needed to get
+/// them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VimeoUrl {
+    pub id: String,
+    pub hash: Option<String>,
+}
+
+/// Implement [`VimeoUrl`].
+impl VimeoUrl {
+    /// URL of the player config of the video.
+    fn config_url(&self) -> String {
+        match &self.hash {
+            Some(hash) => format!("{VIMEO_PLAYER_URL}/{}/config?h={hash}", self.id),
+            None => format!("{VIMEO_PLAYER_URL}/{}/config", self.id),
+        }
+    }
+}
+
+/// Parse a Vimeo video URL, including embedded player URLs, returning
+/// `None` if it isn't one.
+#[must_use]
+pub fn parse_vimeo_url(url: &str) -> Option<VimeoUrl> {
+    let url = url.trim();
+    let caps = VIMEO_URL_REGEX
+        .captures(url)
+        .or_else(|| VIMEO_PLAYER_URL_REGEX.captures(url))?;
+    Some(VimeoUrl {
+        id: caps[1].to_string(),
+        hash: caps.get(2).map(|hash| hash.as_str().to_string()),
+    })
+}
+
+/// Check if a URL points to a Vimeo video.
+#[must_use]
+pub fn is_vimeo_url(url: &str) -> bool {
+    parse_vimeo_url(url).is_some()
+}
+
+/// The config of the Vimeo player for a video.
+#[derive(Debug, Deserialize)]
+struct VimeoConfig {
+    request: VimeoRequest,
+    video: VimeoVideo,
+}
+
+#[derive(Debug, Deserialize)]
+struct VimeoRequest {
+    files: VimeoFiles,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct VimeoFiles {
+    #[serde(default)]
+    progressive: Vec<VimeoProgressive>,
+    hls: Option<VimeoHls>,
+}
+
+/// A single file with the whole video, in one resolution.
+#[derive(Debug, Deserialize)]
+struct VimeoProgressive {
+    url: String,
+    #[serde(default)]
+    height: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct VimeoHls {
+    default_cdn: Option<String>,
+    #[serde(default)]
+    cdns: HashMap<String, VimeoCdn>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VimeoCdn {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VimeoOwner {
+    name: String,
+}
+
+/// The details of a Vimeo video, its duration is in seconds.
+#[derive(Debug, Deserialize)]
+struct VimeoVideo {
+    title: String,
+    #[serde(default)]
+    duration: Option<u64>,
+    owner: Option<VimeoOwner>,
+    #[serde(default)]
+    thumbs: HashMap<String, String>,
+    url: Option<String>,
+}
+
+/// How to stream a Vimeo video.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum VimeoStream {
+    /// A single file, played over HTTP.
+    Progressive(String),
+    /// An HLS manifest.
+    Hls(String),
+}
+
+/// Implement [`VimeoConfig`].
+impl VimeoConfig {
+    /// Pick the stream to play, the progressive file with the lowest
+    /// resolution as the audio is the same in all of them, or else the HLS
+    /// manifest of the default CDN.
+    fn stream(&self) -> Option<VimeoStream> {
+        let files = &self.request.files;
+        if let Some(progressive) = files.progressive.iter().min_by_key(|file| file.height) {
+            return Some(VimeoStream::Progressive(progressive.url.clone()));
+        }
+        let hls = files.hls.as_ref()?;
+        hls.default_cdn
+            .as_ref()
+            .and_then(|cdn| hls.cdns.get(cdn))
+            .or_else(|| hls.cdns.values().next())
+            .map(|cdn| VimeoStream::Hls(cdn.url.clone()))
+    }
+
+    /// Convert to a [`ResolvedTrack`] playing the stream, or through yt-dlp if
+    /// the video has none.
+    fn into_track(self, id: &str) -> ResolvedTrack {
+        let stream = self.stream();
+        let video = self.video;
+        let url = video
+            .url
+            .unwrap_or_else(|| format!("https://vimeo.com/{id}"));
+        let artist = video.owner.map(|owner| owner.name);
+        let metadata = AuxMetadata {
+            title: Some(video.title),
+            artist: artist.clone(),
+            channel: artist,
+            duration: video.duration.map(Duration::from_secs),
+            thumbnail: video
+                .thumbs
+                .get("640")
+                .or_else(|| video.thumbs.get("base"))
+                .cloned(),
+            source_url: Some(url.clone()),
+            ..Default::default()
+        };
+        let track = ResolvedTrack::new(QueryType::VideoLink(url)).with_metadata(metadata);
+        match stream {
+            Some(VimeoStream::Progressive(stream_url)) => track.with_stream_url(stream_url),
+            Some(VimeoStream::Hls(hls_url)) => track.with_hls_url(hls_url),
+            None => track,
+        }
+    }
+}
+
+/// Client for Vimeo links using the config of the Vimeo player, no
+/// credentials are needed.
+#[derive(Clone, Debug)]
+pub struct VimeoClient {
+    client: reqwest::Client,
+}
+
+/// Implement [`VimeoClient`].
+impl VimeoClient {
+    /// Create a new [`VimeoClient`].
+    #[must_use]
+    pub fn new(client: reqwest::Client) -> Self {
+        VimeoClient { client }
+    }
+
+    /// Get the video a Vimeo URL points to, with its stream.
+    /// # Errors
+    /// Returns an error if the URL isn't a Vimeo URL or the request fails,
+    /// like for private videos.
+    pub async fn track(&self, url: &str) -> Result<ResolvedTrack, Error> {
+        let Some(parsed) = parse_vimeo_url(url) else {
+            return Err(format!("Not a Vimeo URL: {url}").into());
+        };
+        let config: VimeoConfig = self
+            .client
+            .get(parsed.config_url())
+            // The config is only served to embedding pages
+            .header(reqwest::header::REFERER, "https://vimeo.com/")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(config.into_track(&parsed.id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vimeo_url() {
+        assert_eq!(
+            parse_vimeo_url("https://vimeo.com/76979871"),
+            Some(VimeoUrl {
+                id: "76979871".to_string(),
+                hash: None,
+            })
+        );
+        assert_eq!(
+            parse_vimeo_url("https://vimeo.com/76979871/8272103f6e"),
+            Some(VimeoUrl {
+                id: "76979871".to_string(),
+                hash: Some("8272103f6e".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_vimeo_url("https://player.vimeo.com/video/76979871?h=8272103f6e&autoplay=1")
+                .and_then(|url| url.hash),
+            Some("8272103f6e".to_string())
+        );
+        assert!(is_vimeo_url(
+            "https://vimeo.com/channels/staffpicks/76979871"
+        ));
+        assert!(!is_vimeo_url("https://vimeo.com/staffpicks"));
+        assert!(!is_vimeo_url("https://www.youtube.com/watch?v=76979871"));
+    }
+
+    #[test]
+    fn test_vimeo_config() {
+        let json = r#"{"request": {"files": {
+            "progressive": [
+                {"url": "https://vod.example.com/1080.mp4", "quality": "1080p", "height": 1080},
+                {"url": "https://vod.example.com/360.mp4", "quality": "360p", "height": 360}
+            ],
+            "hls": {"default_cdn": "akamai", "cdns": {"akamai": {"url": "https://hls.example.com/master.m3u8"}}}
+        }}, "video": {"id": 76979871, "title": "The New Vimeo Player", "duration": 62,
+            "owner": {"name": "Vimeo Staff"}, "thumbs": {"640": "https://i.vimeocdn.com/640.jpg"},
+            "url": "https://vimeo.com/76979871"}}"#;
+        let mut config: VimeoConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            config.stream(),
+            Some(VimeoStream::Progressive(
+                "https://vod.example.com/360.mp4".to_string()
+            ))
+        );
+        config.request.files.progressive.clear();
+        assert_eq!(
+            config.stream(),
+            Some(VimeoStream::Hls(
+                "https://hls.example.com/master.m3u8".to_string()
+            ))
+        );
+
+        let track = config.into_track("76979871");
+        assert_eq!(track.get_title(), "The New Vimeo Player");
+        assert_eq!(track.get_url(), "https://vimeo.com/76979871");
+        assert_eq!(track.get_raw_duration(), Some(Duration::from_secs(62)));
+        assert_eq!(track.hls_url(), Some("https://hls.example.com/master.m3u8"));
+    }
+}
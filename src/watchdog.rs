@@ -0,0 +1,184 @@
+use crate::Data;
+use serenity::all::{async_trait, GuildId};
+use songbird::tracks::{PlayMode, ReadyState, TrackHandle};
+use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often the watchdog checks that the track playing is moving along.
+pub const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Checks in a row the position of a playing track has to stay put before
+/// it counts as stuck.
+pub const STALL_CHECKS: usize = 3;
+/// Times in a row the same track is recovered before it is skipped instead.
+pub const STALL_RECOVERY_ATTEMPTS: usize = 3;
+
+/// Tells whether a track is stuck from its position at each check.
+#[derive(Clone, Debug, Default)]
+pub struct StallDetector {
+    position: Option<Duration>,
+    /// Checks in a row the position stayed put.
+    stalled_checks: usize,
+}
+
+/// Implement [`StallDetector`].
+impl StallDetector {
+    /// Note the position of the track at a check. Returns whether it hasn't
+    /// moved for [`STALL_CHECKS`] checks, after which it starts over.
+    pub fn check(&mut self, position: Duration) -> bool {
+        if self.position.replace(position) == Some(position) {
+            self.stalled_checks += 1;
+        } else {
+            self.stalled_checks = 0;
+        }
+        if self.stalled_checks >= STALL_CHECKS {
+            self.stalled_checks = 0;
+            return true;
+        }
+        false
+    }
+
+    /// Forget the position, e.g. when another track plays or it is paused.
+    pub fn reset(&mut self) {
+        *self = StallDetector::default();
+    }
+}
+
+/// What the [`StallWatchdog`] of a guild knows about its track.
+#[derive(Default)]
+struct WatchdogState {
+    song: Option<TrackHandle>,
+    detector: StallDetector,
+    /// Identity of the last track recovered, see
+    /// [`crate::ResolvedTrack::identity`], with the times in a row it was.
+    recovered: Option<(String, usize)>,
+}
+
+/// Checks every [`STALL_CHECK_INTERVAL`] that the track playing in a guild
+/// is moving along, added as a global event of the call. A track whose
+/// position stops advancing while it is supposed to be playing, e.g. when
+/// its stream hangs, is stopped and played again from where it got stuck
+/// with a fresh stream, see [`crate::DataInner::stalled`]. A track that keeps
+/// getting stuck is skipped.
+pub struct StallWatchdog {
+    pub guild_id: GuildId,
+    pub data: Arc<Data>,
+    state: Mutex<WatchdogState>,
+}
+
+/// Implement [`StallWatchdog`].
+impl StallWatchdog {
+    /// Create a new [`StallWatchdog`] for the tracks of a guild.
+    pub fn new(guild_id: GuildId, data: Arc<Data>) -> Self {
+        StallWatchdog {
+            guild_id,
+            data,
+            state: Mutex::new(WatchdogState::default()),
+        }
+    }
+
+    /// Note the position of the song playing in the guild, returning whether
+    /// it is stuck.
+    fn check(&self, song: &TrackHandle, position: Duration) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return false;
+        };
+        if !state
+            .song
+            .as_ref()
+            .is_some_and(|checked| checked.uuid() == song.uuid())
+        {
+            state.song = Some(song.clone());
+            state.detector.reset();
+        }
+        state.detector.check(position)
+    }
+
+    /// Forget the position of the song, it isn't playing.
+    fn reset(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.detector.reset();
+        }
+    }
+
+    /// Count a recovery of a track, returning whether it may be recovered
+    /// again rather than skipped.
+    fn count_recovery(&self, identity: String) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return false;
+        };
+        let recoveries = match state.recovered.take() {
+            Some((recovered, recoveries)) if recovered == identity => recoveries + 1,
+            _ => 1,
+        };
+        state.recovered = Some((identity, recoveries));
+        recoveries <= STALL_RECOVERY_ATTEMPTS
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for StallWatchdog {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let Some(song) = self
+            .data
+            .current_tracks
+            .get(&self.guild_id)
+            .map(|song| song.clone())
+        else {
+            self.reset();
+            return None;
+        };
+        // Tracks still opening haven't started moving yet
+        let position = match song.get_info().await {
+            Ok(info)
+                if info.playing == PlayMode::Play && matches!(info.ready, ReadyState::Playable) =>
+            {
+                info.position
+            }
+            _ => {
+                self.reset();
+                return None;
+            }
+        };
+        if !self.check(&song, position) {
+            return None;
+        }
+        let queue = self.data.guild_queues.get(&self.guild_id)?.clone();
+        let track = queue.current().await?;
+        #[cfg(feature = "crack-tracing")]
+        tracing::warn!("{} is stuck at {position:?}", track.get_url());
+        // Stopping it ends it, which plays it again from where it got stuck
+        // unless it keeps getting stuck, then the next track plays
+        if self.count_recovery(track.identity()) {
+            self.data.stalled.insert(self.guild_id, position);
+        }
+        if song.stop().is_err() {
+            // It ended meanwhile
+            self.data.stalled.remove(&self.guild_id);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stall_detector() {
+        let mut detector = StallDetector::default();
+        assert!(!detector.check(Duration::from_secs(1)));
+        assert!(!detector.check(Duration::from_secs(6)));
+        // Moving along resets the count
+        for _ in 0..STALL_CHECKS - 1 {
+            assert!(!detector.check(Duration::from_secs(6)));
+        }
+        assert!(!detector.check(Duration::from_secs(11)));
+        for _ in 0..STALL_CHECKS - 1 {
+            assert!(!detector.check(Duration::from_secs(11)));
+        }
+        assert!(detector.check(Duration::from_secs(11)));
+        // It starts over once stuck
+        assert!(!detector.check(Duration::from_secs(11)));
+    }
+}